@@ -36,6 +36,31 @@ abigen!(
     "src/abi/IERC20.abi",
     event_derives(serde::Deserialize, serde::Serialize)
 );
+abigen!(
+    CurveRegistry,
+    "src/abi/ICurveRegistry.abi",
+    event_derives(serde::Deserialize, serde::Serialize)
+);
+abigen!(
+    BalancerVault,
+    "src/abi/IBalancerVault.abi",
+    event_derives(serde::Deserialize, serde::Serialize)
+);
+abigen!(
+    BalancerWeightedPool,
+    "src/abi/IBalancerWeightedPool.abi",
+    event_derives(serde::Deserialize, serde::Serialize)
+);
+abigen!(
+    SolidlyFactory,
+    "src/abi/ISolidlyFactory.abi",
+    event_derives(serde::Deserialize, serde::Serialize)
+);
+abigen!(
+    VelodromePoolFactory,
+    "src/abi/IVelodromePoolFactory.abi",
+    event_derives(serde::Deserialize, serde::Serialize)
+);
 abigen!(
     BrainDance,
     "src/abi/IBrainDance.abi",
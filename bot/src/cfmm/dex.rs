@@ -1,12 +1,21 @@
 // credit to 0xKitsune's cfmms-rs: https://github.com/0xKitsune/cfmms-rs/tree/main/src/dex
 use std::sync::Arc;
 
-use ethers::prelude::*;
+use ethers::{
+    abi::{self, ParamType, Token},
+    prelude::*,
+    utils::keccak256,
+};
 use eyre::Result;
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use revm::db::{CacheDB, EmptyDB};
 
 use crate::{
-    prelude::{Pool, PoolVariant, UniswapV2Factory, UniswapV3Factory},
+    prelude::{
+        detect_v2_swap_fee, find_balance_slot, fork_factory::ForkFactory, uniswap_v4,
+        BalancerVault, BalancerWeightedPool, CurveRegistry, Erc20, Pool, PoolExtra, PoolVariant,
+        SolidlyFactory, UniswapV2Factory, UniswapV3Factory, VelodromePoolFactory,
+    },
     types::PairSyncError,
     utils,
 };
@@ -16,6 +25,11 @@ pub struct Dex {
     pub factory_address: Address,
     pub pool_variant: PoolVariant,
     pub creation_block: BlockNumber,
+    /// fee charged by all pools of this factory (1e6 units), only used for UniswapV2 variants
+    /// (Solidly fees are read from the factory)
+    pub swap_fee: U256,
+    /// detect fee of each pool by simulating a swap instead of trusting `swap_fee`
+    pub detect_swap_fee: bool,
 }
 
 impl Dex {
@@ -25,11 +39,25 @@ impl Dex {
             factory_address,
             pool_variant,
             creation_block: BlockNumber::Number(creation_block.into()),
+            swap_fee: U256::from(3000),
+            detect_swap_fee: false,
         }
     }
 
+    // Set the fee charged by pools of this factory (1e6 units, e.g. 2500 = 0.25%)
+    pub fn with_swap_fee(mut self, swap_fee: u32) -> Dex {
+        self.swap_fee = U256::from(swap_fee);
+        self
+    }
+
+    // Detect the fee of each pool by simulating a tiny swap when syncing
+    pub fn with_swap_fee_detection(mut self) -> Dex {
+        self.detect_swap_fee = true;
+        self
+    }
+
     // Parse logs and extract pools
-    pub fn new_pool_from_event(&self, log: Log, provider: Arc<Provider<Ws>>) -> Option<Pool> {
+    pub async fn new_pool_from_event(&self, log: Log, provider: Arc<Provider<Ws>>) -> Option<Pool> {
         match self.pool_variant {
             PoolVariant::UniswapV2 => {
                 let uniswap_v2_factory = UniswapV2Factory::new(self.factory_address, provider);
@@ -53,7 +81,7 @@ impl Dex {
                     address,
                     token_0,
                     token_1,
-                    U256::from(3000),
+                    self.swap_fee,
                     PoolVariant::UniswapV2,
                 ))
             }
@@ -84,10 +112,313 @@ impl Dex {
                     PoolVariant::UniswapV3,
                 ))
             }
+            PoolVariant::CurveStableSwap => {
+                // registry only logs the pool address, everything else is read from registry
+                let address = Address::from(*log.topics.get(1)?);
+                let curve_registry = CurveRegistry::new(self.factory_address, provider.clone());
+
+                // only plain two coin pools are tracked
+                let n_coins = curve_registry.get_n_coins(address).call().await.ok()?;
+                if n_coins[0] != U256::from(2) {
+                    return None;
+                }
+
+                // ignore pool does not have weth or native eth as one of its coins
+                let coins = curve_registry.get_coins(address).call().await.ok()?;
+                let (coin_0, coin_1) = (coins[0], coins[1]);
+                let eth_like = [
+                    utils::constants::get_weth_address(),
+                    utils::constants::get_eth_placeholder_address(),
+                ];
+                if !eth_like.contains(&coin_0) && !eth_like.contains(&coin_1) {
+                    return None;
+                }
+
+                let balances = curve_registry.get_balances(address).call().await.ok()?;
+                let balances = [balances[0], balances[1]];
+                let balances_slot = find_curve_balances_slot(address, balances, &provider).await?;
+
+                // curve fees are in 1e10 units, convert to the 1e6 units used by other pools
+                let fees = curve_registry.get_fees(address).call().await.ok()?;
+                let swap_fee = fees[0] / 10_000;
+
+                let pool = Pool::new(
+                    address,
+                    coin_0,
+                    coin_1,
+                    swap_fee,
+                    PoolVariant::CurveStableSwap,
+                );
+                let token_0_index = if pool.token_0 == coin_0 { 0 } else { 1 };
+
+                // registry reports 18 decimals for native eth
+                let decimals = curve_registry.get_decimals(address).call().await.ok()?;
+                let decimals = match token_0_index {
+                    0 => [decimals[0].low_u32() as u8, decimals[1].low_u32() as u8],
+                    _ => [decimals[1].low_u32() as u8, decimals[0].low_u32() as u8],
+                };
+
+                Some(pool.with_extra(PoolExtra::Curve {
+                    token_0_index,
+                    balances_slot,
+                    decimals,
+                }))
+            }
+            PoolVariant::BalancerWeighted => {
+                let pool_id = *log.topics.get(1)?;
+                let address = Address::from(*log.topics.get(2)?);
+
+                // only two token pools keep both balances packed in a single vault slot
+                if log.data.len() != 32 || U256::from_big_endian(&log.data) != U256::from(2) {
+                    return None;
+                }
+
+                // ignore pool does not have weth as one of its tokens
+                let balancer_vault = BalancerVault::new(self.factory_address, provider.clone());
+                let (tokens, _, _) = balancer_vault
+                    .get_pool_tokens(pool_id.to_fixed_bytes())
+                    .call()
+                    .await
+                    .ok()?;
+                if tokens.len() != 2 || !tokens.contains(&utils::constants::get_weth_address()) {
+                    return None;
+                }
+
+                // pools registered by other factories (e.g. stable pools) do not expose weights
+                let weighted_pool = BalancerWeightedPool::new(address, provider.clone());
+                let weights = weighted_pool.get_normalized_weights().call().await.ok()?;
+                if weights.len() != 2 {
+                    return None;
+                }
+
+                // balancer fees are in 1e18 units, convert to the 1e6 units used by other pools
+                let swap_fee = weighted_pool.get_swap_fee_percentage().call().await.ok()?;
+                let swap_fee = swap_fee / U256::exp10(12);
+
+                let pool = Pool::new(
+                    address,
+                    tokens[0],
+                    tokens[1],
+                    swap_fee,
+                    PoolVariant::BalancerWeighted,
+                );
+                let weights = match pool.token_0 == tokens[0] {
+                    true => [weights[0].as_u64(), weights[1].as_u64()],
+                    false => [weights[1].as_u64(), weights[0].as_u64()],
+                };
+
+                let balances_key = find_balancer_balances_key(
+                    self.factory_address,
+                    pool_id,
+                    (pool.token_0, pool.token_1),
+                    &provider,
+                )
+                .await?;
+
+                Some(pool.with_extra(PoolExtra::Balancer {
+                    pool_id,
+                    weights,
+                    balances_key,
+                }))
+            }
+            PoolVariant::Solidly => {
+                let solidly_factory = SolidlyFactory::new(self.factory_address, provider.clone());
+
+                let (token_0, token_1, stable, address, _) = if let Ok(pair) = solidly_factory
+                    .decode_event::<(Address, Address, bool, Address, U256)>(
+                        "PairCreated",
+                        log.topics,
+                        log.data,
+                    ) {
+                    pair
+                } else {
+                    return None;
+                };
+
+                // ignore pair does not have weth as one of its tokens
+                if ![token_0, token_1].contains(&utils::constants::get_weth_address()) {
+                    return None;
+                }
+
+                // fees are set by the factory in bps, per pair (velodrome v2) or per curve
+                // (velodrome v1), ignore pair if factory exposes neither
+                let pool_factory =
+                    VelodromePoolFactory::new(self.factory_address, provider.clone());
+                let fee_bps = match pool_factory.get_fee(address, stable).call().await {
+                    Ok(fee_bps) => fee_bps,
+                    Err(_) => solidly_factory.get_fee(stable).call().await.ok()?,
+                };
+                let swap_fee = fee_bps * 100;
+
+                let (erc20_0, erc20_1) = (
+                    Erc20::new(token_0, provider.clone()),
+                    Erc20::new(token_1, provider),
+                );
+                let decimals = [
+                    erc20_0.decimals().call().await.ok()?,
+                    erc20_1.decimals().call().await.ok()?,
+                ];
+
+                // factory emits tokens already sorted
+                let pool = Pool::new(address, token_0, token_1, swap_fee, PoolVariant::Solidly);
+
+                Some(pool.with_extra(PoolExtra::Solidly { stable, decimals }))
+            }
+            PoolVariant::UniswapV4 => {
+                let pool_id = *log.topics.get(1)?;
+                let currency_0 = Address::from(*log.topics.get(2)?);
+                let currency_1 = Address::from(*log.topics.get(3)?);
+
+                let data = abi::decode(
+                    &[
+                        ParamType::Uint(24),
+                        ParamType::Int(24),
+                        ParamType::Address,
+                        ParamType::Uint(160),
+                        ParamType::Int(24),
+                    ],
+                    &log.data,
+                )
+                .ok()?;
+                let fee = data[0].clone().into_uint()?.as_u32();
+                let tick_spacing = I256::from_raw(data[1].clone().into_int()?).as_i32();
+                let hooks = data[2].clone().into_address()?;
+
+                // ignore pool does not have weth or native eth (zero address) as one of its tokens
+                let eth_like = [utils::constants::get_weth_address(), Address::zero()];
+                if !eth_like.contains(&currency_0) && !eth_like.contains(&currency_1) {
+                    return None;
+                }
+
+                // hooks that run during swaps can make the victim's outcome differ from ours
+                if !uniswap_v4::is_hook_allowed(hooks, fee) {
+                    return None;
+                }
+
+                // every v4 pool lives inside of the pool manager
+                let pool = Pool::new(
+                    self.factory_address,
+                    currency_0,
+                    currency_1,
+                    U256::from(fee),
+                    PoolVariant::UniswapV4,
+                );
+
+                Some(pool.with_extra(PoolExtra::UniswapV4 {
+                    pool_id,
+                    hooks,
+                    tick_spacing,
+                    state_slot: uniswap_v4::pool_state_slot(pool_id),
+                }))
+            }
         }
     }
 }
 
+// Find the storage slot curve pool keeps `balances[0]` in (layout differs between vyper versions)
+//
+// Arguments:
+// * `pool`: address of curve pool
+// * `balances`: current balances of the pool's two coins
+// * `provider`: websocket provider used to read storage
+//
+// Returns:
+// Some(u64): slot of `balances[0]`
+// None: if pool is empty or balances are not kept in storage (e.g. native eth read from balance)
+async fn find_curve_balances_slot(
+    pool: Address,
+    balances: [U256; 2],
+    provider: &Arc<Provider<Ws>>,
+) -> Option<u64> {
+    // both balances need to be known for the slot to be unambiguous
+    if balances.iter().any(|b| b.is_zero()) {
+        return None;
+    }
+
+    // balances are declared after a handful of config variables in all pool templates
+    let max_slot = 32u64;
+    let reads = (0..=max_slot).map(|slot| {
+        let provider = provider.clone();
+        async move {
+            provider
+                .get_storage_at(pool, H256::from_low_u64_be(slot), None)
+                .await
+                .map(|value| U256::from(value.to_fixed_bytes()))
+        }
+    });
+    let values: Vec<U256> = futures::future::join_all(reads)
+        .await
+        .into_iter()
+        .collect::<Result<_, _>>()
+        .ok()?;
+
+    values
+        .windows(2)
+        .position(|w| w[0] == balances[0] && w[1] == balances[1])
+        .map(|slot| slot as u64)
+}
+
+// Find the vault storage slot holding a two token pool's packed cash balances
+//
+// Vault keeps `mapping(bytes32 => TwoTokenPoolTokens)`, each entry stores `tokenA`, `tokenB` and a
+// `mapping(bytes32 => TwoTokenPoolBalances)` keyed by `keccak256(tokenA, tokenB)`, the mapping's
+// slot is found by looking for the entry whose `tokenA` matches
+//
+// Arguments:
+// * `vault`: address of balancer vault
+// * `pool_id`: id of pool in vault
+// * `tokens`: pool's (tokenA, tokenB), sorted like the vault sorts them
+// * `provider`: websocket provider used to read storage
+//
+// Returns:
+// Some(H256): slot of the pool's `sharedCash`
+// None: if no slot holds the pool's tokens
+async fn find_balancer_balances_key(
+    vault: Address,
+    pool_id: H256,
+    tokens: (Address, Address),
+    provider: &Arc<Provider<Ws>>,
+) -> Option<H256> {
+    let (token_a, token_b) = tokens;
+
+    // location of `TwoTokenPoolTokens` struct if the mapping is declared at `slot`
+    let entry_location = |slot: u64| -> H256 {
+        H256::from(keccak256(abi::encode(&[
+            Token::FixedBytes(pool_id.as_bytes().to_vec()),
+            Token::Uint(U256::from(slot)),
+        ])))
+    };
+
+    let max_slot = 32u64;
+    let reads = (0..=max_slot).map(|slot| {
+        let provider = provider.clone();
+        let location = entry_location(slot);
+        async move {
+            provider
+                .get_storage_at(vault, location, None)
+                .await
+                .map(Address::from)
+        }
+    });
+    let values: Vec<Address> = futures::future::join_all(reads)
+        .await
+        .into_iter()
+        .collect::<Result<_, _>>()
+        .ok()?;
+
+    let slot = values.iter().position(|token| *token == token_a)? as u64;
+
+    // `balances` mapping sits two slots after the start of the struct
+    let pair_hash = keccak256([token_a.as_bytes(), token_b.as_bytes()].concat());
+    let balances_slot = U256::from(entry_location(slot).to_fixed_bytes()) + 2;
+    let balances_key = keccak256(abi::encode(&[
+        Token::FixedBytes(pair_hash.to_vec()),
+        Token::Uint(balances_slot),
+    ]));
+
+    Some(H256::from(balances_key))
+}
+
 // get all pairs for a given dex between `start_block` and `current_block`
 pub async fn sync_dex(
     dexes: Vec<Dex>,
@@ -112,7 +443,7 @@ pub async fn sync_dex(
                     .progress_chars("##-"),
             );
 
-            let pools = get_all_pools(
+            let mut pools = get_all_pools(
                 dex,
                 async_provider.clone(),
                 BlockNumber::Number(current_block),
@@ -121,6 +452,16 @@ pub async fn sync_dex(
             )
             .await?;
 
+            // detection runs blocking evm simulations, keep them off the async workers
+            if dex.detect_swap_fee && dex.pool_variant == PoolVariant::UniswapV2 {
+                let provider = async_provider.clone();
+                pools = tokio::task::spawn_blocking(move || {
+                    detect_swap_fees(&mut pools, provider, current_block);
+                    pools
+                })
+                .await?;
+            }
+
             progress_bar.reset();
             progress_bar.set_style(
                 ProgressStyle::with_template("{msg} {bar:40.green/grey} {pos:>7}/{len:7} Pairs")
@@ -146,6 +487,41 @@ pub async fn sync_dex(
     Ok(aggregated_pools)
 }
 
+// Overwrite the configured fee of each pool with the fee found by simulating a swap
+//
+// Arguments:
+// * `pools`: UniswapV2 style pools to detect fees for
+// * `provider`: websocket provider used by the fork
+// * `block`: block to fork from
+//
+// Returns: This function returns nothing, pools that fail detection keep the configured fee
+fn detect_swap_fees(pools: &mut Vec<Pool>, provider: Arc<Provider<Ws>>, block: U64) {
+    let fork_block = Some(BlockId::Number(BlockNumber::Number(block)));
+    let mut fork_factory =
+        ForkFactory::new_sandbox_factory(provider, CacheDB::new(EmptyDB::default()), fork_block);
+
+    // weth's balance slot is the same for every pool, find it through the first one holding weth
+    let weth = utils::constants::get_weth_address();
+    let weth_balance_slot = pools
+        .iter()
+        .find_map(|pool| find_balance_slot(weth, pool.address, &mut fork_factory).ok()?);
+    let weth_balance_slot = match weth_balance_slot {
+        Some(slot) => slot,
+        None => {
+            log::warn!("Could not find weth balance slot, keeping configured swap fees");
+            return;
+        }
+    };
+
+    for pool in pools.iter_mut() {
+        match detect_v2_swap_fee(pool, weth_balance_slot, &mut fork_factory) {
+            Ok(Some(swap_fee)) => pool.swap_fee = swap_fee,
+            Ok(None) => log::warn!("Could not detect swap fee for pool {:?}", pool.address),
+            Err(e) => log::warn!("Swap fee detection failed for {:?}: {}", pool.address, e),
+        }
+    }
+}
+
 /// function to get all pair created events for a given Dex factory address
 async fn get_all_pools(
     dex: Dex,
@@ -202,7 +578,7 @@ async fn get_all_pools(
 
             // for each pair created log, create a new Pair type and add it to the pairs vec
             for log in logs {
-                match dex.new_pool_from_event(log, provider.clone()) {
+                match dex.new_pool_from_event(log, provider.clone()).await {
                     Some(pool) => pools.push(pool),
                     None => continue,
                 }
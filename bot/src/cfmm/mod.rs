@@ -3,3 +3,17 @@ pub use dex::*;
 
 pub mod pool;
 pub use pool::*;
+
+pub mod stable_swap;
+pub use stable_swap::*;
+
+pub mod weighted_product;
+pub use weighted_product::*;
+
+pub mod solidly;
+pub use solidly::*;
+
+pub mod uniswap_v4;
+
+pub mod pool_snapshot;
+pub use pool_snapshot::*;
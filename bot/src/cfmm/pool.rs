@@ -4,7 +4,9 @@ use std::{
     str::FromStr,
 };
 
-use ethers::prelude::*;
+use ethers::{prelude::*, utils::keccak256};
+
+use crate::utils;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Pool {
@@ -13,12 +15,66 @@ pub struct Pool {
     pub token_1: Address,
     pub swap_fee: U256,
     pub pool_variant: PoolVariant,
+    pub extra: PoolExtra,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum PoolVariant {
     UniswapV2,
     UniswapV3,
+    CurveStableSwap,
+    BalancerWeighted,
+    Solidly,
+    UniswapV4,
+}
+
+/// Key pools are registered under
+///
+/// Most pools are their own contract, pools living inside of a singleton (balancer's vault, v4's
+/// `PoolManager`) are keyed by the storage slot their swap state lives in so that they can be
+/// looked up straight from a state diff
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PoolIdentifier {
+    Address(Address),
+    Singleton { singleton: Address, slot: H256 },
+}
+
+/// Variant specific info that is needed to read a pool's state
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PoolExtra {
+    #[default]
+    None,
+    Curve {
+        /// index of `token_0` in the pool's `coins` array (`token_1` is the other index)
+        token_0_index: u8,
+        /// storage slot of `balances[0]`, `balances[i]` lives at `balances_slot + i`
+        balances_slot: u64,
+        /// decimals of `token_0` and `token_1` (invariant is solved on balances scaled to 18)
+        decimals: [u8; 2],
+    },
+    Balancer {
+        /// id the vault knows the pool by
+        pool_id: H256,
+        /// normalized weights of `token_0` and `token_1` (1e18 = 100%)
+        weights: [u64; 2],
+        /// vault storage slot holding the pool's packed cash balances (`sharedCash`)
+        balances_key: H256,
+    },
+    Solidly {
+        /// true if pair uses the `x^3y+y^3x` curve, false for `xy`
+        stable: bool,
+        /// decimals of `token_0` and `token_1` (stable math normalizes balances to 18 decimals)
+        decimals: [u8; 2],
+    },
+    UniswapV4 {
+        /// id the pool manager knows the pool by (hash of its `PoolKey`)
+        pool_id: H256,
+        /// hook contract of pool, zero address if pool has no hook
+        hooks: Address,
+        tick_spacing: i32,
+        /// pool manager storage slot holding the pool's `slot0`
+        state_slot: H256,
+    },
 }
 
 impl Pool {
@@ -42,6 +98,31 @@ impl Pool {
             token_1,
             swap_fee,
             pool_variant,
+            extra: PoolExtra::default(),
+        }
+    }
+
+    // Attach variant specific info to pool
+    pub fn with_extra(mut self, extra: PoolExtra) -> Pool {
+        self.extra = extra;
+        self
+    }
+
+    // Key the pool is registered under
+    pub fn id(&self) -> PoolIdentifier {
+        match self.extra {
+            PoolExtra::Balancer { balances_key, .. } => PoolIdentifier::Singleton {
+                singleton: utils::constants::get_balancer_vault_address(),
+                slot: balances_key,
+            },
+            // v4 pools use the pool manager as their address
+            PoolExtra::UniswapV4 { state_slot, .. } => PoolIdentifier::Singleton {
+                singleton: self.address,
+                slot: state_slot,
+            },
+            PoolExtra::None | PoolExtra::Curve { .. } | PoolExtra::Solidly { .. } => {
+                PoolIdentifier::Address(self.address)
+            }
         }
     }
 }
@@ -57,12 +138,61 @@ impl PoolVariant {
                 H256::from_str("0x783cca1c0412dd0d695e784568c96da2e9c22ff989357a2e8b1d9b2b4e6b7118")
                     .unwrap()
             }
+            // emitted by curve's registry, not by the pools' factory
+            PoolVariant::CurveStableSwap => H256::from(keccak256("PoolAdded(address,bytes)")),
+            // emitted by balancer's vault, pools do not have a shared factory
+            PoolVariant::BalancerWeighted => {
+                H256::from(keccak256("PoolRegistered(bytes32,address,uint8)"))
+            }
+            PoolVariant::Solidly => H256::from(keccak256(
+                "PairCreated(address,address,bool,address,uint256)",
+            )),
+            PoolVariant::UniswapV4 => H256::from(keccak256(
+                "Initialize(bytes32,address,address,uint24,int24,address,uint160,int24)",
+            )),
         }
     }
+
+    // Name used in configs and metric labels
+    pub fn name(&self) -> &'static str {
+        match self {
+            PoolVariant::UniswapV2 => "uniswap_v2",
+            PoolVariant::UniswapV3 => "uniswap_v3",
+            PoolVariant::CurveStableSwap => "curve",
+            PoolVariant::BalancerWeighted => "balancer",
+            PoolVariant::Solidly => "solidly",
+            PoolVariant::UniswapV4 => "uniswap_v4",
+        }
+    }
+
+    // Can the sandwich contract swap on pools of this variant, pools of other variants are only
+    // tracked so that victims routing through them are detected
+    pub fn is_executable(&self) -> bool {
+        self.executable().is_some()
+    }
+
+    // Narrow variant down to one the sandwich contract can swap on
+    pub fn executable(&self) -> Option<ExecutableVariant> {
+        match self {
+            PoolVariant::UniswapV2 => Some(ExecutableVariant::UniswapV2),
+            PoolVariant::UniswapV3 => Some(ExecutableVariant::UniswapV3),
+            PoolVariant::CurveStableSwap
+            | PoolVariant::BalancerWeighted
+            | PoolVariant::Solidly
+            | PoolVariant::UniswapV4 => None,
+        }
+    }
+}
+
+/// Pool variants the sandwich contract knows how to swap on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExecutableVariant {
+    UniswapV2,
+    UniswapV3,
 }
 
 impl Hash for Pool {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.address.hash(state);
+        self.id().hash(state);
     }
 }
@@ -0,0 +1,387 @@
+use dashmap::DashMap;
+use ethers::{prelude::*, utils::keccak256};
+
+use crate::{
+    prelude::{
+        get_amount_out, get_amount_out_solidly, uniswap_v4, Pool, PoolExtra, PoolIdentifier,
+        PoolVariant,
+    },
+    utils,
+};
+
+/// Last known state of a pool, built from `Sync`/`Swap` logs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PoolSnapshot {
+    /// reserves of `token_0` and `token_1`, virtual reserves for concentrated liquidity pools
+    pub reserves: (U256, U256),
+    /// active liquidity, only set for concentrated liquidity pools
+    pub liquidity: u128,
+    /// price after the last swap, only set for concentrated liquidity pools
+    pub sqrt_price_x96: U256,
+    /// block the snapshot was taken in
+    pub block: U64,
+}
+
+impl PoolSnapshot {
+    // Find how much weth (or native eth) the pool holds
+    //
+    // Arguments:
+    // * `&self`: reference to `PoolSnapshot` instance
+    // * `pool`: pool the snapshot belongs to
+    //
+    // Returns:
+    // U256: reserve of the weth side of pool
+    pub fn weth_reserve(&self, pool: &Pool) -> U256 {
+        let eth_like = [
+            utils::constants::get_weth_address(),
+            utils::constants::get_eth_placeholder_address(),
+            Address::zero(),
+        ];
+        match eth_like.contains(&pool.token_0) {
+            true => self.reserves.0,
+            false => self.reserves.1,
+        }
+    }
+
+    // Quote a swap off-chain using the snapshot
+    //
+    // Arguments:
+    // * `&self`: reference to `PoolSnapshot` instance
+    // * `pool`: pool the snapshot belongs to
+    // * `amount_in`: amount of token in
+    // * `token_in`: address of token in
+    //
+    // Returns:
+    // Some(U256): amount out
+    // None: if pool variant can not be quoted from reserves alone (ticks or amp are needed)
+    pub fn get_amount_out(&self, pool: &Pool, amount_in: U256, token_in: Address) -> Option<U256> {
+        let is_token_0_in = token_in == pool.token_0;
+        let (reserve_in, reserve_out) = match is_token_0_in {
+            true => self.reserves,
+            false => (self.reserves.1, self.reserves.0),
+        };
+
+        match (pool.pool_variant, pool.extra) {
+            (PoolVariant::UniswapV2, _) => Some(get_amount_out(
+                amount_in,
+                reserve_in,
+                reserve_out,
+                pool.swap_fee,
+            )),
+            (PoolVariant::Solidly, PoolExtra::Solidly { stable, decimals }) => {
+                let (decimals_in, decimals_out) = match is_token_0_in {
+                    true => (decimals[0], decimals[1]),
+                    false => (decimals[1], decimals[0]),
+                };
+                Some(get_amount_out_solidly(
+                    amount_in,
+                    reserve_in,
+                    reserve_out,
+                    decimals_in,
+                    decimals_out,
+                    stable,
+                    pool.swap_fee,
+                ))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Keeps a snapshot of every tracked pool that emitted a `Sync`/`Swap` log
+#[derive(Debug)]
+pub struct PoolSnapshots {
+    snapshots: DashMap<PoolIdentifier, PoolSnapshot>,
+    // pools holding less weth than this are not worth simulating
+    min_weth_liquidity: U256,
+}
+
+impl PoolSnapshots {
+    // Create a new empty snapshot store
+    //
+    // Arguments:
+    // * `min_weth_liquidity`: min weth a pool needs to hold to be simulated
+    //
+    // Returns:
+    // `PoolSnapshots`: store without any snapshots
+    pub fn new(min_weth_liquidity: U256) -> Self {
+        Self {
+            snapshots: DashMap::new(),
+            min_weth_liquidity,
+        }
+    }
+
+    // Create snapshot store using `MIN_POOL_WETH_LIQUIDITY`
+    pub fn from_env() -> Self {
+        Self::new(utils::dotenv::get_min_pool_weth_liquidity())
+    }
+
+    // Get latest snapshot of a pool
+    pub fn get(&self, id: &PoolIdentifier) -> Option<PoolSnapshot> {
+        self.snapshots.get(id).map(|s| *s.value())
+    }
+
+    // Number of pools with a snapshot
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    // Check if there are no snapshots yet
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    // Check if a pool holds enough weth to be worth simulating
+    //
+    // Pools without a snapshot have not swapped since the bot started watching, they are let
+    // through so that quiet long tail pools are not missed
+    //
+    // Arguments:
+    // * `&self`: reference to `PoolSnapshots` instance
+    // * `pool`: pool to check
+    //
+    // Returns:
+    // bool: false if pool is known to hold less weth than the configured minimum
+    pub fn is_liquid(&self, pool: &Pool) -> bool {
+        match self.get(&pool.id()) {
+            Some(snapshot) => snapshot.weth_reserve(pool) >= self.min_weth_liquidity,
+            None => true,
+        }
+    }
+
+    // Check if a victim's exact in swap leaves any room for a frontrun, quoted off the snapshot
+    //
+    // Only snapshots taken in `block` describe the state victim executes on, pools with an older
+    // (or without a) snapshot and pools that can not be quoted from reserves are assumed to leave
+    // room so that the simulation decides
+    //
+    // Arguments:
+    // * `&self`: reference to `PoolSnapshots` instance
+    // * `pool`: pool victim swaps on (must be victim's only hop)
+    // * `token_in`: token victim sells
+    // * `amount_in`: amount victim sells
+    // * `amount_out_min`: min amount victim accepts before reverting
+    // * `block`: latest block
+    //
+    // Returns:
+    // bool: false if victim already receives less than its min amount out without a frontrun
+    pub fn leaves_frontrun_room(
+        &self,
+        pool: &Pool,
+        token_in: Address,
+        amount_in: U256,
+        amount_out_min: U256,
+        block: U64,
+    ) -> bool {
+        let snapshot = match self.get(&pool.id()) {
+            Some(snapshot) if snapshot.block == block => snapshot,
+            _ => return true,
+        };
+        match snapshot.get_amount_out(pool, amount_in, token_in) {
+            Some(amount_out) => amount_out > amount_out_min,
+            None => true,
+        }
+    }
+
+    // Update a pool's snapshot from one of its `Sync`/`Swap` logs
+    //
+    // Arguments:
+    // * `&self`: reference to `PoolSnapshots` instance
+    // * `pool`: pool that emitted the log
+    // * `log`: log emitted by pool (or by the singleton the pool lives in)
+    //
+    // Returns:
+    // bool: true if snapshot was updated, false if log could not be decoded
+    pub fn update_from_log(&self, pool: &Pool, log: &Log) -> bool {
+        let block = log.block_number.unwrap_or_default();
+        let topic = match log.topics.first() {
+            Some(topic) => *topic,
+            None => return false,
+        };
+
+        let word = |index: usize| -> Option<U256> {
+            log.data
+                .get(index * 32..(index + 1) * 32)
+                .map(U256::from_big_endian)
+        };
+
+        let snapshot = if topic == v2_sync_signature() || topic == solidly_sync_signature() {
+            let (reserve_0, reserve_1) = match (word(0), word(1)) {
+                (Some(r0), Some(r1)) => (r0, r1),
+                _ => return false,
+            };
+            PoolSnapshot {
+                reserves: (reserve_0, reserve_1),
+                block,
+                ..Default::default()
+            }
+        } else if topic == v3_swap_signature() || topic == v4_swap_signature() {
+            // both events put price and liquidity right after the two amounts
+            let (sqrt_price_x96, liquidity) = match (word(2), word(3)) {
+                (Some(price), Some(liquidity)) => (price, liquidity.low_u128()),
+                _ => return false,
+            };
+            PoolSnapshot {
+                reserves: virtual_reserves(liquidity, sqrt_price_x96),
+                liquidity,
+                sqrt_price_x96,
+                block,
+            }
+        } else {
+            return false;
+        };
+
+        self.snapshots.insert(pool.id(), snapshot);
+        true
+    }
+}
+
+// Topics of all logs that snapshots are built from
+pub fn snapshot_event_signatures() -> Vec<H256> {
+    vec![
+        v2_sync_signature(),
+        solidly_sync_signature(),
+        v3_swap_signature(),
+        v4_swap_signature(),
+    ]
+}
+
+// Find the identifier of the pool that emitted a snapshot log
+//
+// Arguments:
+// * `log`: `Sync`/`Swap` log
+//
+// Returns:
+// Some(PoolIdentifier): key pool is registered under
+// None: if log is a v4 swap without a pool id
+pub fn pool_id_from_log(log: &Log) -> Option<PoolIdentifier> {
+    match log.topics.first() {
+        // v4 swaps are emitted by the pool manager with the pool id as first topic
+        Some(topic) if *topic == v4_swap_signature() => Some(PoolIdentifier::Singleton {
+            singleton: log.address,
+            slot: uniswap_v4::pool_state_slot(*log.topics.get(1)?),
+        }),
+        _ => Some(PoolIdentifier::Address(log.address)),
+    }
+}
+
+// Reserves that would give the same price and depth as concentrated liquidity in the current tick
+fn virtual_reserves(liquidity: u128, sqrt_price_x96: U256) -> (U256, U256) {
+    if sqrt_price_x96.is_zero() {
+        return (U256::zero(), U256::zero());
+    }
+
+    let q96 = U256::one() << 96;
+    let liquidity = U256::from(liquidity);
+    (
+        liquidity * q96 / sqrt_price_x96,
+        liquidity * sqrt_price_x96 / q96,
+    )
+}
+
+fn v2_sync_signature() -> H256 {
+    H256::from(keccak256("Sync(uint112,uint112)"))
+}
+
+fn solidly_sync_signature() -> H256 {
+    H256::from(keccak256("Sync(uint256,uint256)"))
+}
+
+fn v3_swap_signature() -> H256 {
+    H256::from(keccak256(
+        "Swap(address,address,int256,int256,uint160,uint128,int24)",
+    ))
+}
+
+fn v4_swap_signature() -> H256 {
+    H256::from(keccak256(
+        "Swap(bytes32,address,int128,int128,uint160,uint128,int24,uint24)",
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sync_log(pool: Address, reserve_0: U256, reserve_1: U256) -> Log {
+        let mut data = [0u8; 64];
+        reserve_0.to_big_endian(&mut data[..32]);
+        reserve_1.to_big_endian(&mut data[32..]);
+        Log {
+            address: pool,
+            topics: vec![v2_sync_signature()],
+            data: data.to_vec().into(),
+            ..Default::default()
+        }
+    }
+
+    fn weth_pool(weth_is_token_0: bool) -> Pool {
+        let weth = utils::constants::get_weth_address();
+        let token = match weth_is_token_0 {
+            true => Address::repeat_byte(0xff),
+            false => Address::repeat_byte(0x01),
+        };
+        Pool::new(
+            Address::repeat_byte(0xaa),
+            weth,
+            token,
+            U256::from(3000),
+            PoolVariant::UniswapV2,
+        )
+    }
+
+    #[test]
+    fn pools_below_min_weth_liquidity_are_filtered() {
+        let snapshots = PoolSnapshots::new(ethers::utils::parse_ether("1").unwrap());
+        let pool = weth_pool(true);
+        assert!(snapshots.is_liquid(&pool));
+
+        let dust = ethers::utils::parse_ether("0.1").unwrap();
+        let log = sync_log(pool.address, dust, U256::exp10(24));
+        assert!(snapshots.update_from_log(&pool, &log));
+        assert!(!snapshots.is_liquid(&pool));
+    }
+
+    #[test]
+    fn weth_reserve_follows_token_order() {
+        let snapshots = PoolSnapshots::new(U256::zero());
+        let pool = weth_pool(false);
+        let log = sync_log(pool.address, U256::from(7), U256::from(42));
+        snapshots.update_from_log(&pool, &log);
+
+        let snapshot = snapshots.get(&pool.id()).unwrap();
+        assert_eq!(snapshot.weth_reserve(&pool), U256::from(42));
+    }
+
+    #[test]
+    fn victims_without_slippage_leave_no_frontrun_room() {
+        let snapshots = PoolSnapshots::new(U256::zero());
+        let pool = weth_pool(true);
+        let weth = utils::constants::get_weth_address();
+        let reserve = ethers::utils::parse_ether("100").unwrap();
+        let mut log = sync_log(pool.address, reserve, reserve);
+        log.block_number = Some(U64::from(10));
+        snapshots.update_from_log(&pool, &log);
+
+        let amount_in = ethers::utils::parse_ether("1").unwrap();
+        let quote = snapshots
+            .get(&pool.id())
+            .unwrap()
+            .get_amount_out(&pool, amount_in, weth)
+            .unwrap();
+
+        let block = U64::from(10);
+        assert!(snapshots.leaves_frontrun_room(&pool, weth, amount_in, quote / 2, block));
+        assert!(!snapshots.leaves_frontrun_room(&pool, weth, amount_in, quote, block));
+        // stale snapshots let the simulation decide
+        assert!(snapshots.leaves_frontrun_room(&pool, weth, amount_in, quote, U64::from(11)));
+    }
+
+    #[test]
+    fn virtual_reserves_at_price_one() {
+        let q96 = U256::one() << 96;
+        let (reserve_0, reserve_1) = virtual_reserves(1_000_000, q96);
+        assert_eq!(reserve_0, U256::from(1_000_000));
+        assert_eq!(reserve_1, U256::from(1_000_000));
+    }
+}
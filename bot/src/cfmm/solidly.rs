@@ -0,0 +1,149 @@
+// port of solidly's pair math: https://github.com/solidlyexchange/solidly/blob/master/contracts/BaseV1-core.sol
+use ethers::prelude::*;
+
+// Max newton iterations, same as the solidity implementation
+const MAX_ITERATIONS: usize = 255;
+
+// Find amount out of a solidly pair (`getAmountOut` in solidity)
+//
+// Arguments:
+// * `amount_in`: amount of token in
+// * `reserve_in`: pool reserves of token in
+// * `reserve_out`: pool reserves of token out
+// * `decimals_in`: decimals of token in
+// * `decimals_out`: decimals of token out
+// * `stable`: true if pair uses the `x^3y+y^3x` curve, false for `xy`
+// * `swap_fee`: fee taken by pool (1e6 units, 100 = 0.01%)
+//
+// Returns:
+// U256: amount out, zero if pool has no liquidity
+pub fn get_amount_out_solidly(
+    amount_in: U256,
+    reserve_in: U256,
+    reserve_out: U256,
+    decimals_in: u8,
+    decimals_out: u8,
+    stable: bool,
+    swap_fee: U256,
+) -> U256 {
+    if reserve_in.is_zero() || reserve_out.is_zero() {
+        return U256::zero();
+    }
+
+    // fee is taken from amount in before it touches the curve
+    let amount_in = amount_in - amount_in * swap_fee / U256::from(1_000_000);
+
+    if !stable {
+        return amount_in * reserve_out / (reserve_in + amount_in);
+    }
+
+    let one = U256::exp10(18);
+    let scale_in = U256::exp10(decimals_in.into());
+    let scale_out = U256::exp10(decimals_out.into());
+
+    // stable math works on balances normalized to 18 decimals
+    let reserve_in = reserve_in * one / scale_in;
+    let reserve_out = reserve_out * one / scale_out;
+    let amount_in = amount_in * one / scale_in;
+
+    let xy = k(reserve_in, reserve_out);
+    let y = match get_y(amount_in + reserve_in, xy, reserve_out) {
+        Some(y) => y,
+        None => return U256::zero(),
+    };
+
+    reserve_out.saturating_sub(y) * scale_out / one
+}
+
+// Invariant of a stable pair for normalized balances
+fn k(x: U256, y: U256) -> U256 {
+    let one = U256::exp10(18);
+    let a = x * y / one;
+    let b = x * x / one + y * y / one;
+    a * b / one
+}
+
+// `x0 * y^3 + x0^3 * y`
+fn f(x0: U256, y: U256) -> U256 {
+    let one = U256::exp10(18);
+    x0 * (y * y / one * y / one) / one + (x0 * x0 / one * x0 / one) * y / one
+}
+
+// Derivative of `f` with respect to `y`
+fn d(x0: U256, y: U256) -> U256 {
+    let one = U256::exp10(18);
+    U256::from(3) * x0 * (y * y / one) / one + (x0 * x0 / one * x0 / one)
+}
+
+// Solve balance of token out so that invariant `xy` holds after balance in is set to `x0`
+fn get_y(x0: U256, xy: U256, mut y: U256) -> Option<U256> {
+    let one = U256::exp10(18);
+
+    for _ in 0..MAX_ITERATIONS {
+        let y_prev = y;
+        let k = f(x0, y);
+        let derivative = d(x0, y);
+        if derivative.is_zero() {
+            return None;
+        }
+
+        y = match k < xy {
+            true => y + (xy - k) * one / derivative,
+            false => y.checked_sub((k - xy) * one / derivative)?,
+        };
+
+        let diff = if y > y_prev { y - y_prev } else { y_prev - y };
+        if diff <= U256::one() {
+            return Some(y);
+        }
+    }
+
+    Some(y)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn volatile_pair_is_constant_product_after_fee() {
+        let reserve = U256::exp10(18) * 1_000;
+        let amount_in = U256::exp10(18);
+        let fee = U256::from(2000);
+
+        let out = get_amount_out_solidly(amount_in, reserve, reserve, 18, 18, false, fee);
+
+        let amount_in_after_fee = amount_in - amount_in * fee / U256::from(1_000_000);
+        assert_eq!(
+            out,
+            amount_in_after_fee * reserve / (reserve + amount_in_after_fee)
+        );
+    }
+
+    #[test]
+    fn stable_pair_has_less_slippage_than_volatile() {
+        let reserve = U256::exp10(18) * 1_000_000;
+        let amount_in = U256::exp10(18) * 10_000;
+
+        let stable = get_amount_out_solidly(amount_in, reserve, reserve, 18, 18, true, 0.into());
+        let volatile = get_amount_out_solidly(amount_in, reserve, reserve, 18, 18, false, 0.into());
+
+        assert!(stable > volatile);
+        assert!(stable <= amount_in);
+    }
+
+    #[test]
+    fn stable_pair_normalizes_decimals() {
+        // 1m usdc (6 decimals) against 1m dai (18 decimals)
+        let reserve_usdc = U256::exp10(6) * 1_000_000;
+        let reserve_dai = U256::exp10(18) * 1_000_000;
+        let amount_in = U256::exp10(6) * 100;
+
+        let out =
+            get_amount_out_solidly(amount_in, reserve_usdc, reserve_dai, 6, 18, true, 0.into());
+
+        // ~100 dai out for 100 usdc in
+        assert!(out <= U256::exp10(18) * 100);
+        assert!(out > U256::exp10(18) * 99);
+    }
+}
@@ -0,0 +1,193 @@
+// port of curve's StableSwap invariant: https://github.com/curvefi/curve-contract/blob/master/contracts/pool-templates/base/SwapTemplateBase.vy
+use ethers::prelude::*;
+
+/// Precision curve stores `A` with in newer pools (`A_precise()`)
+pub const A_PRECISION: u64 = 100;
+/// Precision of curve's `fee()` (4000000 = 0.04%)
+pub const FEE_DENOMINATOR: u64 = 10_000_000_000;
+
+// Max newton iterations, same as the vyper implementation
+const MAX_ITERATIONS: usize = 255;
+
+/// Off-chain model of a curve plain pool used for quoting swaps
+#[derive(Clone, Debug)]
+pub struct StableSwap {
+    /// amplification coefficient multiplied by `A_PRECISION`
+    pub amp: U256,
+    /// swap fee in `FEE_DENOMINATOR` units
+    pub fee: U256,
+    /// raw coin balances held by pool
+    pub balances: Vec<U256>,
+    /// multipliers that normalize each coin to 18 decimals (1e18 for 18 decimal coins)
+    pub rates: Vec<U256>,
+}
+
+impl StableSwap {
+    // Create a new `StableSwap` instance
+    pub fn new(amp: U256, fee: U256, balances: Vec<U256>, rates: Vec<U256>) -> Self {
+        Self {
+            amp,
+            fee,
+            balances,
+            rates,
+        }
+    }
+
+    // Find amount of coin `j` received when swapping `dx` of coin `i` (`get_dy` in vyper)
+    //
+    // Arguments:
+    // * `i`: index of coin in
+    // * `j`: index of coin out
+    // * `dx`: amount of coin in
+    //
+    // Returns:
+    // Option<U256>: amount out after fees, None if invariant does not converge or pool is empty
+    pub fn get_dy(&self, i: usize, j: usize, dx: U256) -> Option<U256> {
+        let precision = U256::exp10(18);
+        let xp = self.xp();
+
+        let x = xp[i] + dx * self.rates[i] / precision;
+        let y = self.get_y(i, j, x, &xp)?;
+        let dy = xp[j].checked_sub(y)?.checked_sub(U256::one())?;
+        let fee = self.fee * dy / U256::from(FEE_DENOMINATOR);
+
+        Some((dy - fee) * precision / self.rates[j])
+    }
+
+    // Balances normalized to 18 decimals
+    fn xp(&self) -> Vec<U256> {
+        let precision = U256::exp10(18);
+        self.balances
+            .iter()
+            .zip(self.rates.iter())
+            .map(|(balance, rate)| balance * rate / precision)
+            .collect()
+    }
+
+    // Solve invariant `D` for normalized balances `xp`
+    fn get_d(&self, xp: &[U256]) -> Option<U256> {
+        let n_coins = U256::from(xp.len());
+        let a_precision = U256::from(A_PRECISION);
+
+        let s: U256 = xp.iter().fold(U256::zero(), |acc, x| acc + x);
+        if s.is_zero() {
+            return Some(U256::zero());
+        }
+
+        let ann = self.amp * n_coins;
+        let mut d = s;
+
+        for _ in 0..MAX_ITERATIONS {
+            let mut d_p = d;
+            for x in xp {
+                if x.is_zero() {
+                    return None;
+                }
+                d_p = d_p * d / (x * n_coins);
+            }
+
+            let d_prev = d;
+            let numerator = (ann * s / a_precision + d_p * n_coins) * d;
+            let denominator = (ann - a_precision) * d / a_precision + (n_coins + 1) * d_p;
+            d = numerator / denominator;
+
+            if abs_diff(d, d_prev) <= U256::one() {
+                return Some(d);
+            }
+        }
+
+        None
+    }
+
+    // Solve balance of coin `j` after balance of coin `i` is set to `x`
+    fn get_y(&self, i: usize, j: usize, x: U256, xp: &[U256]) -> Option<U256> {
+        let n_coins = U256::from(xp.len());
+        let a_precision = U256::from(A_PRECISION);
+
+        let d = self.get_d(xp)?;
+        let ann = self.amp * n_coins;
+
+        let mut c = d;
+        let mut s = U256::zero();
+        for (k, balance) in xp.iter().enumerate() {
+            let x_k = match k {
+                k if k == i => x,
+                k if k == j => continue,
+                _ => *balance,
+            };
+            if x_k.is_zero() {
+                return None;
+            }
+            s += x_k;
+            c = c * d / (x_k * n_coins);
+        }
+
+        c = c * d * a_precision / (ann * n_coins);
+        let b = s + d * a_precision / ann;
+
+        let mut y = d;
+        for _ in 0..MAX_ITERATIONS {
+            let y_prev = y;
+            y = (y * y + c) / (U256::from(2) * y + b).checked_sub(d)?;
+
+            if abs_diff(y, y_prev) <= U256::one() {
+                return Some(y);
+            }
+        }
+
+        None
+    }
+}
+
+fn abs_diff(a: U256, b: U256) -> U256 {
+    if a > b {
+        a - b
+    } else {
+        b - a
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn balanced_pool(amp: u64, fee: u64) -> StableSwap {
+        let balance = U256::exp10(18) * 1_000_000;
+        StableSwap::new(
+            U256::from(amp * A_PRECISION),
+            U256::from(fee),
+            vec![balance, balance],
+            vec![U256::exp10(18), U256::exp10(18)],
+        )
+    }
+
+    #[test]
+    fn small_swap_in_balanced_pool_is_close_to_one_to_one() {
+        let pool = balanced_pool(200, 0);
+        let dx = U256::exp10(18);
+        let dy = pool.get_dy(0, 1, dx).unwrap();
+
+        // less than 1bps slippage on a tiny swap
+        assert!(dy <= dx);
+        assert!(dy > dx * 9999 / 10000);
+    }
+
+    #[test]
+    fn fee_is_taken_from_output() {
+        let dx = U256::exp10(18) * 1000;
+        let no_fee = balanced_pool(200, 0).get_dy(0, 1, dx).unwrap();
+        // 0.04% fee
+        let with_fee = balanced_pool(200, 4_000_000).get_dy(0, 1, dx).unwrap();
+
+        assert_eq!(with_fee, no_fee - no_fee * 4 / 10000);
+    }
+
+    #[test]
+    fn higher_amp_means_less_slippage() {
+        let dx = U256::exp10(18) * 200_000;
+        let low_amp = balanced_pool(10, 0).get_dy(0, 1, dx).unwrap();
+        let high_amp = balanced_pool(1000, 0).get_dy(0, 1, dx).unwrap();
+
+        assert!(high_amp > low_amp);
+    }
+}
@@ -0,0 +1,134 @@
+// helpers for pools living inside of uniswap v4's singleton `PoolManager`: https://github.com/Uniswap/v4-core
+use std::{str::FromStr, sync::OnceLock};
+
+use ethers::{
+    abi::{self, Token},
+    prelude::*,
+    utils::keccak256,
+};
+use hashbrown::HashSet;
+
+use crate::utils;
+
+/// Hook permissions are encoded in the lowest bits of the hook's address
+pub mod hook_flags {
+    pub const BEFORE_INITIALIZE: u16 = 1 << 13;
+    pub const AFTER_INITIALIZE: u16 = 1 << 12;
+    pub const BEFORE_ADD_LIQUIDITY: u16 = 1 << 11;
+    pub const AFTER_ADD_LIQUIDITY: u16 = 1 << 10;
+    pub const BEFORE_REMOVE_LIQUIDITY: u16 = 1 << 9;
+    pub const AFTER_REMOVE_LIQUIDITY: u16 = 1 << 8;
+    pub const BEFORE_SWAP: u16 = 1 << 7;
+    pub const AFTER_SWAP: u16 = 1 << 6;
+    pub const BEFORE_DONATE: u16 = 1 << 5;
+    pub const AFTER_DONATE: u16 = 1 << 4;
+    pub const BEFORE_SWAP_RETURNS_DELTA: u16 = 1 << 3;
+    pub const AFTER_SWAP_RETURNS_DELTA: u16 = 1 << 2;
+    pub const AFTER_ADD_LIQUIDITY_RETURNS_DELTA: u16 = 1 << 1;
+    pub const AFTER_REMOVE_LIQUIDITY_RETURNS_DELTA: u16 = 1 << 0;
+
+    /// Flags that let a hook run code (or change amounts) while a swap is executed
+    pub const SWAP_HOOKS: u16 =
+        BEFORE_SWAP | AFTER_SWAP | BEFORE_SWAP_RETURNS_DELTA | AFTER_SWAP_RETURNS_DELTA;
+}
+
+/// Fee value marking a pool whose fee is set by its hook
+pub const DYNAMIC_FEE_FLAG: u32 = 0x800000;
+
+// `_pools` mapping is the 7th storage variable of `PoolManager` (`StateLibrary.POOLS_SLOT`)
+const POOLS_SLOT: u64 = 6;
+
+// Hooks that are trusted to run during swaps, read from `V4_ALLOWED_HOOKS`
+fn allowed_hooks() -> &'static HashSet<Address> {
+    static ALLOWED_HOOKS: OnceLock<HashSet<Address>> = OnceLock::new();
+    ALLOWED_HOOKS.get_or_init(|| {
+        utils::dotenv::get_list("V4_ALLOWED_HOOKS")
+            .iter()
+            .map(|h| Address::from_str(h).expect("Failed to parse address in \"V4_ALLOWED_HOOKS\""))
+            .collect()
+    })
+}
+
+// Check if a v4 pool can be sandwiched safely given its hook
+//
+// Hooks that run during swaps (or set the fee dynamically) can change the outcome of a swap
+// depending on who sends it, such pools are refused unless the hook is explicitly allowed
+//
+// Arguments:
+// * `hooks`: hook contract of pool (zero address if pool has no hook)
+// * `fee`: fee of pool as emitted in `Initialize`
+//
+// Returns:
+// bool: true if pool can be tracked, false otherwise
+pub fn is_hook_allowed(hooks: Address, fee: u32) -> bool {
+    if hooks.is_zero() {
+        return fee != DYNAMIC_FEE_FLAG;
+    }
+
+    let flags = u16::from_be_bytes([hooks.0[18], hooks.0[19]]);
+    let touches_swaps = (flags & hook_flags::SWAP_HOOKS) != 0 || fee == DYNAMIC_FEE_FLAG;
+
+    !touches_swaps || allowed_hooks().contains(&hooks)
+}
+
+// Find the `PoolManager` storage slot holding a pool's `slot0` (price, tick and fees)
+//
+// Arguments:
+// * `pool_id`: id of pool (hash of its `PoolKey`)
+//
+// Returns:
+// H256: storage slot of `_pools[pool_id].slot0`
+pub fn pool_state_slot(pool_id: H256) -> H256 {
+    H256::from(keccak256(abi::encode(&[
+        Token::FixedBytes(pool_id.as_bytes().to_vec()),
+        Token::Uint(U256::from(POOLS_SLOT)),
+    ])))
+}
+
+// Extract `sqrtPriceX96` from a packed `slot0`
+//
+// `slot0` is packed as [ 24 bits lp fee | 24 bits protocol fee | 24 bits tick | 160 bits price ]
+pub fn sqrt_price_from_slot0(slot0: H256) -> U256 {
+    let slot0 = U256::from(slot0.to_fixed_bytes());
+    slot0 & ((U256::one() << 160) - 1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn hook_with_flags(flags: u16) -> Address {
+        let mut hook = [0x11u8; 20];
+        hook[18..].copy_from_slice(&flags.to_be_bytes());
+        Address::from(hook)
+    }
+
+    #[test]
+    fn pools_without_hooks_are_allowed() {
+        assert!(is_hook_allowed(Address::zero(), 3000));
+        assert!(!is_hook_allowed(Address::zero(), DYNAMIC_FEE_FLAG));
+    }
+
+    #[test]
+    fn unknown_swap_hooks_are_refused() {
+        let liquidity_only = hook_with_flags(hook_flags::BEFORE_ADD_LIQUIDITY);
+        assert!(is_hook_allowed(liquidity_only, 3000));
+
+        let before_swap = hook_with_flags(hook_flags::BEFORE_SWAP | hook_flags::AFTER_DONATE);
+        assert!(!is_hook_allowed(before_swap, 3000));
+
+        let returns_delta = hook_with_flags(hook_flags::AFTER_SWAP_RETURNS_DELTA);
+        assert!(!is_hook_allowed(returns_delta, 3000));
+    }
+
+    #[test]
+    fn sqrt_price_is_lowest_160_bits_of_slot0() {
+        let sqrt_price = U256::from(79228162514264337593543950336u128);
+        let tick_and_fees = U256::from(0xabcdefu64) << 160;
+
+        let mut slot0 = [0u8; 32];
+        (sqrt_price | tick_and_fees).to_big_endian(&mut slot0);
+
+        assert_eq!(sqrt_price_from_slot0(H256::from(slot0)), sqrt_price);
+    }
+}
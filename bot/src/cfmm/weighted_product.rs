@@ -0,0 +1,121 @@
+// port of balancer's weighted math: https://github.com/balancer/balancer-v2-monorepo/blob/master/pkg/pool-weighted/contracts/WeightedMath.sol
+use ethers::prelude::*;
+
+/// Precision balancer uses for weights and swap fees (1e18 = 100%)
+pub const WEIGHT_ONE: u64 = 1_000_000_000_000_000_000;
+
+// Vault rejects swaps that put in more than 30% of the balance in, same as `_MAX_IN_RATIO`
+const MAX_IN_RATIO: u64 = 300_000_000_000_000_000;
+
+/// Off-chain model of a balancer weighted pool used for quoting swaps
+#[derive(Clone, Debug)]
+pub struct WeightedProduct {
+    /// token balances held by vault for this pool
+    pub balances: Vec<U256>,
+    /// normalized weights of each token in `WEIGHT_ONE` units
+    pub weights: Vec<U256>,
+    /// swap fee in `WEIGHT_ONE` units
+    pub swap_fee: U256,
+}
+
+impl WeightedProduct {
+    // Create a new `WeightedProduct` instance
+    pub fn new(balances: Vec<U256>, weights: Vec<U256>, swap_fee: U256) -> Self {
+        Self {
+            balances,
+            weights,
+            swap_fee,
+        }
+    }
+
+    // Find amount of token `j` received when swapping `amount_in` of token `i` (`calcOutGivenIn`)
+    //
+    // Arguments:
+    // * `i`: index of token in
+    // * `j`: index of token out
+    // * `amount_in`: amount of token in (before fees)
+    //
+    // Returns:
+    // Option<U256>: amount out, None if pool is empty or amount in exceeds max in ratio
+    pub fn get_amount_out(&self, i: usize, j: usize, amount_in: U256) -> Option<U256> {
+        let one = U256::from(WEIGHT_ONE);
+        let (balance_in, balance_out) = (self.balances[i], self.balances[j]);
+        let (weight_in, weight_out) = (self.weights[i], self.weights[j]);
+
+        if balance_in.is_zero() || balance_out.is_zero() || weight_out.is_zero() {
+            return None;
+        }
+
+        if amount_in > balance_in * U256::from(MAX_IN_RATIO) / one {
+            return None;
+        }
+
+        // fee is taken from amount in (rounded up like the vault does)
+        let fee = (amount_in * self.swap_fee + one - 1) / one;
+        let amount_in = amount_in.checked_sub(fee)?;
+
+        // equal weights reduce to constant product, no need to lose precision in float pow
+        if weight_in == weight_out {
+            return Some(balance_out * amount_in / (balance_in + amount_in));
+        }
+
+        // balance_out * (1 - (balance_in / (balance_in + amount_in)) ^ (weight_in / weight_out))
+        let base = to_f64(balance_in) / to_f64(balance_in + amount_in);
+        let exponent = to_f64(weight_in) / to_f64(weight_out);
+        let complement = 1.0 - base.powf(exponent);
+
+        // round down, pool never gives out more than the exact result
+        let complement = U256::from((complement * WEIGHT_ONE as f64) as u128);
+        Some(balance_out * complement / one)
+    }
+}
+
+// Lossy conversion used for the fractional power, precision is ~15 significant digits
+fn to_f64(x: U256) -> f64 {
+    let high = (x >> 128).low_u128() as f64;
+    let low = x.low_u128() as f64;
+    high * 2f64.powi(128) + low
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pool(weight_0: u64, swap_fee: u64) -> WeightedProduct {
+        let balance = U256::exp10(18) * 1_000;
+        WeightedProduct::new(
+            vec![balance, balance],
+            vec![U256::from(weight_0), U256::from(WEIGHT_ONE - weight_0)],
+            U256::from(swap_fee),
+        )
+    }
+
+    #[test]
+    fn equal_weights_match_constant_product() {
+        let dx = U256::exp10(18);
+        let dy = pool(WEIGHT_ONE / 2, 0).get_amount_out(0, 1, dx).unwrap();
+
+        let balance = U256::exp10(18) * 1_000;
+        assert_eq!(dy, balance * dx / (balance + dx));
+    }
+
+    #[test]
+    fn heavier_token_in_receives_more_out() {
+        let dx = U256::exp10(18) * 10;
+        let equal = pool(WEIGHT_ONE / 2, 0).get_amount_out(0, 1, dx).unwrap();
+        // 80/20 pool with equal balances prices token 0 at 4x token 1
+        let heavy_in = pool(WEIGHT_ONE / 10 * 8, 0)
+            .get_amount_out(0, 1, dx)
+            .unwrap();
+
+        assert!(heavy_in > equal);
+    }
+
+    #[test]
+    fn swaps_above_max_in_ratio_are_rejected() {
+        let balance = U256::exp10(18) * 1_000;
+        let dx = balance / 2;
+
+        assert!(pool(WEIGHT_ONE / 2, 0).get_amount_out(0, 1, dx).is_none());
+    }
+}
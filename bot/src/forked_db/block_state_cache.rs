@@ -0,0 +1,337 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    OnceLock, RwLock,
+};
+
+use dashmap::DashMap;
+use ethers::{
+    types::{AccountDiff, BlockId, BlockNumber, BlockTrace, Diff, U64},
+    utils::keccak256,
+};
+use revm::primitives::{
+    AccountInfo, Bytecode, B160 as rAddress, B256, KECCAK_EMPTY, U256 as rU256,
+};
+
+/// State fetched over rpc for the block that is currently being forked
+///
+/// Every `GlobalBackend` forking the same block reads through this cache before making rpc calls
+/// so that pool and weth storage is only fetched once per block. On a new block the block's own
+/// state diff is applied so that cached state stays valid without being refetched
+#[derive(Debug, Default)]
+pub struct BlockStateCache {
+    // fork block the cached state belongs to, lookups and inserts hold it for reading so that the
+    // block can not change between checking it and touching the cached state
+    block_number: RwLock<u64>,
+    accounts: DashMap<rAddress, AccountInfo>,
+    storage: DashMap<(rAddress, rU256), rU256>,
+    // block hashes do not depend on the fork block, they are kept across blocks
+    block_hashes: DashMap<rU256, B256>,
+    /// lookups answered from cache
+    pub hits: AtomicU64,
+    /// lookups that had to fall back to rpc
+    pub misses: AtomicU64,
+}
+
+// Cache shared by all fork factories
+pub fn block_state_cache() -> &'static BlockStateCache {
+    static CACHE: OnceLock<BlockStateCache> = OnceLock::new();
+    CACHE.get_or_init(BlockStateCache::default)
+}
+
+impl BlockStateCache {
+    // Run `f` if cached state belongs to the block a backend forks from, the block can not change
+    // while `f` runs
+    fn with_block<T>(&self, fork_block: Option<BlockId>, f: impl FnOnce() -> T) -> Option<T> {
+        let block_number = self.block_number.read().unwrap();
+        match fork_block {
+            Some(BlockId::Number(BlockNumber::Number(number)))
+                if number.as_u64() == *block_number =>
+            {
+                Some(f())
+            }
+            _ => None,
+        }
+    }
+
+    // Get cached account info, None if not cached or cache belongs to another block
+    pub fn basic(&self, fork_block: Option<BlockId>, address: rAddress) -> Option<AccountInfo> {
+        let info = self
+            .with_block(fork_block, || {
+                self.accounts.get(&address).map(|info| info.clone())
+            })
+            .flatten();
+        self.record_lookup(info.is_some());
+        info
+    }
+
+    // Get cached storage slot, None if not cached or cache belongs to another block
+    pub fn storage(
+        &self,
+        fork_block: Option<BlockId>,
+        address: rAddress,
+        index: rU256,
+    ) -> Option<rU256> {
+        let value = self
+            .with_block(fork_block, || {
+                self.storage.get(&(address, index)).map(|value| *value)
+            })
+            .flatten();
+        self.record_lookup(value.is_some());
+        value
+    }
+
+    // Get cached block hash
+    pub fn block_hash(&self, number: rU256) -> Option<B256> {
+        let hash = self.block_hashes.get(&number).map(|hash| *hash);
+        self.record_lookup(hash.is_some());
+        hash
+    }
+
+    // Store account info fetched over rpc at `fork_block`
+    pub fn insert_basic(&self, fork_block: Option<BlockId>, address: rAddress, info: AccountInfo) {
+        self.with_block(fork_block, || self.accounts.insert(address, info));
+    }
+
+    // Store storage slot fetched over rpc at `fork_block`
+    pub fn insert_storage(
+        &self,
+        fork_block: Option<BlockId>,
+        address: rAddress,
+        index: rU256,
+        value: rU256,
+    ) {
+        self.with_block(fork_block, || self.storage.insert((address, index), value));
+    }
+
+    // Store block hash fetched over rpc
+    pub fn insert_block_hash(&self, number: rU256, hash: B256) {
+        self.block_hashes.insert(number, hash);
+    }
+
+    // Move cache onto a new fork block
+    //
+    // Arguments:
+    // * `&self`: reference to `BlockStateCache` instance
+    // * `fork_block`: block that backends will fork from next
+    // * `block_traces`: state diffs of the block that was just mined (None if they could not be
+    // traced, in that case all cached state is dropped)
+    pub fn advance(&self, fork_block: U64, block_traces: Option<&[BlockTrace]>) {
+        // lookups and inserts wait until the cache fully belongs to the new block
+        let mut block_number = self.block_number.write().unwrap();
+
+        // replays run concurrently, one that finishes after a later block's must not move back
+        // (same block again is a reorg and still drops cached state)
+        if fork_block.as_u64() < *block_number {
+            return;
+        }
+
+        match block_traces {
+            // cache holds state from before the mined block, replaying its diffs updates it
+            Some(block_traces) if *block_number + 1 == fork_block.as_u64() => {
+                block_traces
+                    .iter()
+                    .filter_map(|trace| trace.state_diff.as_ref())
+                    .flat_map(|state_diff| state_diff.0.iter())
+                    .for_each(|(address, account_diff)| {
+                        self.apply_account_diff(address.0.into(), account_diff)
+                    });
+            }
+            _ => {
+                self.accounts.clear();
+                self.storage.clear();
+            }
+        }
+
+        *block_number = fork_block.as_u64();
+    }
+
+    // Update cached state of an account with the changes a tx made to it
+    fn apply_account_diff(&self, address: rAddress, account_diff: &AccountDiff) {
+        if let Diff::Died(_) = account_diff.balance {
+            self.accounts.remove(&address);
+            self.storage
+                .retain(|(slot_address, _), _| *slot_address != address);
+            return;
+        }
+
+        if let Some(mut info) = self.accounts.get_mut(&address) {
+            if let Some(balance) = diff_to(&account_diff.balance) {
+                info.balance = balance.into();
+            }
+            if let Some(nonce) = diff_to(&account_diff.nonce) {
+                info.nonce = nonce.as_u64();
+            }
+            if let Some(code) = diff_to(&account_diff.code) {
+                info.code_hash = match code.is_empty() {
+                    true => KECCAK_EMPTY,
+                    false => keccak256(&code).into(),
+                };
+                info.code = Some(Bytecode::new_raw(code.0).to_checked());
+            }
+        }
+
+        for (slot, diff) in account_diff.storage.iter() {
+            let value = match diff {
+                Diff::Died(_) => rU256::ZERO,
+                diff => match diff_to(diff) {
+                    Some(value) => rU256::from_be_bytes(value.0),
+                    None => continue,
+                },
+            };
+            self.storage
+                .insert((address, rU256::from_be_bytes(slot.0)), value);
+        }
+    }
+
+    fn record_lookup(&self, is_hit: bool) {
+        match is_hit {
+            true => self.hits.fetch_add(1, Ordering::Relaxed),
+            false => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+}
+
+// Value after a change, None if value did not change or was removed
+fn diff_to<T: Clone>(diff: &Diff<T>) -> Option<T> {
+    match diff {
+        Diff::Born(value) => Some(value.clone()),
+        Diff::Changed(changed) => Some(changed.to.clone()),
+        Diff::Same | Diff::Died(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use ethers::types::{ChangedType, StateDiff, H160, H256};
+
+    use super::*;
+
+    fn fork_block(number: u64) -> Option<BlockId> {
+        Some(BlockId::Number(BlockNumber::Number(number.into())))
+    }
+
+    fn storage_trace(address: H160, slot: H256, from: H256, to: H256) -> BlockTrace {
+        let account_diff = AccountDiff {
+            balance: Diff::Same,
+            nonce: Diff::Same,
+            code: Diff::Same,
+            storage: BTreeMap::from([(slot, Diff::Changed(ChangedType { from, to }))]),
+        };
+        BlockTrace {
+            output: Default::default(),
+            trace: None,
+            vm_trace: None,
+            state_diff: Some(StateDiff(BTreeMap::from([(address, account_diff)]))),
+            transaction_hash: None,
+        }
+    }
+
+    #[test]
+    fn cache_is_scoped_to_fork_block() {
+        let cache = BlockStateCache::default();
+        cache.advance(10.into(), None);
+
+        let address = rAddress::from([0x11; 20]);
+        cache.insert_storage(fork_block(10), address, rU256::from(1), rU256::from(42));
+
+        let storage = |block| cache.storage(fork_block(block), address, rU256::from(1));
+        assert_eq!(storage(10), Some(rU256::from(42)));
+        assert_eq!(storage(9), None);
+    }
+
+    #[test]
+    fn new_block_applies_its_state_diff() {
+        let cache = BlockStateCache::default();
+        cache.advance(10.into(), None);
+
+        let address = H160::repeat_byte(0x11);
+        let (changed_slot, untouched_slot) = (H256::from_low_u64_be(1), H256::from_low_u64_be(2));
+        let slot_index = |slot: H256| rU256::from_be_bytes(slot.0);
+
+        let insert = |slot, value: u64| {
+            cache.insert_storage(
+                fork_block(10),
+                address.0.into(),
+                slot_index(slot),
+                rU256::from(value),
+            )
+        };
+        insert(changed_slot, 1);
+        insert(untouched_slot, 7);
+
+        let trace = storage_trace(
+            address,
+            changed_slot,
+            H256::from_low_u64_be(1),
+            H256::from_low_u64_be(5),
+        );
+        cache.advance(11.into(), Some(&[trace]));
+
+        let storage = |slot| cache.storage(fork_block(11), address.0.into(), slot_index(slot));
+        assert_eq!(storage(changed_slot), Some(rU256::from(5)));
+        assert_eq!(storage(untouched_slot), Some(rU256::from(7)));
+    }
+
+    #[test]
+    fn stale_block_does_not_move_cache_back() {
+        let cache = BlockStateCache::default();
+        cache.advance(11.into(), None);
+
+        let address = rAddress::from([0x11; 20]);
+        cache.insert_storage(fork_block(11), address, rU256::from(1), rU256::from(42));
+        cache.advance(10.into(), None);
+
+        let storage = |block| cache.storage(fork_block(block), address, rU256::from(1));
+        assert_eq!(storage(11), Some(rU256::from(42)));
+        assert_eq!(storage(10), None);
+    }
+
+    #[test]
+    fn inserts_racing_a_new_block_are_not_kept() {
+        let cache = std::sync::Arc::new(BlockStateCache::default());
+        let address = rAddress::from([0x11; 20]);
+
+        for block in 10..60u64 {
+            cache.advance(block.into(), None);
+
+            // backend still fetching state of the previous fork block while the cache moves on
+            let writer = {
+                let cache = cache.clone();
+                std::thread::spawn(move || {
+                    for index in 0..500u64 {
+                        cache.insert_storage(
+                            fork_block(block),
+                            address,
+                            rU256::from(index),
+                            rU256::from(block),
+                        );
+                    }
+                })
+            };
+            cache.advance((block + 1).into(), None);
+            writer.join().unwrap();
+
+            // nothing fetched at the old block may show up as state of the new one
+            for index in 0..500u64 {
+                assert_eq!(
+                    cache.storage(fork_block(block + 1), address, rU256::from(index)),
+                    None
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn skipped_block_drops_cached_state() {
+        let cache = BlockStateCache::default();
+        cache.advance(10.into(), None);
+
+        let address = rAddress::from([0x11; 20]);
+        cache.insert_storage(fork_block(10), address, rU256::from(1), rU256::from(42));
+        cache.advance(12.into(), Some(&[]));
+
+        assert_eq!(cache.storage(fork_block(12), address, rU256::from(1)), None);
+    }
+}
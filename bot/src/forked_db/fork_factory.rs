@@ -2,7 +2,7 @@ use std::sync::mpsc::channel as oneshot_channel;
 use std::sync::Arc;
 
 use super::{
-    database_error::DatabaseResult,
+    database_error::{DatabaseError, DatabaseResult},
     fork_db::ForkDB,
     global_backend::{BackendFetchRequest, GlobalBackend},
 };
@@ -115,6 +115,83 @@ impl ForkFactory {
         Ok(())
     }
 
+    // Warm local db with storage slots before simulating
+    //
+    // All lookups are sent to the backend at once so that it can fetch them in a single batch
+    // instead of forks fetching them one blocking call at a time during execution
+    //
+    // Arguments:
+    // * `&mut self`: mutable reference to `ForkFactory` instance
+    // * `slots`: (address, slot) pairs to fetch
+    //
+    // Returns:
+    // Ok(Vec<rU256>): value of each slot (in the order they were requested)
+    // Err(DatabaseError): if any lookup failed
+    pub fn prefetch_storage(&mut self, slots: &[(rAddress, rU256)]) -> DatabaseResult<Vec<rU256>> {
+        let mut missing_accounts: Vec<rAddress> = vec![];
+        for (address, _) in slots {
+            if self.initial_db.accounts.get(address).is_none()
+                && !missing_accounts.contains(address)
+            {
+                missing_accounts.push(*address);
+            }
+        }
+
+        let cached = |db: &CacheDB<EmptyDB>, address: &rAddress, slot: &rU256| {
+            db.accounts
+                .get(address)
+                .and_then(|acc| acc.storage.get(slot).copied())
+        };
+        let missing_slots: Vec<(rAddress, rU256)> = slots
+            .iter()
+            .filter(|(address, slot)| cached(&self.initial_db, address, slot).is_none())
+            .copied()
+            .collect();
+
+        let (infos, values) = tokio::task::block_in_place(|| {
+            let mut account_receivers = vec![];
+            for address in missing_accounts.iter() {
+                let (sender, rx) = oneshot_channel();
+                let req = BackendFetchRequest::Basic(*address, sender);
+                self.backend.clone().try_send(req)?;
+                account_receivers.push(rx);
+            }
+
+            let mut storage_receivers = vec![];
+            for (address, slot) in missing_slots.iter() {
+                let (sender, rx) = oneshot_channel();
+                let req = BackendFetchRequest::Storage(*address, *slot, sender);
+                self.backend.clone().try_send(req)?;
+                storage_receivers.push(rx);
+            }
+
+            let infos = account_receivers
+                .into_iter()
+                .map(|rx| rx.recv()?)
+                .collect::<DatabaseResult<Vec<_>>>()?;
+            let values = storage_receivers
+                .into_iter()
+                .map(|rx| rx.recv()?)
+                .collect::<DatabaseResult<Vec<_>>>()?;
+            Ok::<_, DatabaseError>((infos, values))
+        })?;
+
+        // keep record of fetched state so every fork created from here on starts warm
+        for (address, info) in missing_accounts.into_iter().zip(infos) {
+            self.initial_db.insert_account_info(address, info);
+        }
+        for ((address, slot), value) in missing_slots.into_iter().zip(values) {
+            self.initial_db
+                .insert_account_storage(address, slot, value)
+                .unwrap();
+        }
+
+        Ok(slots
+            .iter()
+            .map(|(address, slot)| cached(&self.initial_db, address, slot).unwrap_or_default())
+            .collect())
+    }
+
     // Insert account basic info into local db
     pub fn insert_account_info(&mut self, address: rAddress, info: AccountInfo) {
         self.initial_db.insert_account_info(address, info);
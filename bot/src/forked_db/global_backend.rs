@@ -2,7 +2,7 @@
 // https://github.com/foundry-rs/foundry/blob/master/evm/src/executor/fork/backend.rs
 use ethers::{
     providers::{Middleware, Provider, ProviderError, Ws},
-    types::{Address, BigEndianHash, BlockId, H256, U256},
+    types::{BlockId, U256},
     utils::keccak256,
 };
 use eyre::Result;
@@ -23,9 +23,18 @@ use std::{
     collections::VecDeque,
     pin::Pin,
     sync::{mpsc::Sender as OneshotSender, Arc},
+    time::Duration,
 };
+use tokio::time::Sleep;
 
+use super::block_state_cache::block_state_cache;
 use super::database_error::{DatabaseError, DatabaseResult};
+use super::rpc_batcher::{fetch_batch, BatchResponse, FetchKey};
+
+// how long lookups are collected while a request is in flight before being sent as one batch
+const BATCH_WINDOW: Duration = Duration::from_millis(2);
+// batch is sent straight away once this many lookups are queued
+const MAX_BATCH_SIZE: usize = 100;
 
 // **incoming req and outcoming req handled using revm types
 // all logic internal to this module handled using ethers types (because of provider)
@@ -33,16 +42,13 @@ type AccountInfoSender = OneshotSender<DatabaseResult<AccountInfo>>;
 type StorageSender = OneshotSender<DatabaseResult<rU256>>;
 type BlockHashSender = OneshotSender<DatabaseResult<B256>>;
 
-type BasicFuture<Err> =
-    Pin<Box<dyn Future<Output = (Result<(rU256, u64, rBytes), Err>, rAddress)> + Send>>;
-type StorageFuture<Err> =
-    Pin<Box<dyn Future<Output = (Result<rU256, Err>, rAddress, rU256)> + Send>>;
+type BatchFuture = Pin<Box<dyn Future<Output = BatchResponse> + Send>>;
 type BlockHashFuture<Err> = Pin<Box<dyn Future<Output = (Result<B256, Err>, rU256)> + Send>>;
 
 /// Request variants that are executed by the provider
 enum FetchRequestFuture<Err> {
-    Basic(BasicFuture<Err>),
-    Storage(StorageFuture<Err>),
+    /// account and storage lookups coalesced into as few round trips as possible
+    Batch(BatchFuture),
     BlockHash(BlockHashFuture<Err>),
 }
 
@@ -76,6 +82,10 @@ pub struct GlobalBackend {
     incoming: Receiver<BackendFetchRequest>,
     /// unprocessed queued requests
     queued_requests: VecDeque<BackendFetchRequest>,
+    /// Account and storage lookups waiting to be sent in the next batch
+    queued_fetches: Vec<FetchKey>,
+    /// Fires when the current batch window closes
+    batch_window: Option<Pin<Box<Sleep>>>,
 }
 
 impl GlobalBackend {
@@ -96,6 +106,8 @@ impl GlobalBackend {
             block_requests: Default::default(),
             incoming: rx,
             queued_requests: Default::default(),
+            queued_fetches: Default::default(),
+            batch_window: None,
         }
     }
 
@@ -103,7 +115,8 @@ impl GlobalBackend {
     ///
     /// We always check:
     ///  1. if the requested value is already stored in the cache, then answer the sender
-    ///  2. otherwise, fetch it via the provider but check if a request for that value is already in
+    ///  2. if another backend already fetched the value for the same block, then answer the sender
+    ///  3. otherwise, fetch it via the provider but check if a request for that value is already in
     /// progress (e.g. another Sender just requested the same account)
     fn on_request(&mut self, req: BackendFetchRequest) {
         match req {
@@ -111,6 +124,9 @@ impl GlobalBackend {
                 let acc = self.db.accounts.get(&addr);
                 if let Some(acc) = acc {
                     let _ = sender.send(Ok(acc.info.clone()));
+                } else if let Some(info) = block_state_cache().basic(self.block_num, addr) {
+                    self.db.insert_account_info(addr, info.clone());
+                    let _ = sender.send(Ok(info));
                 } else {
                     self.request_account(addr, sender);
                 }
@@ -123,6 +139,9 @@ impl GlobalBackend {
                     .and_then(|acc| acc.storage.get(&idx));
                 if let Some(value) = value {
                     let _ = sender.send(Ok(*value));
+                } else if let Some(value) = block_state_cache().storage(self.block_num, addr, idx) {
+                    let _ = self.db.insert_account_storage(addr, idx, value);
+                    let _ = sender.send(Ok(value));
                 } else {
                     // account present but not storage -> fetch storage
                     self.request_account_storage(addr.0.into(), idx, sender)
@@ -132,6 +151,9 @@ impl GlobalBackend {
                 let hash = self.db.block_hashes.get(&number);
                 if let Some(hash) = hash {
                     let _ = sender.send(Ok(hash.0.into()));
+                } else if let Some(hash) = block_state_cache().block_hash(number) {
+                    self.db.block_hashes.insert(number, hash);
+                    let _ = sender.send(Ok(hash));
                 } else {
                     self.request_hash(number, sender);
                 }
@@ -147,21 +169,7 @@ impl GlobalBackend {
             }
             Entry::Vacant(entry) => {
                 entry.insert(vec![listener]);
-                let provider = self.provider.clone();
-                let block_num = self.block_num;
-                let fut = Box::pin(async move {
-                    // convert from revm to ethers
-                    let address_ethers: Address = address.0.into();
-
-                    let balance = provider.get_balance(address_ethers, block_num);
-                    let nonce = provider.get_transaction_count(address_ethers, block_num);
-                    let code = provider.get_code(address_ethers, block_num);
-                    let resp = tokio::try_join!(balance, nonce, code);
-
-                    let resp = resp.map(|(b, n, c)| (b.into(), n.as_u64(), c.0));
-                    (resp, address)
-                });
-                self.pending_requests.push(FetchRequestFuture::Basic(fut));
+                self.queued_fetches.push(FetchKey::Basic(address));
             }
         }
     }
@@ -174,24 +182,7 @@ impl GlobalBackend {
             }
             Entry::Vacant(entry) => {
                 entry.insert(vec![listener]);
-                let provider = self.provider.clone();
-                let block_num = self.block_num;
-                let fut = Box::pin(async move {
-                    // convert from revm to ethers type
-                    let idx_ethers = H256::from_uint(&U256::from(idx));
-                    let address_ethers: Address = address.0.into();
-
-                    let storage = provider
-                        .get_storage_at(address_ethers, idx_ethers, block_num)
-                        .await;
-                    let storage = storage.map(|storage| storage.into_uint());
-
-                    // convert ethers types to revm types
-                    let storage = storage.map(|s| s.into());
-                    // convert back to revm types
-                    (storage, address, idx)
-                });
-                self.pending_requests.push(FetchRequestFuture::Storage(fut));
+                self.queued_fetches.push(FetchKey::Storage(address, idx));
             }
         }
     }
@@ -231,6 +222,106 @@ impl GlobalBackend {
             }
         }
     }
+
+    // Send queued lookups as one batch once the batch window closes (or the batch is full)
+    //
+    // Lookups are only held back while another request is in flight, with nothing in flight
+    // there is nothing to wait for and the batch is sent straight away
+    fn flush_queued_fetches(&mut self, cx: &mut Context<'_>) {
+        if self.queued_fetches.is_empty() {
+            return;
+        }
+
+        if !self.pending_requests.is_empty() && self.queued_fetches.len() < MAX_BATCH_SIZE {
+            let window = self
+                .batch_window
+                .get_or_insert_with(|| Box::pin(tokio::time::sleep(BATCH_WINDOW)));
+            if window.poll_unpin(cx).is_pending() {
+                return;
+            }
+        }
+
+        self.batch_window = None;
+        let keys = std::mem::take(&mut self.queued_fetches);
+        let fut = Box::pin(fetch_batch(self.provider.clone(), self.block_num, keys));
+        self.pending_requests.push(FetchRequestFuture::Batch(fut));
+    }
+
+    // Update the cache with a fetched account and notify all listeners
+    fn on_account_fetched(
+        &mut self,
+        addr: rAddress,
+        resp: Result<(rU256, u64, rBytes), Arc<eyre::Error>>,
+    ) {
+        // get the response
+        let (balance, nonce, code) = match resp {
+            Ok(res) => res,
+            Err(err) => {
+                if let Some(listeners) = self.account_requests.remove(&addr) {
+                    listeners.into_iter().for_each(|l| {
+                        let _ = l.send(Err(DatabaseError::GetAccount(addr, Arc::clone(&err))));
+                    })
+                }
+                return;
+            }
+        };
+
+        // convert it to revm-style types
+        let (code, code_hash) = if !code.is_empty() {
+            (Some(code.clone()), keccak256(&code).into())
+        } else {
+            (Some(bytes::Bytes::default()), KECCAK_EMPTY)
+        };
+
+        // update the cache
+        let acc = AccountInfo {
+            nonce,
+            balance,
+            code: code.map(|bytes| Bytecode::new_raw(bytes).to_checked()),
+            code_hash,
+        };
+        self.db.insert_account_info(addr, acc.clone());
+        block_state_cache().insert_basic(self.block_num, addr, acc.clone());
+
+        // notify all listeners
+        if let Some(listeners) = self.account_requests.remove(&addr) {
+            listeners.into_iter().for_each(|l| {
+                let _ = l.send(Ok(acc.clone()));
+            })
+        }
+    }
+
+    // Update the cache with a fetched storage slot and notify all listeners
+    fn on_storage_fetched(
+        &mut self,
+        addr: rAddress,
+        idx: rU256,
+        resp: Result<rU256, Arc<eyre::Error>>,
+    ) {
+        let value = match resp {
+            Ok(value) => value,
+            Err(err) => {
+                // notify all listeners
+                if let Some(listeners) = self.storage_requests.remove(&(addr, idx)) {
+                    listeners.into_iter().for_each(|l| {
+                        let _ = l.send(Err(DatabaseError::GetStorage(addr, idx, Arc::clone(&err))));
+                    })
+                }
+                return;
+            }
+        };
+
+        // update the cache
+        self.db.insert_account_storage(addr, idx, value).unwrap();
+        block_state_cache().insert_storage(self.block_num, addr, idx, value);
+
+        // notify all listeners
+        if let Some(listeners) = self.storage_requests.remove(&(addr, idx)) {
+            listeners.into_iter().for_each(|l| {
+                let _ = l.send(Ok(value));
+            })
+        }
+    }
 }
 
 impl Future for GlobalBackend {
@@ -257,84 +348,20 @@ impl Future for GlobalBackend {
                 }
             }
 
+            // coalesce account and storage lookups into batched rpc calls
+            pin.flush_queued_fetches(cx);
+
             // poll all requests in progress
             for n in (0..pin.pending_requests.len()).rev() {
                 let mut request = pin.pending_requests.swap_remove(n);
                 match &mut request {
-                    FetchRequestFuture::Basic(fut) => {
-                        if let Poll::Ready((resp, addr)) = fut.poll_unpin(cx) {
-                            // get the response
-                            let (balance, nonce, code) = match resp {
-                                Ok(res) => res,
-                                Err(err) => {
-                                    let err = Arc::new(eyre::Error::new(err));
-                                    if let Some(listeners) = pin.account_requests.remove(&addr) {
-                                        listeners.into_iter().for_each(|l| {
-                                            let _ = l.send(Err(DatabaseError::GetAccount(
-                                                addr,
-                                                Arc::clone(&err),
-                                            )));
-                                        })
-                                    }
-                                    continue;
-                                }
-                            };
-
-                            // convert it to revm-style types
-                            let (code, code_hash) = if !code.is_empty() {
-                                (Some(code.clone()), keccak256(&code).into())
-                            } else {
-                                (Some(bytes::Bytes::default()), KECCAK_EMPTY)
-                            };
-
-                            // update the cache
-                            let acc = AccountInfo {
-                                nonce,
-                                balance,
-                                code: code.map(|bytes| Bytecode::new_raw(bytes).to_checked()),
-                                code_hash,
-                            };
-                            pin.db.insert_account_info(addr, acc.clone());
-
-                            // notify all listeners
-                            if let Some(listeners) = pin.account_requests.remove(&addr) {
-                                listeners.into_iter().for_each(|l| {
-                                    let _ = l.send(Ok(acc.clone()));
-                                })
+                    FetchRequestFuture::Batch(fut) => {
+                        if let Poll::Ready(response) = fut.poll_unpin(cx) {
+                            for (addr, resp) in response.accounts {
+                                pin.on_account_fetched(addr, resp);
                             }
-                            continue;
-                        }
-                    }
-                    FetchRequestFuture::Storage(fut) => {
-                        if let Poll::Ready((resp, addr, idx)) = fut.poll_unpin(cx) {
-                            let value = match resp {
-                                Ok(value) => value,
-                                Err(err) => {
-                                    // notify all listeners
-                                    let err = Arc::new(eyre::Error::new(err));
-                                    if let Some(listeners) =
-                                        pin.storage_requests.remove(&(addr, idx))
-                                    {
-                                        listeners.into_iter().for_each(|l| {
-                                            let _ = l.send(Err(DatabaseError::GetStorage(
-                                                addr,
-                                                idx,
-                                                Arc::clone(&err),
-                                            )));
-                                        })
-                                    }
-                                    continue;
-                                }
-                            };
-
-                            // update the cache
-                            pin.db.insert_account_storage(addr, idx, value).unwrap();
-
-                            // notify all listeners
-                            if let Some(listeners) = pin.storage_requests.remove(&(addr, idx)) {
-                                listeners.into_iter().for_each(|l| {
-                                    let _ = l.send(Ok(value));
-                                })
+                            for ((addr, idx), resp) in response.storage {
+                                pin.on_storage_fetched(addr, idx, resp);
                             }
                             continue;
                         }
@@ -360,6 +387,7 @@ impl Future for GlobalBackend {
 
                             // update the cache
                             pin.db.block_hashes.insert(number, value);
+                            block_state_cache().insert_block_hash(number, value);
 
                             // notify all listeners
                             if let Some(listeners) = pin.block_requests.remove(&number) {
@@ -4,5 +4,11 @@ pub use database_error::DatabaseError;
 pub mod global_backend;
 pub use global_backend::*;
 
+pub mod block_state_cache;
+pub use block_state_cache::*;
+
+pub mod rpc_batcher;
+pub use rpc_batcher::*;
+
 pub mod fork_db;
 pub mod fork_factory;
@@ -0,0 +1,492 @@
+// coalesces the account and storage lookups made by a `GlobalBackend` into as few rpc round trips
+// as the node allows: a json-rpc batch over http, one `eth_getProof` per account, or plain calls
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc, OnceLock,
+};
+
+use ethers::{
+    providers::{Middleware, Provider, ProviderError, Ws},
+    types::{Address, BigEndianHash, BlockId, BlockNumber, Bytes, H256, U256},
+};
+use futures::future::join_all;
+use hashbrown::HashMap;
+use revm::primitives::{Bytes as rBytes, B160 as rAddress, U256 as rU256};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+use crate::utils::{self, metrics::metrics};
+
+/// Value a backend is missing
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FetchKey {
+    /// balance, nonce and code of an account
+    Basic(rAddress),
+    /// a storage slot of an account
+    Storage(rAddress, rU256),
+}
+
+type FetchError = Arc<eyre::Error>;
+
+/// Values fetched for a batch of `FetchKey`s
+#[derive(Debug, Default)]
+pub struct BatchResponse {
+    /// (balance, nonce, code) of each requested account
+    pub accounts: Vec<(rAddress, Result<(rU256, u64, rBytes), FetchError>)>,
+    pub storage: Vec<((rAddress, rU256), Result<rU256, FetchError>)>,
+}
+
+impl BatchResponse {
+    // Values that could not be fetched
+    fn failed_keys(&self) -> Vec<FetchKey> {
+        let accounts = self
+            .accounts
+            .iter()
+            .filter(|(_, account)| account.is_err())
+            .map(|(address, _)| FetchKey::Basic(*address));
+        let storage = self
+            .storage
+            .iter()
+            .filter(|(_, value)| value.is_err())
+            .map(|((address, index), _)| FetchKey::Storage(*address, *index));
+        accounts.chain(storage).collect()
+    }
+
+    // Replace failed values with the results of fetching them again
+    fn replace_failed(&mut self, retried: BatchResponse) {
+        self.accounts.retain(|(_, account)| account.is_ok());
+        self.storage.retain(|(_, value)| value.is_ok());
+        self.accounts.extend(retried.accounts);
+        self.storage.extend(retried.storage);
+    }
+}
+
+/// Counters showing how many round trips batching saved
+#[derive(Debug, Default)]
+pub struct RpcBatchStats {
+    /// calls that would have been made one by one (3 per account, 1 per slot)
+    pub unbatched_calls: AtomicU64,
+    /// round trips actually made
+    pub round_trips: AtomicU64,
+}
+
+impl RpcBatchStats {
+    // Number of round trips avoided by batching
+    pub fn round_trips_saved(&self) -> u64 {
+        self.unbatched_calls
+            .load(Ordering::Relaxed)
+            .saturating_sub(self.round_trips.load(Ordering::Relaxed))
+    }
+
+    fn record_round_trips(&self, method: &str, round_trips: u64) {
+        self.round_trips.fetch_add(round_trips, Ordering::Relaxed);
+        metrics().rpc_calls.add(method, round_trips);
+    }
+}
+
+// Stats shared by all backends
+pub fn rpc_batch_stats() -> &'static RpcBatchStats {
+    static STATS: OnceLock<RpcBatchStats> = OnceLock::new();
+    STATS.get_or_init(RpcBatchStats::default)
+}
+
+// Set from `RPC_USE_GET_PROOF`, cleared the first time the node rejects `eth_getProof`
+fn get_proof_supported() -> &'static AtomicBool {
+    static SUPPORTED: OnceLock<AtomicBool> = OnceLock::new();
+    SUPPORTED.get_or_init(|| AtomicBool::new(utils::dotenv::get_bool("RPC_USE_GET_PROOF", true)))
+}
+
+fn http_client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(reqwest::Client::new)
+}
+
+// Fetch all values in as few round trips as possible
+//
+// Arguments:
+// * `provider`: websocket provider, used when no http endpoint is configured
+// * `block_num`: block to fetch state at
+// * `keys`: values to fetch (already deduplicated by the backend)
+//
+// Returns:
+// BatchResponse: one result per requested value
+pub async fn fetch_batch(
+    provider: Arc<Provider<Ws>>,
+    block_num: Option<BlockId>,
+    keys: Vec<FetchKey>,
+) -> BatchResponse {
+    let unbatched_calls: u64 = keys
+        .iter()
+        .map(|key| match key {
+            FetchKey::Basic(_) => 3,
+            FetchKey::Storage(..) => 1,
+        })
+        .sum();
+    rpc_batch_stats()
+        .unbatched_calls
+        .fetch_add(unbatched_calls, Ordering::Relaxed);
+
+    if let Some(url) = utils::dotenv::get_http_rpc_url() {
+        return fetch_json_rpc_batch(&url, block_num, &keys).await;
+    }
+
+    if get_proof_supported().load(Ordering::Relaxed) {
+        if let Some(response) = fetch_with_proofs(&provider, block_num, &keys).await {
+            return response;
+        }
+    }
+
+    fetch_individually(&provider, block_num, &keys).await
+}
+
+// Send every call in a single json-rpc batch over http
+async fn fetch_json_rpc_batch(
+    url: &str,
+    block_num: Option<BlockId>,
+    keys: &[FetchKey],
+) -> BatchResponse {
+    let block = json!(block_num.unwrap_or(BlockId::Number(BlockNumber::Latest)));
+
+    let mut calls: Vec<Value> = vec![];
+    let mut push_call = |method: &str, params: Value| {
+        let id = calls.len();
+        calls.push(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+        id
+    };
+
+    let mut account_calls = vec![];
+    let mut storage_calls = vec![];
+    for key in keys {
+        match *key {
+            FetchKey::Basic(address) => {
+                let params = json!([Address::from(address.0), block]);
+                let ids = (
+                    push_call("eth_getBalance", params.clone()),
+                    push_call("eth_getTransactionCount", params.clone()),
+                    push_call("eth_getCode", params),
+                );
+                account_calls.push((address, ids));
+            }
+            FetchKey::Storage(address, index) => {
+                let slot = H256::from_uint(&U256::from(index));
+                let params = json!([Address::from(address.0), slot, block]);
+                storage_calls.push(((address, index), push_call("eth_getStorageAt", params)));
+            }
+        }
+    }
+
+    rpc_batch_stats().record_round_trips("json_rpc_batch", 1);
+    let mut results = match post_batch(url, &calls).await {
+        Ok(results) => results,
+        Err(err) => {
+            let err = Arc::new(err);
+            return BatchResponse {
+                accounts: account_calls
+                    .into_iter()
+                    .map(|(address, _)| (address, Err(err.clone())))
+                    .collect(),
+                storage: storage_calls
+                    .into_iter()
+                    .map(|(key, _)| (key, Err(err.clone())))
+                    .collect(),
+            };
+        }
+    };
+
+    let accounts = account_calls
+        .into_iter()
+        .map(|(address, ids)| (address, take_account(&mut results, ids)))
+        .collect();
+
+    let storage = storage_calls
+        .into_iter()
+        .map(|(key, id)| {
+            let value = take_result::<H256>(&mut results, id).map(|v| v.into_uint().into());
+            (key, value)
+        })
+        .collect();
+
+    BatchResponse { accounts, storage }
+}
+
+// Post a json-rpc batch and index responses by id (nodes may answer out of order)
+async fn post_batch(url: &str, calls: &[Value]) -> eyre::Result<HashMap<u64, Value>> {
+    let response = http_client()
+        .post(url)
+        .header("Content-Type", "application/json")
+        .body(serde_json::to_vec(calls)?)
+        .send()
+        .await?;
+    let responses: Vec<Value> = serde_json::from_slice(&response.bytes().await?)?;
+
+    Ok(index_responses(responses))
+}
+
+// Index json-rpc responses by id, responses without a numeric id can not be matched to a call
+fn index_responses(responses: Vec<Value>) -> HashMap<u64, Value> {
+    responses
+        .into_iter()
+        .filter_map(|response| Some((response.get("id")?.as_u64()?, response)))
+        .collect()
+}
+
+// Take the result of a single call out of a batch response
+fn take_result<T: DeserializeOwned>(
+    results: &mut HashMap<u64, Value>,
+    id: usize,
+) -> Result<T, FetchError> {
+    let mut response = results
+        .remove(&(id as u64))
+        .ok_or_else(|| Arc::new(eyre::eyre!("Missing response for batched call {}", id)))?;
+
+    match response.get_mut("result").map(Value::take) {
+        Some(result) => serde_json::from_value(result).map_err(|e| Arc::new(e.into())),
+        None => Err(Arc::new(eyre::eyre!(
+            "Batched call {} failed: {}",
+            id,
+            response.get("error").cloned().unwrap_or_default()
+        ))),
+    }
+}
+
+// Take balance, nonce and code of an account out of a batch response
+fn take_account(
+    results: &mut HashMap<u64, Value>,
+    (balance_id, nonce_id, code_id): (usize, usize, usize),
+) -> Result<(rU256, u64, rBytes), FetchError> {
+    let balance: U256 = take_result(results, balance_id)?;
+    let nonce: U256 = take_result(results, nonce_id)?;
+    let code: Bytes = take_result(results, code_id)?;
+    Ok((balance.into(), nonce.as_u64(), code.0))
+}
+
+// Fetch each account (and all of its requested slots) with a single `eth_getProof`
+//
+// Returns:
+// Some(BatchResponse): one result per requested value
+// None: if node does not support `eth_getProof`
+async fn fetch_with_proofs(
+    provider: &Arc<Provider<Ws>>,
+    block_num: Option<BlockId>,
+    keys: &[FetchKey],
+) -> Option<BatchResponse> {
+    // group slots by account, `bool` is set if balance/nonce/code were requested
+    let mut accounts: HashMap<rAddress, (bool, Vec<rU256>)> = HashMap::new();
+    for key in keys {
+        match *key {
+            FetchKey::Basic(address) => accounts.entry(address).or_default().0 = true,
+            FetchKey::Storage(address, index) => accounts.entry(address).or_default().1.push(index),
+        }
+    }
+
+    let fetches = accounts.into_iter().map(|(address, (needs_basic, slots))| {
+        let provider = provider.clone();
+        async move {
+            let ethers_address = Address::from(address.0);
+            let locations = slots
+                .iter()
+                .map(|index| H256::from_uint(&U256::from(*index)))
+                .collect();
+
+            // proofs do not carry code, only fetch it when account info was requested
+            let proof = provider.get_proof(ethers_address, locations, block_num);
+            let code = async {
+                match needs_basic {
+                    true => provider.get_code(ethers_address, block_num).await.map(Some),
+                    false => Ok(None),
+                }
+            };
+            let (proof, code) = tokio::join!(proof, code);
+
+            (address, slots, proof, code)
+        }
+    });
+    let fetched = join_all(fetches).await;
+
+    let round_trips = fetched
+        .iter()
+        .map(|(_, _, _, code)| 1 + !matches!(code, Ok(None)) as u64)
+        .sum();
+    rpc_batch_stats().record_round_trips("eth_getProof", round_trips);
+
+    if fetched
+        .iter()
+        .any(|(_, _, proof, _)| matches!(proof, Err(e) if is_unsupported_method(e)))
+    {
+        log::info!("Node does not support eth_getProof, falling back to single rpc calls");
+        get_proof_supported().store(false, Ordering::Relaxed);
+        return None;
+    }
+
+    let mut response = BatchResponse::default();
+    for (address, slots, proof, code) in fetched {
+        let proof = proof.map_err(|e| Arc::new(eyre::Error::new(e)));
+
+        if let Some(code) = code.transpose() {
+            let account = match (&proof, code) {
+                (Ok(proof), Ok(code)) => Ok((proof.balance.into(), proof.nonce.as_u64(), code.0)),
+                (Err(e), _) => Err(e.clone()),
+                (_, Err(e)) => Err(Arc::new(eyre::Error::new(e))),
+            };
+            response.accounts.push((address, account));
+        }
+
+        for (i, index) in slots.into_iter().enumerate() {
+            let value = match &proof {
+                Ok(proof) => match proof.storage_proof.get(i) {
+                    Some(storage_proof) => Ok(storage_proof.value.into()),
+                    None => Err(Arc::new(eyre::eyre!("Proof is missing slot {}", index))),
+                },
+                Err(e) => Err(e.clone()),
+            };
+            response.storage.push(((address, index), value));
+        }
+    }
+
+    // a proof can fail for a single account (e.g. pruned state), retry just those with plain calls
+    let failed = response.failed_keys();
+    if !failed.is_empty() {
+        let retried = fetch_individually(provider, block_num, &failed).await;
+        response.replace_failed(retried);
+    }
+
+    Some(response)
+}
+
+// Fetch every value with its own call (concurrently)
+async fn fetch_individually(
+    provider: &Arc<Provider<Ws>>,
+    block_num: Option<BlockId>,
+    keys: &[FetchKey],
+) -> BatchResponse {
+    let mut account_fetches = vec![];
+    let mut storage_fetches = vec![];
+    for key in keys {
+        let provider = provider.clone();
+        match *key {
+            FetchKey::Basic(address) => account_fetches.push(async move {
+                let ethers_address = Address::from(address.0);
+                let balance = provider.get_balance(ethers_address, block_num);
+                let nonce = provider.get_transaction_count(ethers_address, block_num);
+                let code = provider.get_code(ethers_address, block_num);
+                let account = tokio::try_join!(balance, nonce, code)
+                    .map(|(b, n, c)| (b.into(), n.as_u64(), c.0))
+                    .map_err(|e| Arc::new(eyre::Error::new(e)));
+                (address, account)
+            }),
+            FetchKey::Storage(address, index) => storage_fetches.push(async move {
+                let slot = H256::from_uint(&U256::from(index));
+                let value = provider
+                    .get_storage_at(Address::from(address.0), slot, block_num)
+                    .await
+                    .map(|value| value.into_uint().into())
+                    .map_err(|e| Arc::new(eyre::Error::new(e)));
+                ((address, index), value)
+            }),
+        }
+    }
+
+    let round_trips = (account_fetches.len() * 3 + storage_fetches.len()) as u64;
+    rpc_batch_stats().record_round_trips("single", round_trips);
+
+    let (accounts, storage) = tokio::join!(join_all(account_fetches), join_all(storage_fetches));
+    BatchResponse { accounts, storage }
+}
+
+// Check if an rpc error means the method is not available on the node
+fn is_unsupported_method(err: &ProviderError) -> bool {
+    let err = err.to_string().to_lowercase();
+    err.contains("-32601") || err.contains("not supported") || err.contains("does not exist")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn responses(raw: Value) -> HashMap<u64, Value> {
+        index_responses(serde_json::from_value(raw).unwrap())
+    }
+
+    #[test]
+    fn batch_responses_are_matched_by_id() {
+        // nodes may answer out of order, responses without an id are dropped
+        let mut results = responses(json!([
+            { "jsonrpc": "2.0", "id": 1, "result": "0x2a" },
+            { "jsonrpc": "2.0", "id": 0, "result": "0x07" },
+            { "jsonrpc": "2.0", "result": "0x01" },
+        ]));
+        assert_eq!(results.len(), 2);
+
+        assert_eq!(take_result::<U256>(&mut results, 0).unwrap(), U256::from(7));
+        assert_eq!(
+            take_result::<U256>(&mut results, 1).unwrap(),
+            U256::from(42)
+        );
+        // every result can only be taken once
+        assert!(take_result::<U256>(&mut results, 1).is_err());
+    }
+
+    #[test]
+    fn failed_call_is_an_error() {
+        let mut results = responses(json!([
+            {
+                "jsonrpc": "2.0",
+                "id": 0,
+                "error": { "code": -32000, "message": "missing trie node" }
+            },
+        ]));
+        let err = take_result::<U256>(&mut results, 0).unwrap_err();
+        assert!(err.to_string().contains("missing trie node"));
+    }
+
+    #[test]
+    fn account_is_taken_from_its_three_calls() {
+        let mut results = responses(json!([
+            { "jsonrpc": "2.0", "id": 0, "result": "0x64" },
+            { "jsonrpc": "2.0", "id": 1, "result": "0x3" },
+            { "jsonrpc": "2.0", "id": 2, "result": "0x6001" },
+        ]));
+        let (balance, nonce, code) = take_account(&mut results, (0, 1, 2)).unwrap();
+        assert_eq!(balance, rU256::from(100));
+        assert_eq!(nonce, 3);
+        assert_eq!(code.to_vec(), vec![0x60, 0x01]);
+    }
+
+    #[test]
+    fn account_fails_if_any_call_fails() {
+        let mut results = responses(json!([
+            { "jsonrpc": "2.0", "id": 0, "result": "0x64" },
+            { "jsonrpc": "2.0", "id": 2, "result": "0x" },
+        ]));
+        assert!(take_account(&mut results, (0, 1, 2)).is_err());
+    }
+
+    #[test]
+    fn failed_values_are_replaced_by_retries() {
+        let address = rAddress::from([0x11; 20]);
+        let err: FetchError = Arc::new(eyre::eyre!("proof failed"));
+        let mut response = BatchResponse {
+            accounts: vec![(address, Err(err.clone()))],
+            storage: vec![
+                ((address, rU256::from(1)), Ok(rU256::from(5))),
+                ((address, rU256::from(2)), Err(err)),
+            ],
+        };
+
+        let failed = response.failed_keys();
+        assert_eq!(
+            failed,
+            vec![
+                FetchKey::Basic(address),
+                FetchKey::Storage(address, rU256::from(2))
+            ]
+        );
+
+        response.replace_failed(BatchResponse {
+            accounts: vec![(address, Ok((rU256::from(1), 0, rBytes::new())))],
+            storage: vec![((address, rU256::from(2)), Ok(rU256::from(9)))],
+        });
+        assert!(response.failed_keys().is_empty());
+        assert_eq!(response.storage.len(), 2);
+    }
+}
@@ -1,14 +1,10 @@
-use std::str::FromStr;
-
 use colored::Colorize;
 use dotenv::dotenv;
 use ethers::prelude::*;
 use eyre::Result;
 
-use fern::colors::{Color, ColoredLevelConfig};
-
 use rusty_sando::{
-    prelude::{sync_dex, Dex, PoolVariant},
+    prelude::{sync_dex, Dex},
     runner::Bot,
     utils::{self, dotenv::read_env_vars},
 };
@@ -18,30 +14,8 @@ async fn main() -> Result<()> {
     log::info!("Starting Bot Initialization");
     dotenv().ok();
 
-    // setup logger configs
-    let mut colors = ColoredLevelConfig::new();
-    colors.trace = Color::Cyan;
-    colors.debug = Color::Magenta;
-    colors.info = Color::Green;
-    colors.warn = Color::Red;
-    colors.error = Color::BrightRed;
-
-    // setup logging both to stdout and file
-    fern::Dispatch::new()
-        .format(move |out, message, record| {
-            out.finish(format_args!(
-                "{}[{}] {}",
-                chrono::Local::now().format("[%H:%M:%S]"),
-                colors.color(record.level()),
-                message
-            ))
-        })
-        .chain(std::io::stdout())
-        .chain(fern::log_file("output.log")?)
-        // hide all logs for everything other than bot
-        .level(log::LevelFilter::Error)
-        .level_for("rusty_sando", log::LevelFilter::Info)
-        .apply()?;
+    // setup logging both to stdout and a json file
+    utils::logging::init_logging()?;
 
     read_env_vars();
 
@@ -53,66 +27,46 @@ async fn main() -> Result<()> {
     // Create the websocket client
     let client = utils::create_websocket_client().await.unwrap();
 
+    // txs are signed and simulated for the configured chain, refuse to run against another one
+    let chain = utils::chain::chain_config();
+    let node_chain_id = client.get_chainid().await?;
+    if node_chain_id != U256::from(chain.chain_id) {
+        eyre::bail!(
+            "Node is on chain {} but CHAIN is configured for chain {}",
+            node_chain_id,
+            chain.chain_id
+        );
+    }
+    log::info!("Running on {} (chain id {})", chain.name, chain.chain_id);
+
     ///////////////////////////////////////
     //  Setup all dexes and their pools  //
     ///////////////////////////////////////
-    let mut dexes = vec![];
-
-    // Add UniswapV2 pairs
-    dexes.push(Dex::new(
-        H160::from_str("0x5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f").unwrap(),
-        PoolVariant::UniswapV2,
-        10000835,
-    ));
-
-    //// Add Sushiswap pairs
-    dexes.push(Dex::new(
-        H160::from_str("0xC0AEe478e3658e2610c5F7A4A2E1777cE9e4f2Ac").unwrap(),
-        PoolVariant::UniswapV2,
-        10794229,
-    ));
-
-    //// Add CryptoCom-Swap pairs
-    dexes.push(Dex::new(
-        H160::from_str("0x9DEB29c9a4c7A88a3C0257393b7f3335338D9A9D").unwrap(),
-        PoolVariant::UniswapV2,
-        10828414,
-    ));
-
-    //// Add Convergence-Swap pairs
-    dexes.push(Dex::new(
-        H160::from_str("0x4eef5746ED22A2fD368629C1852365bf5dcb79f1").unwrap(),
-        PoolVariant::UniswapV2,
-        12385067,
-    ));
-
-    //// Add Pancake-Swap pairs
-    dexes.push(Dex::new(
-        H160::from_str("0x1097053Fd2ea711dad45caCcc45EfF7548fCB362").unwrap(),
-        PoolVariant::UniswapV2,
-        15614590,
-    ));
-
-    //// Add Shiba-Swap pairs, home of shitcoins
-    dexes.push(Dex::new(
-        H160::from_str("0x115934131916C8b277DD010Ee02de363c09d037c").unwrap(),
-        PoolVariant::UniswapV2,
-        12771526,
-    ));
-
-    //// Add Saitaswap pools
-    dexes.push(Dex::new(
-        H160::from_str("0x35113a300ca0D7621374890ABFEAC30E88f214b1").unwrap(),
-        PoolVariant::UniswapV2,
-        15210780,
-    ));
+    let mut dexes: Vec<Dex> = chain
+        .dexes
+        .iter()
+        .map(|dex| {
+            let dex_instance = Dex::new(dex.factory, dex.pool_variant, dex.creation_block);
+            match dex.swap_fee {
+                Some(swap_fee) => dex_instance.with_swap_fee(swap_fee),
+                None => dex_instance,
+            }
+        })
+        .collect();
+    if dexes.is_empty() {
+        log::warn!(
+            "No dexes configured for {}, set \"CHAIN_DEXES\"",
+            chain.name
+        );
+    }
 
-    //// Add UniswapV3 pools
-    dexes.push(Dex::new(
-        H160::from_str("0x1F98431c8aD98523631AE4a59f267346ea31F984").unwrap(),
-        PoolVariant::UniswapV3,
-        12369621,
-    ));
+    // configured fees are per factory, forks that let pools pick their own fee need detection
+    if utils::dotenv::get_detect_v2_swap_fees() {
+        dexes = dexes
+            .into_iter()
+            .map(|dex| dex.with_swap_fee_detection())
+            .collect();
+    }
 
     let current_block = client.get_block_number().await.unwrap();
     let all_pools = sync_dex(dexes.clone(), &client, current_block, None)
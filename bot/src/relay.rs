@@ -1,28 +1,32 @@
 use std::sync::Arc;
 
-use crate::utils;
+use crate::utils::{
+    self,
+    chain::{chain_config, BundleSubmission},
+    signer::{relay_signer, searcher_signer, SearcherSigner},
+};
 use ethers::prelude::*;
 use ethers_flashbots::*;
 use reqwest::Url;
 
 pub struct BundleRelay {
     pub flashbots_client:
-        SignerMiddleware<FlashbotsMiddleware<Arc<Provider<Ws>>, LocalWallet>, LocalWallet>,
+        SignerMiddleware<FlashbotsMiddleware<Arc<Provider<Ws>>, SearcherSigner>, SearcherSigner>,
     pub relay_name: String,
+    /// fee recipient of the relay's builder, None if unknown
+    pub coinbase: Option<Address>,
 }
 
 impl BundleRelay {
     pub fn new(
         relay_end_point: Url,
         relay_name: String,
+        coinbase: Option<Address>,
         client: &Arc<Provider<Ws>>,
     ) -> Result<BundleRelay, url::ParseError> {
-        // Extract wallets from .env keys
-        let bundle_private_key = std::env::var("FLASHBOTS_AUTH_KEY").unwrap();
-        let searcher_private_key = std::env::var("SEARCHER_PRIVATE_KEY").unwrap();
-
-        let bundle_signer = bundle_private_key.parse::<LocalWallet>().unwrap();
-        let searcher_signer = searcher_private_key.parse::<LocalWallet>().unwrap();
+        // bundles are signed with the relay reputation key, txs with the searcher signer
+        let bundle_signer = relay_signer().clone();
+        let searcher_signer = searcher_signer().clone();
 
         // Setup the Ethereum client with flashbots middleware
         let flashbots_middleware =
@@ -35,6 +39,7 @@ impl BundleRelay {
         Ok(BundleRelay {
             flashbots_client,
             relay_name,
+            coinbase,
         })
     }
 }
@@ -62,31 +67,25 @@ pub fn construct_bundle(
     bundle_request
 }
 
+// Connect to every relay of the configured chain, empty if bundles are not sent to relays
 pub async fn get_all_relay_endpoints() -> Vec<BundleRelay> {
-    let client = utils::create_websocket_client().await.unwrap();
+    let endpoints = match &chain_config().submission {
+        BundleSubmission::Relays(endpoints) => endpoints,
+        BundleSubmission::Direct => return vec![],
+    };
 
-    let endpoints = vec![
-        ("flashbots", "https://relay.flashbots.net/"),
-        ("builder0x69", "http://builder0x69.io/"),
-        ("edennetwork", "https://api.edennetwork.io/v1/bundle"),
-        ("beaverbuild", "https://rpc.beaverbuild.org/"),
-        ("lightspeedbuilder", "https://rpc.lightspeedbuilder.info/"),
-        ("eth-builder", "https://eth-builder.com/"),
-        ("ultrasound", "https://relay.ultrasound.money/"),
-        ("agnostic-relay", "https://agnostic-relay.net/"),
-        ("relayoor-wtf", "https://relayooor.wtf/"),
-        ("rsync-builder", "https://rsync-builder.xyz/"),
-        //"http://relayooor.wtf/",
-        //"http://mainnet.aestus.live/",
-        //"https://mainnet-relay.securerpc.com",
-        //"http://agnostic-relay.net/",
-        //"http://relay.ultrasound.money/",
-    ];
+    let client = utils::create_websocket_client().await.unwrap();
 
     let mut relays: Vec<BundleRelay> = vec![];
 
-    for (name, endpoint) in endpoints {
-        let relay = BundleRelay::new(Url::parse(endpoint).unwrap(), name.into(), &client).unwrap();
+    for endpoint in endpoints {
+        let relay = BundleRelay::new(
+            endpoint.url.clone(),
+            endpoint.name.clone(),
+            endpoint.coinbase,
+            &client,
+        )
+        .unwrap();
         relays.push(relay);
     }
 
@@ -0,0 +1,413 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use dashmap::DashMap;
+use ethers::prelude::*;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::RwLock,
+};
+
+use crate::prelude::{sync_dex, Dex, Pool, PoolIdentifier};
+use crate::types::TokenRegistry;
+use crate::utils::{self, tx_builder::SandwichMaker};
+
+use super::bundle_sender::BundleSender;
+use super::control::{BribeConfig, ControlState};
+use super::risk::RiskManager;
+use super::state::BotState;
+
+// requests are small json-rpc calls, anything bigger is rejected
+const MAX_REQUEST_SIZE: usize = 64 * 1024;
+
+/// Everything the admin api can inspect or change
+pub struct AdminContext {
+    pub control: Arc<ControlState>,
+    pub risk: Arc<RiskManager>,
+    pub token_registry: Arc<TokenRegistry>,
+    pub sandwich_state: Arc<BotState>,
+    pub sandwich_maker: Arc<SandwichMaker>,
+    pub bundle_sender: Arc<RwLock<BundleSender>>,
+    pub all_pools: Arc<DashMap<PoolIdentifier, Pool>>,
+    pub dexes: Vec<Dex>,
+}
+
+// Serve the admin json-rpc api on `addr` until the bot exits
+//
+// Methods:
+// * `admin_status`: submission switches, bribe config, blocked pools and risk counters
+// * `admin_pause` / `admin_resume`: stop or restart sending bundles
+// * `admin_setDryRun` [bool]: build bundles without sending them
+// * `admin_blockToken` / `admin_unblockToken` [address]: edit the token blacklist
+// * `admin_blockPool` / `admin_unblockPool` [address]: edit the pool blacklist
+// * `admin_setBribe` [{dust_overpay, min_share_ppb, jitter_ppb}]: change bribe (fields optional)
+// * `admin_getState`: weth balance, dust tokens and nonce of the sandwich contract
+// * `admin_getBacklog`: recipes waiting for the mega sandwich
+// * `admin_resyncPools`: sync all pools of every dex again (in background)
+//
+// Calls must be sent with `Content-Type: application/json`, browsers can not send those cross
+// origin without a preflight so a page visited on the host can not drive the api
+//
+// Arguments:
+// * `addr`: address to listen on (must stay on loopback unless `token` is set)
+// * `token`: if set, calls must carry `Authorization: Bearer <token>`
+// * `ctx`: state shared with the bot
+//
+// Returns: This function returns nothing
+pub fn start_admin_server(addr: SocketAddr, token: Option<String>, ctx: AdminContext) {
+    let ctx = Arc::new(ctx);
+    let token = Arc::new(token);
+
+    tokio::spawn(async move {
+        let listener = match TcpListener::bind(addr).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                log::error!("Failed to start admin api on {}: {:?}", addr, e);
+                return;
+            }
+        };
+        log::info!("Serving admin api on http://{}", addr);
+
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    log::error!("Failed to accept admin connection: {:?}", e);
+                    continue;
+                }
+            };
+
+            let (ctx, token) = (ctx.clone(), token.clone());
+            tokio::spawn(async move {
+                if let Err(e) = handle_connection(stream, token.as_deref(), &ctx).await {
+                    log::debug!("Admin connection failed: {:?}", e);
+                }
+            });
+        }
+    });
+}
+
+// Answer a single json-rpc call
+async fn handle_connection(
+    mut stream: TcpStream,
+    token: Option<&str>,
+    ctx: &AdminContext,
+) -> std::io::Result<()> {
+    let (headers, body) = read_request(&mut stream).await?;
+
+    if let Err(status) = check_headers(&headers, token) {
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            status
+        );
+        stream.write_all(response.as_bytes()).await?;
+        return stream.shutdown().await;
+    }
+
+    let response = match serde_json::from_slice::<Value>(&body) {
+        Ok(request) => {
+            let id = request.get("id").cloned().unwrap_or(Value::Null);
+            let method = request
+                .get("method")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            let params = request.get("params").cloned().unwrap_or(json!([]));
+
+            match handle_call(ctx, method, &params).await {
+                Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                Err((code, message)) => json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": { "code": code, "message": message }
+                }),
+            }
+        }
+        Err(e) => json!({
+            "jsonrpc": "2.0",
+            "id": null,
+            "error": { "code": -32700, "message": e.to_string() }
+        }),
+    };
+
+    let body = response.to_string();
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+// Reject calls that are not json or do not carry the admin token
+//
+// Arguments:
+// * `headers`: request headers with lowercased names
+// * `token`: token calls must carry, None if calls are not authenticated
+//
+// Returns:
+// Ok(()): call can be handled
+// Err(&str): http status to answer with
+fn check_headers(headers: &[(String, String)], token: Option<&str>) -> Result<(), &'static str> {
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    };
+
+    let is_json = header("content-type")
+        .and_then(|content_type| content_type.split(';').next())
+        .map(|mime| mime.trim().eq_ignore_ascii_case("application/json"))
+        .unwrap_or(false);
+    if !is_json {
+        return Err("415 Unsupported Media Type");
+    }
+
+    if let Some(token) = token {
+        let given = header("authorization")
+            .and_then(|auth| auth.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        if !constant_time_eq(given.as_bytes(), token.as_bytes()) {
+            return Err("401 Unauthorized");
+        }
+    }
+
+    Ok(())
+}
+
+// Compare without bailing on the first differing byte so the token can not be timed out
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+// Read an http request and return its headers (names lowercased) and body
+async fn read_request(stream: &mut TcpStream) -> std::io::Result<(Vec<(String, String)>, Vec<u8>)> {
+    let mut buf = vec![];
+    let mut chunk = [0u8; 4096];
+
+    loop {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+
+        if let Some(header_end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            // first line is the request line
+            let headers: Vec<(String, String)> = String::from_utf8_lossy(&buf[..header_end])
+                .lines()
+                .skip(1)
+                .filter_map(|line| line.split_once(':'))
+                .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+                .collect();
+            let content_length = headers
+                .iter()
+                .find(|(name, _)| name == "content-length")
+                .and_then(|(_, length)| length.parse::<usize>().ok())
+                .unwrap_or_default();
+
+            let body_start = header_end + 4;
+            if buf.len() >= body_start + content_length {
+                let body = buf[body_start..body_start + content_length].to_vec();
+                return Ok((headers, body));
+            }
+        }
+
+        if buf.len() > MAX_REQUEST_SIZE {
+            return Err(std::io::ErrorKind::InvalidData.into());
+        }
+    }
+}
+
+// Run a single admin method
+//
+// Returns:
+// Ok(Value): result of call
+// Err((i64, String)): json-rpc error code and message
+async fn handle_call(
+    ctx: &AdminContext,
+    method: &str,
+    params: &Value,
+) -> Result<Value, (i64, String)> {
+    let control = &ctx.control;
+
+    match method {
+        "admin_status" => Ok(json!({
+            "paused": control.is_paused(),
+            "dry_run": control.is_dry_run(),
+            "bribe": control.bribe(),
+            "blocked_pools": control.blocked_pools(),
+            "risk": ctx.risk.status(),
+        })),
+        "admin_pause" => {
+            control.set_paused(true);
+            log::warn!("Bundle submission paused through admin api");
+            Ok(json!(true))
+        }
+        "admin_resume" => {
+            control.set_paused(false);
+            log::warn!("Bundle submission resumed through admin api");
+            Ok(json!(true))
+        }
+        "admin_setDryRun" => {
+            let dry_run: bool = param(params, 0)?;
+            control.set_dry_run(dry_run);
+            log::warn!("Dry run set to {} through admin api", dry_run);
+            Ok(json!(dry_run))
+        }
+        "admin_blockToken" => Ok(json!(ctx.token_registry.block(param(params, 0)?))),
+        "admin_unblockToken" => Ok(json!(ctx.token_registry.unblock(param(params, 0)?))),
+        "admin_blockPool" => Ok(json!(control.set_pool_blocked(param(params, 0)?, true))),
+        "admin_unblockPool" => Ok(json!(control.set_pool_blocked(param(params, 0)?, false))),
+        "admin_setBribe" => {
+            // fields that are left out keep their current value
+            let mut bribe = json!(control.bribe());
+            let changes: serde_json::Map<String, Value> = param(params, 0)?;
+            for (key, value) in changes {
+                bribe[key] = value;
+            }
+
+            let bribe: BribeConfig = serde_json::from_value(bribe).map_err(invalid_params)?;
+            match control.set_bribe(bribe) {
+                true => Ok(json!(bribe)),
+                false => Err((
+                    -32602,
+                    format!(
+                        "bribe shares add up to more than revenue or dust overpay is above {}",
+                        BribeConfig::max_dust_overpay()
+                    ),
+                )),
+            }
+        }
+        "admin_getState" => {
+            let weth_balance = *ctx.sandwich_state.weth_balance.read().await;
+            let token_dust = ctx.sandwich_state.token_dust.read().await.clone();
+            let nonce = *ctx.sandwich_maker.nonce.read().await;
+            Ok(json!({
+                "weth_balance": weth_balance,
+                "token_dust": token_dust,
+                "nonce": nonce,
+            }))
+        }
+        "admin_getBacklog" => {
+            // release bundle sender lock before waiting on each pool's recipes
+            let pending = ctx.bundle_sender.read().await.pending_sandwiches.clone();
+
+            let mut backlog = vec![];
+            for (pool, recipes) in pending {
+                let recipes = recipes.read().await;
+                let revenue = recipes
+                    .iter()
+                    .fold(U256::zero(), |total, recipe| total + recipe.revenue);
+                let meats: Vec<TxHash> = recipes
+                    .iter()
+                    .flat_map(|recipe| recipe.meats.iter().map(|meat| meat.hash))
+                    .collect();
+                backlog.push(json!({
+                    "pool": pool.address,
+                    "pool_variant": format!("{:?}", pool.pool_variant),
+                    "recipes": recipes.len(),
+                    "revenue": revenue,
+                    "meats": meats,
+                }));
+            }
+            Ok(json!(backlog))
+        }
+        "admin_resyncPools" => {
+            let resync_guard = match control.start_resync() {
+                Some(resync_guard) => resync_guard,
+                None => return Err((-32000, "pool resync already running".to_string())),
+            };
+
+            let (all_pools, dexes) = (ctx.all_pools.clone(), ctx.dexes.clone());
+            tokio::spawn(async move {
+                // resync is marked as finished when guard drops, even if syncing panics
+                let _resync_guard = resync_guard;
+                match resync_pools(&all_pools, dexes).await {
+                    Ok(synced) => log::info!("Resynced {} pools through admin api", synced),
+                    Err(e) => log::error!("Failed to resync pools: {:?}", e),
+                }
+            });
+            Ok(json!(true))
+        }
+        _ => Err((-32601, format!("method {} not found", method))),
+    }
+}
+
+// Sync every pool of every dex from its factory's creation block
+async fn resync_pools(
+    all_pools: &DashMap<PoolIdentifier, Pool>,
+    dexes: Vec<Dex>,
+) -> eyre::Result<usize> {
+    let client = utils::create_websocket_client().await?;
+    let current_block = client.get_block_number().await?;
+    let pools = sync_dex(dexes, &client, current_block, None).await?;
+
+    let synced = pools.len();
+    for pool in pools {
+        all_pools.insert(pool.id(), pool);
+    }
+    Ok(synced)
+}
+
+// Parse positional param at `index`
+fn param<T: DeserializeOwned>(params: &Value, index: usize) -> Result<T, (i64, String)> {
+    let value = params.get(index).cloned().unwrap_or(Value::Null);
+    serde_json::from_value(value).map_err(invalid_params)
+}
+
+fn invalid_params(e: serde_json::Error) -> (i64, String) {
+    (-32602, format!("invalid params: {}", e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn headers(headers: &[(&str, &str)]) -> Vec<(String, String)> {
+        headers
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn calls_must_be_json() {
+        let form = headers(&[("content-type", "application/x-www-form-urlencoded")]);
+        assert_eq!(
+            check_headers(&form, None),
+            Err("415 Unsupported Media Type")
+        );
+        assert_eq!(check_headers(&[], None), Err("415 Unsupported Media Type"));
+
+        let json = headers(&[("content-type", "application/json; charset=utf-8")]);
+        assert_eq!(check_headers(&json, None), Ok(()));
+    }
+
+    #[test]
+    fn calls_must_carry_token_when_set() {
+        let no_token = headers(&[("content-type", "application/json")]);
+        assert_eq!(
+            check_headers(&no_token, Some("secret")),
+            Err("401 Unauthorized")
+        );
+
+        let wrong_token = headers(&[
+            ("content-type", "application/json"),
+            ("authorization", "Bearer secreT"),
+        ]);
+        assert_eq!(
+            check_headers(&wrong_token, Some("secret")),
+            Err("401 Unauthorized")
+        );
+
+        let token = headers(&[
+            ("content-type", "application/json"),
+            ("authorization", "Bearer secret"),
+        ]);
+        assert_eq!(check_headers(&token, Some("secret")), Ok(()));
+    }
+}
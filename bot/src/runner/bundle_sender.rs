@@ -1,20 +1,35 @@
 use colored::Colorize;
 use ethers::prelude::{rand::Rng, *};
 use hashbrown::HashMap;
-use std::{collections::BTreeMap, sync::Arc};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, OnceLock,
+    },
+};
 use tokio::sync::RwLock;
+use tracing::Instrument;
 
 use crate::{
     prelude::{
         fork_factory::ForkFactory,
         make_sandwich,
         sandwich_types::{OptimalRecipe, RawIngredients},
-        BlockInfo, Pool, PoolVariant, SendBundleError,
+        BlockInfo, Pool, SendBundleError,
     },
     relay, utils,
-    utils::tx_builder::SandwichMaker,
+    utils::{
+        chain::{chain_config, BundleSubmission},
+        metrics::metrics,
+        tx_builder::SandwichMaker,
+    },
 };
 
+use super::control::{BribeConfig, ControlState};
+use super::risk::RiskManager;
+use super::shadow::{shadow_recorder, ShadowBundle};
+use super::simulation_executor::SimulationExecutor;
 use super::state::BotState;
 
 pub struct BundleSender {
@@ -53,11 +68,18 @@ impl BundleSender {
     // enchanement: proof of concept atm, a lot of room for improvement
     // bump victim transactions if detect tx with same sender + nonce
     // don't clear at end and just remove recipes based on mined txs in latest block (collect backlog)
+    #[tracing::instrument(
+        name = "mega_sandwich",
+        skip_all,
+        fields(target_block = %next_block.number, victims = tracing::field::Empty)
+    )]
     pub async fn make_mega_sandwich(
         &mut self,
         next_block: BlockInfo,
         sandwich_state: Arc<BotState>,
         sandwich_maker: Arc<SandwichMaker>,
+        control: Arc<ControlState>,
+        simulation_executor: &SimulationExecutor,
     ) {
         let mut handles = Vec::new();
         for (target_pool, recipes) in self.pending_sandwiches.iter() {
@@ -76,29 +98,27 @@ impl BundleSender {
             let sandwich_maker = sandwich_maker.clone();
             let target_pool = target_pool.clone();
             // could cleanup this code because a lot of copy + pasting from runner/mod.rs
-            let handle = tokio::spawn(prepare_and_send_sandwich(
-                recipes,
-                sandwich_state,
-                next_block,
-                sandwich_maker,
-                target_pool,
-            ));
-            handles.push(handle);
+            // searches share the workers with victim searches, mega sandwich is due first
+            let (result_sender, result_receiver) = tokio::sync::oneshot::channel();
+            simulation_executor.submit(U256::MAX, tracing::Span::current(), async move {
+                let optimal_sandwich = prepare_and_send_sandwich(
+                    recipes,
+                    sandwich_state,
+                    next_block,
+                    sandwich_maker,
+                    target_pool,
+                )
+                .await;
+                let _ = result_sender.send(optimal_sandwich);
+            });
+            handles.push(result_receiver);
         }
-        let results = futures::future::try_join_all(handles).await;
-        let optimal_sandwiches = match results {
-            Ok(optimal_sandwiches) => {
-                let optimal_sandwiches: Vec<_> = optimal_sandwiches
-                    .into_iter()
-                    .filter_map(|optimal_sandwich| optimal_sandwich)
-                    .collect();
-                optimal_sandwiches
-            }
-            Err(e) => {
-                eprintln!("One of the tasks panicked: {:?}", e);
-                vec![]
-            }
-        };
+        // a search that panicked or was dropped from a full queue just leaves its pool out
+        let results = futures::future::join_all(handles).await;
+        let optimal_sandwiches: Vec<_> = results
+            .into_iter()
+            .filter_map(|optimal_sandwich| optimal_sandwich.ok().flatten())
+            .collect();
 
         println!("optimal_sandwiches size: {:?}", optimal_sandwiches.len());
         if optimal_sandwiches.len() <= 1 {
@@ -112,7 +132,8 @@ impl BundleSender {
         let mut combined_state_diffs: BTreeMap<H160, AccountDiff> = BTreeMap::new();
         let target_pool = optimal_sandwiches[0].target_pool.clone();
         for optimal_sandwich in &optimal_sandwiches {
-            let max_fee = calculate_bribe_for_max_fee(optimal_sandwich, &next_block);
+            let max_fee =
+                calculate_bribe_for_max_fee(optimal_sandwich, &next_block, &control.bribe());
             match max_fee {
                 Ok(_) => {
                     frontrun_data.extend(optimal_sandwich.frontrun_data.clone());
@@ -129,6 +150,9 @@ impl BundleSender {
                 }
             }
         }
+        let victims: Vec<TxHash> = meats.iter().map(|meat| meat.hash).collect();
+        tracing::Span::current().record("victims", tracing::field::debug(&victims));
+
         println!("frontrun_data: {:?}", hex::encode(&frontrun_data));
         println!("backrun_data: {:?}", hex::encode(&backrun_data));
         // println!("frontrun_access_list: {:?}", &frontrun_access_list);
@@ -162,6 +186,17 @@ impl BundleSender {
             }
         }
 
+        #[tracing::instrument(
+            skip_all,
+            fields(
+                target_block = %next_block.number,
+                pool = ?target_pool.address,
+                victims = ?recipes
+                    .iter()
+                    .flat_map(|recipe| recipe.meats.iter().map(|meat| meat.hash))
+                    .collect::<Vec<_>>()
+            )
+        )]
         async fn prepare_and_send_sandwich(
             mut recipes: Vec<OptimalRecipe>,
             sandwich_state: Arc<BotState>,
@@ -246,16 +281,57 @@ impl BundleSender {
 // * `&recipe`: information on how to construct sandwich bundle
 // * `target_block`: holds basefee and timestamp of target block
 // * `sandwich_maker`: holds signer, bot address for constructing frontslice and backslice
+// * `control`: runtime switches (paused, dry run, bribe config)
+// * `risk`: limits checked before signing, told whether the bundle landed
 //
 // Returns:
-// Ok(()): return nothing if sent succesful
+// Ok(()): return nothing if sent succesful (or skipped because submission is paused)
 // Err(SendBundleError): return error if send bundle fails
+#[tracing::instrument(
+    skip_all,
+    fields(
+        target_block = %target_block.number,
+        pool = ?recipe.target_pool.address,
+        victims = %recipe.print_meats()
+    )
+)]
 pub async fn send_bundle(
     recipe: &OptimalRecipe,
     target_block: BlockInfo,
     sandwich_maker: Arc<SandwichMaker>,
     sandwich_state: Arc<BotState>,
+    control: Arc<ControlState>,
+    risk: Arc<RiskManager>,
 ) -> Result<(), SendBundleError> {
+    if control.is_paused() {
+        log::info!("{:?} [submission paused]", recipe.print_meats());
+        return Ok(());
+    }
+
+    // bundles that reach builders after they stop building for the slot can't land
+    if let Some(deadline_ms) = utils::dotenv::get_submission_deadline_ms() {
+        let time_remaining_ms = target_block.time_remaining_ms(deadline_ms);
+        if time_remaining_ms <= 0 {
+            return Err(SendBundleError::SubmissionDeadlinePassed(
+                -time_remaining_ms,
+            ));
+        }
+    }
+
+    let max_fee = calculate_bribe_for_max_fee(&recipe, &target_block, &control.bribe())?;
+
+    let gas_cost = (U256::from(recipe.frontrun_gas_used) * target_block.base_fee)
+        + (U256::from(recipe.backrun_gas_used) * max_fee);
+    let profit = recipe.revenue.checked_sub(gas_cost).unwrap_or_default();
+
+    // nothing is signed for bundles that break a risk limit
+    let other_token = if recipe.target_pool.token_0 != utils::constants::get_weth_address() {
+        recipe.target_pool.token_0
+    } else {
+        recipe.target_pool.token_1
+    };
+    risk.check(other_token, recipe.frontrun_in, gas_cost, profit)?;
+
     let nonce = {
         let read_lock = sandwich_maker.nonce.read().await;
         (*read_lock).clone()
@@ -265,7 +341,7 @@ pub async fn send_bundle(
         to: Some(NameOrAddress::Address(sandwich_maker.sandwich_address)),
         from: Some(sandwich_maker.searcher_wallet.address()),
         data: Some(recipe.frontrun_data.clone()),
-        chain_id: Some(U64::from(1)),
+        chain_id: Some(U64::from(chain_config().chain_id)),
         max_priority_fee_per_gas: Some(U256::from(0)),
         max_fee_per_gas: Some(target_block.base_fee),
         gas: Some((U256::from(recipe.frontrun_gas_used) * 10) / 7), // gasused = 70% gaslimit
@@ -279,13 +355,11 @@ pub async fn send_bundle(
 
     let raw_signed_meat_txs: Vec<Bytes> = recipe.meats.iter().map(|meat| meat.rlp()).collect();
 
-    let max_fee = calculate_bribe_for_max_fee(&recipe, &target_block)?;
-
     let back_slice_request = Eip1559TransactionRequest {
         to: Some(NameOrAddress::Address(sandwich_maker.sandwich_address)),
         from: Some(sandwich_maker.searcher_wallet.address()),
         data: Some(recipe.backrun_data.clone()),
-        chain_id: Some(U64::from(1)),
+        chain_id: Some(U64::from(chain_config().chain_id)),
         max_priority_fee_per_gas: Some(max_fee),
         max_fee_per_gas: Some(max_fee),
         gas: Some((U256::from(recipe.backrun_gas_used) * 10) / 7), // gasused = 70% gaslimit
@@ -297,43 +371,89 @@ pub async fn send_bundle(
     let raw_signed_backrun_tx =
         utils::sign_eip1559(back_slice_request, &sandwich_maker.searcher_wallet).await?;
 
+    let bundled_transactions = {
+        let mut bundled_transactions: Vec<Bytes> = vec![raw_signed_frontrun_tx];
+        for meat in raw_signed_meat_txs {
+            bundled_transactions.push(meat.clone());
+        }
+        bundled_transactions.push(raw_signed_backrun_tx);
+        bundled_transactions
+    };
+
+    if control.is_dry_run() {
+        log::info!(
+            "{:?} [dry run] bundle not sent, profit {:?}",
+            recipe.print_meats(),
+            profit
+        );
+        // checked against the target block once it is mined
+        shadow_recorder().record(ShadowBundle {
+            target_block: target_block.number,
+            target_pool: recipe.target_pool,
+            meats: recipe.meats.iter().map(|meat| meat.hash).collect(),
+            transactions: bundled_transactions,
+            profit,
+        });
+        return Ok(());
+    }
+
+    let settlement = BundleSettlement {
+        recipe: recipe.clone(),
+        target_block: target_block.number,
+        max_fee,
+        profit,
+        gas_cost,
+        sandwich_state,
+        sandwich_maker,
+        control,
+        risk,
+    };
+
+    let relays = match &chain_config().submission {
+        BundleSubmission::Relays(_) => relay::get_all_relay_endpoints().await,
+        BundleSubmission::Direct => {
+            let direct_span = tracing::info_span!("relay", relay = "direct");
+            tokio::spawn(send_direct(bundled_transactions, settlement).instrument(direct_span));
+            return Ok(());
+        }
+    };
+
+    let relays = relays_safe_for_recipe(relays, recipe, &target_block).await;
+    if relays.is_empty() {
+        log::warn!(
+            "{:?} [coinbase] recipe does not hold up for any relay's builder",
+            recipe.print_meats()
+        );
+        return Ok(());
+    }
+
     let bundle = relay::construct_bundle(
-        {
-            let mut bundled_transactions: Vec<Bytes> = vec![raw_signed_frontrun_tx];
-            for meat in raw_signed_meat_txs {
-                bundled_transactions.push(meat.clone());
-            }
-            bundled_transactions.push(raw_signed_backrun_tx);
-            bundled_transactions
-        },
+        bundled_transactions,
         target_block.number,
         target_block.timestamp.as_u64(),
     );
 
-    let profit = recipe
-        .revenue
-        .checked_sub(
-            (U256::from(recipe.frontrun_gas_used) * target_block.base_fee)
-                + (U256::from(recipe.backrun_gas_used) * max_fee),
-        )
-        .unwrap_or_default();
-
     // send bundle to all relay endpoints (concurrently)
-    for relay in relay::get_all_relay_endpoints().await {
-        let sandwich_state = sandwich_state.clone();
-        let sandwich_maker = sandwich_maker.clone();
+    let inclusion = Arc::new(RelayInclusion::new(relays.len()));
+    for relay in relays {
+        let (settlement, inclusion) = (settlement.clone(), inclusion.clone());
         let bundle = bundle.clone();
-        let recipe = recipe.clone();
+        let relay_span = tracing::info_span!("relay", relay = %relay.relay_name);
 
-        tokio::spawn(async move {
+        let relay_task = async move {
+            let recipe = &settlement.recipe;
             let pending_bundle = match relay.flashbots_client.inner().send_bundle(&bundle).await {
                 Ok(pb) => pb,
                 Err(_) => {
                     //log::error!("Failed to send bundle: {:?}", e);
+                    if let Some((bundle_hash, is_included)) = inclusion.report(None, false) {
+                        settlement.settle(bundle_hash, is_included).await;
+                    }
                     return;
                 }
             };
 
+            metrics().bundles_sent.inc(&relay.relay_name);
             log::info!(
                 "{:?} {}",
                 recipe.print_meats(),
@@ -358,54 +478,302 @@ pub async fn send_bundle(
                 }
             };
 
-            // only do this operation once (could do this in a cleaner way :<)
-            if relay.relay_name == "flashbots" {
-                utils::alert::alert_bundle(
-                    bundle_hash,
-                    target_block.number,
-                    is_bundle_included,
-                    &recipe,
-                    max_fee,
-                    profit,
-                )
-                .await;
-
-                match (is_bundle_included, recipe.has_dust) {
-                    (true, false) => {
-                        let other_token =
-                            if recipe.target_pool.token_0 != utils::constants::get_weth_address() {
-                                recipe.target_pool.token_0
-                            } else {
-                                recipe.target_pool.token_1
-                            };
-
-                        sandwich_state.update_weth_balance(profit).await;
-                        sandwich_state.add_dust(other_token).await;
-                        let mut nonce = sandwich_maker.nonce.write().await;
-                        *nonce += U256::from(2);
-                        log::info!("Adding new dust: {:?}", other_token);
-                    }
-                    (true, _) => {
-                        // update weth balance
-                        sandwich_state.update_weth_balance(profit).await;
-                        let mut nonce = sandwich_maker.nonce.write().await;
-                        *nonce += U256::from(2);
-                        log::info!("Updating weth balance");
-                    }
-                    (false, _) => { /* bundle not included, do nothing */ }
-                }
+            // only do this operation once, on the first relay that sees the bundle land
+            if let Some((bundle_hash, is_included)) =
+                inclusion.report(Some(bundle_hash), is_bundle_included)
+            {
+                settlement.settle(bundle_hash, is_included).await;
             }
-        });
+        };
+        tokio::spawn(relay_task.instrument(relay_span));
     }
 
     Ok(())
 }
 
+// Drop relays whose builder the recipe does not hold up against
+//
+// Recipe was simulated with `target_block.coinbase` (coinbase of the first relay that has one),
+// contracts can behave differently for other builders so the recipe is simulated again for every
+// other known relay coinbase, relays without a known coinbase are kept
+//
+// Arguments:
+// * `relays`: relays bundle would be sent to
+// * `recipe`: recipe bundle was built from
+// * `target_block`: block recipe was simulated on
+//
+// Returns:
+// Vec<BundleRelay>: relays recipe passes the salmonella check for and is as profitable on
+async fn relays_safe_for_recipe(
+    relays: Vec<relay::BundleRelay>,
+    recipe: &OptimalRecipe,
+    target_block: &BlockInfo,
+) -> Vec<relay::BundleRelay> {
+    let mut coinbases: Vec<Address> = relays
+        .iter()
+        .filter_map(|relay| relay.coinbase)
+        .filter(|coinbase| *coinbase != target_block.coinbase)
+        .collect();
+    coinbases.sort();
+    coinbases.dedup();
+    if coinbases.is_empty() {
+        return relays;
+    }
+
+    let fork_block = Some(BlockId::Number(BlockNumber::Number(target_block.number)));
+    let fork_factory = match utils::create_websocket_client().await {
+        Ok(client) => {
+            match utils::state_diff::to_cache_db(&recipe.state_diffs, fork_block, &client).await {
+                Ok(initial_db) => Some(ForkFactory::new_sandbox_factory(
+                    client.clone(),
+                    initial_db,
+                    fork_block,
+                )),
+                Err(_) => None,
+            }
+        }
+        Err(_) => None,
+    };
+
+    let mut rejected = vec![];
+    for coinbase in coinbases {
+        // without a fork nothing is known about other builders, only keep relays already checked
+        let fork_factory = match &fork_factory {
+            Some(fork_factory) => fork_factory,
+            None => {
+                rejected.push(coinbase);
+                continue;
+            }
+        };
+
+        let block = BlockInfo {
+            coinbase,
+            ..target_block.clone()
+        };
+        match make_sandwich::resimulate_recipe(recipe, &block, fork_factory.new_sandbox_fork()) {
+            Ok(revenue) if revenue >= recipe.revenue => {}
+            Ok(revenue) => {
+                log::warn!(
+                    "{:?} [coinbase {:?}] revenue drops to {:?}",
+                    recipe.print_meats(),
+                    coinbase,
+                    revenue
+                );
+                rejected.push(coinbase);
+            }
+            Err(e) => {
+                metrics().simulation_failures.inc(e.kind());
+                log::warn!(
+                    "{:?} [coinbase {:?}] recipe fails: {:?}",
+                    recipe.print_meats(),
+                    coinbase,
+                    e
+                );
+                rejected.push(coinbase);
+            }
+        }
+    }
+
+    relays
+        .into_iter()
+        .filter(|relay| !matches!(relay.coinbase, Some(coinbase) if rejected.contains(&coinbase)))
+        .collect()
+}
+
+// Send a bundle's txs one by one straight to the rpc node (devnets without builders)
+//
+// Arguments:
+// * `bundled_transactions`: signed frontrun, meats and backrun in bundle order
+// * `settlement`: accounts for the bundle once the backrun is mined
+//
+// Returns: This function returns nothing
+async fn send_direct(bundled_transactions: Vec<Bytes>, settlement: BundleSettlement) {
+    let recipe = &settlement.recipe;
+    let client = match utils::create_websocket_client().await {
+        Ok(client) => client,
+        Err(e) => {
+            log::error!(
+                "{:?} Failed to connect to node: {:?}",
+                recipe.print_meats(),
+                e
+            );
+            return;
+        }
+    };
+
+    let mut transactions = bundled_transactions;
+    let backrun = match transactions.pop() {
+        Some(backrun) => backrun,
+        None => return,
+    };
+
+    for (index, tx) in transactions.into_iter().enumerate() {
+        match client.send_raw_transaction(tx).await {
+            Ok(_) => {}
+            // meats are usually already in the node's mempool
+            Err(e) if index > 0 => log::debug!("{:?} Meat not sent: {:?}", recipe.print_meats(), e),
+            Err(e) => {
+                log::error!(
+                    "{:?} Failed to send frontrun: {:?}",
+                    recipe.print_meats(),
+                    e
+                );
+                return;
+            }
+        }
+    }
+
+    let backrun_hash = H256::from(ethers::utils::keccak256(&backrun));
+    let receipt = match client.send_raw_transaction(backrun).await {
+        Ok(pending_tx) => pending_tx.await,
+        Err(e) => {
+            log::error!("{:?} Failed to send backrun: {:?}", recipe.print_meats(), e);
+            return;
+        }
+    };
+
+    metrics().bundles_sent.inc("direct");
+    log::info!(
+        "{:?} {}",
+        recipe.print_meats(),
+        "Bundle sent to node".bold().white().on_black()
+    );
+
+    let is_bundle_included =
+        matches!(receipt, Ok(Some(receipt)) if receipt.status == Some(1.into()));
+    settlement.settle(backrun_hash, is_bundle_included).await;
+}
+
+/// Decides which relay settles a bundle that was sent to several relays
+#[derive(Debug)]
+struct RelayInclusion {
+    /// relays that have not reported on the bundle yet
+    pending_relays: AtomicUsize,
+    is_settled: AtomicBool,
+    /// hash of bundle as reported by the first relay that accepted it
+    bundle_hash: OnceLock<H256>,
+}
+
+impl RelayInclusion {
+    fn new(relays: usize) -> Self {
+        Self {
+            pending_relays: AtomicUsize::new(relays),
+            is_settled: AtomicBool::new(false),
+            bundle_hash: OnceLock::new(),
+        }
+    }
+
+    // Record what a relay saw happen to the bundle, every relay reports exactly once
+    //
+    // Arguments:
+    // * `&self`: reference to `RelayInclusion` instance
+    // * `bundle_hash`: hash relay returned for bundle, None if relay did not accept it
+    // * `is_bundle_included`: true if relay saw bundle land
+    //
+    // Returns:
+    // Option<(H256, bool)>: bundle hash and inclusion to settle with, only returned once: on the
+    // first relay that sees the bundle land, or on the last relay if none did (None if no relay
+    // accepted the bundle)
+    fn report(&self, bundle_hash: Option<H256>, is_bundle_included: bool) -> Option<(H256, bool)> {
+        if let Some(bundle_hash) = bundle_hash {
+            let _ = self.bundle_hash.set(bundle_hash);
+        }
+
+        let is_last = self.pending_relays.fetch_sub(1, Ordering::AcqRel) == 1;
+        if !is_bundle_included && !is_last {
+            return None;
+        }
+        if self.is_settled.swap(true, Ordering::AcqRel) {
+            return None;
+        }
+
+        let bundle_hash = bundle_hash.or_else(|| self.bundle_hash.get().copied())?;
+        Some((bundle_hash, is_bundle_included))
+    }
+}
+
+/// Everything needed to account for a sent bundle once it is known whether it landed
+#[derive(Clone)]
+struct BundleSettlement {
+    recipe: OptimalRecipe,
+    target_block: U64,
+    max_fee: U256,
+    profit: U256,
+    gas_cost: U256,
+    sandwich_state: Arc<BotState>,
+    sandwich_maker: Arc<SandwichMaker>,
+    control: Arc<ControlState>,
+    risk: Arc<RiskManager>,
+}
+
+impl BundleSettlement {
+    // Alert on the bundle and update balances, nonce and risk counters
+    //
+    // Arguments:
+    // * `&self`: reference to `BundleSettlement` instance
+    // * `bundle_hash`: bundle hash (backrun tx hash for direct submission)
+    // * `is_bundle_included`: true if bundle landed
+    //
+    // Returns: This function returns nothing
+    async fn settle(&self, bundle_hash: H256, is_bundle_included: bool) {
+        let recipe = &self.recipe;
+        utils::alert::alert_bundle(
+            bundle_hash,
+            self.target_block,
+            is_bundle_included,
+            recipe,
+            self.max_fee,
+            self.profit,
+        )
+        .await;
+
+        if is_bundle_included {
+            metrics().bundles_included.inc();
+            metrics()
+                .profit_gwei
+                .add((self.profit / U256::exp10(9)).low_u64());
+        }
+
+        let halted =
+            self.risk
+                .record_bundle_result(is_bundle_included, self.gas_cost, &self.control);
+        if let Some(reason) = halted {
+            utils::alert::alert_halt(&reason).await;
+        }
+
+        match (is_bundle_included, recipe.has_dust) {
+            (true, false) => {
+                let other_token =
+                    if recipe.target_pool.token_0 != utils::constants::get_weth_address() {
+                        recipe.target_pool.token_0
+                    } else {
+                        recipe.target_pool.token_1
+                    };
+
+                self.sandwich_state.update_weth_balance(self.profit).await;
+                self.sandwich_state.add_dust(other_token).await;
+                let mut nonce = self.sandwich_maker.nonce.write().await;
+                *nonce += U256::from(2);
+                log::info!("Adding new dust: {:?}", other_token);
+            }
+            (true, _) => {
+                // update weth balance
+                self.sandwich_state.update_weth_balance(self.profit).await;
+                let mut nonce = self.sandwich_maker.nonce.write().await;
+                *nonce += U256::from(2);
+                log::info!("Updating weth balance");
+            }
+            (false, _) => { /* bundle not included, do nothing */ }
+        }
+    }
+}
+
 // calculates the optimal bribe for a given opportunity
 //
 // Arguments
 // * `recipe`: information on sandwich bundle
 // * `target_block`: information on target_block
+// * `bribe`: share of revenue to bribe
 //
 // Returns:
 // Ok(U256) -> The maximum fee for opportunity if calculated succesfully
@@ -413,6 +781,7 @@ pub async fn send_bundle(
 fn calculate_bribe_for_max_fee(
     recipe: &OptimalRecipe,
     target_block: &BlockInfo,
+    bribe: &BribeConfig,
 ) -> Result<U256, SendBundleError> {
     // frontrun txfee is fixed, exclude it from bribe calculations
     let revenue_minus_frontrun_tx_fee = match recipe
@@ -426,21 +795,16 @@ fn calculate_bribe_for_max_fee(
     // overpay to get dust onto sandwich contractIf
     // more info: https://twitter.com/libevm/status/1474870661373779969
     let bribe_amount = if !recipe.has_dust {
-        revenue_minus_frontrun_tx_fee + ethers::utils::parse_ether("0.0003861").unwrap()
+        revenue_minus_frontrun_tx_fee + bribe.dust_overpay
     } else {
-        let mut rng = rand::thread_rng();
+        let jitter = match bribe.jitter_ppb {
+            0 => 0,
+            jitter_ppb => rand::thread_rng().gen_range(0..jitter_ppb),
+        };
+        let share_ppb = bribe.min_share_ppb + jitter;
 
-        // enchanement: make bribe adaptive based on competitors
-        match recipe.target_pool.pool_variant {
-            PoolVariant::UniswapV2 => {
-                (revenue_minus_frontrun_tx_fee * (999900000 + rng.gen_range(0..100000)))
-                    / 1000000000
-            }
-            PoolVariant::UniswapV3 => {
-                (revenue_minus_frontrun_tx_fee * (999900000 + rng.gen_range(0..100000)))
-                    / 1000000000
-            }
-        }
+        // enchanement: make bribe adaptive based on competitors (and pool variant)
+        (revenue_minus_frontrun_tx_fee * share_ppb) / 1000000000
     };
 
     // calculating bribe amount
@@ -458,3 +822,37 @@ fn calculate_bribe_for_max_fee(
 
     Ok(max_fee)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn first_relay_seeing_inclusion_settles() {
+        let (hash_a, hash_b) = (H256::repeat_byte(1), H256::repeat_byte(2));
+        let inclusion = RelayInclusion::new(3);
+
+        assert_eq!(inclusion.report(Some(hash_a), false), None);
+        assert_eq!(inclusion.report(Some(hash_b), true), Some((hash_b, true)));
+        assert_eq!(inclusion.report(Some(hash_a), true), None);
+    }
+
+    #[test]
+    fn last_relay_settles_missed_bundle() {
+        let hash = H256::repeat_byte(1);
+        let inclusion = RelayInclusion::new(3);
+
+        assert_eq!(inclusion.report(Some(hash), false), None);
+        assert_eq!(inclusion.report(Some(hash), false), None);
+        // relay that failed to accept bundle still settles with the known hash
+        assert_eq!(inclusion.report(None, false), Some((hash, false)));
+    }
+
+    #[test]
+    fn bundle_no_relay_accepted_is_not_settled() {
+        let inclusion = RelayInclusion::new(2);
+
+        assert_eq!(inclusion.report(None, false), None);
+        assert_eq!(inclusion.report(None, false), None);
+    }
+}
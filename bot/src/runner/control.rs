@@ -0,0 +1,207 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, RwLock,
+};
+
+use dashmap::DashSet;
+use ethers::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::utils;
+
+/// How much of a sandwich's revenue is handed to the builder
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BribeConfig {
+    /// paid on top of revenue when the contract holds no dust of the intermediary token yet
+    pub dust_overpay: U256,
+    /// share of revenue (after frontrun fees) always bribed, in parts per billion
+    pub min_share_ppb: u64,
+    /// random extra share added on top of `min_share_ppb`, in parts per billion
+    pub jitter_ppb: u64,
+}
+
+impl Default for BribeConfig {
+    fn default() -> Self {
+        Self {
+            // more info: https://twitter.com/libevm/status/1474870661373779969
+            dust_overpay: ethers::utils::parse_ether("0.0003861").unwrap(),
+            min_share_ppb: 999_900_000,
+            jitter_ppb: 100_000,
+        }
+    }
+}
+
+impl BribeConfig {
+    // Largest `dust_overpay` accepted, overpaying is a fixed loss on every bundle without dust
+    pub fn max_dust_overpay() -> U256 {
+        ethers::utils::parse_ether("0.01").unwrap()
+    }
+
+    // Check that bribe can never exceed revenue (plus a bounded dust overpay)
+    pub fn is_valid(&self) -> bool {
+        self.min_share_ppb.saturating_add(self.jitter_ppb) <= 1_000_000_000
+            && self.dust_overpay <= Self::max_dust_overpay()
+    }
+}
+
+/// Runtime switches of a running bot, changed through the admin api
+#[derive(Debug)]
+pub struct ControlState {
+    // stop sending bundles (searching carries on so that logs and metrics stay useful)
+    paused: AtomicBool,
+    // build and sign bundles but never send them
+    dry_run: AtomicBool,
+    // pools that are never sandwiched
+    blocked_pools: DashSet<Address>,
+    bribe: RwLock<BribeConfig>,
+    // set while a full pool resync is running
+    resyncing: AtomicBool,
+}
+
+impl ControlState {
+    // Create control state with submission enabled
+    //
+    // Arguments:
+    // * `dry_run`: true to never send bundles
+    //
+    // Returns:
+    // `ControlState`: unpaused state with default bribe config and no blocked pools
+    pub fn new(dry_run: bool) -> Self {
+        Self {
+            paused: AtomicBool::new(false),
+            dry_run: AtomicBool::new(dry_run),
+            blocked_pools: DashSet::new(),
+            bribe: RwLock::new(BribeConfig::default()),
+            resyncing: AtomicBool::new(false),
+        }
+    }
+
+    // Create control state using `DRY_RUN`
+    pub fn from_env() -> Self {
+        Self::new(utils::dotenv::get_bool("DRY_RUN", false))
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run.load(Ordering::Relaxed)
+    }
+
+    pub fn set_dry_run(&self, dry_run: bool) {
+        self.dry_run.store(dry_run, Ordering::Relaxed);
+    }
+
+    pub fn is_pool_blocked(&self, pool: &Address) -> bool {
+        self.blocked_pools.contains(pool)
+    }
+
+    // Block or unblock a pool, returns false if pool was already in that state
+    pub fn set_pool_blocked(&self, pool: Address, blocked: bool) -> bool {
+        match blocked {
+            true => self.blocked_pools.insert(pool),
+            false => self.blocked_pools.remove(&pool).is_some(),
+        }
+    }
+
+    pub fn blocked_pools(&self) -> Vec<Address> {
+        self.blocked_pools.iter().map(|pool| *pool).collect()
+    }
+
+    pub fn bribe(&self) -> BribeConfig {
+        *self.bribe.read().unwrap()
+    }
+
+    // Replace bribe config, rejected if it could bribe more than the revenue
+    pub fn set_bribe(&self, bribe: BribeConfig) -> bool {
+        if !bribe.is_valid() {
+            return false;
+        }
+        *self.bribe.write().unwrap() = bribe;
+        true
+    }
+
+    // Mark a pool resync as started, None if one is already running
+    //
+    // Resync is marked as finished once the returned guard is dropped, also if the resync panics
+    pub fn start_resync(self: &Arc<Self>) -> Option<ResyncGuard> {
+        match self.resyncing.swap(true, Ordering::AcqRel) {
+            true => None,
+            false => Some(ResyncGuard(self.clone())),
+        }
+    }
+}
+
+/// Keeps a pool resync marked as running until dropped
+#[derive(Debug)]
+pub struct ResyncGuard(Arc<ControlState>);
+
+impl Drop for ResyncGuard {
+    fn drop(&mut self) {
+        self.0.resyncing.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bribe_above_revenue_is_rejected() {
+        let control = ControlState::new(false);
+        let bribe = BribeConfig {
+            min_share_ppb: 999_950_000,
+            jitter_ppb: 100_000,
+            ..Default::default()
+        };
+
+        assert!(!control.set_bribe(bribe));
+        assert_eq!(control.bribe(), BribeConfig::default());
+    }
+
+    #[test]
+    fn dust_overpay_is_bounded() {
+        let control = ControlState::new(false);
+        let bribe = BribeConfig {
+            dust_overpay: ethers::utils::parse_ether("1").unwrap(),
+            ..Default::default()
+        };
+
+        assert!(!control.set_bribe(bribe));
+        assert_eq!(control.bribe(), BribeConfig::default());
+    }
+
+    #[test]
+    fn resync_is_released_when_it_panics() {
+        let control = Arc::new(ControlState::new(false));
+
+        let guard = control.start_resync().unwrap();
+        assert!(control.start_resync().is_none());
+
+        let resync = std::thread::spawn(move || {
+            let _guard = guard;
+            panic!("resync failed");
+        });
+        assert!(resync.join().is_err());
+
+        assert!(control.start_resync().is_some());
+    }
+
+    #[test]
+    fn pools_can_be_blocked_and_unblocked() {
+        let control = ControlState::new(false);
+        let pool = Address::repeat_byte(0xaa);
+
+        assert!(control.set_pool_blocked(pool, true));
+        assert!(!control.set_pool_blocked(pool, true));
+        assert!(control.is_pool_blocked(&pool));
+
+        assert!(control.set_pool_blocked(pool, false));
+        assert!(!control.is_pool_blocked(&pool));
+    }
+}
@@ -0,0 +1,135 @@
+use std::{net::SocketAddr, sync::atomic::Ordering, sync::Arc};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::prelude::{block_state_cache, rpc_batch_stats};
+use crate::utils::metrics::{metrics, write_counter, write_gauge};
+
+use super::simulation_executor::ExecutorMetrics;
+
+// Serve prometheus metrics on `addr` until the bot exits
+//
+// Arguments:
+// * `addr`: address to listen on
+// * `executor_metrics`: counters of the simulation executor, exported next to the bot's metrics
+//
+// Returns: This function returns nothing
+pub fn start_metrics_server(addr: SocketAddr, executor_metrics: Arc<ExecutorMetrics>) {
+    tokio::spawn(async move {
+        let listener = match TcpListener::bind(addr).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                log::error!("Failed to start metrics server on {}: {:?}", addr, e);
+                return;
+            }
+        };
+        log::info!("Serving metrics on http://{}/metrics", addr);
+
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    log::error!("Failed to accept metrics connection: {:?}", e);
+                    continue;
+                }
+            };
+
+            let executor_metrics = executor_metrics.clone();
+            tokio::spawn(async move {
+                if let Err(e) = handle_connection(stream, &executor_metrics).await {
+                    log::debug!("Metrics connection failed: {:?}", e);
+                }
+            });
+        }
+    });
+}
+
+// Answer a single scrape, only `GET /metrics` is served
+async fn handle_connection(
+    mut stream: TcpStream,
+    executor_metrics: &ExecutorMetrics,
+) -> std::io::Result<()> {
+    // request line is all we need, scrapes do not send a body
+    let mut buf = [0u8; 1024];
+    let n = stream.read(&mut buf).await?;
+    let request = String::from_utf8_lossy(&buf[..n]);
+
+    let response = match request.starts_with("GET /metrics") {
+        true => {
+            let body = render(executor_metrics);
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+        }
+        false => {
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+        }
+    };
+
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+// Render bot metrics together with the counters kept by the executor, fork cache and rpc batcher
+fn render(executor_metrics: &ExecutorMetrics) -> String {
+    let mut out = String::new();
+    metrics().render(&mut out);
+
+    write_gauge(
+        &mut out,
+        "simulation_queue_depth",
+        "Simulations waiting for a worker",
+        executor_metrics.queue_depth.load(Ordering::Relaxed),
+    );
+    write_counter(
+        &mut out,
+        "simulations_submitted_total",
+        "Simulations submitted to the executor",
+        executor_metrics.submitted.load(Ordering::Relaxed),
+    );
+    write_counter(
+        &mut out,
+        "simulations_dropped_total",
+        "Simulations dropped because the queue was full",
+        executor_metrics.dropped.load(Ordering::Relaxed),
+    );
+    write_counter(
+        &mut out,
+        "simulations_completed_total",
+        "Simulations run to completion",
+        executor_metrics.completed.load(Ordering::Relaxed),
+    );
+    write_gauge(
+        &mut out,
+        "simulation_avg_queue_latency_seconds",
+        "Average time a simulation waits for a worker",
+        executor_metrics.avg_queue_latency().as_secs_f64(),
+    );
+
+    let cache = block_state_cache();
+    write_counter(
+        &mut out,
+        "block_state_cache_hits_total",
+        "Fork lookups answered from the shared block state cache",
+        cache.hits.load(Ordering::Relaxed),
+    );
+    write_counter(
+        &mut out,
+        "block_state_cache_misses_total",
+        "Fork lookups that fell back to rpc",
+        cache.misses.load(Ordering::Relaxed),
+    );
+    write_counter(
+        &mut out,
+        "rpc_round_trips_saved_total",
+        "Rpc round trips avoided by batching fork lookups",
+        rpc_batch_stats().round_trips_saved(),
+    );
+
+    out
+}
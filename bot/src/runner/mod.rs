@@ -3,32 +3,63 @@ use std::sync::Arc;
 
 use crate::prelude::fork_factory::ForkFactory;
 use crate::prelude::sandwich_types::RawIngredients;
-use crate::prelude::{make_sandwich, Dex, Pool};
+use crate::prelude::{
+    get_amount_out_pool_evm, make_sandwich, Dex, Pool, PoolIdentifier, PoolSnapshots,
+};
 use crate::rpc_extensions;
-use crate::types::BlockOracle;
+use crate::types::{BlacklistReason, BlockOracle, SimulationError, TokenRegistry};
 use crate::utils;
+use crate::utils::metrics::metrics;
+use crate::utils::swap_decoder::{DecodedSwap, SwapAmounts};
 use crate::utils::tx_builder::SandwichMaker;
 use colored::Colorize;
 use ethers::prelude::*;
 use eyre::Result;
 use log;
+use tracing::Instrument;
 
 mod oracles;
+use tokio::sync::mpsc::unbounded_channel;
 use tokio::sync::RwLock;
 
 mod state;
 use state::BotState;
 
+mod pending_victims;
+use pending_victims::{PendingVictim, PendingVictimPool};
+
 mod bundle_sender;
 use bundle_sender::*;
 
+pub mod simulation_executor;
+use simulation_executor::SimulationExecutor;
+
+mod metrics_server;
+
+mod control;
+use control::ControlState;
+
+mod admin_server;
+use admin_server::AdminContext;
+
+mod shadow;
+
+mod risk;
+use risk::RiskManager;
+
 pub struct Bot {
     sandwich_state: Arc<BotState>,
     latest_block_oracle: Arc<RwLock<BlockOracle>>,
     client: Arc<Provider<Ws>>,
-    all_pools: Arc<DashMap<Address, Pool>>,
+    all_pools: Arc<DashMap<PoolIdentifier, Pool>>,
+    pool_snapshots: Arc<PoolSnapshots>,
     sandwich_maker: Arc<SandwichMaker>,
     bundle_sender: Arc<RwLock<BundleSender>>,
+    pending_victims: Arc<RwLock<PendingVictimPool>>,
+    token_registry: Arc<TokenRegistry>,
+    simulation_executor: Arc<SimulationExecutor>,
+    control: Arc<ControlState>,
+    risk: Arc<RiskManager>,
     dexes: Vec<Dex>,
 }
 
@@ -48,12 +79,13 @@ impl Bot {
         dexes: Vec<Dex>,
     ) -> Result<Bot> {
         // create hashmap from our vec of pools (faster access when doing lookups)
-        let all_pools: DashMap<Address, Pool> = DashMap::new();
+        let all_pools: DashMap<PoolIdentifier, Pool> = DashMap::new();
         for pool in pool_vec {
-            all_pools.insert(pool.address, pool);
+            all_pools.insert(pool.id(), pool);
         }
 
         let all_pools = Arc::new(all_pools);
+        let pool_snapshots = Arc::new(PoolSnapshots::from_env());
 
         let sandwich_inception_block = utils::dotenv::get_sandwich_inception_block();
         let sandwich_state = BotState::new(sandwich_inception_block, &client).await?;
@@ -66,13 +98,33 @@ impl Bot {
 
         let bundle_sender = Arc::new(RwLock::new(BundleSender::new().await));
 
+        let pending_victims = PendingVictimPool::new(
+            utils::dotenv::get_pending_victim_ttl_blocks(),
+            utils::dotenv::get_pending_victim_max(),
+        );
+        let pending_victims = Arc::new(RwLock::new(pending_victims));
+
+        let token_registry = Arc::new(TokenRegistry::from_env());
+
+        let simulation_executor = Arc::new(SimulationExecutor::from_env()?);
+
+        let control = Arc::new(ControlState::from_env());
+
+        let risk = Arc::new(RiskManager::from_env());
+
         Ok(Bot {
             client,
             all_pools,
+            pool_snapshots,
             latest_block_oracle,
             sandwich_state,
             sandwich_maker,
             bundle_sender,
+            pending_victims,
+            token_registry,
+            simulation_executor,
+            control,
+            risk,
             dexes,
         })
     }
@@ -88,13 +140,41 @@ impl Bot {
     pub async fn run(&mut self) -> Result<()> {
         log::info!("Starting bot");
 
+        // victims that became includable after being deferred are sent back through here
+        let (deferred_sender, mut deferred_receiver) = unbounded_channel::<PendingVictim>();
+
         oracles::start_add_new_pools(&mut self.all_pools, self.dexes.clone());
-        oracles::start_block_oracle(&mut self.latest_block_oracle);
+        oracles::start_pool_snapshot_oracle(self.all_pools.clone(), self.pool_snapshots.clone());
+        oracles::start_block_oracle(
+            &mut self.latest_block_oracle,
+            self.pending_victims.clone(),
+            deferred_sender,
+        );
         oracles::start_mega_sandwich_oracle(
+            self.latest_block_oracle.clone(),
             self.bundle_sender.clone(),
             self.sandwich_state.clone(),
             self.sandwich_maker.clone(),
+            self.control.clone(),
+            self.simulation_executor.clone(),
         );
+        oracles::start_risk_oracle(self.risk.clone(), self.control.clone());
+        if let Some(addr) = utils::dotenv::get_metrics_addr() {
+            metrics_server::start_metrics_server(addr, self.simulation_executor.metrics());
+        }
+        if let Some(addr) = utils::dotenv::get_admin_addr() {
+            let ctx = AdminContext {
+                control: self.control.clone(),
+                risk: self.risk.clone(),
+                token_registry: self.token_registry.clone(),
+                sandwich_state: self.sandwich_state.clone(),
+                sandwich_maker: self.sandwich_maker.clone(),
+                bundle_sender: self.bundle_sender.clone(),
+                all_pools: self.all_pools.clone(),
+                dexes: self.dexes.clone(),
+            };
+            admin_server::start_admin_server(addr, utils::dotenv::get_admin_token(), ctx);
+        }
 
         let mut mempool_stream = if let Ok(stream) =
             rpc_extensions::subscribe_pending_txs_with_body(&self.client).await
@@ -104,27 +184,75 @@ impl Bot {
             panic!("Failed to create mempool stream");
         };
 
-        while let Some(mut victim_tx) = mempool_stream.next().await {
-            let client = utils::create_websocket_client().await?;
-            let block_oracle = {
-                let read_lock = self.latest_block_oracle.read().await;
-                (*read_lock).clone()
-            };
-            let all_pools = &self.all_pools;
-            let sandwich_balance = {
-                let read_lock = self.sandwich_state.weth_balance.read().await;
-                (*read_lock).clone()
-            };
-            // ignore txs that we can't include in next block
-            // enhancement: simulate all txs, store result, and use result when tx can included
-            if victim_tx.max_fee_per_gas.unwrap_or(U256::zero()) < block_oracle.next_block.base_fee
-            {
-                // log::info!("{}", format!("{:?} mf<nbf", victim_tx.hash).cyan());
-                continue;
+        loop {
+            tokio::select! {
+                victim_tx = mempool_stream.next() => {
+                    match victim_tx {
+                        Some(victim_tx) => {
+                            let victim_hash = victim_tx.hash;
+                            if let Err(e) = self.process_victim(victim_tx, false).await {
+                                log::error!("{:?} failed to process victim: {:?}", victim_hash, e);
+                            }
+                        }
+                        // mempool stream closed, let caller reconnect
+                        None => break,
+                    }
+                }
+                Some(deferred) = deferred_receiver.recv() => {
+                    log::info!(
+                        "{}",
+                        format!("{:?} deferred victim is now includable", deferred.tx.hash).cyan()
+                    );
+                    let victim_hash = deferred.tx.hash;
+                    if let Err(e) = self.process_victim(deferred.tx, true).await {
+                        log::error!("{:?} failed to process victim: {:?}", victim_hash, e);
+                    }
+                }
             }
+        }
+        Ok(())
+    }
+
+    // Search for sandwich opportunities created by a single victim tx
+    //
+    // Arguments:
+    // * `&self`: reference to self
+    // * `victim_tx`: mempool tx to sandwich
+    // * `is_deferred`: true if the victim was previously deferred (sender already recovered)
+    //
+    // Returns:
+    // Ok(()) if victim was processed (or skipped)
+    // Err(eyre::Error) if failed to create a new client
+    #[tracing::instrument(
+        name = "victim",
+        skip_all,
+        fields(
+            victim = ?victim_tx.hash,
+            target_block = tracing::field::Empty,
+            slot_time_remaining_ms = tracing::field::Empty
+        )
+    )]
+    async fn process_victim(&self, mut victim_tx: Transaction, is_deferred: bool) -> Result<()> {
+        let block_oracle = {
+            let read_lock = self.latest_block_oracle.read().await;
+            (*read_lock).clone()
+        };
+        let span = tracing::Span::current();
+        span.record("target_block", block_oracle.next_block.number.as_u64());
+        span.record(
+            "slot_time_remaining_ms",
+            block_oracle.next_block.time_remaining_ms(0),
+        );
+        let all_pools = &self.all_pools;
+        let sandwich_balance = {
+            let read_lock = self.sandwich_state.weth_balance.read().await;
+            (*read_lock).clone()
+        };
 
-            // recover from field from vrs (ECDSA)
-            // enhancement: expensive operation, can avoid by modding rpc to share `from` field
+        // recover from field from vrs (ECDSA)
+        // enhancement: expensive operation, can avoid by modding rpc to share `from` field
+        if !is_deferred {
+            metrics().mempool_txs_seen.inc();
             if let Ok(from) = victim_tx.recover_from() {
                 victim_tx.from = from;
             } else {
@@ -132,79 +260,226 @@ impl Bot {
                     "{}",
                     format!("{:?} ecdsa recovery failed", victim_tx.hash).red()
                 );
-                continue;
+                metrics().mempool_txs_filtered.inc("ecdsa_recovery_failed");
+                return Ok(());
             };
+        }
+
+        // swaps past their deadline revert in next block, no need to trace or simulate them
+        let victim_swap = utils::swap_decoder::decode_swap(&victim_tx);
+        if let Some(victim_swap) = &victim_swap {
+            if victim_swap.is_expired(block_oracle.next_block.timestamp) {
+                log::info!("{}", format!("{:?} [expired]", victim_tx.hash).yellow());
+                metrics().mempool_txs_filtered.inc("expired");
+                return Ok(());
+            }
+        }
+
+        // txs that we can't include in next block are stored until the base fee drops enough,
+        // they are traced once they become includable (state will have moved on by then)
+        if victim_tx.max_fee_per_gas.unwrap_or(U256::zero()) < block_oracle.next_block.base_fee {
+            metrics().mempool_txs_filtered.inc("max_fee_below_base_fee");
+            let victim_hash = victim_tx.hash;
+            let was_stored = self.pending_victims.write().await.insert(PendingVictim {
+                tx: victim_tx,
+                first_seen_block: block_oracle.latest_block.number,
+            });
+            if !was_stored {
+                log::info!(
+                    "{}",
+                    format!("{:?} mf<nbf, pending pool full", victim_hash).cyan()
+                );
+            }
+            return Ok(());
+        }
+
+        // get all state diffs that this tx produces
+        let state_diffs = utils::state_diff::get_from_txs(
+            &self.client,
+            &vec![victim_tx.clone()],
+            BlockNumber::Number(block_oracle.latest_block.number),
+        )
+        .await;
+
+        let state_diffs = if let Some(sd) = state_diffs {
+            sd
+        } else {
+            // log::info!("{:?}", victim_tx.hash);
+            metrics().mempool_txs_filtered.inc("trace_failed");
+            return Ok(());
+        };
+
+        // if tx has statediff on pool addr then record it in `sandwichable_pools`
+        let sandwichable_pools = if let Some(sp) = utils::state_diff::extract_pools(
+            &state_diffs,
+            &all_pools,
+            &self.pool_snapshots,
+            &self.token_registry,
+            block_oracle.latest_block.number,
+        ) {
+            sp
+        } else {
+            // log::info!("{:?}", victim_tx.hash);
+            metrics().mempool_txs_filtered.inc("no_sandwichable_pools");
+            return Ok(());
+        };
+        metrics().pools_matched.add(sandwichable_pools.len() as u64);
 
-            // get all state diffs that this tx produces
-            let state_diffs = if let Some(sd) = utils::state_diff::get_from_txs(
-                &self.client,
-                &vec![victim_tx.clone()],
-                BlockNumber::Number(block_oracle.latest_block.number),
-            )
+        let fork_block = Some(BlockId::Number(BlockNumber::Number(
+            block_oracle.next_block.number,
+        )));
+
+        // create evm simulation handler by setting up `fork_factory`, only victims that made it
+        // past the checks above get their own client
+        let client = utils::create_websocket_client().await?;
+        let initial_db = utils::state_diff::to_cache_db(&state_diffs, fork_block, &self.client)
             .await
-            {
-                sd
-            } else {
-                // log::info!("{:?}", victim_tx.hash);
+            .unwrap();
+        let fork_factory = ForkFactory::new_sandbox_factory(client.clone(), initial_db, fork_block);
+
+        // order touched pools into the victim's route so every leg of a multi hop swap is seen
+        let route = utils::state_diff::reconstruct_route(&sandwichable_pools, victim_swap.as_ref());
+        let (is_multi_hop, hop_count) = (route.is_multi_hop(), route.hops.len());
+
+        // bigger weth swaps leave more room for a sandwich, they are simulated first when busy
+        let estimated_value = victim_swap
+            .as_ref()
+            .and_then(|victim_swap| victim_swap.weth_in())
+            .unwrap_or(victim_tx.value);
+
+        // search for opportunities in all legs of the route (concurrently), meats are always
+        // simulated in full so the hops before and after a leg still execute in between our txs
+        for (hop_index, hop) in route.hops.into_iter().enumerate() {
+            // amounts of single hop exact in victims, their slippage can be checked without
+            // simulating the whole victim tx
+            let is_single_hop = |swap: &&DecodedSwap| swap.path == [hop.token_in, hop.token_out];
+            let exact_in_amounts = victim_swap.as_ref().filter(is_single_hop).and_then(
+                |victim_swap| match victim_swap.amounts {
+                    SwapAmounts::ExactIn {
+                        amount_in,
+                        amount_out_min,
+                    } => Some((amount_in, amount_out_min)),
+                    _ => None,
+                },
+            );
+
+            // some pools are tracked for detection but sandwich contract cannot swap on them yet,
+            // quote the victim's leg so that missed opportunities are still counted
+            if !hop.pool.pool_variant.is_executable() {
+                log::info!(
+                    "{:?} [{:?}, no executor]",
+                    victim_tx.hash,
+                    hop.pool.pool_variant
+                );
+
+                if let Some((amount_in, amount_out_min)) = exact_in_amounts {
+                    let victim_hash = victim_tx.hash;
+                    let mut evm = revm::EVM::new();
+                    evm.database(fork_factory.new_sandbox_fork());
+                    let quote_span = tracing::info_span!("quote", pool = ?hop.pool.address);
+
+                    // quotes are cheap and never lead to a bundle, they only run when idle
+                    self.simulation_executor
+                        .submit(U256::zero(), quote_span, async move {
+                            match get_amount_out_pool_evm(
+                                amount_in,
+                                &hop.pool,
+                                hop.token_in,
+                                hop.token_out,
+                                &mut evm,
+                            ) {
+                                Ok(amount_out) if amount_out > amount_out_min => {
+                                    metrics()
+                                        .unexecutable_opportunities
+                                        .inc(hop.pool.pool_variant.name());
+                                    log::info!(
+                                        "{:?} [{:?}, slippage room {:?}]",
+                                        victim_hash,
+                                        hop.pool.pool_variant,
+                                        amount_out - amount_out_min
+                                    );
+                                }
+                                Ok(_) => {}
+                                Err(e) => {
+                                    log::debug!("{:?} failed to quote leg: {:?}", victim_hash, e)
+                                }
+                            }
+                        });
+                }
                 continue;
-            };
+            }
 
-            // if tx has statediff on pool addr then record it in `sandwichable_pools`
-            let sandwichable_pools =
-                if let Some(sp) = utils::state_diff::extract_pools(&state_diffs, &all_pools) {
-                    sp
-                } else {
-                    // log::info!("{:?}", victim_tx.hash);
-                    continue;
-                };
-
-            let fork_block = Some(BlockId::Number(BlockNumber::Number(
-                block_oracle.next_block.number,
-            )));
-
-            // create evm simulation handler by setting up `fork_factory`
-            let initial_db = utils::state_diff::to_cache_db(&state_diffs, fork_block, &self.client)
-                .await
-                .unwrap();
-            let fork_factory =
-                ForkFactory::new_sandbox_factory(client.clone(), initial_db, fork_block);
-
-            // search for opportunities in all pools that the tx touches (concurrently)
-            for sandwichable_pool in sandwichable_pools {
-                if !sandwichable_pool.is_weth_input {
-                    // enhancement: increase opportunities by handling swaps in pools with stables
-                    log::info!("{:?} [weth_is_output]", victim_tx.hash);
+            if self.control.is_pool_blocked(&hop.pool.address) {
+                log::info!("{:?} [pool blocked]", victim_tx.hash);
+                continue;
+            }
+
+            if hop.token_in != utils::constants::get_weth_address() {
+                // legs paying out weth are frontrun by selling the victim's token, the sandwich
+                // contract only holds weth so they are left to the legs around them
+                log::info!("{:?} [weth_is_output]", victim_tx.hash);
+                continue;
+            } else if is_multi_hop {
+                log::info!(
+                    "{}",
+                    format!(
+                        "{:?} [weth_is_input, hop {}/{}]",
+                        victim_tx.hash,
+                        hop_index + 1,
+                        hop_count
+                    )
+                    .green()
+                );
+            } else {
+                log::info!(
+                    "{}",
+                    format!("{:?} [weth_is_input]", victim_tx.hash).green()
+                );
+            }
+
+            // single hop victims without slippage left revert on any frontrun, the pool's
+            // snapshot tells us so without simulating
+            if let Some((amount_in, amount_out_min)) = exact_in_amounts {
+                if !self.pool_snapshots.leaves_frontrun_room(
+                    &hop.pool,
+                    hop.token_in,
+                    amount_in,
+                    amount_out_min,
+                    block_oracle.latest_block.number,
+                ) {
+                    log::info!("{:?} [no slippage room]", victim_tx.hash);
+                    metrics().mempool_txs_filtered.inc("no_slippage_room");
                     continue;
-                } else {
-                    log::info!(
-                        "{}",
-                        format!("{:?} [weth_is_input]", victim_tx.hash).green()
-                    );
                 }
+            }
+
+            // prepare variables for new thread
+            let victim_tx = victim_tx.clone();
+            let mut fork_factory = fork_factory.clone();
+            let block_oracle = block_oracle.clone();
+            let sandwich_state = self.sandwich_state.clone();
+            let token_registry = self.token_registry.clone();
+            let sandwich_maker = self.sandwich_maker.clone();
+            let bundle_sender = self.bundle_sender.clone();
+            let control = self.control.clone();
+            let risk = self.risk.clone();
+            let state_diffs = state_diffs.clone();
 
-                // prepare variables for new thread
-                let victim_tx = victim_tx.clone();
-                let sandwichable_pool = sandwichable_pool.clone();
-                let mut fork_factory = fork_factory.clone();
-                let block_oracle = block_oracle.clone();
-                let sandwich_state = self.sandwich_state.clone();
-                let sandwich_maker = self.sandwich_maker.clone();
-                let bundle_sender = self.bundle_sender.clone();
-                let state_diffs = state_diffs.clone();
-
-                tokio::spawn(async move {
+            let search_span = tracing::info_span!(
+                "search",
+                pool = ?hop.pool.address,
+                hop = hop_index + 1
+            );
+            self.simulation_executor
+                .submit(estimated_value, search_span, async move {
                     // enhancement: increase opportunities by handling swaps in pools with stables
                     let input_token = utils::constants::get_weth_address();
                     let victim_hash = victim_tx.hash;
 
                     // variables used when searching for opportunity
-                    let raw_ingredients = if let Ok(data) = RawIngredients::new(
-                        &sandwichable_pool.pool,
-                        vec![victim_tx],
-                        input_token,
-                        state_diffs,
-                    )
-                    .await
+                    let raw_ingredients = if let Ok(data) =
+                        RawIngredients::new(&hop.pool, vec![victim_tx], input_token, state_diffs)
+                            .await
                     {
                         data
                     } else {
@@ -213,21 +488,54 @@ impl Bot {
                     };
 
                     // find optimal input to sandwich tx
-                    let mut optimal_sandwich = match make_sandwich::create_optimal_sandwich(
+                    let search_started = std::time::Instant::now();
+                    let optimal_sandwich = make_sandwich::create_optimal_sandwich(
                         &raw_ingredients,
                         sandwich_balance,
                         &block_oracle.next_block,
                         &mut fork_factory,
                         &sandwich_maker,
                     )
-                    .await
-                    {
+                    .await;
+                    metrics()
+                        .simulation_latency
+                        .observe(search_started.elapsed());
+
+                    let mut optimal_sandwich = match optimal_sandwich {
                         Ok(optimal) => optimal,
                         Err(e) => {
+                            metrics().simulation_failures.inc(e.kind());
                             log::info!(
                                 "{}",
                                 format!("{:?} sim failed due to {:?}", &victim_hash, e).yellow()
                             );
+
+                            // remember tokens that failed so that they are not simulated again
+                            let token = raw_ingredients.intermediary_token;
+                            let block = block_oracle.latest_block.number;
+                            match e {
+                                SimulationError::FrontrunNotSafu(opcodes) => {
+                                    let reason = BlacklistReason::FrontrunNotSafu;
+                                    if token_registry.record_failure(token, reason, block) {
+                                        utils::alert::alert_poison(token, opcodes).await;
+                                    }
+                                }
+                                SimulationError::BackrunNotSafu(opcodes) => {
+                                    let reason = BlacklistReason::BackrunNotSafu;
+                                    if token_registry.record_failure(token, reason, block) {
+                                        utils::alert::alert_poison(token, opcodes).await;
+                                    }
+                                }
+                                SimulationError::BackrunReverted(_) => {
+                                    let reason = BlacklistReason::SellReverted;
+                                    token_registry.record_failure(token, reason, block);
+                                }
+                                SimulationError::TokenNotSandwichable(_) => {
+                                    let reason = BlacklistReason::TransferTax;
+                                    token_registry.record_failure(token, reason, block);
+                                }
+                                _ => {}
+                            }
                             return;
                         }
                     };
@@ -252,12 +560,14 @@ impl Bot {
                     let sandwich_state = sandwich_state.clone();
 
                     if optimal_sandwich.revenue > U256::zero() {
-                        tokio::spawn(async move {
+                        let send_task = async move {
                             match bundle_sender::send_bundle(
                                 &optimal_sandwich,
                                 block_oracle.next_block,
                                 sandwich_maker,
                                 sandwich_state.clone(),
+                                control,
+                                risk,
                             )
                             .await
                             {
@@ -274,7 +584,8 @@ impl Bot {
                                     );
                                 }
                             };
-                        });
+                        };
+                        tokio::spawn(send_task.in_current_span());
                     }
 
                     // spawn thread to add tx for mega sandwich calculation
@@ -287,7 +598,6 @@ impl Bot {
                             .await;
                     });
                 });
-            }
         }
         Ok(())
     }
@@ -1,24 +1,40 @@
 use colored::Colorize;
 use dashmap::DashMap;
 use ethers::prelude::*;
+use hashbrown::HashSet;
 use std::sync::Arc;
-use std::thread;
-use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::RwLock;
 
-use crate::prelude::{sync_dex, BlockInfo, Dex, Pool};
-use crate::types::BlockOracle;
+use crate::prelude::{
+    block_state_cache, pool_id_from_log, snapshot_event_signatures, sync_dex, Dex, Pool,
+    PoolIdentifier, PoolSnapshots,
+};
+use crate::types::{time_until, BlockOracle};
 use crate::utils;
+use crate::utils::metrics::metrics;
 use crate::utils::tx_builder::SandwichMaker;
 
 use super::bundle_sender::BundleSender;
+use super::control::ControlState;
+use super::pending_victims::{PendingVictim, PendingVictimPool};
+use super::risk::RiskManager;
+use super::shadow::shadow_recorder;
+use super::simulation_executor::SimulationExecutor;
 use super::state::BotState;
 
-// Update latest block variable whenever we recieve a new block
+// Update latest block variable whenever we recieve a new block, release deferred victims that
+// became includable and move the shared fork state cache onto the next block
 //
 // Arguments:
 // * `oracle`: oracle to update
-pub fn start_block_oracle(oracle: &mut Arc<RwLock<BlockOracle>>) {
+// * `pending_victims`: victims waiting for the base fee to drop
+// * `victim_sender`: channel used to hand includable victims back to the runner
+pub fn start_block_oracle(
+    oracle: &mut Arc<RwLock<BlockOracle>>,
+    pending_victims: Arc<RwLock<PendingVictimPool>>,
+    victim_sender: UnboundedSender<PendingVictim>,
+) {
     let next_block_clone = oracle.clone();
 
     tokio::spawn(async move {
@@ -32,12 +48,52 @@ pub fn start_block_oracle(oracle: &mut Arc<RwLock<BlockOracle>>) {
                 panic!("Failed to create new block stream");
             };
 
-            while let Some(block) = block_stream.next().await {
+            loop {
+                // once the build deadline of the slot after the next block's passes without a
+                // block, the next block's slot was missed and bundles go to the following slot
+                let missed_slot_deadline = {
+                    let read_lock = next_block_clone.read().await;
+                    read_lock.slot_clock.has_fixed_slots().then(|| {
+                        read_lock.next_block.timestamp.as_u64() + read_lock.slot_clock.slot_duration
+                    })
+                };
+                let missed_slot_wait = missed_slot_deadline
+                    .map(|deadline| time_until(deadline, slot_build_deadline_ms()));
+                let block = tokio::select! {
+                    block = block_stream.next() => match block {
+                        Some(block) => block,
+                        None => break,
+                    },
+                    // blocks without fixed slots can take as long as they take
+                    _ = tokio::time::sleep(missed_slot_wait.unwrap_or_default()),
+                        if missed_slot_wait.is_some() =>
+                    {
+                        let mut lock = next_block_clone.write().await;
+                        if lock.update_missed_slot(slot_build_deadline_ms()) {
+                            metrics().missed_slots.inc();
+                            log::warn!(
+                                "Slot missed, next block {:?} expected at {:?}",
+                                lock.next_block.number,
+                                lock.next_block.timestamp
+                            );
+                        }
+                        continue;
+                    }
+                };
+
+                let now = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs();
+                let lag = now as i64 - block.timestamp.low_u64() as i64;
+                metrics().block_oracle_lag_seconds.set(lag);
+
                 // lock the RwLock for write access and update the variable
                 {
                     let mut lock = next_block_clone.write().await;
                     lock.update_block_number(block.number.unwrap());
                     lock.update_block_timestamp(block.timestamp);
+                    lock.update_block_env(&block);
                     lock.update_base_fee(block);
 
                     let latest_block = &lock.latest_block;
@@ -52,12 +108,58 @@ pub fn start_block_oracle(oracle: &mut Arc<RwLock<BlockOracle>>) {
                     .on_black()
                     );
                 } // remove write lock due to being out of scope here
+
+                // re-evaluate deferred victims that can now be included
+                let (latest_block, next_block) = {
+                    let read_lock = next_block_clone.read().await;
+                    (read_lock.latest_block.clone(), read_lock.next_block.clone())
+                };
+                let includable = pending_victims
+                    .write()
+                    .await
+                    .drain_includable(&latest_block, &next_block);
+
+                for victim in includable {
+                    if victim_sender.send(victim).is_err() {
+                        // runner has stopped, nobody is left to sandwich these victims
+                        break;
+                    }
+                }
+
+                // replay the mined block's state changes so forks of next block reuse cached state,
+                // without holding up the oracle (forks miss the cache until it has moved on)
+                let replay_client = client.clone();
+                let (mined_block, fork_block) = (latest_block.number, next_block.number);
+                tokio::spawn(async move {
+                    let block_traces = match replay_client
+                        .trace_replay_block_transactions(
+                            BlockNumber::Number(mined_block),
+                            vec![TraceType::StateDiff],
+                        )
+                        .await
+                    {
+                        Ok(block_traces) => Some(block_traces),
+                        Err(e) => {
+                            metrics().block_state_replay_failures.inc();
+                            log::error!(
+                                "Failed to replay block {:?}, dropping cached state: {:?}",
+                                mined_block,
+                                e
+                            );
+                            None
+                        }
+                    };
+                    block_state_cache().advance(fork_block, block_traces.as_deref());
+                });
+
+                // dry run bundles are replayed on the mined block without holding up the oracle
+                tokio::spawn(shadow_recorder().evaluate_block(client.clone(), latest_block.number));
             }
         }
     });
 }
 
-pub fn start_add_new_pools(all_pools: &mut Arc<DashMap<Address, Pool>>, dexes: Vec<Dex>) {
+pub fn start_add_new_pools(all_pools: &mut Arc<DashMap<PoolIdentifier, Pool>>, dexes: Vec<Dex>) {
     let all_pools = all_pools.clone();
 
     tokio::spawn(async move {
@@ -93,7 +195,7 @@ pub fn start_add_new_pools(all_pools: &mut Arc<DashMap<Address, Pool>>, dexes: V
                     // turn fetched pools into hashmap
                     for pool in fetched_new_pools {
                         // Create hashmap from our vec
-                        all_pools.insert(pool.address, pool);
+                        all_pools.insert(pool.id(), pool);
                     }
 
                     counter = 0;
@@ -105,12 +207,166 @@ pub fn start_add_new_pools(all_pools: &mut Arc<DashMap<Address, Pool>>, dexes: V
     });
 }
 
+// Pool addresses per log query when snapshot logs have to be fetched per pool
+const SNAPSHOT_LOGS_ADDRESS_CHUNK: usize = 500;
+
+// Keep pool snapshots up to date from `Sync`/`Swap` logs of every new block
+//
+// Arguments:
+// * `all_pools`: pools to keep snapshots for, logs of other contracts are ignored
+// * `pool_snapshots`: snapshot store to update
+pub fn start_pool_snapshot_oracle(
+    all_pools: Arc<DashMap<PoolIdentifier, Pool>>,
+    pool_snapshots: Arc<PoolSnapshots>,
+) {
+    tokio::spawn(async move {
+        let mut is_backfilled = false;
+
+        // loop so we can reconnect if the websocket connection is lost
+        loop {
+            let client = utils::create_websocket_client().await.unwrap();
+
+            let mut block_stream = if let Ok(stream) = client.subscribe_blocks().await {
+                stream
+            } else {
+                panic!("Failed to create new block stream");
+            };
+
+            // rebuild snapshots of recently active pools so that filtering works from the start
+            if !is_backfilled {
+                let current_block = client.get_block_number().await.unwrap().as_u64();
+                let backfill_blocks = utils::dotenv::get_pool_snapshot_backfill_blocks();
+
+                // providers cap how many logs a query may return, window is halved whenever a
+                // query is rejected and single blocks fall back on querying per pool address
+                let mut window = 100;
+                let mut from_block = current_block.saturating_sub(backfill_blocks);
+                while from_block <= current_block {
+                    let to_block = (from_block + window - 1).min(current_block);
+                    let filter = Filter::new()
+                        .topic0(ValueOrArray::Array(snapshot_event_signatures()))
+                        .from_block(from_block)
+                        .to_block(to_block);
+                    let logs = match client.get_logs(&filter).await {
+                        Ok(logs) => Ok(logs),
+                        Err(_) if window > 1 => {
+                            window /= 2;
+                            continue;
+                        }
+                        Err(_) => get_logs_by_pool_address(&client, &all_pools, &filter).await,
+                    };
+                    match logs {
+                        Ok(logs) => apply_snapshot_logs(&all_pools, &pool_snapshots, logs),
+                        Err(e) => log::error!(
+                            "Failed to backfill pool snapshots of block {}: {:?}",
+                            from_block,
+                            e
+                        ),
+                    }
+                    from_block = to_block + 1;
+                }
+
+                is_backfilled = true;
+                log::info!("Backfilled {} pool snapshots", pool_snapshots.len());
+            }
+
+            while let Some(block) = block_stream.next().await {
+                let block_hash = match block.hash {
+                    Some(hash) => hash,
+                    None => continue,
+                };
+
+                let filter = Filter::new()
+                    .topic0(ValueOrArray::Array(snapshot_event_signatures()))
+                    .at_block_hash(block_hash);
+                let logs = match client.get_logs(&filter).await {
+                    Ok(logs) => Ok(logs),
+                    Err(_) => get_logs_by_pool_address(&client, &all_pools, &filter).await,
+                };
+                match logs {
+                    Ok(logs) => apply_snapshot_logs(&all_pools, &pool_snapshots, logs),
+                    Err(e) => log::error!("Failed to fetch snapshot logs: {:?}", e),
+                }
+            }
+        }
+    });
+}
+
+// Run a log query once per chunk of tracked pool addresses, used when a provider rejects the
+// unfiltered query for returning too many logs
+//
+// Arguments:
+// * `client`: websocket client to query
+// * `all_pools`: tracked pools, singleton pools are queried through their singleton
+// * `filter`: query to narrow down
+//
+// Returns:
+// Ok(Vec<Log>): logs of all chunks ordered as emitted
+// Err(ProviderError): if any chunk fails
+async fn get_logs_by_pool_address(
+    client: &Arc<Provider<Ws>>,
+    all_pools: &DashMap<PoolIdentifier, Pool>,
+    filter: &Filter,
+) -> Result<Vec<Log>, ProviderError> {
+    let addresses = all_pools
+        .iter()
+        .map(|entry| match *entry.key() {
+            PoolIdentifier::Address(address) => address,
+            PoolIdentifier::Singleton { singleton, .. } => singleton,
+        })
+        .collect::<HashSet<Address>>()
+        .into_iter()
+        .collect::<Vec<Address>>();
+
+    let mut logs = vec![];
+    for chunk in addresses.chunks(SNAPSHOT_LOGS_ADDRESS_CHUNK) {
+        let chunk_filter = filter.clone().address(chunk.to_vec());
+        logs.extend(client.get_logs(&chunk_filter).await?);
+    }
+
+    logs.sort_by_key(|log| (log.block_number, log.log_index));
+    Ok(logs)
+}
+
+// Apply logs (in order) to the snapshots of the pools that emitted them
+fn apply_snapshot_logs(
+    all_pools: &DashMap<PoolIdentifier, Pool>,
+    pool_snapshots: &PoolSnapshots,
+    logs: Vec<Log>,
+) {
+    for log in logs {
+        let pool = match pool_id_from_log(&log).and_then(|id| all_pools.get(&id)) {
+            Some(pool) => *pool.value(),
+            None => continue,
+        };
+        pool_snapshots.update_from_log(&pool, &log);
+    }
+}
+
+// Build a mega sandwich out of the recipes found for the next block, `MEGA_SANDWICH_DEADLINE_MS`
+// relative to the start of its slot. If the slot is missed, recipes are kept and the mega
+// sandwich is rebuilt for the slot the block oracle moves the next block to
+//
+// Arguments:
+// * `oracle`: block oracle, provides the next block
+// * `bundle_sender`: holds the recipes to combine
+// * `sandwich_state`: shared bot state
+// * `sandwich_maker`: builds and signs sandwich txs
+// * `control`: submission controls
+// * `simulation_executor`: runs the per pool searches of the mega sandwich
 pub fn start_mega_sandwich_oracle(
+    oracle: Arc<RwLock<BlockOracle>>,
     bundle_sender: Arc<RwLock<BundleSender>>,
     sandwich_state: Arc<BotState>,
     sandwich_maker: Arc<SandwichMaker>,
+    control: Arc<ControlState>,
+    simulation_executor: Arc<SimulationExecutor>,
 ) {
     tokio::spawn(async move {
+        let deadline_ms = utils::dotenv::get_mega_sandwich_deadline_ms();
+        // how often to check whether the block oracle moved the next block
+        let poll_interval = std::time::Duration::from_millis(100);
+
         // loop so we can reconnect if the websocket connection is lost
         loop {
             let client = utils::create_websocket_client().await.unwrap();
@@ -121,28 +377,125 @@ pub fn start_mega_sandwich_oracle(
                 panic!("Failed to create new block stream");
             };
 
+            let mut latest_block_number = oracle.read().await.latest_block.number;
+            // number and timestamp of the last next block a mega sandwich was built for
+            let mut built_for: Option<(U64, U256)> = None;
+
+            loop {
+                let next_block = oracle.read().await.next_block.clone();
+                // oracle may not have caught up with a block we already saw
+                let is_pending = next_block.number > latest_block_number
+                    && built_for != Some((next_block.number, next_block.timestamp));
+                let wait = match is_pending {
+                    true => time_until(next_block.timestamp.as_u64(), deadline_ms),
+                    false => poll_interval,
+                };
+
+                tokio::select! {
+                    block = block_stream.next() => {
+                        let block = match block {
+                            Some(block) => block,
+                            None => break,
+                        };
+                        latest_block_number = block.number.unwrap_or(latest_block_number);
+
+                        // clear all recipes
+                        // enchanement: don't do this step but keep recipes because they can be
+                        // used in future
+                        {
+                            let mut bundle_sender_guard = bundle_sender.write().await;
+                            bundle_sender_guard.pending_sandwiches.clear();
+                        } // lock removed here
+                    }
+                    _ = tokio::time::sleep(wait) => {
+                        if !is_pending {
+                            continue;
+                        }
+
+                        bundle_sender
+                            .write()
+                            .await
+                            .make_mega_sandwich(
+                                next_block.clone(),
+                                sandwich_state.clone(),
+                                sandwich_maker.clone(),
+                                control.clone(),
+                                &simulation_executor,
+                            )
+                            .await;
+                        // lock removed here
+
+                        // same block number is built again if the oracle moves it to a later slot
+                        built_for = Some((next_block.number, next_block.timestamp));
+                    }
+                }
+            }
+        }
+    });
+}
+
+// Deadline relative to the start of a slot after which bundles can no longer be built for it, the
+// earlier of `MEGA_SANDWICH_DEADLINE_MS` and `SUBMISSION_DEADLINE_MS`
+fn slot_build_deadline_ms() -> i64 {
+    let build_deadline_ms = utils::dotenv::get_mega_sandwich_deadline_ms();
+    match utils::dotenv::get_submission_deadline_ms() {
+        Some(submission_deadline_ms) => build_deadline_ms.min(submission_deadline_ms),
+        None => build_deadline_ms,
+    }
+}
+
+// Track realised profit and loss by reading the bot's balances at every new block, halts
+// submission once today's loss goes above `MAX_DAILY_LOSS_ETH`
+//
+// Balance is the sandwich contract's weth plus the searcher's eth (gas), so withdrawals from
+// either show up as losses
+//
+// Arguments:
+// * `risk`: risk manager to report balances to
+// * `control`: paused when losses halt submission
+pub fn start_risk_oracle(risk: Arc<RiskManager>, control: Arc<ControlState>) {
+    tokio::spawn(async move {
+        let sandwich_contract = utils::dotenv::get_sandwich_contract_address();
+        let searcher = utils::signer::searcher_signer().address();
+
+        // loop so we can reconnect if the websocket connection is lost
+        loop {
+            let client = utils::create_websocket_client().await.unwrap();
+            let weth = utils::constants::get_weth_address();
+            let weth_contract = utils::contracts::get_erc20_contract(&weth, &client);
+
+            let mut block_stream = if let Ok(stream) = client.subscribe_blocks().await {
+                stream
+            } else {
+                panic!("Failed to create new block stream");
+            };
+
             while let Some(block) = block_stream.next().await {
-                // clear all recipes
-                // enchanement: don't do this step but keep recipes because they can be used in future
-                {
-                    let mut bundle_sender_guard = bundle_sender.write().await;
-                    bundle_sender_guard.pending_sandwiches.clear();
-                } // lock removed here
+                let block_number = block.number.unwrap_or_default();
+                let block_id = BlockId::Number(BlockNumber::Number(block_number));
 
-                // 10.5 seconds from when new block was detected, caluclate mega sandwich
-                thread::sleep(Duration::from_millis(10_500));
-                let next_block_info = BlockInfo::find_next_block_info(block);
-                {
-                    bundle_sender
-                        .write()
-                        .await
-                        .make_mega_sandwich(
-                            next_block_info,
-                            sandwich_state.clone(),
-                            sandwich_maker.clone(),
-                        )
-                        .await;
-                } // lock removed here
+                let weth_balance = weth_contract
+                    .balance_of(sandwich_contract)
+                    .block(block_id)
+                    .call()
+                    .await;
+                let searcher_balance = client.get_balance(searcher, Some(block_id)).await;
+
+                let balance = match (weth_balance, searcher_balance) {
+                    (Ok(weth_balance), Ok(searcher_balance)) => weth_balance + searcher_balance,
+                    _ => {
+                        log::error!(
+                            "Failed to read balances for risk checks at {}",
+                            block_number
+                        );
+                        continue;
+                    }
+                };
+
+                let halted = risk.record_balance(balance, &control);
+                if let Some(reason) = halted {
+                    utils::alert::alert_halt(&reason).await;
+                }
             }
         }
     });
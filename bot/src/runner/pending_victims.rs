@@ -0,0 +1,182 @@
+use ethers::prelude::*;
+use hashbrown::HashMap;
+
+use crate::types::BlockInfo;
+
+/// Victim that could not be included in the next block when it was first seen
+#[derive(Debug, Clone)]
+pub struct PendingVictim {
+    pub tx: Transaction,
+    /// block number that was latest when the victim was deferred
+    pub first_seen_block: U64,
+}
+
+/// Holds victims whose `max_fee_per_gas` is below the next block's base fee so that they
+/// can be re-evaluated once the base fee drops enough for them to become includable
+#[derive(Debug)]
+pub struct PendingVictimPool {
+    victims: HashMap<TxHash, PendingVictim>,
+    // number of blocks a victim is kept around before being dropped
+    ttl_blocks: u64,
+    // max number of victims held at once
+    max_victims: usize,
+}
+
+impl PendingVictimPool {
+    // Create a new `PendingVictimPool` instance
+    //
+    // Arguments:
+    // * `ttl_blocks`: number of blocks a victim is kept for before being dropped
+    // * `max_victims`: max number of victims held at once
+    //
+    // Returns:
+    // `PendingVictimPool`: empty pool
+    pub fn new(ttl_blocks: u64, max_victims: usize) -> Self {
+        Self {
+            victims: HashMap::new(),
+            ttl_blocks,
+            max_victims,
+        }
+    }
+
+    // Defer a victim until it can be included
+    //
+    // Arguments:
+    // * `&mut self`: mutable reference to `PendingVictimPool` instance
+    // * `victim`: victim to store
+    //
+    // Returns:
+    // bool: true if the victim was stored, false if the pool is full of better paying victims
+    pub fn insert(&mut self, victim: PendingVictim) -> bool {
+        if self.victims.contains_key(&victim.tx.hash) {
+            return true;
+        }
+
+        if self.victims.len() >= self.max_victims {
+            // evict the victim that is furthest away from being includable
+            let lowest = self
+                .victims
+                .values()
+                .min_by_key(|v| v.tx.max_fee_per_gas.unwrap_or_default())
+                .map(|v| (v.tx.hash, v.tx.max_fee_per_gas.unwrap_or_default()));
+
+            match lowest {
+                Some((hash, max_fee))
+                    if max_fee < victim.tx.max_fee_per_gas.unwrap_or_default() =>
+                {
+                    self.victims.remove(&hash);
+                }
+                _ => return false,
+            }
+        }
+
+        self.victims.insert(victim.tx.hash, victim);
+        true
+    }
+
+    // Remove expired victims and return all victims that can be included in `next_block`
+    //
+    // Arguments:
+    // * `&mut self`: mutable reference to `PendingVictimPool` instance
+    // * `latest_block`: block that was just mined
+    // * `next_block`: block that victims are checked against
+    //
+    // Returns:
+    // Vec<PendingVictim>: victims whose max fee now covers the next base fee
+    pub fn drain_includable(
+        &mut self,
+        latest_block: &BlockInfo,
+        next_block: &BlockInfo,
+    ) -> Vec<PendingVictim> {
+        let ttl_blocks = self.ttl_blocks;
+        self.victims.retain(|_, v| {
+            latest_block.number.as_u64() <= v.first_seen_block.as_u64() + ttl_blocks
+        });
+
+        let includable_hashes: Vec<TxHash> = self
+            .victims
+            .values()
+            .filter(|v| v.tx.max_fee_per_gas.unwrap_or_default() >= next_block.base_fee)
+            .map(|v| v.tx.hash)
+            .collect();
+
+        includable_hashes
+            .iter()
+            .filter_map(|hash| self.victims.remove(hash))
+            .collect()
+    }
+
+    // Number of victims currently waiting
+    pub fn len(&self) -> usize {
+        self.victims.len()
+    }
+
+    // Is there no victim waiting
+    pub fn is_empty(&self) -> bool {
+        self.victims.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn victim(hash: u8, max_fee: u64, first_seen_block: u64) -> PendingVictim {
+        PendingVictim {
+            tx: Transaction {
+                hash: TxHash::repeat_byte(hash),
+                max_fee_per_gas: Some(max_fee.into()),
+                ..Default::default()
+            },
+            first_seen_block: first_seen_block.into(),
+        }
+    }
+
+    fn block(number: u64, base_fee: u64) -> BlockInfo {
+        BlockInfo::new(number.into(), U256::zero(), base_fee.into())
+    }
+
+    #[test]
+    fn victims_are_released_once_includable() {
+        let mut pool = PendingVictimPool::new(10, 10);
+        assert!(pool.insert(victim(1, 100, 1)));
+        assert!(pool.insert(victim(2, 50, 1)));
+
+        assert!(pool
+            .drain_includable(&block(1, 0), &block(2, 120))
+            .is_empty());
+
+        let includable = pool.drain_includable(&block(2, 0), &block(3, 80));
+        assert_eq!(includable.len(), 1);
+        assert_eq!(includable[0].tx.hash, TxHash::repeat_byte(1));
+        assert_eq!(pool.len(), 1);
+    }
+
+    #[test]
+    fn expired_victims_are_dropped() {
+        let mut pool = PendingVictimPool::new(2, 10);
+        pool.insert(victim(1, 100, 1));
+
+        // still held at the last block of its ttl
+        assert!(pool
+            .drain_includable(&block(3, 0), &block(4, 200))
+            .is_empty());
+        assert_eq!(pool.len(), 1);
+
+        // dropped after, even though it became includable
+        assert!(pool.drain_includable(&block(4, 0), &block(5, 1)).is_empty());
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn full_pool_evicts_lowest_max_fee() {
+        let mut pool = PendingVictimPool::new(10, 1);
+        assert!(pool.insert(victim(1, 50, 1)));
+        assert!(!pool.insert(victim(2, 40, 1)));
+        assert!(pool.insert(victim(3, 60, 1)));
+
+        let includable = pool.drain_includable(&block(1, 0), &block(2, 0));
+        assert_eq!(includable.len(), 1);
+        assert_eq!(includable[0].tx.hash, TxHash::repeat_byte(3));
+    }
+}
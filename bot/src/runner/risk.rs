@@ -0,0 +1,366 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Mutex,
+};
+
+use chrono::NaiveDate;
+use ethers::prelude::*;
+use hashbrown::HashMap;
+use serde::Serialize;
+
+use crate::types::RiskViolation;
+use crate::utils::{self, metrics::metrics};
+
+use super::control::ControlState;
+
+/// Guards applied to every bundle before it is signed (None = no limit)
+#[derive(Clone, Debug, Default)]
+pub struct RiskLimits {
+    /// max weth put into a single frontrun
+    pub max_frontrun: Option<U256>,
+    /// max weth put into a single frontrun buying a specific token, overrides `max_frontrun`
+    pub max_frontrun_per_token: HashMap<Address, U256>,
+    /// max gas paid by included bundles per (utc) day
+    pub max_daily_gas: Option<U256>,
+    /// bundles in a row that can miss inclusion before submission is halted
+    pub max_consecutive_misses: Option<u64>,
+    /// min profit after gas and bribe
+    pub min_profit: U256,
+    /// max drop in balance per (utc) day before submission is halted
+    pub max_daily_loss: Option<U256>,
+}
+
+impl RiskLimits {
+    // Read limits from `MAX_FRONTRUN_WETH`, `MAX_FRONTRUN_PER_TOKEN`, `MAX_DAILY_GAS_ETH`,
+    // `MAX_CONSECUTIVE_MISSES`, `MIN_PROFIT_ETH` and `MAX_DAILY_LOSS_ETH`
+    pub fn from_env() -> Self {
+        Self {
+            max_frontrun: utils::dotenv::get_ether("MAX_FRONTRUN_WETH"),
+            max_frontrun_per_token: utils::dotenv::get_max_frontrun_per_token()
+                .into_iter()
+                .collect(),
+            max_daily_gas: utils::dotenv::get_ether("MAX_DAILY_GAS_ETH"),
+            max_consecutive_misses: utils::dotenv::get_max_consecutive_misses(),
+            min_profit: utils::dotenv::get_ether("MIN_PROFIT_ETH").unwrap_or_default(),
+            max_daily_loss: utils::dotenv::get_ether("MAX_DAILY_LOSS_ETH"),
+        }
+    }
+}
+
+/// Counters that reset at the start of every (utc) day
+#[derive(Debug)]
+struct DailyRisk {
+    day: NaiveDate,
+    gas_spent: U256,
+    // sandwich weth + searcher eth at first block seen today
+    opening_balance: Option<U256>,
+    latest_balance: Option<U256>,
+    // halt for losses only once per day so that operators can resume after checking
+    loss_halted: bool,
+}
+
+impl DailyRisk {
+    fn new(day: NaiveDate) -> Self {
+        Self {
+            day,
+            gas_spent: U256::zero(),
+            opening_balance: None,
+            latest_balance: None,
+            loss_halted: false,
+        }
+    }
+
+    fn rollover(&mut self, today: NaiveDate) {
+        if self.day != today {
+            *self = Self::new(today);
+        }
+    }
+
+    fn realised_loss(&self) -> U256 {
+        match (self.opening_balance, self.latest_balance) {
+            (Some(opening), Some(latest)) => opening.saturating_sub(latest),
+            _ => U256::zero(),
+        }
+    }
+
+    fn realised_gain(&self) -> U256 {
+        match (self.opening_balance, self.latest_balance) {
+            (Some(opening), Some(latest)) => latest.saturating_sub(opening),
+            _ => U256::zero(),
+        }
+    }
+}
+
+/// Snapshot of risk counters, returned by the admin api
+#[derive(Debug, Serialize)]
+pub struct RiskStatus {
+    pub daily_gas_spent: U256,
+    pub realised_gain: U256,
+    pub realised_loss: U256,
+    pub consecutive_misses: u64,
+}
+
+/// Enforces `RiskLimits` and halts submission when losses or misses pile up
+#[derive(Debug)]
+pub struct RiskManager {
+    limits: RiskLimits,
+    daily: Mutex<DailyRisk>,
+    consecutive_misses: AtomicU64,
+}
+
+impl RiskManager {
+    pub fn new(limits: RiskLimits) -> Self {
+        Self {
+            limits,
+            daily: Mutex::new(DailyRisk::new(today())),
+            consecutive_misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(RiskLimits::from_env())
+    }
+
+    // Check a bundle against the limits before it is signed
+    //
+    // Arguments:
+    // * `&self`: reference to `RiskManager` instance
+    // * `token`: token bought by the frontrun
+    // * `frontrun_in`: weth put into the frontrun
+    // * `gas_cost`: gas paid if bundle is included
+    // * `profit`: profit after gas and bribe
+    //
+    // Returns:
+    // Ok(()) if bundle is within all limits
+    // Err(RiskViolation) with the first limit that was exceeded
+    pub fn check(
+        &self,
+        token: Address,
+        frontrun_in: U256,
+        gas_cost: U256,
+        profit: U256,
+    ) -> Result<(), RiskViolation> {
+        let result = self.check_limits(token, frontrun_in, gas_cost, profit);
+        if let Err(violation) = &result {
+            metrics().risk_rejections.inc(violation.kind());
+        }
+        result
+    }
+
+    fn check_limits(
+        &self,
+        token: Address,
+        frontrun_in: U256,
+        gas_cost: U256,
+        profit: U256,
+    ) -> Result<(), RiskViolation> {
+        match self.limits.max_frontrun_per_token.get(&token) {
+            Some(max) if frontrun_in > *max => {
+                return Err(RiskViolation::TokenFrontrunTooLarge(frontrun_in, token))
+            }
+            Some(_) => {}
+            None => {
+                if let Some(max) = self.limits.max_frontrun {
+                    if frontrun_in > max {
+                        return Err(RiskViolation::FrontrunTooLarge(frontrun_in));
+                    }
+                }
+            }
+        }
+
+        if profit < self.limits.min_profit {
+            return Err(RiskViolation::ProfitTooLow(profit));
+        }
+
+        if let Some(max) = self.limits.max_daily_gas {
+            let mut daily = self.daily.lock().unwrap();
+            daily.rollover(today());
+            if daily.gas_spent.saturating_add(gas_cost) > max {
+                return Err(RiskViolation::DailyGasLimitReached());
+            }
+        }
+
+        Ok(())
+    }
+
+    // Record whether a sent bundle landed, halts after too many misses in a row
+    //
+    // Arguments:
+    // * `&self`: reference to `RiskManager` instance
+    // * `is_included`: true if bundle was included
+    // * `gas_cost`: gas paid by bundle (only counted if included)
+    // * `control`: paused when submission is halted
+    //
+    // Returns:
+    // Option<String>: reason submission was halted (to alert on), None if it was not
+    pub fn record_bundle_result(
+        &self,
+        is_included: bool,
+        gas_cost: U256,
+        control: &ControlState,
+    ) -> Option<String> {
+        if is_included {
+            self.consecutive_misses.store(0, Ordering::Relaxed);
+            let mut daily = self.daily.lock().unwrap();
+            daily.rollover(today());
+            daily.gas_spent += gas_cost;
+            metrics()
+                .daily_gas_spent_gwei
+                .set(gwei(daily.gas_spent) as i64);
+            return None;
+        }
+
+        let misses = self.consecutive_misses.fetch_add(1, Ordering::Relaxed) + 1;
+        match self.limits.max_consecutive_misses {
+            Some(max) if misses >= max => {
+                // start counting again so that a resumed bot gets a full allowance
+                self.consecutive_misses.store(0, Ordering::Relaxed);
+                let reason = format!("{} bundles in a row were not included", misses);
+                Some(halt(reason, control))
+            }
+            _ => None,
+        }
+    }
+
+    // Record balances seen at a new block, halts if today's loss is above the limit
+    //
+    // Arguments:
+    // * `&self`: reference to `RiskManager` instance
+    // * `balance`: weth held by sandwich contract plus eth held by searcher
+    // * `control`: paused when submission is halted
+    //
+    // Returns:
+    // Option<String>: reason submission was halted (to alert on), None if it was not
+    pub fn record_balance(&self, balance: U256, control: &ControlState) -> Option<String> {
+        let loss = {
+            let mut daily = self.daily.lock().unwrap();
+            daily.rollover(today());
+            daily.opening_balance.get_or_insert(balance);
+            daily.latest_balance = Some(balance);
+
+            let (gain, loss) = (daily.realised_gain(), daily.realised_loss());
+            metrics()
+                .realised_pnl_gwei
+                .set(gwei(gain) as i64 - gwei(loss) as i64);
+
+            match self.limits.max_daily_loss {
+                Some(max) if loss > max && !daily.loss_halted => {
+                    daily.loss_halted = true;
+                    Some(loss)
+                }
+                _ => None,
+            }
+        };
+
+        loss.map(|loss| {
+            let reason = format!(
+                "realised loss of {} eth today",
+                ethers::utils::format_ether(loss)
+            );
+            halt(reason, control)
+        })
+    }
+
+    pub fn status(&self) -> RiskStatus {
+        let mut daily = self.daily.lock().unwrap();
+        daily.rollover(today());
+        RiskStatus {
+            daily_gas_spent: daily.gas_spent,
+            realised_gain: daily.realised_gain(),
+            realised_loss: daily.realised_loss(),
+            consecutive_misses: self.consecutive_misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// Pause bundle submission, alerting is left to the caller
+fn halt(reason: String, control: &ControlState) -> String {
+    control.set_paused(true);
+    metrics().risk_halts.inc();
+    log::error!("Bundle submission halted: {}", reason);
+    reason
+}
+
+fn today() -> NaiveDate {
+    chrono::Utc::now().date_naive()
+}
+
+fn gwei(wei: U256) -> u64 {
+    (wei / U256::exp10(9)).low_u64()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn limits() -> RiskLimits {
+        let token = Address::repeat_byte(0xaa);
+        RiskLimits {
+            max_frontrun: Some(U256::exp10(18)),
+            max_frontrun_per_token: [(token, U256::exp10(17))].into_iter().collect(),
+            max_daily_gas: Some(U256::exp10(16)),
+            min_profit: U256::from(1000),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn bundles_over_limits_are_rejected() {
+        let risk = RiskManager::new(limits());
+        let (capped, other) = (Address::repeat_byte(0xaa), Address::repeat_byte(0xbb));
+        let (gas, profit) = (U256::exp10(15), U256::from(1000));
+
+        assert_eq!(risk.check(other, U256::exp10(18), gas, profit), Ok(()));
+        assert_eq!(
+            risk.check(other, U256::exp10(18) + 1, gas, profit),
+            Err(RiskViolation::FrontrunTooLarge(U256::exp10(18) + 1))
+        );
+        assert_eq!(
+            risk.check(capped, U256::exp10(18), gas, profit),
+            Err(RiskViolation::TokenFrontrunTooLarge(
+                U256::exp10(18),
+                capped
+            ))
+        );
+        assert_eq!(
+            risk.check(other, U256::one(), gas, U256::from(999)),
+            Err(RiskViolation::ProfitTooLow(U256::from(999)))
+        );
+        assert_eq!(
+            risk.check(other, U256::one(), U256::exp10(16) + 1, profit),
+            Err(RiskViolation::DailyGasLimitReached())
+        );
+    }
+
+    #[test]
+    fn misses_and_losses_halt_submission() {
+        let risk = RiskManager::new(RiskLimits {
+            max_consecutive_misses: Some(2),
+            max_daily_loss: Some(U256::from(100)),
+            ..Default::default()
+        });
+        let control = ControlState::new(false);
+
+        risk.record_bundle_result(false, U256::zero(), &control);
+        risk.record_bundle_result(true, U256::from(10), &control);
+        risk.record_bundle_result(false, U256::zero(), &control);
+        assert!(!control.is_paused());
+
+        risk.record_balance(U256::from(1000), &control);
+        risk.record_balance(U256::from(900), &control);
+        assert!(!control.is_paused());
+        assert_eq!(risk.status().daily_gas_spent, U256::from(10));
+        assert_eq!(risk.status().realised_loss, U256::from(100));
+
+        assert!(risk.record_balance(U256::from(899), &control).is_some());
+        assert!(control.is_paused());
+
+        // loss only halts once a day, second miss in a row halts again
+        control.set_paused(false);
+        assert!(risk.record_balance(U256::from(800), &control).is_none());
+        assert!(risk
+            .record_bundle_result(false, U256::zero(), &control)
+            .is_some());
+        assert!(control.is_paused());
+    }
+}
@@ -0,0 +1,347 @@
+use std::sync::{Arc, OnceLock};
+
+use colored::Colorize;
+use dashmap::DashMap;
+use ethers::{prelude::*, utils::rlp};
+use revm::{
+    db::{CacheDB, EmptyDB},
+    EVM,
+};
+
+use crate::prelude::{fork_factory::ForkFactory, BlockInfo, Pool, SimulationError};
+use crate::simulate::helpers::{get_balance_of_evm, setup_block_state, setup_tx_state};
+use crate::utils::{self, metrics::metrics};
+
+/// Bundle that was built and signed in dry run mode instead of being sent
+#[derive(Debug, Clone)]
+pub struct ShadowBundle {
+    pub target_block: U64,
+    pub target_pool: Pool,
+    /// victim txs the bundle sandwiches, in bundle order
+    pub meats: Vec<TxHash>,
+    /// signed frontrun, meats and backrun that would have been sent
+    pub transactions: Vec<Bytes>,
+    /// expected profit after gas and bribe
+    pub profit: U256,
+}
+
+/// What would have happened to a shadow bundle in the block it targeted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShadowOutcome {
+    /// bundle replayed on the mined txs ahead of its victims without reverting
+    Landed {
+        /// profit after gas and bribe measured by the replay (zero if bundle lost money)
+        profit: U256,
+    },
+    /// frontrun or backrun reverted or was invalid on the mined txs ahead of the victims
+    Reverted,
+    /// at least one victim was not included in the target block
+    VictimMissing,
+    /// mined state could not be replayed (rpc or evm error), nothing is known about the bundle
+    ReplayFailed,
+}
+
+impl ShadowOutcome {
+    fn label(&self) -> &'static str {
+        match self {
+            ShadowOutcome::Landed { .. } => "landed",
+            ShadowOutcome::Reverted => "reverted",
+            ShadowOutcome::VictimMissing => "victim_missing",
+            ShadowOutcome::ReplayFailed => "replay_failed",
+        }
+    }
+}
+
+/// Keeps dry run bundles until the block they targeted is mined
+#[derive(Debug, Default)]
+pub struct ShadowRecorder {
+    bundles: DashMap<U64, Vec<ShadowBundle>>,
+}
+
+// Recorder shared by the bundle sender and block oracle
+pub fn shadow_recorder() -> &'static ShadowRecorder {
+    static RECORDER: OnceLock<ShadowRecorder> = OnceLock::new();
+    RECORDER.get_or_init(ShadowRecorder::default)
+}
+
+impl ShadowRecorder {
+    // Remember a bundle that would have been sent
+    pub fn record(&self, bundle: ShadowBundle) {
+        self.bundles
+            .entry(bundle.target_block)
+            .or_default()
+            .push(bundle);
+    }
+
+    // Replay every bundle that targeted a mined block and report what would have happened
+    //
+    // Arguments:
+    // * `&self`: reference to `ShadowRecorder` instance
+    // * `client`: websocket client used to fetch the mined block and fork its parent
+    // * `block_number`: block that was just mined
+    //
+    // Returns: This function returns nothing
+    pub async fn evaluate_block(&self, client: Arc<Provider<Ws>>, block_number: U64) {
+        // bundles for blocks that were skipped (missed oracle updates) can never be evaluated
+        self.bundles
+            .retain(|target_block, _| *target_block >= block_number);
+
+        let bundles = match self.bundles.remove(&block_number) {
+            Some((_, bundles)) => bundles,
+            None => return,
+        };
+
+        let block = match client.get_block_with_txs(block_number).await {
+            Ok(Some(block)) => block,
+            _ => {
+                log::warn!(
+                    "Failed to fetch block {}, {} shadow bundles not evaluated",
+                    block_number,
+                    bundles.len()
+                );
+                return;
+            }
+        };
+
+        // forks block on rpc calls, keep them off the async workers
+        let evaluated = tokio::task::spawn_blocking(move || {
+            bundles
+                .into_iter()
+                .map(|bundle| {
+                    let outcome = evaluate_shadow_bundle(&client, &bundle, &block);
+                    (bundle, outcome)
+                })
+                .collect::<Vec<_>>()
+        })
+        .await;
+        let evaluated = match evaluated {
+            Ok(evaluated) => evaluated,
+            Err(e) => {
+                log::error!(
+                    "Shadow bundle replay for block {} panicked: {:?}",
+                    block_number,
+                    e
+                );
+                return;
+            }
+        };
+
+        for (bundle, outcome) in evaluated {
+            metrics().shadow_bundles.inc(outcome.label());
+
+            let replayed_profit = match outcome {
+                ShadowOutcome::Landed { profit } => profit,
+                _ => U256::zero(),
+            };
+            let is_profitable = !replayed_profit.is_zero();
+            if is_profitable {
+                metrics()
+                    .shadow_profit_gwei
+                    .add((replayed_profit / U256::exp10(9)).low_u64());
+            }
+
+            log::info!(
+                "{}",
+                format!(
+                    "{:?} [shadow] block {} pool {:?}: {:?}, profitable: {}, expected profit {:?}",
+                    bundle.meats,
+                    block_number,
+                    bundle.target_pool.address,
+                    outcome,
+                    is_profitable,
+                    bundle.profit
+                )
+                .cyan()
+            );
+            log::debug!(
+                "{:?} [shadow] bundle {:?}",
+                bundle.meats,
+                bundle.transactions
+            );
+        }
+    }
+}
+
+// Replay a shadow bundle in the block it targeted, right before its first victim
+//
+// Arguments:
+// * `client`: websocket client used to fork the target block's parent
+// * `bundle`: bundle that would have been sent
+// * `block`: mined target block with its txs
+//
+// Returns:
+// ShadowOutcome: whether bundle would have landed and what it would have made
+pub fn evaluate_shadow_bundle(
+    client: &Arc<Provider<Ws>>,
+    bundle: &ShadowBundle,
+    block: &Block<Transaction>,
+) -> ShadowOutcome {
+    let block_txs: Vec<TxHash> = block.transactions.iter().map(|tx| tx.hash).collect();
+    let first_victim = match first_victim_index(bundle, &block_txs) {
+        Some(first_victim) => first_victim,
+        None => return ShadowOutcome::VictimMissing,
+    };
+
+    match replay_shadow_bundle(client, bundle, block, first_victim) {
+        Ok(Some(profit)) => ShadowOutcome::Landed { profit },
+        Ok(None) => ShadowOutcome::Reverted,
+        Err(e) => {
+            log::warn!(
+                "{:?} [shadow] failed to replay bundle: {:?}",
+                bundle.meats,
+                e
+            );
+            ShadowOutcome::ReplayFailed
+        }
+    }
+}
+
+// Find where a bundle would have been placed in the mined block
+//
+// Arguments:
+// * `bundle`: bundle that would have been sent
+// * `block_txs`: hashes of the target block's txs, in block order
+//
+// Returns:
+// Option<usize>: index of the earliest victim, None if any victim was not mined in the block
+fn first_victim_index(bundle: &ShadowBundle, block_txs: &[TxHash]) -> Option<usize> {
+    let positions: Option<Vec<usize>> = bundle
+        .meats
+        .iter()
+        .map(|meat| block_txs.iter().position(|tx| tx == meat))
+        .collect();
+    positions?.into_iter().min()
+}
+
+// Replay the mined txs ahead of the first victim on the parent block, then the bundle on top
+//
+// Arguments:
+// * `client`: websocket client used to fork the target block's parent
+// * `bundle`: bundle that would have been sent
+// * `block`: mined target block with its txs
+// * `first_victim`: index of the bundle's first victim in `block`
+//
+// Returns:
+// Ok(Some(U256)): profit after gas and bribe (zero if bundle lost money)
+// Ok(None): frontrun or backrun reverted or could not be included on the mined state
+// Err(SimulationError): if the mined txs or the sandwich contract's balance could not be replayed
+fn replay_shadow_bundle(
+    client: &Arc<Provider<Ws>>,
+    bundle: &ShadowBundle,
+    block: &Block<Transaction>,
+    first_victim: usize,
+) -> Result<Option<U256>, SimulationError> {
+    let parent_block = block.number.unwrap_or_default() - 1;
+    let fork_block = Some(BlockId::Number(BlockNumber::Number(parent_block)));
+    let fork_factory = ForkFactory::new_sandbox_factory(
+        client.clone(),
+        CacheDB::new(EmptyDB::default()),
+        fork_block,
+    );
+
+    let mut evm = EVM::new();
+    evm.database(fork_factory.new_sandbox_fork());
+    let block_info = BlockInfo::from_block(block);
+    setup_block_state(&mut evm, &block_info);
+
+    for tx in &block.transactions[..first_victim] {
+        setup_tx_state(&mut evm, tx);
+        evm.transact_commit().map_err(SimulationError::EvmError)?;
+    }
+
+    let weth = utils::constants::get_weth_address();
+    let sandwich_contract = utils::dotenv::get_sandwich_contract_address();
+    let start_balance = get_balance_of_evm(weth, sandwich_contract, &block_info, &mut evm)?;
+
+    let mut gas_cost = U256::zero();
+    for raw_tx in &bundle.transactions {
+        let tx: Transaction = match rlp::decode(raw_tx) {
+            Ok(tx) => tx,
+            Err(_) => return Ok(None),
+        };
+
+        setup_tx_state(&mut evm, &tx);
+        let result = match evm.transact_commit() {
+            Ok(result) => result,
+            // e.g. max fee below the mined base fee, builders would have dropped the bundle
+            Err(_) => return Ok(None),
+        };
+
+        // victims pay for themselves and are allowed to revert
+        if !bundle.meats.contains(&tx.hash) {
+            if !result.is_success() {
+                return Ok(None);
+            }
+            gas_cost +=
+                U256::from(result.gas_used()) * effective_gas_price(&tx, block_info.base_fee);
+        }
+    }
+
+    let end_balance = get_balance_of_evm(weth, sandwich_contract, &block_info, &mut evm)?;
+    let revenue = end_balance.checked_sub(start_balance).unwrap_or_default();
+    Ok(Some(revenue.checked_sub(gas_cost).unwrap_or_default()))
+}
+
+// Gas price a tx pays per unit of gas in a block with `base_fee`
+fn effective_gas_price(tx: &Transaction, base_fee: U256) -> U256 {
+    match (tx.max_fee_per_gas, tx.max_priority_fee_per_gas) {
+        (Some(max_fee), Some(priority_fee)) => max_fee.min(base_fee + priority_fee),
+        _ => tx.gas_price.unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::prelude::PoolVariant;
+
+    fn bundle(meats: Vec<TxHash>) -> ShadowBundle {
+        ShadowBundle {
+            target_block: 1.into(),
+            target_pool: Pool::new(
+                Address::repeat_byte(0xaa),
+                Address::repeat_byte(1),
+                Address::repeat_byte(2),
+                U256::from(3000),
+                PoolVariant::UniswapV2,
+            ),
+            meats,
+            transactions: vec![],
+            profit: U256::one(),
+        }
+    }
+
+    #[test]
+    fn bundle_is_placed_before_earliest_victim() {
+        let (victim_a, victim_b, other) = (
+            TxHash::repeat_byte(1),
+            TxHash::repeat_byte(2),
+            TxHash::repeat_byte(3),
+        );
+        let bundle = bundle(vec![victim_a, victim_b]);
+
+        let block_txs = [other, victim_b, victim_a];
+        assert_eq!(first_victim_index(&bundle, &block_txs), Some(1));
+
+        let block_txs = [other, victim_a];
+        assert_eq!(first_victim_index(&bundle, &block_txs), None);
+    }
+
+    #[test]
+    fn gas_price_is_capped_by_max_fee() {
+        let gwei = U256::exp10(9);
+        let tx = Transaction {
+            max_fee_per_gas: Some(gwei * 30),
+            max_priority_fee_per_gas: Some(gwei * 2),
+            ..Default::default()
+        };
+        assert_eq!(effective_gas_price(&tx, gwei * 10), gwei * 12);
+        assert_eq!(effective_gas_price(&tx, gwei * 29), gwei * 30);
+
+        let legacy = Transaction {
+            gas_price: Some(gwei * 5),
+            ..Default::default()
+        };
+        assert_eq!(effective_gas_price(&legacy, gwei * 10), gwei * 5);
+    }
+}
@@ -0,0 +1,359 @@
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering as AtomicOrdering},
+        Arc, Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+use ethers::prelude::*;
+use eyre::Result;
+use tokio::{runtime::Handle, sync::Notify, task::JoinHandle};
+use tracing::{Instrument, Span};
+
+use crate::utils;
+
+type SimulationTask = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+// Runtime simulation workers run on, set by the first executor that is started
+static WORKER_RUNTIME: OnceLock<Handle> = OnceLock::new();
+
+// Simulation waiting for a free worker
+struct QueuedSimulation {
+    estimated_value: U256,
+    // increases with every submission, lower means older
+    sequence: u64,
+    enqueued_at: Instant,
+    task: SimulationTask,
+}
+
+// Highest estimated value is popped first, older simulations win ties
+impl Ord for QueuedSimulation {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.estimated_value
+            .cmp(&other.estimated_value)
+            .then_with(|| other.sequence.cmp(&self.sequence))
+    }
+}
+
+impl PartialOrd for QueuedSimulation {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for QueuedSimulation {
+    fn eq(&self, other: &Self) -> bool {
+        self.sequence == other.sequence
+    }
+}
+
+impl Eq for QueuedSimulation {}
+
+/// Queue depth and latency counters of the simulation executor
+#[derive(Debug, Default)]
+pub struct ExecutorMetrics {
+    /// simulations waiting for a worker
+    pub queue_depth: AtomicUsize,
+    pub submitted: AtomicU64,
+    /// simulations dropped because the queue was full
+    pub dropped: AtomicU64,
+    pub completed: AtomicU64,
+    /// total time completed simulations waited in queue (microseconds)
+    pub queue_latency_us: AtomicU64,
+    /// total time completed simulations took to run (microseconds)
+    pub run_latency_us: AtomicU64,
+}
+
+impl ExecutorMetrics {
+    // Average time a simulation waits in queue before a worker picks it up
+    pub fn avg_queue_latency(&self) -> Duration {
+        self.average(&self.queue_latency_us)
+    }
+
+    // Average time a simulation takes once picked up
+    pub fn avg_run_latency(&self) -> Duration {
+        self.average(&self.run_latency_us)
+    }
+
+    fn average(&self, total_us: &AtomicU64) -> Duration {
+        let completed = self.completed.load(AtomicOrdering::Relaxed);
+        match completed {
+            0 => Duration::ZERO,
+            n => Duration::from_micros(total_us.load(AtomicOrdering::Relaxed) / n),
+        }
+    }
+}
+
+/// Runs sandwich simulations on a fixed number of workers fed by a bounded priority queue
+///
+/// Workers live on their own runtime so that bursts of mempool traffic (and the blocking calls
+/// made by `ForkDB`) cannot starve the runtime that streams the mempool and sends bundles
+pub struct SimulationExecutor {
+    queue: Arc<Mutex<BinaryHeap<QueuedSimulation>>>,
+    notify: Arc<Notify>,
+    capacity: usize,
+    sequence: AtomicU64,
+    metrics: Arc<ExecutorMetrics>,
+}
+
+impl SimulationExecutor {
+    // Create a new `SimulationExecutor` and start its workers
+    //
+    // Arguments:
+    // * `workers`: number of simulations run at once (and threads of the worker runtime)
+    // * `capacity`: max number of simulations waiting in queue
+    //
+    // Returns:
+    // Ok(SimulationExecutor): executor with an empty queue
+    // Err(eyre::Error): if there would be no workers or no room in queue
+    pub fn new(workers: usize, capacity: usize) -> Result<Self> {
+        // a runtime without workers never runs anything and an empty queue drops everything
+        if workers == 0 {
+            eyre::bail!("SIMULATION_WORKERS must be at least 1");
+        }
+        if capacity == 0 {
+            eyre::bail!("SIMULATION_QUEUE_CAPACITY must be at least 1");
+        }
+
+        let queue = Arc::new(Mutex::new(BinaryHeap::new()));
+        let notify = Arc::new(Notify::new());
+        let metrics = Arc::new(ExecutorMetrics::default());
+
+        let (worker_queue, worker_notify, worker_metrics) =
+            (queue.clone(), notify.clone(), metrics.clone());
+
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(workers)
+            .thread_name("simulation-worker")
+            .enable_all()
+            .build()?;
+        let _ = WORKER_RUNTIME.set(rt.handle().clone());
+
+        // runtime is owned by its own thread so it is never dropped from inside async context
+        std::thread::Builder::new()
+            .name("simulation-executor".to_string())
+            .spawn(move || {
+                rt.block_on(async move {
+                    tokio::spawn(log_metrics(worker_metrics.clone()));
+                    let handles: Vec<_> = (0..workers)
+                        .map(|_| {
+                            tokio::spawn(run_worker(
+                                worker_queue.clone(),
+                                worker_notify.clone(),
+                                worker_metrics.clone(),
+                            ))
+                        })
+                        .collect();
+                    futures::future::join_all(handles).await;
+                });
+            })?;
+
+        Ok(Self {
+            queue,
+            notify,
+            capacity,
+            sequence: AtomicU64::new(0),
+            metrics,
+        })
+    }
+
+    // Create executor using `SIMULATION_WORKERS` and `SIMULATION_QUEUE_CAPACITY`
+    pub fn from_env() -> Result<Self> {
+        Self::new(
+            utils::dotenv::get_simulation_workers(),
+            utils::dotenv::get_simulation_queue_capacity(),
+        )
+    }
+
+    // Queue a simulation, dropping the oldest queued simulation if the queue is full
+    //
+    // Arguments:
+    // * `&self`: reference to `SimulationExecutor` instance
+    // * `estimated_value`: rough value of opportunity, higher values are simulated first
+    // * `span`: span the simulation runs in (keeps victim context when a worker picks it up)
+    // * `task`: simulation to run
+    pub fn submit<F>(&self, estimated_value: U256, span: Span, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let simulation = QueuedSimulation {
+            estimated_value,
+            sequence: self.sequence.fetch_add(1, AtomicOrdering::Relaxed),
+            enqueued_at: Instant::now(),
+            task: Box::pin(task.instrument(span)),
+        };
+
+        {
+            let mut queue = self.queue.lock().unwrap();
+            if push_bounded(&mut queue, simulation, self.capacity) {
+                self.metrics.dropped.fetch_add(1, AtomicOrdering::Relaxed);
+            }
+            self.metrics
+                .queue_depth
+                .store(queue.len(), AtomicOrdering::Relaxed);
+        }
+
+        self.metrics.submitted.fetch_add(1, AtomicOrdering::Relaxed);
+        self.notify.notify_one();
+    }
+
+    // Get counters of executor
+    pub fn metrics(&self) -> Arc<ExecutorMetrics> {
+        self.metrics.clone()
+    }
+}
+
+// Spawn part of a running simulation (e.g. one interval of the optimal search) on the worker
+// runtime, parts skip the queue because the simulation they belong to already holds a worker
+//
+// Falls back to the current runtime if no executor was started (tests and backtests)
+pub fn spawn_on_workers<F>(task: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    match WORKER_RUNTIME.get() {
+        Some(handle) => handle.spawn(task),
+        None => tokio::spawn(task),
+    }
+}
+
+// Push a simulation into queue, dropping the oldest queued simulation if the queue is full
+//
+// Returns:
+// bool: true if a simulation was dropped to make room
+fn push_bounded(
+    queue: &mut BinaryHeap<QueuedSimulation>,
+    simulation: QueuedSimulation,
+    capacity: usize,
+) -> bool {
+    let mut dropped = false;
+    if queue.len() >= capacity {
+        // oldest simulation is the most likely to be stale by the time a worker is free
+        let mut simulations = std::mem::take(queue).into_vec();
+        let oldest = simulations
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, s)| s.sequence)
+            .map(|(index, _)| index);
+        if let Some(oldest) = oldest {
+            simulations.swap_remove(oldest);
+            dropped = true;
+        }
+        *queue = BinaryHeap::from(simulations);
+    }
+
+    queue.push(simulation);
+    dropped
+}
+
+// Pull simulations from queue (highest estimated value first) and run them one at a time
+async fn run_worker(
+    queue: Arc<Mutex<BinaryHeap<QueuedSimulation>>>,
+    notify: Arc<Notify>,
+    metrics: Arc<ExecutorMetrics>,
+) {
+    loop {
+        let simulation = {
+            let mut queue = queue.lock().unwrap();
+            let simulation = queue.pop();
+            metrics
+                .queue_depth
+                .store(queue.len(), AtomicOrdering::Relaxed);
+            simulation
+        };
+
+        let simulation = match simulation {
+            Some(simulation) => simulation,
+            None => {
+                notify.notified().await;
+                continue;
+            }
+        };
+
+        let queue_latency = simulation.enqueued_at.elapsed();
+        let started_at = Instant::now();
+
+        // run as its own task so that a panicking simulation does not take the worker down
+        if let Err(e) = tokio::spawn(simulation.task).await {
+            log::error!("Simulation task failed: {:?}", e);
+        }
+
+        metrics
+            .queue_latency_us
+            .fetch_add(queue_latency.as_micros() as u64, AtomicOrdering::Relaxed);
+        metrics.run_latency_us.fetch_add(
+            started_at.elapsed().as_micros() as u64,
+            AtomicOrdering::Relaxed,
+        );
+        metrics.completed.fetch_add(1, AtomicOrdering::Relaxed);
+    }
+}
+
+// Log executor counters every slot
+async fn log_metrics(metrics: Arc<ExecutorMetrics>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(12));
+    loop {
+        interval.tick().await;
+        log::info!(
+            "Simulation executor: depth {}, submitted {}, dropped {}, completed {}, avg wait {:?}, avg run {:?}",
+            metrics.queue_depth.load(AtomicOrdering::Relaxed),
+            metrics.submitted.load(AtomicOrdering::Relaxed),
+            metrics.dropped.load(AtomicOrdering::Relaxed),
+            metrics.completed.load(AtomicOrdering::Relaxed),
+            metrics.avg_queue_latency(),
+            metrics.avg_run_latency(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn simulation(estimated_value: u64, sequence: u64) -> QueuedSimulation {
+        QueuedSimulation {
+            estimated_value: U256::from(estimated_value),
+            sequence,
+            enqueued_at: Instant::now(),
+            task: Box::pin(async {}),
+        }
+    }
+
+    fn pop_sequences(queue: &mut BinaryHeap<QueuedSimulation>) -> Vec<u64> {
+        std::iter::from_fn(|| queue.pop().map(|s| s.sequence)).collect()
+    }
+
+    #[test]
+    fn highest_value_is_simulated_first_and_older_wins_ties() {
+        let mut queue = BinaryHeap::new();
+        push_bounded(&mut queue, simulation(1, 0), 8);
+        push_bounded(&mut queue, simulation(5, 1), 8);
+        push_bounded(&mut queue, simulation(5, 2), 8);
+        push_bounded(&mut queue, simulation(3, 3), 8);
+
+        assert_eq!(pop_sequences(&mut queue), vec![1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn full_queue_drops_oldest_simulation() {
+        let mut queue = BinaryHeap::new();
+        assert!(!push_bounded(&mut queue, simulation(9, 0), 2));
+        assert!(!push_bounded(&mut queue, simulation(1, 1), 2));
+        // oldest goes even though it is worth the most
+        assert!(push_bounded(&mut queue, simulation(2, 2), 2));
+
+        assert_eq!(pop_sequences(&mut queue), vec![2, 1]);
+    }
+
+    #[test]
+    fn executor_needs_workers_and_capacity() {
+        assert!(SimulationExecutor::new(0, 8).is_err());
+        assert!(SimulationExecutor::new(1, 0).is_err());
+    }
+}
@@ -1,15 +1,21 @@
 use crate::prelude::fork_db::ForkDB;
 use crate::prelude::fork_factory::ForkFactory;
+use crate::prelude::{
+    get_amount_out_solidly, ExecutableVariant, Pool, PoolExtra, PoolVariant, StableSwap,
+    WeightedProduct, A_PRECISION,
+};
 use crate::types::{BlockInfo, SimulationError};
-use crate::utils::dotenv::{get_sandwich_contract_address, get_searcher_wallet};
+use crate::utils::chain::chain_config;
+use crate::utils::dotenv::get_sandwich_contract_address;
+use crate::utils::signer::searcher_signer;
 use crate::utils::{self, constants};
 use ethers::abi::{self, parse_abi, ParamType};
 use ethers::prelude::BaseContract;
 use ethers::signers::Signer;
 use ethers::types::transaction::eip2930::{AccessList, AccessListItem};
-use ethers::types::{Address, BigEndianHash, Bytes, H256, U256};
+use ethers::types::{Address, BigEndianHash, Bytes, Transaction, H256, U256};
 use ethers::utils::parse_ether;
-use revm::primitives::{ExecutionResult, Output, TransactTo};
+use revm::primitives::{CreateScheme, EVMError, ExecutionResult, Output, TransactTo};
 use revm::{
     primitives::{Address as rAddress, Bytecode, U256 as rU256},
     EVM,
@@ -54,7 +60,7 @@ pub fn attach_braindance_module(fork_factory: &mut ForkFactory) {
 // Returns: This function returns nothing
 pub fn inject_sando(fork_factory: &mut ForkFactory, starting_weth_balance: U256) {
     // give searcher some balance to pay for gas fees
-    let searcher = get_searcher_wallet().address();
+    let searcher = searcher_signer().address();
     let gas_money = parse_ether(100).unwrap();
     let account = revm::primitives::AccountInfo::new(gas_money.into(), 0, Bytecode::default());
     fork_factory.insert_account_info(searcher.0.into(), account);
@@ -109,27 +115,74 @@ fn inject_braindance_code(fork_factory: &mut ForkFactory) {
 // Setup evm blockstate
 //
 // Arguments:
-// * `&mut evm`: mutable refernece to `EVM<ForkDB>` instance which we want to modify
+// * `&mut evm`: mutable refernece to `EVM` instance which we want to modify
 // * `&next_block`: reference to `BlockInfo` of next block to set values against
 //
 // Returns: This function returns nothing
-pub fn setup_block_state(evm: &mut EVM<ForkDB>, next_block: &BlockInfo) {
+pub fn setup_block_state<DB>(evm: &mut EVM<DB>, next_block: &BlockInfo) {
+    // victims are signed for the configured chain
+    evm.env.cfg.chain_id = chain_config().chain_id.into();
     evm.env.block.number = rU256::from(next_block.number.as_u64());
     evm.env.block.timestamp = next_block.timestamp.into();
     evm.env.block.basefee = next_block.base_fee.into();
-    // use something other than default
-    evm.env.block.coinbase =
-        rAddress::from_str("0xDecafC0FFEe15BAD000000000000000000000000").unwrap();
+    if !next_block.gas_limit.is_zero() {
+        evm.env.block.gas_limit = next_block.gas_limit.into();
+    }
+    evm.env.block.prevrandao = Some(next_block.prevrandao.0.into());
+    evm.env.block.coinbase = match next_block.coinbase.is_zero() {
+        true => rAddress::from_str("0xDecafC0FFEe15BAD000000000000000000000000").unwrap(),
+        false => next_block.coinbase.0.into(),
+    };
+    if let Some(excess_blob_gas) = next_block.excess_blob_gas {
+        evm.env.block.set_blob_excess_gas_and_price(excess_blob_gas);
+    }
+}
+
+// Setup evm tx env to replay a signed tx
+//
+// Arguments:
+// * `&mut evm`: mutable reference to `EVM<ForkDB>` instance which we want to modify
+// * `tx`: reference to tx to replay
+//
+// Returns: This function returns nothing
+pub fn setup_tx_state(evm: &mut EVM<ForkDB>, tx: &Transaction) {
+    evm.env.tx.caller = rAddress::from_slice(&tx.from.0);
+    evm.env.tx.transact_to = match tx.to {
+        Some(to) => TransactTo::Call(to.0.into()),
+        None => TransactTo::Create(CreateScheme::Create),
+    };
+    evm.env.tx.data = tx.input.0.clone();
+    evm.env.tx.value = tx.value.into();
+    evm.env.tx.chain_id = tx.chain_id.map(|id| id.as_u64());
+    evm.env.tx.nonce = Some(tx.nonce.as_u64());
+    evm.env.tx.gas_limit = tx.gas.as_u64();
+    evm.env.tx.access_list = tx
+        .access_list
+        .as_ref()
+        .map(convert_access_list_to_revm)
+        .unwrap_or_default();
+    match tx.max_fee_per_gas {
+        // type 2 tx
+        Some(max_fee) => {
+            evm.env.tx.gas_priority_fee = tx.max_priority_fee_per_gas.map(|mpf| mpf.into());
+            evm.env.tx.gas_price = max_fee.into();
+        }
+        // legacy tx
+        None => {
+            evm.env.tx.gas_priority_fee = None;
+            evm.env.tx.gas_price = tx.gas_price.unwrap_or_default().into();
+        }
+    }
 }
 
 // Find amount out from an amount in using the k=xy formula
-// note: assuming fee is set to 3% for all pools (not case irl)
 //
 // Arguments:
 // * `amount_in`: amount of token in
 // * `target_pool`: address of pool
 // * `token_in`: address of token in
 // * `token_out`: address of token out
+// * `swap_fee`: fee taken by pool (1e6 units, 3000 = 0.3%)
 // * `evm`: mutable reference to evm used for query
 //
 // Returns:
@@ -140,9 +193,118 @@ pub fn get_amount_out_evm(
     target_pool: Address,
     token_in: Address,
     token_out: Address,
+    swap_fee: U256,
     evm: &mut EVM<ForkDB>,
 ) -> Result<U256, SimulationError> {
-    // get reserves
+    let (reserves_0, reserves_1) = get_reserves_evm(target_pool, evm)?;
+
+    let (reserve_in, reserve_out) = match token_in < token_out {
+        true => (reserves_0, reserves_1),
+        false => (reserves_1, reserves_0),
+    };
+
+    Ok(get_amount_out(amount_in, reserve_in, reserve_out, swap_fee))
+}
+
+// Find amount out from an amount in using the k=xy formula
+//
+// Arguments:
+// * `amount_in`: amount of token in
+// * `reserve_in`: pool reserves of token in
+// * `reserve_out`: pool reserves of token out
+// * `swap_fee`: fee taken by pool (1e6 units, 3000 = 0.3%)
+//
+// Returns:
+// U256: amount out, zero if pool has no liquidity
+pub fn get_amount_out(
+    amount_in: U256,
+    reserve_in: U256,
+    reserve_out: U256,
+    swap_fee: U256,
+) -> U256 {
+    let fee_base = U256::from(1000000);
+    let a_in_with_fee: U256 = amount_in * (fee_base - swap_fee);
+    let numerator: U256 = a_in_with_fee * reserve_out;
+    let denominator: U256 = reserve_in * fee_base + a_in_with_fee;
+    numerator.checked_div(denominator).unwrap_or(U256::zero())
+}
+
+// Find the largest frontrun that still lets a victim's v2 swap receive its min amount out
+//
+// Arguments:
+// * `victim_amount_in`: amount victim sells
+// * `victim_amount_out_min`: min amount victim accepts before reverting
+// * `reserve_in`: pool reserves of token in (before frontrun)
+// * `reserve_out`: pool reserves of token out (before frontrun)
+// * `swap_fee`: fee taken by pool (1e6 units, 3000 = 0.3%)
+// * `upper_bound`: largest frontrun to consider
+//
+// Returns:
+// U256: largest frontrun in `[0, upper_bound]`, zero if victim reverts even without a frontrun
+pub fn max_frontrun_for_slippage(
+    victim_amount_in: U256,
+    victim_amount_out_min: U256,
+    reserve_in: U256,
+    reserve_out: U256,
+    swap_fee: U256,
+    upper_bound: U256,
+) -> U256 {
+    let victim_amount_out = |frontrun_in: U256| {
+        let frontrun_out = get_amount_out(frontrun_in, reserve_in, reserve_out, swap_fee);
+        get_amount_out(
+            victim_amount_in,
+            reserve_in + frontrun_in,
+            reserve_out - frontrun_out,
+            swap_fee,
+        )
+    };
+
+    if victim_amount_out(U256::zero()) < victim_amount_out_min {
+        return U256::zero();
+    }
+    if victim_amount_out(upper_bound) >= victim_amount_out_min {
+        return upper_bound;
+    }
+
+    // victim's amount out only shrinks as frontrun grows, binary search the crossing point
+    let (mut low, mut high) = (U256::zero(), upper_bound);
+    while high - low > U256::one() {
+        let mid = (low + high) / 2;
+        match victim_amount_out(mid) >= victim_amount_out_min {
+            true => low = mid,
+            false => high = mid,
+        }
+    }
+    low
+}
+
+// Narrow a pool's variant down to one the sandwich contract can swap on
+//
+// Arguments:
+// * `pool`: pool to check
+//
+// Returns:
+// Ok(ExecutableVariant): variant the sandwich contract can swap on
+// Err(SimulationError): if pool is only tracked for victim detection
+pub fn executable_variant(pool: &Pool) -> Result<ExecutableVariant, SimulationError> {
+    pool.pool_variant
+        .executable()
+        .ok_or(SimulationError::UnsupportedPoolVariant(pool.pool_variant))
+}
+
+// Get reserves of a UniswapV2 style pool
+//
+// Arguments:
+// * `target_pool`: address of pool
+// * `evm`: mutable reference to evm used for query
+//
+// Returns:
+// Ok((U256, U256)): reserves of token_0 and token_1
+// Err(SimulationError): if error during query
+pub fn get_reserves_evm(
+    target_pool: Address,
+    evm: &mut EVM<ForkDB>,
+) -> Result<(U256, U256), SimulationError> {
     evm.env.tx.transact_to = TransactTo::Call(target_pool.0.into());
     evm.env.tx.caller = utils::constants::get_eth_dev().0.into();
     evm.env.tx.value = rU256::ZERO;
@@ -173,17 +335,418 @@ pub fn get_amount_out_evm(
     let reserves_0 = tokens[0].clone().into_uint().unwrap();
     let reserves_1 = tokens[1].clone().into_uint().unwrap();
 
-    let (reserve_in, reserve_out) = match token_in < token_out {
+    Ok((reserves_0, reserves_1))
+}
+
+// Quote a swap on any tracked pool using its state on the fork
+//
+// Arguments:
+// * `amount_in`: amount of token in
+// * `pool`: pool to swap on
+// * `token_in`: address of token in
+// * `token_out`: address of token out
+// * `evm`: mutable reference to evm used for query
+//
+// Returns:
+// Ok(U256): amount out
+// Err(SimulationError): if pool variant cannot be quoted or error during query
+pub fn get_amount_out_pool_evm(
+    amount_in: U256,
+    pool: &Pool,
+    token_in: Address,
+    token_out: Address,
+    evm: &mut EVM<ForkDB>,
+) -> Result<U256, SimulationError> {
+    match pool.pool_variant {
+        PoolVariant::UniswapV2 => get_amount_out_evm(
+            amount_in,
+            pool.address,
+            token_in,
+            token_out,
+            pool.swap_fee,
+            evm,
+        ),
+        PoolVariant::CurveStableSwap => get_amount_out_curve_evm(amount_in, pool, token_in, evm),
+        PoolVariant::BalancerWeighted => {
+            get_amount_out_balancer_evm(amount_in, pool, token_in, token_out, evm)
+        }
+        PoolVariant::Solidly => {
+            get_amount_out_solidly_evm(amount_in, pool, token_in, token_out, evm)
+        }
+        _ => Err(SimulationError::UnsupportedPoolVariant(pool.pool_variant)),
+    }
+}
+
+// Find amount out of a curve plain pool by solving its invariant on balances, `A` and fee read
+// from the fork
+//
+// Arguments:
+// * `amount_in`: amount of token in
+// * `pool`: curve pool to swap on
+// * `token_in`: address of token in
+// * `evm`: mutable reference to evm used for query
+//
+// Returns:
+// Ok(U256): amount out, zero if invariant does not converge
+// Err(SimulationError): if error during query
+pub fn get_amount_out_curve_evm(
+    amount_in: U256,
+    pool: &Pool,
+    token_in: Address,
+    evm: &mut EVM<ForkDB>,
+) -> Result<U256, SimulationError> {
+    let (token_0_index, decimals) = match pool.extra {
+        PoolExtra::Curve {
+            token_0_index,
+            decimals,
+            ..
+        } => (token_0_index as usize, decimals),
+        _ => return Err(SimulationError::UnsupportedPoolVariant(pool.pool_variant)),
+    };
+
+    let curve_pool = BaseContract::from(
+        parse_abi(&[
+            "function balances(uint256) external view returns (uint256)",
+            "function A() external view returns (uint256)",
+            "function A_precise() external view returns (uint256)",
+            "function fee() external view returns (uint256)",
+        ])
+        .unwrap(),
+    );
+    let mut query = |method: &str, calldata: Bytes| -> Result<U256, SimulationError> {
+        let output = call_evm(pool.address, calldata, evm)?;
+        curve_pool
+            .decode_output(method, &output)
+            .map_err(SimulationError::AbiError)
+    };
+
+    // older pools do not expose `A_precise()` and store `A` without precision
+    let amp = match query("A_precise", curve_pool.encode("A_precise", ()).unwrap()) {
+        Ok(amp) => amp,
+        Err(_) => query("A", curve_pool.encode("A", ()).unwrap())? * U256::from(A_PRECISION),
+    };
+    let fee = query("fee", curve_pool.encode("fee", ()).unwrap())?;
+    let balances = vec![
+        query(
+            "balances",
+            curve_pool.encode("balances", U256::zero()).unwrap(),
+        )?,
+        query(
+            "balances",
+            curve_pool.encode("balances", U256::one()).unwrap(),
+        )?,
+    ];
+
+    // rates normalize each coin to 18 decimals, ordered by coin index
+    let mut rates = decimals
+        .iter()
+        .map(|d| U256::exp10(36 - *d as usize))
+        .collect::<Vec<U256>>();
+    if token_0_index == 1 {
+        rates.swap(0, 1);
+    }
+
+    let (i, j) = match token_in == pool.token_0 {
+        true => (token_0_index, 1 - token_0_index),
+        false => (1 - token_0_index, token_0_index),
+    };
+
+    Ok(StableSwap::new(amp, fee, balances, rates)
+        .get_dy(i, j, amount_in)
+        .unwrap_or(U256::zero()))
+}
+
+// Find amount out of a balancer weighted pool using weighted math on the vault's balances and
+// the pool's swap fee read from the fork
+//
+// Arguments:
+// * `amount_in`: amount of token in
+// * `pool`: balancer pool to swap on
+// * `token_in`: address of token in
+// * `token_out`: address of token out
+// * `evm`: mutable reference to evm used for query
+//
+// Returns:
+// Ok(U256): amount out, zero if the vault would reject the swap
+// Err(SimulationError): if error during query
+pub fn get_amount_out_balancer_evm(
+    amount_in: U256,
+    pool: &Pool,
+    token_in: Address,
+    token_out: Address,
+    evm: &mut EVM<ForkDB>,
+) -> Result<U256, SimulationError> {
+    let (pool_id, weights) = match pool.extra {
+        PoolExtra::Balancer {
+            pool_id, weights, ..
+        } => (pool_id, weights),
+        _ => return Err(SimulationError::UnsupportedPoolVariant(pool.pool_variant)),
+    };
+
+    let balancer = BaseContract::from(
+        parse_abi(&[
+            "function getPoolTokens(bytes32) external view returns (address[], uint256[], uint256)",
+            "function getSwapFeePercentage() external view returns (uint256)",
+        ])
+        .unwrap(),
+    );
+
+    let output = call_evm(
+        constants::get_balancer_vault_address(),
+        balancer
+            .encode("getPoolTokens", pool_id.to_fixed_bytes())
+            .unwrap(),
+        evm,
+    )?;
+    let (tokens, balances, _): (Vec<Address>, Vec<U256>, U256) = balancer
+        .decode_output("getPoolTokens", &output)
+        .map_err(SimulationError::AbiError)?;
+
+    let output = call_evm(
+        pool.address,
+        balancer.encode("getSwapFeePercentage", ()).unwrap(),
+        evm,
+    )?;
+    let swap_fee: U256 = balancer
+        .decode_output("getSwapFeePercentage", &output)
+        .map_err(SimulationError::AbiError)?;
+
+    let balance_of = |token: Address| -> U256 {
+        tokens
+            .iter()
+            .position(|t| *t == token)
+            .map(|i| balances[i])
+            .unwrap_or_default()
+    };
+    let weight_of = |token: Address| -> U256 {
+        match token == pool.token_0 {
+            true => U256::from(weights[0]),
+            false => U256::from(weights[1]),
+        }
+    };
+
+    let weighted_product = WeightedProduct::new(
+        vec![balance_of(token_in), balance_of(token_out)],
+        vec![weight_of(token_in), weight_of(token_out)],
+        swap_fee,
+    );
+
+    Ok(weighted_product
+        .get_amount_out(0, 1, amount_in)
+        .unwrap_or(U256::zero()))
+}
+
+// Find amount out of a solidly pair using reserves read from the fork
+//
+// Arguments:
+// * `amount_in`: amount of token in
+// * `target_pool`: solidly pair to quote
+// * `token_in`: address of token in
+// * `token_out`: address of token out
+// * `evm`: mutable reference to evm used for query
+//
+// Returns:
+// Ok(U256): amount out
+// Err(SimulationError): if pool is not a solidly pair or error during query
+pub fn get_amount_out_solidly_evm(
+    amount_in: U256,
+    target_pool: &Pool,
+    token_in: Address,
+    token_out: Address,
+    evm: &mut EVM<ForkDB>,
+) -> Result<U256, SimulationError> {
+    let (stable, decimals) = match target_pool.extra {
+        PoolExtra::Solidly { stable, decimals } => (stable, decimals),
+        _ => {
+            return Err(SimulationError::UnsupportedPoolVariant(
+                target_pool.pool_variant,
+            ))
+        }
+    };
+
+    // solidly pairs expose the same `getReserves` as v2 pairs
+    let (reserves_0, reserves_1) = get_reserves_evm(target_pool.address, evm)?;
+
+    let (reserve_in, reserve_out, decimals_in, decimals_out) = match token_in < token_out {
+        true => (reserves_0, reserves_1, decimals[0], decimals[1]),
+        false => (reserves_1, reserves_0, decimals[1], decimals[0]),
+    };
+
+    Ok(get_amount_out_solidly(
+        amount_in,
+        reserve_in,
+        reserve_out,
+        decimals_in,
+        decimals_out,
+        stable,
+        target_pool.swap_fee,
+    ))
+}
+
+// Make a call on the fork and return its output
+//
+// Arguments:
+// * `to`: contract to call
+// * `data`: calldata
+// * `evm`: mutable reference to evm used for query
+//
+// Returns:
+// Ok(Bytes): output of call
+// Err(SimulationError): if call reverts, halts or errors
+fn call_evm(to: Address, data: Bytes, evm: &mut EVM<ForkDB>) -> Result<Bytes, SimulationError> {
+    evm.env.tx.transact_to = TransactTo::Call(to.0.into());
+    evm.env.tx.caller = utils::constants::get_eth_dev().0.into();
+    evm.env.tx.value = rU256::ZERO;
+    evm.env.tx.gas_limit = 700000;
+    evm.env.tx.data = data.0;
+    let result = match evm.transact_ref() {
+        Ok(result) => result.result,
+        Err(e) => return Err(SimulationError::EvmError(e)),
+    };
+
+    match result {
+        ExecutionResult::Success { output, .. } => match output {
+            Output::Call(o) => Ok(o.into()),
+            Output::Create(o, _) => Ok(o.into()),
+        },
+        ExecutionResult::Revert { output, .. } => Err(SimulationError::EvmReverted(output)),
+        ExecutionResult::Halt { reason, .. } => Err(SimulationError::EvmHalted(reason)),
+    }
+}
+
+// Fees (1e6 units) that V2 forks are known to charge, ordered from cheapest to most expensive
+const KNOWN_V2_SWAP_FEES: [u64; 7] = [1000, 2000, 2500, 3000, 5000, 10000, 30000];
+
+// Storage slots searched when looking for an erc20's `balanceOf` mapping
+const MAX_BALANCE_SLOT: u64 = 20;
+
+// Storage key of `holder`'s entry in a solidity `mapping(address => uint)` at `slot`
+fn balance_key(holder: Address, slot: U256) -> U256 {
+    ethers::utils::keccak256(abi::encode(&[
+        abi::Token::Address(holder),
+        abi::Token::Uint(slot),
+    ]))
+    .into()
+}
+
+// Find the slot of an erc20's `balanceOf` mapping by matching storage against `balanceOf(holder)`
+//
+// Arguments:
+// * `token`: erc20 to search
+// * `holder`: account holding a non zero balance of `token`
+// * `fork_factory`: fork factory used to read storage
+//
+// Returns:
+// Ok(Some(U256)): slot of the balance mapping
+// Ok(None): holder has no balance or token does not use a plain solidity mapping
+// Err(SimulationError): if error during simulation
+pub fn find_balance_slot(
+    token: Address,
+    holder: Address,
+    fork_factory: &mut ForkFactory,
+) -> Result<Option<U256>, SimulationError> {
+    let mut evm = EVM::new();
+    evm.database(fork_factory.new_sandbox_fork());
+
+    let balance = get_balance_of_evm(token, holder, &BlockInfo::default(), &mut evm)?;
+    if balance.is_zero() {
+        return Ok(None);
+    }
+
+    let candidates: Vec<(rAddress, rU256)> = (0..MAX_BALANCE_SLOT)
+        .map(|slot| (token.0.into(), balance_key(holder, slot.into()).into()))
+        .collect();
+    let values = fork_factory
+        .prefetch_storage(&candidates)
+        .map_err(|e| SimulationError::EvmError(EVMError::Database(e)))?;
+
+    let balance: rU256 = balance.into();
+    Ok(values.iter().position(|v| *v == balance).map(U256::from))
+}
+
+// Find the fee charged by a UniswapV2 style pool by simulating a tiny swap
+//
+// Sends a small amount of weth to the pool and finds the cheapest known fee whose quote the
+// pool accepts (pool only accepts quotes that charge at least its own fee)
+//
+// Arguments:
+// * `pool`: pool to find fee for (must hold weth)
+// * `weth_balance_slot`: slot of weth's `balanceOf` mapping (see `find_balance_slot`)
+// * `fork_factory`: fork factory used for the simulation, pool's weth balance is modified
+//
+// Returns:
+// Ok(Some(U256)): detected fee (1e6 units)
+// Ok(None): pool has no liquidity or charges an unknown fee
+// Err(SimulationError): if error during simulation
+pub fn detect_v2_swap_fee(
+    pool: &Pool,
+    weth_balance_slot: U256,
+    fork_factory: &mut ForkFactory,
+) -> Result<Option<U256>, SimulationError> {
+    let weth = constants::get_weth_address();
+
+    let mut evm = EVM::new();
+    evm.database(fork_factory.new_sandbox_fork());
+
+    let (reserves_0, reserves_1) = get_reserves_evm(pool.address, &mut evm)?;
+    let weth_is_token_0 = pool.token_0 == weth;
+    let (reserve_in, reserve_out) = match weth_is_token_0 {
         true => (reserves_0, reserves_1),
         false => (reserves_1, reserves_0),
     };
 
-    let a_in_with_fee: U256 = amount_in * 997;
-    let numerator: U256 = a_in_with_fee * reserve_out;
-    let denominator: U256 = reserve_in * 1000 + a_in_with_fee;
-    let amount_out: U256 = numerator.checked_div(denominator).unwrap_or(U256::zero());
+    // tiny relative to reserves so that rounding does not hide the fee
+    let amount_in = reserve_in / 1000;
+    if amount_in.is_zero() || reserve_out.is_zero() {
+        return Ok(None);
+    }
+
+    // credit pool with weth (same as transferring it in before calling swap)
+    let slot = balance_key(pool.address, weth_balance_slot);
+    fork_factory
+        .insert_account_storage(weth.0.into(), slot.into(), (reserve_in + amount_in).into())
+        .map_err(|e| SimulationError::EvmError(EVMError::Database(e)))?;
 
-    Ok(amount_out)
+    let mut evm = EVM::new();
+    evm.database(fork_factory.new_sandbox_fork());
+
+    let pair = BaseContract::from(
+        parse_abi(&["function swap(uint amount0Out, uint amount1Out, address to, bytes data)"])
+            .unwrap(),
+    );
+
+    for fee in KNOWN_V2_SWAP_FEES.map(U256::from) {
+        let amount_out = get_amount_out(amount_in, reserve_in, reserve_out, fee);
+        let (amount_0_out, amount_1_out) = match weth_is_token_0 {
+            true => (U256::zero(), amount_out),
+            false => (amount_out, U256::zero()),
+        };
+
+        evm.env.tx.transact_to = TransactTo::Call(pool.address.0.into());
+        evm.env.tx.caller = utils::constants::get_eth_dev().0.into();
+        evm.env.tx.value = rU256::ZERO;
+        evm.env.tx.gas_limit = 700000;
+        evm.env.tx.data = pair
+            .encode(
+                "swap",
+                (
+                    amount_0_out,
+                    amount_1_out,
+                    constants::get_eth_dev(),
+                    Bytes::new(),
+                ),
+            )
+            .unwrap()
+            .0;
+
+        match evm.transact_ref() {
+            Ok(result) if result.result.is_success() => return Ok(Some(fee)),
+            Ok(_) => continue,
+            Err(e) => return Err(SimulationError::EvmError(e)),
+        }
+    }
+
+    Ok(None)
 }
 
 // Get token balance
@@ -264,6 +827,28 @@ pub fn convert_access_list(access_list: Vec<(rAddress, Vec<rU256>)>) -> AccessLi
     AccessList(converted_access_list)
 }
 
+// Converts access list from ethers to revm type
+//
+// Arguments:
+// * `access_list`: access list in ethers format
+//
+// Returns:
+// `Vec<(rAddress, Vec<rU256>)>`: access list in revm format
+pub fn convert_access_list_to_revm(access_list: &AccessList) -> Vec<(rAddress, Vec<rU256>)> {
+    access_list
+        .0
+        .iter()
+        .map(|item| {
+            let slots = item
+                .storage_keys
+                .iter()
+                .map(|slot| rU256::from_be_bytes(slot.0))
+                .collect();
+            (item.address.0.into(), slots)
+        })
+        .collect()
+}
+
 // Holds constant value representing braindance contract address
 pub fn braindance_address() -> rAddress {
     rAddress::from_str("00000000000000000000000000000000F3370000").unwrap()
@@ -278,3 +863,223 @@ pub fn braindance_controller_address() -> rAddress {
 pub fn braindance_starting_balance() -> U256 {
     parse_ether(420).unwrap()
 }
+
+#[cfg(test)]
+mod test {
+    use ethers::types::I256;
+    use revm::db::EmptyDB;
+
+    use super::*;
+
+    #[test]
+    fn block_state_follows_block_info() {
+        let next_block = BlockInfo {
+            number: 18_000_001u64.into(),
+            timestamp: 1_700_000_012u64.into(),
+            base_fee: U256::from(20_000_000_000u64),
+            coinbase: Address::repeat_byte(0x69),
+            gas_limit: 30_000_000u64.into(),
+            prevrandao: H256::repeat_byte(0x42),
+            excess_blob_gas: Some(0),
+        };
+
+        let mut evm = EVM::<EmptyDB>::new();
+        setup_block_state(&mut evm, &next_block);
+
+        let block = &evm.env.block;
+        assert_eq!(block.number, rU256::from(18_000_001u64));
+        assert_eq!(block.timestamp, rU256::from(1_700_000_012u64));
+        assert_eq!(block.basefee, rU256::from(20_000_000_000u64));
+        assert_eq!(block.gas_limit, rU256::from(30_000_000u64));
+        assert_eq!(block.coinbase, rAddress::from(Address::repeat_byte(0x69).0));
+        assert_eq!(block.prevrandao, Some(H256::repeat_byte(0x42).0.into()));
+        assert!(block.blob_excess_gas_and_price.is_some());
+        assert_eq!(evm.env.cfg.chain_id, chain_config().chain_id.into());
+    }
+
+    #[test]
+    fn unknown_coinbase_and_gas_limit_keep_defaults() {
+        let mut evm = EVM::<EmptyDB>::new();
+        let default_gas_limit = evm.env.block.gas_limit;
+        setup_block_state(&mut evm, &BlockInfo::new(1.into(), 1.into(), 1.into()));
+
+        // a coinbase that is not the zero address so coinbase payments are not burned
+        assert_eq!(
+            evm.env.block.coinbase,
+            rAddress::from_str("0xDecafC0FFEe15BAD000000000000000000000000").unwrap()
+        );
+        assert_eq!(evm.env.block.gas_limit, default_gas_limit);
+        assert!(evm.env.block.blob_excess_gas_and_price.is_none());
+    }
+
+    #[test]
+    fn access_list_converts_both_ways() {
+        let access_list = AccessList(vec![AccessListItem {
+            address: Address::repeat_byte(0xaa),
+            storage_keys: vec![H256::from_low_u64_be(1), H256::repeat_byte(0xff)],
+        }]);
+
+        let converted = convert_access_list_to_revm(&access_list);
+        assert_eq!(converted[0].1[0], rU256::from(1));
+        assert_eq!(convert_access_list(converted), access_list);
+    }
+
+    // Fork evm at `fork_block` with no preloaded state
+    async fn fork_evm(fork_block: u64) -> EVM<ForkDB> {
+        dotenv::dotenv().ok();
+        let provider = utils::create_websocket_client().await.unwrap();
+        let fork_factory = ForkFactory::new_sandbox_factory(
+            provider,
+            revm::db::CacheDB::new(EmptyDB::default()),
+            Some(fork_block.into()),
+        );
+
+        let mut evm = EVM::new();
+        evm.database(fork_factory.new_sandbox_fork());
+        evm
+    }
+
+    #[test]
+    fn curve_quote_matches_onchain_get_dy() {
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let mut evm = fork_evm(17721757).await;
+
+            // FRAXBP, coins are [FRAX (18 decimals), USDC (6 decimals)]
+            let frax = Address::from_str("0x853d955aCEf822Db058eb8505911ED77F175b99e").unwrap();
+            let usdc = Address::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48").unwrap();
+            let pool = Pool::new(
+                Address::from_str("0xDcEF968d416a41Cdac0ED8702fAC8128A64241A2").unwrap(),
+                frax,
+                usdc,
+                U256::from(100),
+                PoolVariant::CurveStableSwap,
+            )
+            .with_extra(PoolExtra::Curve {
+                token_0_index: 0,
+                balances_slot: 0,
+                decimals: [18, 6],
+            });
+
+            let curve_pool = BaseContract::from(
+                parse_abi(&[
+                    "function get_dy(int128,int128,uint256) external view returns (uint256)",
+                ])
+                .unwrap(),
+            );
+
+            let swaps = [
+                (frax, 0i128, 1i128, U256::exp10(18) * 250_000),
+                (usdc, 1i128, 0i128, U256::exp10(6) * 1_000_000),
+            ];
+            for (token_in, i, j, amount_in) in swaps {
+                let calldata = curve_pool
+                    .encode("get_dy", (I256::from(i), I256::from(j), amount_in))
+                    .unwrap();
+                let output = call_evm(pool.address, calldata, &mut evm).unwrap();
+                let onchain: U256 = curve_pool.decode_output("get_dy", &output).unwrap();
+
+                let quoted =
+                    get_amount_out_curve_evm(amount_in, &pool, token_in, &mut evm).unwrap();
+                assert!(!quoted.is_zero());
+                assert_eq!(quoted, onchain);
+            }
+        });
+    }
+
+    // Amount out of a single swap as reported by the vault's `queryBatchSwap`
+    fn query_batch_swap(
+        pool_id: H256,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+        evm: &mut EVM<ForkDB>,
+    ) -> U256 {
+        let selector = ethers::utils::id(
+            "queryBatchSwap(uint8,(bytes32,uint256,uint256,uint256,bytes)[],address[],(address,bool,address,bool))",
+        );
+        let eth_dev = constants::get_eth_dev();
+        let args = abi::encode(&[
+            // GIVEN_IN
+            abi::Token::Uint(U256::zero()),
+            // single swap from assets[0] to assets[1]
+            abi::Token::Array(vec![abi::Token::Tuple(vec![
+                abi::Token::FixedBytes(pool_id.as_bytes().to_vec()),
+                abi::Token::Uint(U256::zero()),
+                abi::Token::Uint(U256::one()),
+                abi::Token::Uint(amount_in),
+                abi::Token::Bytes(vec![]),
+            ])]),
+            abi::Token::Array(vec![
+                abi::Token::Address(token_in),
+                abi::Token::Address(token_out),
+            ]),
+            abi::Token::Tuple(vec![
+                abi::Token::Address(eth_dev),
+                abi::Token::Bool(false),
+                abi::Token::Address(eth_dev),
+                abi::Token::Bool(false),
+            ]),
+        ]);
+
+        let output = call_evm(
+            constants::get_balancer_vault_address(),
+            [selector.to_vec(), args].concat().into(),
+            evm,
+        )
+        .unwrap();
+
+        // deltas are from the vault's point of view, amount out is paid so its delta is negative
+        let deltas = abi::decode(&[ParamType::Array(Box::new(ParamType::Int(256)))], &output)
+            .unwrap()[0]
+            .clone()
+            .into_array()
+            .unwrap();
+        I256::from_raw(deltas[1].clone().into_int().unwrap()).unsigned_abs()
+    }
+
+    #[test]
+    fn balancer_quote_matches_vault_output() {
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let mut evm = fork_evm(17721757).await;
+
+            // 80BAL/20WETH
+            let pool_id = H256::from_str(
+                "0x5c6ee304399dbdb9c8ef030ab642b10820db8f56000200000000000000000014",
+            )
+            .unwrap();
+            let bal = Address::from_str("0xba100000625a3754423978a60c9317c58a424e3D").unwrap();
+            let weth = constants::get_weth_address();
+            let pool = Pool::new(
+                Address::from_str("0x5c6Ee304399DBdB9C8Ef030aB642B10820DB8F56").unwrap(),
+                bal,
+                weth,
+                U256::from(10000),
+                PoolVariant::BalancerWeighted,
+            )
+            .with_extra(PoolExtra::Balancer {
+                pool_id,
+                weights: [800_000_000_000_000_000, 200_000_000_000_000_000],
+                balances_key: H256::zero(),
+            });
+
+            let swaps = [
+                (weth, bal, U256::exp10(18) * 10),
+                (bal, weth, U256::exp10(18) * 5_000),
+            ];
+            for (token_in, token_out, amount_in) in swaps {
+                let vault = query_batch_swap(pool_id, token_in, token_out, amount_in, &mut evm);
+                let quoted =
+                    get_amount_out_balancer_evm(amount_in, &pool, token_in, token_out, &mut evm)
+                        .unwrap();
+
+                // weighted math uses a float pow, allow 1e-9 relative error vs fixed point
+                let error = match quoted > vault {
+                    true => quoted - vault,
+                    false => vault - quoted,
+                };
+                assert!(!vault.is_zero());
+                assert!(error * U256::exp10(9) <= vault);
+            }
+        });
+    }
+}
@@ -1,18 +1,40 @@
+use ethers::prelude::Address;
+use hashbrown::{HashMap, HashSet};
 use revm::interpreter::{opcode, Interpreter};
 use revm::interpreter::{CallInputs, CreateInputs, Gas, InstructionResult};
-use revm::primitives::{Bytes, B160};
+use revm::primitives::{Bytes, B160, U256 as rU256};
 use revm::{Database, EVMData, Inspector};
 
+use super::salmonella_policy::{get_salmonella_policy, SalmonellaPolicy};
+
 #[derive(Debug)]
 pub enum IsSandoSafu {
     Safu,
     NotSafu(Vec<OpCode>),
 }
 
+/// Why an opcode was flagged
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SalmonellaReason {
+    /// opcode is denied by the salmonella policy
+    DeniedOpcode,
+    /// opcode is not known by the evm
+    UnknownOpcode,
+    /// contract read GAS more often than it made calls (checking gas_used)
+    GasCheck,
+    /// contract compared a value against COINBASE
+    CoinbaseComparison,
+    /// contract compared a value against TIMESTAMP or NUMBER
+    BlockBranching,
+}
+
 #[derive(Debug, Clone)]
 pub struct OpCode {
     name: String,
     code: u8,
+    // contract that executed the opcode
+    contract: Option<B160>,
+    reason: SalmonellaReason,
 }
 
 impl OpCode {
@@ -29,23 +51,76 @@ impl OpCode {
             None => "UNKNOWN".to_string(),
         };
 
-        OpCode { code, name }
+        let reason = match name.as_str() {
+            "UNKNOWN" => SalmonellaReason::UnknownOpcode,
+            _ => SalmonellaReason::DeniedOpcode,
+        };
+
+        OpCode {
+            code,
+            name,
+            contract: None,
+            reason,
+        }
+    }
+
+    // attach the contract that executed the opcode and why it was flagged
+    fn flagged(mut self, contract: B160, reason: SalmonellaReason) -> Self {
+        self.contract = Some(contract);
+        self.reason = reason;
+        self
+    }
+
+    // contract that executed the opcode
+    pub fn contract(&self) -> Option<Address> {
+        self.contract.map(|c| Address::from(c.0))
+    }
+
+    // why the opcode was flagged
+    pub fn reason(&self) -> &SalmonellaReason {
+        &self.reason
     }
 }
 
 pub struct SalmonellaInspectoooor {
     suspicious_opcodes: Vec<OpCode>,
-    gas_opcode_counter: u64,
-    call_opcode_counter: u64,
+    policy: &'static SalmonellaPolicy,
+    // contracts trusted for this simulation only (e.g. the target pool)
+    trusted_contracts: HashSet<B160>,
+    // per contract (gas opcode counter, call opcode counter)
+    opcode_counters: HashMap<B160, (u64, u64)>,
+    // block context opcodes (COINBASE/TIMESTAMP/NUMBER) read by each contract
+    block_context_reads: HashSet<(B160, u8)>,
 }
 
 impl SalmonellaInspectoooor {
     // create new salmonella inspector
-    pub fn new() -> Self {
+    //
+    // Arguments:
+    // * `trusted_contracts`: contracts that are trusted on top of the policy's allowlist,
+    //   the pool being sandwiched should always be trusted (v3 pools compare timestamps)
+    //
+    // Returns:
+    // `SalmonellaInspectoooor`: new inspector using the globally configured policy
+    pub fn new(trusted_contracts: Vec<Address>) -> Self {
+        Self::with_policy(get_salmonella_policy(), trusted_contracts)
+    }
+
+    // create new salmonella inspector that enforces `policy` instead of the global one
+    //
+    // Arguments:
+    // * `policy`: salmonella policy to enforce
+    // * `trusted_contracts`: contracts that are trusted on top of the policy's allowlist
+    //
+    // Returns:
+    // `SalmonellaInspectoooor`: new inspector
+    pub fn with_policy(policy: &'static SalmonellaPolicy, trusted_contracts: Vec<Address>) -> Self {
         Self {
             suspicious_opcodes: Vec::new(),
-            gas_opcode_counter: 0,
-            call_opcode_counter: 0,
+            policy,
+            trusted_contracts: trusted_contracts.into_iter().map(|c| c.0.into()).collect(),
+            opcode_counters: HashMap::new(),
+            block_context_reads: HashSet::new(),
         }
     }
 
@@ -57,18 +132,28 @@ impl SalmonellaInspectoooor {
     // Returns:
     // IsSandoSafu: enum that is either Safu or NotSafu
     pub fn is_sando_safu(self) -> IsSandoSafu {
-        // if more gas opcodes used then call then we know that the contract is checking gas_used
-        let mut suspicious_opcodes = self.suspicious_opcodes.clone();
-        if self.gas_opcode_counter < self.call_opcode_counter {
-            let gas_opcode = OpCode::new_from_code(opcode::GAS);
-            suspicious_opcodes.insert(0, gas_opcode);
+        // if more gas opcodes used then calls then we know that the contract is checking gas_used
+        let mut suspicious_opcodes = self.suspicious_opcodes;
+        if self.policy.check_gas_usage {
+            for (contract, (gas_counter, call_counter)) in self.opcode_counters.iter() {
+                if gas_counter > call_counter {
+                    let gas_opcode = OpCode::new_from_code(opcode::GAS)
+                        .flagged(*contract, SalmonellaReason::GasCheck);
+                    suspicious_opcodes.insert(0, gas_opcode);
+                }
+            }
         }
 
-        match self.suspicious_opcodes.len() == 0 {
+        match suspicious_opcodes.is_empty() {
             true => IsSandoSafu::Safu,
             false => IsSandoSafu::NotSafu(suspicious_opcodes),
         }
     }
+
+    // is the contract trusted by either the policy or this simulation
+    fn is_trusted(&self, contract: &B160) -> bool {
+        self.trusted_contracts.contains(contract) || self.policy.is_allowed_contract(contract)
+    }
 }
 
 impl<DB: Database> Inspector<DB> for SalmonellaInspectoooor {
@@ -86,42 +171,82 @@ impl<DB: Database> Inspector<DB> for SalmonellaInspectoooor {
     fn step(
         &mut self,
         interp: &mut Interpreter,
-        _data: &mut EVMData<'_, DB>,
+        data: &mut EVMData<'_, DB>,
         _is_static: bool,
     ) -> InstructionResult {
-        let executed_opcode = interp.current_opcode();
-
-        let mut add_suspicious = |opcode: OpCode| self.suspicious_opcodes.push(opcode);
-        let mut increment_call_counter = || self.call_opcode_counter += 1;
+        let contract = interp.contract.address;
+        if self.is_trusted(&contract) {
+            return InstructionResult::Continue;
+        }
 
-        let executed_opcode = OpCode::new_from_code(executed_opcode);
+        let executed_opcode = OpCode::new_from_code(interp.current_opcode());
 
         match executed_opcode.code {
-            // these opcodes can be used to divert execution flow when ran locally vs on mainnet
-            // extra safe version, can easily ignore half of these checks if ur up for it
-            opcode::BALANCE => add_suspicious(executed_opcode.clone()),
-            opcode::GASPRICE => add_suspicious(executed_opcode.clone()),
-            opcode::EXTCODEHASH => add_suspicious(executed_opcode.clone()),
-            opcode::BLOCKHASH => add_suspicious(executed_opcode.clone()),
-            opcode::COINBASE => add_suspicious(executed_opcode.clone()),
-            opcode::DIFFICULTY => add_suspicious(executed_opcode.clone()),
-            opcode::GASLIMIT => add_suspicious(executed_opcode.clone()),
-            opcode::SELFBALANCE => add_suspicious(executed_opcode.clone()),
-            opcode::BASEFEE => add_suspicious(executed_opcode.clone()),
-            opcode::CREATE => add_suspicious(executed_opcode.clone()),
-            opcode::CREATE2 => add_suspicious(executed_opcode.clone()),
-            opcode::SELFDESTRUCT => add_suspicious(executed_opcode.clone()),
             // add one to call counter
-            opcode::CALL => increment_call_counter(),
-            opcode::DELEGATECALL => increment_call_counter(),
-            opcode::STATICCALL => increment_call_counter(),
+            opcode::CALL | opcode::CALLCODE | opcode::DELEGATECALL | opcode::STATICCALL => {
+                self.opcode_counters.entry(contract).or_default().1 += 1;
+            }
             // add one to gas opcode counter
-            opcode::GAS => self.gas_opcode_counter += 1,
-            _ => { /* this opcode is safu */ }
-        }
+            opcode::GAS => self.opcode_counters.entry(contract).or_default().0 += 1,
+            // remember block context reads so that later comparisons can be flagged
+            opcode::COINBASE | opcode::TIMESTAMP | opcode::NUMBER => {
+                self.block_context_reads
+                    .insert((contract, executed_opcode.code));
+            }
+            // check if a block context value is being compared against
+            opcode::EQ | opcode::LT | opcode::GT | opcode::SLT | opcode::SGT => {
+                let operands = [interp.stack.peek(0), interp.stack.peek(1)];
+                let block = &data.env.block;
+
+                let mut coinbase = [0u8; 32];
+                coinbase[12..].copy_from_slice(block.coinbase.as_bytes());
+                let coinbase = rU256::from_be_bytes(coinbase);
+
+                let checks = [
+                    (
+                        opcode::COINBASE,
+                        coinbase,
+                        self.policy.check_coinbase_comparison,
+                        SalmonellaReason::CoinbaseComparison,
+                    ),
+                    (
+                        opcode::TIMESTAMP,
+                        block.timestamp,
+                        self.policy.check_block_branching,
+                        SalmonellaReason::BlockBranching,
+                    ),
+                    (
+                        opcode::NUMBER,
+                        block.number,
+                        self.policy.check_block_branching,
+                        SalmonellaReason::BlockBranching,
+                    ),
+                ];
 
-        if &executed_opcode.name == "UNKNOWN" {
-            add_suspicious(executed_opcode);
+                for (context_opcode, value, enabled, reason) in checks {
+                    if !enabled
+                        || !self
+                            .block_context_reads
+                            .contains(&(contract, context_opcode))
+                    {
+                        continue;
+                    }
+
+                    if operands.iter().any(|o| matches!(o, Ok(o) if *o == value)) {
+                        self.suspicious_opcodes
+                            .push(OpCode::new_from_code(context_opcode).flagged(contract, reason));
+                    }
+                }
+            }
+            _ => {
+                if executed_opcode.reason == SalmonellaReason::UnknownOpcode
+                    || self.policy.is_denied(executed_opcode.code)
+                {
+                    let reason = executed_opcode.reason.clone();
+                    self.suspicious_opcodes
+                        .push(executed_opcode.flagged(contract, reason));
+                }
+            }
         }
 
         InstructionResult::Continue
@@ -170,3 +295,129 @@ impl<DB: Database> Inspector<DB> for SalmonellaInspectoooor {
         (ret, address, remaining_gas, out)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use ethers::utils::hex;
+    use revm::db::{CacheDB, EmptyDB};
+    use revm::primitives::{AccountInfo, Bytecode, TransactTo};
+
+    use super::*;
+
+    fn poison_contract() -> B160 {
+        B160::from_low_u64_be(0xdead)
+    }
+
+    fn block_builder() -> B160 {
+        B160::from_low_u64_be(0xc0ffee)
+    }
+
+    fn policy() -> &'static SalmonellaPolicy {
+        Box::leak(Box::new(SalmonellaPolicy {
+            denied_opcodes: [opcode::BALANCE, opcode::SELFBALANCE].into_iter().collect(),
+            allowed_contracts: HashSet::new(),
+            check_gas_usage: true,
+            check_coinbase_comparison: true,
+            check_block_branching: true,
+        }))
+    }
+
+    // run `code` deployed at `poison_contract` through the salmonella inspector
+    fn inspect(
+        code: &str,
+        policy: &'static SalmonellaPolicy,
+        trusted_contracts: Vec<Address>,
+    ) -> IsSandoSafu {
+        let bytecode = Bytecode::new_raw(hex::decode(code.replace(' ', "")).unwrap().into());
+        let mut db = CacheDB::new(EmptyDB::default());
+        db.insert_account_info(
+            poison_contract(),
+            AccountInfo::new(rU256::ZERO, 1, bytecode.hash_slow(), bytecode),
+        );
+
+        let mut evm = revm::EVM::new();
+        evm.database(db);
+        evm.env.block.coinbase = block_builder();
+        evm.env.block.timestamp = rU256::from(1_700_000_000);
+        evm.env.block.number = rU256::from(18_000_000);
+        evm.env.tx.caller = B160::from_low_u64_be(0xbeef);
+        evm.env.tx.transact_to = TransactTo::Call(poison_contract());
+        evm.env.tx.gas_limit = 1_000_000;
+
+        let mut inspector = SalmonellaInspectoooor::with_policy(policy, trusted_contracts);
+        evm.inspect(&mut inspector).unwrap();
+        inspector.is_sando_safu()
+    }
+
+    fn flagged_reasons(result: IsSandoSafu) -> Vec<SalmonellaReason> {
+        match result {
+            IsSandoSafu::Safu => vec![],
+            IsSandoSafu::NotSafu(opcodes) => {
+                assert!(opcodes
+                    .iter()
+                    .all(|o| o.contract() == Some(Address::from(poison_contract().0))));
+                opcodes.into_iter().map(|o| o.reason).collect()
+            }
+        }
+    }
+
+    #[test]
+    fn plain_arithmetic_is_safu() {
+        // PUSH1 1, PUSH1 2, ADD, POP, STOP
+        let result = inspect("6001 6002 01 50 00", policy(), vec![]);
+        assert!(flagged_reasons(result).is_empty());
+    }
+
+    #[test]
+    fn flags_denied_opcode() {
+        // SELFBALANCE, POP, STOP
+        let result = inspect("47 50 00", policy(), vec![]);
+        assert_eq!(
+            flagged_reasons(result),
+            vec![SalmonellaReason::DeniedOpcode]
+        );
+    }
+
+    #[test]
+    fn flags_coinbase_comparison() {
+        // COINBASE, PUSH20 <builder>, EQ, POP, STOP
+        let code = format!("41 73{} 14 50 00", hex::encode(block_builder()));
+        let result = inspect(&code, policy(), vec![]);
+        assert_eq!(
+            flagged_reasons(result),
+            vec![SalmonellaReason::CoinbaseComparison]
+        );
+    }
+
+    #[test]
+    fn flags_timestamp_branching() {
+        // TIMESTAMP, PUSH4 <deadline>, GT, POP, STOP
+        let code = format!("42 63{:08x} 11 50 00", 1_700_000_100u32);
+        let result = inspect(&code, policy(), vec![]);
+        assert_eq!(
+            flagged_reasons(result),
+            vec![SalmonellaReason::BlockBranching]
+        );
+
+        let relaxed = Box::leak(Box::new(SalmonellaPolicy {
+            check_block_branching: false,
+            ..policy().clone()
+        }));
+        assert!(flagged_reasons(inspect(&code, relaxed, vec![])).is_empty());
+    }
+
+    #[test]
+    fn flags_gas_check_without_calls() {
+        // GAS, POP, STOP
+        let result = inspect("5a 50 00", policy(), vec![]);
+        assert_eq!(flagged_reasons(result), vec![SalmonellaReason::GasCheck]);
+    }
+
+    #[test]
+    fn trusted_contracts_are_never_flagged() {
+        // SELFBALANCE, POP, COINBASE, PUSH20 <builder>, EQ, POP, STOP
+        let code = format!("47 50 41 73{} 14 50 00", hex::encode(block_builder()));
+        let trusted = vec![Address::from(poison_contract().0)];
+        assert!(flagged_reasons(inspect(&code, policy(), trusted)).is_empty());
+    }
+}
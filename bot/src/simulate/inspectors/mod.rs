@@ -1,2 +1,3 @@
 pub mod access_list;
 pub mod is_sando_safu;
+pub mod salmonella_policy;
@@ -0,0 +1,216 @@
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use ethers::prelude::Address;
+use hashbrown::HashSet;
+use revm::interpreter::opcode;
+use revm::primitives::B160;
+
+use crate::utils;
+
+/// Opcodes flagged by default, these can be used to divert execution flow when ran locally vs on
+/// mainnet (extra safe version, can easily ignore half of these checks if ur up for it)
+const DEFAULT_DENIED_OPCODES: [u8; 11] = [
+    opcode::BALANCE,
+    opcode::GASPRICE,
+    opcode::EXTCODEHASH,
+    opcode::BLOCKHASH,
+    opcode::DIFFICULTY,
+    opcode::GASLIMIT,
+    opcode::SELFBALANCE,
+    opcode::BASEFEE,
+    opcode::CREATE,
+    opcode::CREATE2,
+    opcode::SELFDESTRUCT,
+];
+
+/// Decides which behaviour during a sandwich simulation is considered salmonella
+#[derive(Debug, Clone)]
+pub struct SalmonellaPolicy {
+    /// opcodes that are flagged whenever they are executed by an untrusted contract
+    pub denied_opcodes: HashSet<u8>,
+    /// contracts whose execution is never flagged (e.g. WETH)
+    pub allowed_contracts: HashSet<B160>,
+    /// flag contracts that use more GAS opcodes than calls (checking gas_used)
+    pub check_gas_usage: bool,
+    /// flag contracts that compare COINBASE against a value
+    pub check_coinbase_comparison: bool,
+    /// flag contracts that compare TIMESTAMP or NUMBER against a value
+    pub check_block_branching: bool,
+}
+
+impl Default for SalmonellaPolicy {
+    fn default() -> Self {
+        Self::trusting(utils::dotenv::get_sandwich_contract_address())
+    }
+}
+
+impl SalmonellaPolicy {
+    // Default policy, only weth and our own sandwich contract are trusted
+    pub fn trusting(sandwich_contract: Address) -> Self {
+        let allowed_contracts = vec![utils::constants::get_weth_address(), sandwich_contract];
+
+        Self {
+            denied_opcodes: DEFAULT_DENIED_OPCODES.into_iter().collect(),
+            allowed_contracts: allowed_contracts.into_iter().map(|a| a.0.into()).collect(),
+            check_gas_usage: true,
+            check_coinbase_comparison: true,
+            check_block_branching: true,
+        }
+    }
+
+    // Create a new policy from env vars, falling back on default policy for missing values
+    //
+    // Returns:
+    // `SalmonellaPolicy`: policy used by `SalmonellaInspectoooor`
+    pub fn from_env() -> Self {
+        Self::from_lookup(utils::dotenv::get_sandwich_contract_address(), |key| {
+            dotenv::var(key).ok()
+        })
+    }
+
+    // Create a new policy from config values, falling back on default policy for missing values
+    //
+    // Keys:
+    // * `SALMONELLA_DENIED_OPCODES`: comma separated opcode names to flag on top of defaults
+    // * `SALMONELLA_ALLOWED_OPCODES`: comma separated opcode names to never flag
+    // * `SALMONELLA_ALLOWED_CONTRACTS`: comma separated addresses whose execution is trusted
+    // * `SALMONELLA_CHECK_GAS`, `SALMONELLA_CHECK_COINBASE`, `SALMONELLA_CHECK_BLOCK_BRANCHING`:
+    //   toggle context aware checks (true/false)
+    //
+    // Arguments:
+    // * `sandwich_contract`: our sandwich contract, always trusted
+    // * `lookup`: returns the value set for a key, None if not set
+    //
+    // Returns:
+    // `SalmonellaPolicy`: policy used by `SalmonellaInspectoooor`
+    pub fn from_lookup(
+        sandwich_contract: Address,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Self {
+        let mut policy = Self::trusting(sandwich_contract);
+        let list = |key: &str| -> Vec<String> {
+            lookup(key)
+                .unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect()
+        };
+        let flag = |key: &str| -> bool {
+            match lookup(key) {
+                Some(value) => value
+                    .parse()
+                    .unwrap_or_else(|_| panic!("{} is not a valid bool", key)),
+                None => true,
+            }
+        };
+
+        for name in list("SALMONELLA_DENIED_OPCODES") {
+            policy.denied_opcodes.insert(opcode_from_name(&name));
+        }
+
+        for name in list("SALMONELLA_ALLOWED_OPCODES") {
+            policy.denied_opcodes.remove(&opcode_from_name(&name));
+        }
+
+        for addr in list("SALMONELLA_ALLOWED_CONTRACTS") {
+            let addr = Address::from_str(&addr)
+                .expect("Failed to parse address in \"SALMONELLA_ALLOWED_CONTRACTS\"");
+            policy.allowed_contracts.insert(addr.0.into());
+        }
+
+        policy.check_gas_usage = flag("SALMONELLA_CHECK_GAS");
+        policy.check_coinbase_comparison = flag("SALMONELLA_CHECK_COINBASE");
+        policy.check_block_branching = flag("SALMONELLA_CHECK_BLOCK_BRANCHING");
+
+        policy
+    }
+
+    // Is the opcode denied by this policy
+    pub fn is_denied(&self, code: u8) -> bool {
+        self.denied_opcodes.contains(&code)
+    }
+
+    // Is the contract trusted by this policy
+    pub fn is_allowed_contract(&self, contract: &B160) -> bool {
+        self.allowed_contracts.contains(contract)
+    }
+}
+
+// Returns the policy loaded from env (only loaded once)
+pub fn get_salmonella_policy() -> &'static SalmonellaPolicy {
+    static POLICY: OnceLock<SalmonellaPolicy> = OnceLock::new();
+    POLICY.get_or_init(SalmonellaPolicy::from_env)
+}
+
+// Find numeric opcode from its name
+//
+// Arguments:
+// * `name`: opcode name as found in `OPCODE_JUMPMAP` (e.g. BALANCE)
+//
+// Returns:
+// u8: numeric opcode, panics if name is unknown as policy would be misconfigured
+fn opcode_from_name(name: &str) -> u8 {
+    let name = name.trim().to_uppercase();
+    (0..=u8::MAX)
+        .find(|code| opcode::OPCODE_JUMPMAP[*code as usize] == Some(name.as_str()))
+        .unwrap_or_else(|| panic!("Unknown opcode \"{}\" in salmonella policy", name))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hashbrown::HashMap;
+
+    #[test]
+    fn opcode_names_are_case_insensitive() {
+        assert_eq!(opcode_from_name("BALANCE"), opcode::BALANCE);
+        assert_eq!(opcode_from_name(" selfbalance "), opcode::SELFBALANCE);
+        assert_eq!(opcode_from_name("Origin"), opcode::ORIGIN);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown opcode")]
+    fn unknown_opcode_name_panics() {
+        opcode_from_name("NOT_AN_OPCODE");
+    }
+
+    #[test]
+    fn config_overrides_default_policy() {
+        let trusted = "0x00000000000000000000000000000000deadbeef";
+        let sandwich_contract = Address::repeat_byte(0x5a);
+        let config: HashMap<&str, &str> = [
+            ("SALMONELLA_DENIED_OPCODES", "ORIGIN, callvalue"),
+            ("SALMONELLA_ALLOWED_OPCODES", "BALANCE"),
+            ("SALMONELLA_ALLOWED_CONTRACTS", trusted),
+            ("SALMONELLA_CHECK_GAS", "false"),
+            ("SALMONELLA_CHECK_COINBASE", "true"),
+        ]
+        .into_iter()
+        .collect();
+
+        let policy = SalmonellaPolicy::from_lookup(sandwich_contract, |key| {
+            config.get(key).map(|value| value.to_string())
+        });
+
+        assert!(policy.is_denied(opcode::ORIGIN));
+        assert!(policy.is_denied(opcode::CALLVALUE));
+        assert!(policy.is_denied(opcode::SELFBALANCE));
+        assert!(!policy.is_denied(opcode::BALANCE));
+        assert!(policy.is_allowed_contract(&Address::from_str(trusted).unwrap().0.into()));
+        assert!(policy.is_allowed_contract(&sandwich_contract.0.into()));
+        assert!(policy.is_allowed_contract(&utils::constants::get_weth_address().0.into()));
+        assert!(!policy.check_gas_usage);
+        assert!(policy.check_coinbase_comparison);
+        assert!(policy.check_block_branching);
+    }
+
+    #[test]
+    #[should_panic(expected = "SALMONELLA_CHECK_GAS is not a valid bool")]
+    fn invalid_flag_panics() {
+        SalmonellaPolicy::from_lookup(Address::zero(), |key| {
+            (key == "SALMONELLA_CHECK_GAS").then(|| "maybe".to_string())
+        });
+    }
+}
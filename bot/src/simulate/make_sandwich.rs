@@ -8,15 +8,22 @@ use crate::prelude::fork_db::ForkDB;
 use crate::prelude::fork_factory::ForkFactory;
 use crate::prelude::is_sando_safu::{IsSandoSafu, SalmonellaInspectoooor};
 use crate::prelude::sandwich_types::RawIngredients;
-use crate::prelude::{convert_access_list, get_amount_out_evm, get_balance_of_evm, PoolVariant, Pool};
+use crate::prelude::simulation_executor::spawn_on_workers;
+use crate::prelude::token_profiler::{get_token_profile, TokenProfile};
+use crate::prelude::{
+    convert_access_list, convert_access_list_to_revm, executable_variant, get_amount_out_evm,
+    get_balance_of_evm, get_reserves_evm, max_frontrun_for_slippage, ExecutableVariant, Pool,
+    PoolVariant,
+};
 use crate::types::sandwich_types::OptimalRecipe;
 use crate::types::{BlockInfo, SimulationError};
+use crate::utils::swap_decoder::SwapAmounts;
 use crate::utils::tx_builder::{self, braindance, SandwichMaker};
-use crate::utils::{constants, dotenv};
+use crate::utils::{constants, dotenv, signer::searcher_signer};
 
 use super::{
     attach_braindance_module, braindance_address, braindance_controller_address,
-    braindance_starting_balance, setup_block_state,
+    braindance_starting_balance, prefetch_pool_state, setup_block_state, setup_tx_state,
 };
 
 // Calculate amount in that produces highest revenue and performs honeypot checks
@@ -31,6 +38,7 @@ use super::{
 // Returns:
 // Ok(OptimalRecipe) if no errors during calculation
 // Err(SimulationError) if error during calculation
+#[tracing::instrument(skip_all, fields(pool = ?ingredients.target_pool.address))]
 pub async fn create_optimal_sandwich(
     ingredients: &RawIngredients,
     sandwich_balance: U256,
@@ -38,10 +46,13 @@ pub async fn create_optimal_sandwich(
     fork_factory: &mut ForkFactory,
     sandwich_maker: &SandwichMaker,
 ) -> Result<OptimalRecipe, SimulationError> {
+    let (token_profile, upper_bound) =
+        prepare_search(ingredients, sandwich_balance, next_block, fork_factory).await?;
+
     let optimal = juiced_quadratic_search(
         ingredients,
         U256::zero(),
-        sandwich_balance,
+        upper_bound,
         next_block,
         fork_factory,
     )
@@ -54,16 +65,20 @@ pub async fn create_optimal_sandwich(
     if optimal.is_zero() {
         return Err(SimulationError::ZeroOptimal());
     }
-    sanity_check(
+    let mut recipe = sanity_check(
         sandwich_balance,
         optimal,
         ingredients,
         next_block,
         sandwich_maker,
         fork_factory.new_sandbox_fork(),
-    )
+        &token_profile,
+    )?;
+    recipe.set_frontrun_in(optimal);
+    Ok(recipe)
 }
 
+#[tracing::instrument(skip_all, fields(pool = ?ingredients.target_pool.address))]
 pub async fn create_optimal_sandwich_multi(
     ingredients: &RawIngredients,
     sandwich_balance: U256,
@@ -71,10 +86,13 @@ pub async fn create_optimal_sandwich_multi(
     fork_factory: &mut ForkFactory,
     sandwich_maker: &SandwichMaker,
 ) -> Result<OptimalRecipe, SimulationError> {
+    let (token_profile, upper_bound) =
+        prepare_search(ingredients, sandwich_balance, next_block, fork_factory).await?;
+
     let optimal = juiced_quadratic_search(
         ingredients,
         U256::zero(),
-        sandwich_balance,
+        upper_bound,
         next_block,
         fork_factory,
     )
@@ -87,14 +105,120 @@ pub async fn create_optimal_sandwich_multi(
     if optimal.is_zero() {
         return Err(SimulationError::ZeroOptimal());
     }
-    sanity_check_multi(
+    let mut recipe = sanity_check_multi(
         sandwich_balance,
         optimal,
         ingredients,
         next_block,
         sandwich_maker,
         fork_factory.new_sandbox_fork(),
+        &token_profile,
+    )?;
+    recipe.set_frontrun_in(optimal);
+    Ok(recipe)
+}
+
+// Checks shared by all searches before looking for the optimal amount in
+//
+// Arguments:
+// `&ingredients`: holds onchain information about opportunity
+// `sandwich_balance`: balance of sandwich contract
+// `&next_block`: holds information about next block
+// `&mut fork_factory`: used to create new forked evm instances for simulations
+//
+// Returns:
+// Ok((TokenProfile, U256)): intermediary token's transfer profile and upper bound for the search
+// Err(SimulationError) if pool can't be sandwiched or upper bound is zero
+async fn prepare_search(
+    ingredients: &RawIngredients,
+    sandwich_balance: U256,
+    next_block: &BlockInfo,
+    fork_factory: &mut ForkFactory,
+) -> Result<(TokenProfile, U256), SimulationError> {
+    // sandwich contract only knows how to swap on uniswap style pools
+    executable_variant(&ingredients.target_pool)?;
+
+    // warm the pool's hot slots in one go instead of fetching them lazily during the search
+    prefetch_pool_state(&ingredients.target_pool, fork_factory);
+
+    // taxed/rebasing tokens are measured up front, search revenue is already taken from real
+    // balances (braindance) but sanity check needs to know how much of our sell reaches the pool
+    let token_profile = get_token_profile(
+        &ingredients.target_pool,
+        ingredients.intermediary_token,
+        next_block,
+        fork_factory,
     )
+    .await?;
+    if !token_profile.is_sandwichable(dotenv::get_max_token_tax_bps()) {
+        return Err(SimulationError::TokenNotSandwichable(token_profile));
+    }
+
+    // frontrunning past the victim's slippage tolerance makes it revert, no need to search there
+    let upper_bound = slippage_upper_bound(ingredients, sandwich_balance, fork_factory)?;
+    if upper_bound.is_zero() {
+        return Err(SimulationError::ZeroOptimal());
+    }
+
+    Ok((token_profile, upper_bound))
+}
+
+// Find the largest frontrun the victim's calldata lets us make before its swap reverts
+//
+// Only a single victim doing an exact in swap straight from `startend_token` to
+// `intermediary_token` through a v2 target pool can be bounded, anything else (multi hop paths,
+// exact out swaps, unknown amounts or routers) falls back to the full sandwich balance
+//
+// Arguments:
+// * `ingredients`: holds onchain information about opportunity
+// * `sandwich_balance`: balance of sandwich contract
+// * `fork_factory`: used to read target pool's reserves before the victim
+//
+// Returns:
+// Ok(U256): upper bound for the optimal search, zero if victim reverts without a frontrun
+// Err(SimulationError): if reserves could not be read
+fn slippage_upper_bound(
+    ingredients: &RawIngredients,
+    sandwich_balance: U256,
+    fork_factory: &mut ForkFactory,
+) -> Result<U256, SimulationError> {
+    let pool = &ingredients.target_pool;
+    let victim_swap = match ingredients.victim_swaps.as_slice() {
+        [Some(victim_swap)] => victim_swap,
+        _ => return Ok(sandwich_balance),
+    };
+
+    let (amount_in, amount_out_min) = match victim_swap.amounts {
+        SwapAmounts::ExactIn {
+            amount_in,
+            amount_out_min,
+        } => (amount_in, amount_out_min),
+        SwapAmounts::ExactOut { .. } | SwapAmounts::Unknown => return Ok(sandwich_balance),
+    };
+
+    // min amount out only describes target pool if it is the victim's only hop
+    let is_single_hop =
+        victim_swap.path == [ingredients.startend_token, ingredients.intermediary_token];
+    if pool.pool_variant != PoolVariant::UniswapV2 || !is_single_hop {
+        return Ok(sandwich_balance);
+    }
+
+    let mut evm = revm::EVM::new();
+    evm.database(fork_factory.new_sandbox_fork());
+    let (reserve_0, reserve_1) = get_reserves_evm(pool.address, &mut evm)?;
+    let (reserve_in, reserve_out) = match ingredients.startend_token == pool.token_0 {
+        true => (reserve_0, reserve_1),
+        false => (reserve_1, reserve_0),
+    };
+
+    Ok(max_frontrun_for_slippage(
+        amount_in,
+        amount_out_min,
+        reserve_in,
+        reserve_out,
+        pool.swap_fee,
+        sandwich_balance,
+    ))
 }
 
 // Roided implementation of https://research.ijcaonline.org/volume65/number14/pxc3886165.pdf
@@ -110,6 +234,7 @@ pub async fn create_optimal_sandwich_multi(
 // Returns:
 // Ok(U256): optimal amount in, if no errors during calculation
 // Err(SimulationError): if error during calculation
+#[tracing::instrument(skip_all)]
 async fn juiced_quadratic_search(
     ingredients: &RawIngredients,
     mut lower_bound: U256,
@@ -184,10 +309,10 @@ async fn juiced_quadratic_search(
             intervals.push(lower_bound + (((upper_bound - lower_bound) * i) / number_of_intervals));
         }
 
-        // calculate revenue at each interval concurrently
+        // calculate revenue at each interval concurrently (on the simulation workers)
         let mut revenues = Vec::new();
         for bound in &intervals {
-            let sim = tokio::task::spawn(evaluate_sandwich_revenue(
+            let sim = spawn_on_workers(evaluate_sandwich_revenue(
                 *bound,
                 ingredients.clone(),
                 next_block.clone(),
@@ -252,10 +377,12 @@ async fn juiced_quadratic_search(
 // `next_block`: holds information about next block
 // `sandwich_maker`: handles encoding of transaction for sandwich contract
 // `fork_db`: fork db used for evm simulations
+// `token_profile`: measured transfer behaviour of intermediary token
 //
 // Returns:
 // Ok(OptimalRecipe): params to pass to sandwich contract to capture opportunity
 // Err(SimulationError): error encountered during simulation
+#[tracing::instrument(skip_all)]
 fn sanity_check(
     _: U256,
     frontrun_in: U256,
@@ -263,15 +390,16 @@ fn sanity_check(
     next_block: &BlockInfo,
     sandwich_maker: &SandwichMaker,
     fork_db: ForkDB,
+    token_profile: &TokenProfile,
 ) -> Result<OptimalRecipe, SimulationError> {
     // setup evm simulation
     let mut evm = revm::EVM::new();
     evm.database(fork_db);
     setup_block_state(&mut evm, &next_block);
 
-    let searcher = dotenv::get_searcher_wallet().address();
+    let searcher = searcher_signer().address();
     let sandwich_contract = dotenv::get_sandwich_contract_address();
-    let pool_variant = ingredients.target_pool.pool_variant;
+    let pool_variant = executable_variant(&ingredients.target_pool)?;
 
     let sandwich_start_balance = get_balance_of_evm(
         ingredients.startend_token,
@@ -286,36 +414,43 @@ fn sanity_check(
     //
     // encode frontrun_in before passing to sandwich contract
     let frontrun_in = match pool_variant {
-        PoolVariant::UniswapV2 => tx_builder::v2::encode_weth(frontrun_in),
-        PoolVariant::UniswapV3 => tx_builder::v3::encode_weth(frontrun_in),
+        ExecutableVariant::UniswapV2 => tx_builder::v2::encode_weth(frontrun_in),
+        ExecutableVariant::UniswapV3 => tx_builder::v3::encode_weth(frontrun_in),
     };
 
     // caluclate frontrun_out using encoded frontrun_in
     let frontrun_out = match pool_variant {
-        PoolVariant::UniswapV2 => {
+        ExecutableVariant::UniswapV2 => {
             let target_pool = ingredients.target_pool.address;
             let token_in = ingredients.startend_token;
             let token_out = ingredients.intermediary_token;
             evm.env.tx.gas_price = next_block.base_fee.into();
             evm.env.tx.gas_limit = 700000;
             evm.env.tx.value = rU256::ZERO;
-            let amount_out =
-                get_amount_out_evm(frontrun_in, target_pool, token_in, token_out, &mut evm)?;
+            let swap_fee = ingredients.target_pool.swap_fee;
+            let amount_out = get_amount_out_evm(
+                frontrun_in,
+                target_pool,
+                token_in,
+                token_out,
+                swap_fee,
+                &mut evm,
+            )?;
             tx_builder::v2::decode_intermediary(amount_out, true, token_out)
         }
-        PoolVariant::UniswapV3 => U256::zero(),
+        ExecutableVariant::UniswapV3 => U256::zero(),
     };
 
     // create tx.data and tx.value for frontrun_in
     let (frontrun_data, frontrun_value) = match pool_variant {
-        PoolVariant::UniswapV2 => sandwich_maker.v2.create_payload_weth_is_input(
+        ExecutableVariant::UniswapV2 => sandwich_maker.v2.create_payload_weth_is_input(
             frontrun_in,
             frontrun_out,
             ingredients.intermediary_token,
             ingredients.target_pool,
             next_block.number
         ),
-        PoolVariant::UniswapV3 => sandwich_maker.v3.create_payload_weth_is_input(
+        ExecutableVariant::UniswapV3 => sandwich_maker.v3.create_payload_weth_is_input(
             frontrun_in.as_u128().into(),
             ingredients.startend_token,
             ingredients.intermediary_token,
@@ -343,7 +478,8 @@ fn sanity_check(
 
     // run again but now with access list (so that we get accurate gas used)
     // run with a salmonella inspector to flag `suspicious` opcodes
-    let mut salmonella_inspector = SalmonellaInspectoooor::new();
+    let mut salmonella_inspector =
+        SalmonellaInspectoooor::new(vec![ingredients.target_pool.address]);
     let frontrun_result = match evm.inspect_commit(&mut salmonella_inspector) {
         Ok(result) => result,
         Err(e) => return Err(SimulationError::FrontrunEvmError(e)),
@@ -418,31 +554,40 @@ fn sanity_check(
     let token_out = ingredients.startend_token;
     let balance = get_balance_of_evm(token_in, sandwich_contract, next_block, &mut evm)?;
     let backrun_in = match pool_variant {
-        PoolVariant::UniswapV2 => {
+        ExecutableVariant::UniswapV2 => {
             tx_builder::v2::encode_intermediary_with_dust(balance, false, token_in)
         }
-        PoolVariant::UniswapV3 => tx_builder::v3::encode_intermediary_token(balance),
+        ExecutableVariant::UniswapV3 => tx_builder::v3::encode_intermediary_token(balance),
     };
 
     // caluclate backrun_out using encoded backrun_in
     let backrun_out = match pool_variant {
-        PoolVariant::UniswapV2 => {
+        ExecutableVariant::UniswapV2 => {
             let target_pool = ingredients.target_pool.address;
-            let out = get_amount_out_evm(backrun_in, target_pool, token_in, token_out, &mut evm)?;
+            let amount_in = token_profile.after_sell_tax(backrun_in);
+            let swap_fee = ingredients.target_pool.swap_fee;
+            let out = get_amount_out_evm(
+                amount_in,
+                target_pool,
+                token_in,
+                token_out,
+                swap_fee,
+                &mut evm,
+            )?;
             tx_builder::v2::encode_weth(out)
         }
-        PoolVariant::UniswapV3 => U256::zero(),
+        ExecutableVariant::UniswapV3 => U256::zero(),
     };
 
     // create tx.data and tx.value for backrun_in
     let (backrun_data, backrun_value) = match pool_variant {
-        PoolVariant::UniswapV2 => sandwich_maker.v2.create_payload_weth_is_output(
+        ExecutableVariant::UniswapV2 => sandwich_maker.v2.create_payload_weth_is_output(
             backrun_in,
             backrun_out,
             ingredients.intermediary_token,
             ingredients.target_pool,
         ),
-        PoolVariant::UniswapV3 => (
+        ExecutableVariant::UniswapV3 => (
             sandwich_maker.v3.create_payload_weth_is_output(
                 backrun_in.as_u128().into(),
                 ingredients.intermediary_token,
@@ -471,7 +616,8 @@ fn sanity_check(
 
     // run again but now with access list (so that we get accurate gas used)
     // run with a salmonella inspector to flag `suspicious` opcodes
-    let mut salmonella_inspector = SalmonellaInspectoooor::new();
+    let mut salmonella_inspector =
+        SalmonellaInspectoooor::new(vec![ingredients.target_pool.address]);
     let backrun_result = match evm.inspect_commit(&mut salmonella_inspector) {
         Ok(result) => result,
         Err(e) => return Err(SimulationError::BackrunEvmError(e)),
@@ -541,10 +687,12 @@ fn sanity_check(
 // `next_block`: holds information about next block
 // `sandwich_maker`: handles encoding of transaction for sandwich contract
 // `fork_db`: fork db used for evm simulations
+// `token_profile`: measured transfer behaviour of intermediary token
 //
 // Returns:
 // Ok(OptimalRecipe): params to pass to sandwich contract to capture opportunity
 // Err(SimulationError): error encountered during simulation
+#[tracing::instrument(skip_all)]
 fn sanity_check_multi(
     _: U256,
     frontrun_in: U256,
@@ -552,15 +700,16 @@ fn sanity_check_multi(
     next_block: &BlockInfo,
     sandwich_maker: &SandwichMaker,
     fork_db: ForkDB,
+    token_profile: &TokenProfile,
 ) -> Result<OptimalRecipe, SimulationError> {
     // setup evm simulation
     let mut evm = revm::EVM::new();
     evm.database(fork_db);
     setup_block_state(&mut evm, &next_block);
 
-    let searcher = dotenv::get_searcher_wallet().address();
+    let searcher = searcher_signer().address();
     let sandwich_contract = dotenv::get_sandwich_contract_address();
-    let pool_variant = ingredients.target_pool.pool_variant;
+    let pool_variant = executable_variant(&ingredients.target_pool)?;
     let sandwich_start_balance = get_balance_of_evm(
         ingredients.startend_token,
         sandwich_contract,
@@ -573,35 +722,42 @@ fn sanity_check_multi(
     //
     // encode frontrun_in before passing to sandwich contract
     let frontrun_in = match pool_variant {
-        PoolVariant::UniswapV2 => tx_builder::v2::decode_intermediary2(frontrun_in, true),
-        PoolVariant::UniswapV3 => frontrun_in,
+        ExecutableVariant::UniswapV2 => tx_builder::v2::decode_intermediary2(frontrun_in, true),
+        ExecutableVariant::UniswapV3 => frontrun_in,
     };
 
     // caluclate frontrun_out using encoded frontrun_in
     let frontrun_out = match pool_variant {
-        PoolVariant::UniswapV2 => {
+        ExecutableVariant::UniswapV2 => {
             let target_pool = ingredients.target_pool.address;
             let token_in = ingredients.startend_token;
             let token_out = ingredients.intermediary_token;
             evm.env.tx.gas_price = next_block.base_fee.into();
             evm.env.tx.gas_limit = 700000;
             evm.env.tx.value = rU256::ZERO;
-            let amount_out =
-                get_amount_out_evm(frontrun_in, target_pool, token_in, token_out, &mut evm)?;
+            let swap_fee = ingredients.target_pool.swap_fee;
+            let amount_out = get_amount_out_evm(
+                frontrun_in,
+                target_pool,
+                token_in,
+                token_out,
+                swap_fee,
+                &mut evm,
+            )?;
             tx_builder::v2::decode_intermediary(amount_out, true, token_out)
         }
-        PoolVariant::UniswapV3 => U256::zero(),
+        ExecutableVariant::UniswapV3 => U256::zero(),
     };
 
     // create tx.data and tx.value for frontrun_in
     let (frontrun_data, frontrun_value) = match pool_variant {
-        PoolVariant::UniswapV2 => sandwich_maker.v2.create_payload_weth_is_input_multi(
+        ExecutableVariant::UniswapV2 => sandwich_maker.v2.create_payload_weth_is_input_multi(
             frontrun_in,
             frontrun_out,
             ingredients.intermediary_token,
             ingredients.target_pool,
         ),
-        PoolVariant::UniswapV3 => sandwich_maker.v3.create_payload_weth_is_input_multi_call(
+        ExecutableVariant::UniswapV3 => sandwich_maker.v3.create_payload_weth_is_input_multi_call(
             frontrun_in,
             ingredients.startend_token,
             ingredients.intermediary_token,
@@ -628,7 +784,8 @@ fn sanity_check_multi(
 
     // run again but now with access list (so that we get accurate gas used)
     // run with a salmonella inspector to flag `suspicious` opcodes
-    let mut salmonella_inspector = SalmonellaInspectoooor::new();
+    let mut salmonella_inspector =
+        SalmonellaInspectoooor::new(vec![ingredients.target_pool.address]);
     let frontrun_result = match evm.inspect_commit(&mut salmonella_inspector) {
         Ok(result) => result,
         Err(e) => return Err(SimulationError::FrontrunEvmError(e)),
@@ -704,31 +861,40 @@ fn sanity_check_multi(
     let balance = get_balance_of_evm(token_in, sandwich_contract, next_block, &mut evm)?;
 
     let backrun_in = match pool_variant {
-        PoolVariant::UniswapV2 => {
+        ExecutableVariant::UniswapV2 => {
             tx_builder::v2::encode_intermediary_with_dust(balance, false, token_in)
         }
-        PoolVariant::UniswapV3 => balance,
+        ExecutableVariant::UniswapV3 => balance,
     };
 
     // caluclate backrun_out using encoded backrun_in
     let backrun_out = match pool_variant {
-        PoolVariant::UniswapV2 => {
+        ExecutableVariant::UniswapV2 => {
             let target_pool = ingredients.target_pool.address;
-            let out = get_amount_out_evm(backrun_in, target_pool, token_in, token_out, &mut evm)?;
+            let amount_in = token_profile.after_sell_tax(backrun_in);
+            let swap_fee = ingredients.target_pool.swap_fee;
+            let out = get_amount_out_evm(
+                amount_in,
+                target_pool,
+                token_in,
+                token_out,
+                swap_fee,
+                &mut evm,
+            )?;
             tx_builder::v2::decode_intermediary(out, true, token_out)
         }
-        PoolVariant::UniswapV3 => U256::zero(),
+        ExecutableVariant::UniswapV3 => U256::zero(),
     };
 
     // create tx.data and tx.value for backrun_in
     let (backrun_data, backrun_value) = match pool_variant {
-        PoolVariant::UniswapV2 => sandwich_maker.v2.create_payload_weth_is_output_multi(
+        ExecutableVariant::UniswapV2 => sandwich_maker.v2.create_payload_weth_is_output_multi(
             backrun_in,
             backrun_out,
             ingredients.intermediary_token,
             ingredients.target_pool,
         ),
-        PoolVariant::UniswapV3 => (
+        ExecutableVariant::UniswapV3 => (
             sandwich_maker.v3.create_payload_weth_is_output_multi_call(
                 backrun_in,
                 ingredients.intermediary_token,
@@ -757,7 +923,8 @@ fn sanity_check_multi(
 
     // run again but now with access list (so that we get accurate gas used)
     // run with a salmonella inspector to flag `suspicious` opcodes
-    let mut salmonella_inspector = SalmonellaInspectoooor::new();
+    let mut salmonella_inspector =
+        SalmonellaInspectoooor::new(vec![ingredients.target_pool.address]);
     let backrun_result = match evm.inspect_commit(&mut salmonella_inspector) {
         Ok(result) => result,
         Err(e) => return Err(SimulationError::BackrunEvmError(e)),
@@ -832,7 +999,7 @@ pub fn sanity_check_mega(
     let mut evm = revm::EVM::new();
     evm.database(fork_db);
     setup_block_state(&mut evm, &next_block);
-    let searcher = dotenv::get_searcher_wallet().address();
+    let searcher = searcher_signer().address();
     let sandwich_contract = dotenv::get_sandwich_contract_address();
     let weth_address = constants::get_weth_address();
     let sandwich_start_balance =
@@ -855,7 +1022,7 @@ pub fn sanity_check_mega(
 
     // run again but now with access list (so that we get accurate gas used)
     // run with a salmonella inspector to flag `suspicious` opcodes
-    let mut salmonella_inspector = SalmonellaInspectoooor::new();
+    let mut salmonella_inspector = SalmonellaInspectoooor::new(vec![target_pool.address]);
     let frontrun_result = match evm.inspect_commit(&mut salmonella_inspector) {
         Ok(result) => result,
         Err(e) => return Err(SimulationError::FrontrunEvmError(e)),
@@ -941,7 +1108,7 @@ pub fn sanity_check_mega(
 
     // run again but now with access list (so that we get accurate gas used)
     // run with a salmonella inspector to flag `suspicious` opcodes
-    let mut salmonella_inspector = SalmonellaInspectoooor::new();
+    let mut salmonella_inspector = SalmonellaInspectoooor::new(vec![target_pool.address]);
     let backrun_result = match evm.inspect_commit(&mut salmonella_inspector) {
         Ok(result) => result,
         Err(e) => return Err(SimulationError::BackrunEvmError(e)),
@@ -994,6 +1161,100 @@ pub fn sanity_check_mega(
     
     // return Err(SimulationError::FrontrunReverted("output".into()));
 }
+
+// Simulate a built recipe again on a block with different values (e.g. another builder's
+// coinbase), frontrun and backrun are checked by the salmonella inspector
+//
+// Arguments:
+// * `recipe`: recipe to simulate, its signed data, values and access lists are used as is
+// * `next_block`: block to simulate on
+// * `fork_db`: database instance used for evm simulations
+//
+// Returns:
+// Ok(U256): revenue of the recipe on `next_block`
+// Err(SimulationError): if frontrun or backrun fails or is not safu
+pub fn resimulate_recipe(
+    recipe: &OptimalRecipe,
+    next_block: &BlockInfo,
+    fork_db: ForkDB,
+) -> Result<U256, SimulationError> {
+    let mut evm = revm::EVM::new();
+    evm.database(fork_db);
+    setup_block_state(&mut evm, next_block);
+
+    let searcher = searcher_signer().address();
+    let sandwich_contract = dotenv::get_sandwich_contract_address();
+    let weth_address = constants::get_weth_address();
+    let sandwich_start_balance =
+        get_balance_of_evm(weth_address, sandwich_contract, next_block, &mut evm)?;
+
+    // frontrun
+    evm.env.tx.caller = searcher.0.into();
+    evm.env.tx.transact_to = TransactTo::Call(sandwich_contract.0.into());
+    evm.env.tx.data = recipe.frontrun_data.0.clone();
+    evm.env.tx.value = recipe.frontrun_value.into();
+    evm.env.tx.gas_limit = 700000;
+    evm.env.tx.gas_price = next_block.base_fee.into();
+    evm.env.tx.gas_priority_fee = None;
+    evm.env.tx.nonce = None;
+    evm.env.tx.access_list = convert_access_list_to_revm(&recipe.frontrun_access_list);
+
+    let mut salmonella_inspector = SalmonellaInspectoooor::new(vec![recipe.target_pool.address]);
+    let frontrun_result = evm
+        .inspect_commit(&mut salmonella_inspector)
+        .map_err(SimulationError::FrontrunEvmError)?;
+    match frontrun_result {
+        ExecutionResult::Success { .. } => { /* continue operation */ }
+        ExecutionResult::Revert { output, .. } => {
+            return Err(SimulationError::FrontrunReverted(output))
+        }
+        ExecutionResult::Halt { reason, .. } => {
+            return Err(SimulationError::FrontrunHalted(reason))
+        }
+    };
+    if let IsSandoSafu::NotSafu(not_safu_opcodes) = salmonella_inspector.is_sando_safu() {
+        return Err(SimulationError::FrontrunNotSafu(not_safu_opcodes));
+    }
+
+    // meats, reverted meats are kept (they were kept when the recipe was built)
+    for meat in recipe.meats.iter() {
+        setup_tx_state(&mut evm, meat);
+        evm.transact_commit().map_err(SimulationError::EvmError)?;
+    }
+    evm.env.tx.nonce = None;
+
+    // backrun
+    evm.env.tx.caller = searcher.0.into();
+    evm.env.tx.transact_to = TransactTo::Call(sandwich_contract.0.into());
+    evm.env.tx.data = recipe.backrun_data.0.clone();
+    evm.env.tx.value = recipe.backrun_value.into();
+    evm.env.tx.gas_limit = 700000;
+    evm.env.tx.gas_price = next_block.base_fee.into();
+    evm.env.tx.gas_priority_fee = None;
+    evm.env.tx.access_list = convert_access_list_to_revm(&recipe.backrun_access_list);
+
+    let mut salmonella_inspector = SalmonellaInspectoooor::new(vec![recipe.target_pool.address]);
+    let backrun_result = evm
+        .inspect_commit(&mut salmonella_inspector)
+        .map_err(SimulationError::BackrunEvmError)?;
+    match backrun_result {
+        ExecutionResult::Success { .. } => { /* continue */ }
+        ExecutionResult::Revert { output, .. } => {
+            return Err(SimulationError::BackrunReverted(output))
+        }
+        ExecutionResult::Halt { reason, .. } => return Err(SimulationError::BackrunHalted(reason)),
+    };
+    if let IsSandoSafu::NotSafu(not_safu_opcodes) = salmonella_inspector.is_sando_safu() {
+        return Err(SimulationError::BackrunNotSafu(not_safu_opcodes));
+    }
+
+    let post_sandwich_balance =
+        get_balance_of_evm(weth_address, sandwich_contract, next_block, &mut evm)?;
+    Ok(post_sandwich_balance
+        .checked_sub(sandwich_start_balance)
+        .unwrap_or_default())
+}
+
 /// Sandwich simulation using BrainDance contract (modified router contract)
 ///
 /// Arguments:
@@ -1011,19 +1272,19 @@ async fn evaluate_sandwich_revenue(
     evm.database(fork_db);
     setup_block_state(&mut evm, &next_block);
 
-    let pool_variant = ingredients.target_pool.pool_variant;
+    let pool_variant = executable_variant(&ingredients.target_pool)?;
 
     /*´:°•.°+.*•´.*:˚.°*.˚•´.°:°•.°•.*•´.*:˚.°*.˚•´.°:°•.°+.*•´.*:*/
     /*                    FRONTRUN TRANSACTION                    */
     /*.•°:°.´+˚.*°.˚:*.´•*.+°.•°:´*.´•*.•°.•°:°.´:•˚°.*°.˚:*.´+°.•*/
     let frontrun_data = match pool_variant {
-        PoolVariant::UniswapV2 => braindance::build_swap_v2_data(
+        ExecutableVariant::UniswapV2 => braindance::build_swap_v2_data(
             frontrun_in,
             ingredients.target_pool.address,
             ingredients.startend_token,
             ingredients.intermediary_token,
         ),
-        PoolVariant::UniswapV3 => braindance::build_swap_v3_data(
+        ExecutableVariant::UniswapV3 => braindance::build_swap_v3_data(
             frontrun_in.as_u128().into(),
             ingredients.target_pool.address,
             ingredients.startend_token,
@@ -1055,13 +1316,13 @@ async fn evaluate_sandwich_revenue(
         }
     };
     let (_frontrun_out, backrun_in) = match pool_variant {
-        PoolVariant::UniswapV2 => {
+        ExecutableVariant::UniswapV2 => {
             match tx_builder::braindance::decode_swap_v2_result(output.into()) {
                 Ok(output) => output,
                 Err(e) => return Err(SimulationError::FailedToDecodeOutput(e)),
             }
         }
-        PoolVariant::UniswapV3 => {
+        ExecutableVariant::UniswapV3 => {
             match tx_builder::braindance::decode_swap_v3_result(output.into()) {
                 Ok(output) => output,
                 Err(e) => return Err(SimulationError::FailedToDecodeOutput(e)),
@@ -1104,13 +1365,13 @@ async fn evaluate_sandwich_revenue(
     /*                    BACKRUN TRANSACTION                     */
     /*.•°:°.´+˚.*°.˚:*.´•*.+°.•°:´*.´•*.•°.•°:°.´:•˚°.*°.˚:*.´+°.•*/
     let backrun_data = match pool_variant {
-        PoolVariant::UniswapV2 => braindance::build_swap_v2_data(
+        ExecutableVariant::UniswapV2 => braindance::build_swap_v2_data(
             backrun_in,
             ingredients.target_pool.address,
             ingredients.intermediary_token,
             ingredients.startend_token,
         ),
-        PoolVariant::UniswapV3 => braindance::build_swap_v3_data(
+        ExecutableVariant::UniswapV3 => braindance::build_swap_v3_data(
             backrun_in.as_u128().into(),
             ingredients.target_pool.address,
             ingredients.intermediary_token,
@@ -1141,13 +1402,13 @@ async fn evaluate_sandwich_revenue(
         ExecutionResult::Halt { reason, .. } => return Err(SimulationError::BackrunHalted(reason)),
     };
     let (_backrun_out, post_sandwich_balance) = match pool_variant {
-        PoolVariant::UniswapV2 => {
+        ExecutableVariant::UniswapV2 => {
             match tx_builder::braindance::decode_swap_v2_result(output.into()) {
                 Ok(output) => output,
                 Err(e) => return Err(SimulationError::FailedToDecodeOutput(e)),
             }
         }
-        PoolVariant::UniswapV3 => {
+        ExecutableVariant::UniswapV3 => {
             match tx_builder::braindance::decode_swap_v3_result(output.into()) {
                 Ok(output) => output,
                 Err(e) => return Err(SimulationError::FailedToDecodeOutput(e)),
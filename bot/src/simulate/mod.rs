@@ -1,7 +1,11 @@
 pub mod helpers;
 pub mod inspectors;
 pub mod make_sandwich;
+pub mod prefetch;
+pub mod token_profiler;
 
 pub use helpers::*;
 pub use inspectors::*;
 pub use make_sandwich::*;
+pub use prefetch::*;
+pub use token_profiler::*;
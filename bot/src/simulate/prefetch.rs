@@ -0,0 +1,289 @@
+use ethers::{
+    abi,
+    types::{Address, I256, U256},
+    utils::keccak256,
+};
+use revm::primitives::{B160 as rAddress, U256 as rU256};
+
+use crate::prelude::fork_factory::ForkFactory;
+use crate::prelude::{DatabaseError, Pool, PoolVariant};
+use crate::utils::{constants, dotenv};
+
+// weth9 keeps `balanceOf` in slot 3
+const WETH_BALANCE_OF_SLOT: u64 = 3;
+
+// `UniswapV2Pair` storage layout
+const V2_TOKEN_0_SLOT: u64 = 6;
+const V2_TOKEN_1_SLOT: u64 = 7;
+const V2_RESERVES_SLOT: u64 = 8;
+const V2_PRICE_0_CUMULATIVE_SLOT: u64 = 9;
+const V2_PRICE_1_CUMULATIVE_SLOT: u64 = 10;
+const V2_UNLOCKED_SLOT: u64 = 12;
+
+// `UniswapV3Pool` storage layout
+const V3_SLOT_0: u64 = 0;
+const V3_FEE_GROWTH_GLOBAL_0_SLOT: u64 = 1;
+const V3_FEE_GROWTH_GLOBAL_1_SLOT: u64 = 2;
+const V3_LIQUIDITY_SLOT: u64 = 4;
+const V3_TICKS_SLOT: u64 = 5;
+const V3_TICK_BITMAP_SLOT: u64 = 6;
+const V3_OBSERVATIONS_SLOT: u64 = 8;
+// a `Tick.Info` struct spans 4 slots
+const V3_TICK_INFO_SLOTS: u64 = 4;
+// initialized ticks warmed on each side of the current tick
+const V3_TICKS_PER_SIDE: usize = 4;
+
+// Warm the fork factory with the storage a swap on `pool` is known to touch
+//
+// Without this a fork only learns which slots it needs while executing and fetches them one
+// blocking call at a time. Prefetching is best effort, failures are logged and the search falls
+// back to fetching lazily
+//
+// Arguments:
+// * `pool`: pool that will be sandwiched
+// * `fork_factory`: mutable reference to `ForkFactory` that forks of the search are created from
+//
+// Returns: This function returns nothing
+pub fn prefetch_pool_state(pool: &Pool, fork_factory: &mut ForkFactory) {
+    let result = match pool.pool_variant {
+        PoolVariant::UniswapV2 => fork_factory
+            .prefetch_storage(&v2_hot_slots(pool))
+            .map(|_| ()),
+        PoolVariant::UniswapV3 => prefetch_v3(pool, fork_factory),
+        // no executor for other variants, nothing will be simulated on them
+        _ => Ok(()),
+    };
+
+    if let Err(e) = result {
+        log::warn!("Failed to prefetch state of {:?}: {:?}", pool.address, e);
+    }
+}
+
+// Slots read and written by a v2 swap (and weth balances moved by the sandwich)
+fn v2_hot_slots(pool: &Pool) -> Vec<(rAddress, rU256)> {
+    let pair = to_raddress(pool.address);
+    let mut slots: Vec<(rAddress, rU256)> = [
+        V2_TOKEN_0_SLOT,
+        V2_TOKEN_1_SLOT,
+        V2_RESERVES_SLOT,
+        V2_PRICE_0_CUMULATIVE_SLOT,
+        V2_PRICE_1_CUMULATIVE_SLOT,
+        V2_UNLOCKED_SLOT,
+    ]
+    .into_iter()
+    .map(|slot| (pair, rU256::from(slot)))
+    .collect();
+
+    slots.extend(weth_balance_slots(pool));
+    slots
+}
+
+// Warm a v3 pool in three rounds, each round needs values fetched by the previous one
+// 1. `slot0`, liquidity, fee growth and weth balances
+// 2. bitmap words around the current tick and the oracle observations that will be written
+// 3. `Tick.Info` of the initialized ticks closest to the current tick
+fn prefetch_v3(pool: &Pool, fork_factory: &mut ForkFactory) -> Result<(), DatabaseError> {
+    let address = to_raddress(pool.address);
+    let tick_spacing = match v3_tick_spacing(pool.swap_fee) {
+        Some(tick_spacing) => tick_spacing,
+        None => return Ok(()),
+    };
+
+    let mut slots: Vec<(rAddress, rU256)> = [
+        V3_SLOT_0,
+        V3_FEE_GROWTH_GLOBAL_0_SLOT,
+        V3_FEE_GROWTH_GLOBAL_1_SLOT,
+        V3_LIQUIDITY_SLOT,
+    ]
+    .into_iter()
+    .map(|slot| (address, rU256::from(slot)))
+    .collect();
+    slots.extend(weth_balance_slots(pool));
+    let slot_0 = U256::from(fork_factory.prefetch_storage(&slots)?[0]);
+
+    let slot_0 = V3Slot0::decode(slot_0);
+    let word_pos = tick_word_pos(slot_0.tick, tick_spacing);
+    let word_positions = [word_pos - 1, word_pos, word_pos + 1];
+
+    let mut slots: Vec<(rAddress, rU256)> = word_positions
+        .iter()
+        .map(|word_pos| (address, v3_bitmap_slot(*word_pos)))
+        .collect();
+    slots.extend(
+        slot_0
+            .written_observations()
+            .into_iter()
+            .map(|index| (address, rU256::from(V3_OBSERVATIONS_SLOT + index as u64))),
+    );
+    let words = fork_factory.prefetch_storage(&slots)?;
+
+    let words: Vec<(i32, U256)> = word_positions
+        .into_iter()
+        .zip(words.into_iter().map(U256::from))
+        .collect();
+    let slots: Vec<(rAddress, rU256)> =
+        nearest_initialized_ticks(&words, tick_spacing, slot_0.tick, V3_TICKS_PER_SIDE)
+            .into_iter()
+            .flat_map(|tick| {
+                let info_slot = v3_tick_info_slot(tick);
+                (0..V3_TICK_INFO_SLOTS).map(move |i| (address, info_slot + rU256::from(i)))
+            })
+            .collect();
+    fork_factory.prefetch_storage(&slots)?;
+
+    Ok(())
+}
+
+/// Fields of a v3 pool's packed `slot0` needed to find hot slots
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct V3Slot0 {
+    tick: i32,
+    observation_index: u16,
+    observation_cardinality_next: u16,
+}
+
+impl V3Slot0 {
+    // `slot0` is packed as [ ... | 16 bits cardinality next | 16 bits cardinality |
+    // 16 bits observation index | 24 bits tick | 160 bits price ]
+    fn decode(slot_0: U256) -> Self {
+        let tick = ((slot_0 >> 160).low_u32() & 0xffffff) as i32;
+        // sign extend int24
+        let tick = (tick << 8) >> 8;
+        Self {
+            tick,
+            observation_index: (slot_0 >> 184).low_u32() as u16,
+            observation_cardinality_next: (slot_0 >> 216).low_u32() as u16,
+        }
+    }
+
+    // Observations a swap reads (current) and may write (next, first swap of a block)
+    fn written_observations(&self) -> Vec<u16> {
+        let next = match self.observation_cardinality_next {
+            0 => return vec![self.observation_index],
+            cardinality => ((self.observation_index as u32 + 1) % cardinality as u32) as u16,
+        };
+        vec![self.observation_index, next]
+    }
+}
+
+// Tick spacing uniswap v3's factory assigns to each fee tier
+fn v3_tick_spacing(swap_fee: U256) -> Option<i32> {
+    match swap_fee.as_u64() {
+        100 => Some(1),
+        500 => Some(10),
+        3000 => Some(60),
+        10000 => Some(200),
+        _ => None,
+    }
+}
+
+// Position of the bitmap word holding a tick (`TickBitmap.position`)
+fn tick_word_pos(tick: i32, tick_spacing: i32) -> i32 {
+    tick.div_euclid(tick_spacing).div_euclid(256)
+}
+
+// Find initialized ticks closest to the current tick (on both sides)
+//
+// Arguments:
+// * `words`: (word position, bitmap word) pairs
+// * `tick_spacing`: tick spacing of pool
+// * `current_tick`: tick pool is at
+// * `per_side`: max number of ticks to return below and above current tick
+//
+// Returns:
+// Vec<i32>: initialized ticks at or below current tick then above it, closest first
+fn nearest_initialized_ticks(
+    words: &[(i32, U256)],
+    tick_spacing: i32,
+    current_tick: i32,
+    per_side: usize,
+) -> Vec<i32> {
+    let mut ticks: Vec<i32> = words
+        .iter()
+        .flat_map(|(word_pos, word)| {
+            (0..256)
+                .filter(move |bit| word.bit(*bit))
+                .map(move |bit| (word_pos * 256 + bit as i32) * tick_spacing)
+        })
+        .collect();
+    ticks.sort_by_key(|tick| (tick - current_tick).abs());
+
+    let (below, above): (Vec<i32>, Vec<i32>) =
+        ticks.into_iter().partition(|tick| *tick <= current_tick);
+    below
+        .into_iter()
+        .take(per_side)
+        .chain(above.into_iter().take(per_side))
+        .collect()
+}
+
+// Slot of `tickBitmap[word_pos]`
+fn v3_bitmap_slot(word_pos: i32) -> rU256 {
+    mapping_slot(
+        abi::Token::Int(I256::from(word_pos).into_raw()),
+        V3_TICK_BITMAP_SLOT,
+    )
+}
+
+// First slot of `ticks[tick]`
+fn v3_tick_info_slot(tick: i32) -> rU256 {
+    mapping_slot(abi::Token::Int(I256::from(tick).into_raw()), V3_TICKS_SLOT)
+}
+
+// Weth balances of the pool and the sandwich contract
+fn weth_balance_slots(pool: &Pool) -> Vec<(rAddress, rU256)> {
+    let weth = to_raddress(constants::get_weth_address());
+    [pool.address, dotenv::get_sandwich_contract_address()]
+        .into_iter()
+        .map(|owner| {
+            let slot = mapping_slot(abi::Token::Address(owner), WETH_BALANCE_OF_SLOT);
+            (weth, slot)
+        })
+        .collect()
+}
+
+// Slot of a value in a solidity mapping stored at `mapping_slot`
+fn mapping_slot(key: abi::Token, mapping_slot: u64) -> rU256 {
+    let slot = keccak256(abi::encode(&[
+        key,
+        abi::Token::Uint(U256::from(mapping_slot)),
+    ]));
+    rU256::from_be_bytes(slot)
+}
+
+fn to_raddress(address: Address) -> rAddress {
+    address.0.into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn slot_0_decodes_negative_tick() {
+        let tick = (-887220i32 as u32 & 0xffffff) as u64;
+        let slot_0 = (U256::from(7) << 216) | (U256::from(6) << 184) | (U256::from(tick) << 160);
+
+        let slot_0 = V3Slot0::decode(slot_0);
+        assert_eq!(slot_0.tick, -887220);
+        assert_eq!(slot_0.written_observations(), vec![6, 0]);
+    }
+
+    #[test]
+    fn word_pos_rounds_towards_negative_infinity() {
+        assert_eq!(tick_word_pos(0, 60), 0);
+        assert_eq!(tick_word_pos(-1, 60), -1);
+        assert_eq!(tick_word_pos(256 * 60, 60), 1);
+    }
+
+    #[test]
+    fn nearest_ticks_are_taken_from_both_sides() {
+        // bits 1, 3 and 10 of word 0 (ticks 60, 180 and 600) and bit 255 of word -1 (tick -60)
+        let word_0 = U256::from((1u64 << 1) | (1u64 << 3) | (1u64 << 10));
+        let word_neg = U256::one() << 255;
+        let words = [(-1, word_neg), (0, word_0)];
+
+        let ticks = nearest_initialized_ticks(&words, 60, 100, 2);
+        assert_eq!(ticks, vec![60, -60, 180, 600]);
+    }
+}
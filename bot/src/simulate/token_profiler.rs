@@ -0,0 +1,347 @@
+use std::sync::OnceLock;
+
+use dashmap::DashMap;
+use ethers::prelude::*;
+use ethers::utils::parse_ether;
+use revm::primitives::{ExecutionResult, Output, TransactTo, U256 as rU256};
+use revm::EVM;
+
+use crate::prelude::fork_db::ForkDB;
+use crate::prelude::fork_factory::ForkFactory;
+use crate::prelude::{ExecutableVariant, Pool};
+use crate::types::{BlockInfo, SimulationError};
+use crate::utils::{self, tx_builder::braindance};
+
+use super::{
+    attach_braindance_module, braindance_address, braindance_controller_address,
+    executable_variant, get_amount_out_evm, get_balance_of_evm, setup_block_state,
+};
+
+// Taxes are expressed in basis points
+const BPS: u64 = 10_000;
+
+// Max number of cached profiles, expired and then oldest profiles are dropped past this
+const PROFILE_CACHE_MAX: usize = 10_000;
+
+/// Transfer behaviour of a token measured by simulating a buy and sell
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenProfile {
+    /// portion of bought tokens that never reach the buyer (bps)
+    pub buy_tax_bps: u64,
+    /// portion of sold tokens that never reach the pool (bps)
+    pub sell_tax_bps: u64,
+    /// balance changes as blocks pass without any transfers (e.g. per block interest or
+    /// reflections), rebases triggered by an outside call (stETH oracle reports, AMPL's
+    /// `rebase`) do not happen during the simulation and are not detected
+    pub is_rebasing: bool,
+}
+
+impl TokenProfile {
+    // Is the token safe to sandwich given a max acceptable tax
+    //
+    // Arguments:
+    // * `&self`: reference to `TokenProfile` instance
+    // * `max_tax_bps`: max acceptable buy/sell tax
+    //
+    // Returns:
+    // bool: true if token can be sandwiched, false otherwise
+    pub fn is_sandwichable(&self, max_tax_bps: u64) -> bool {
+        !self.is_rebasing && self.buy_tax_bps <= max_tax_bps && self.sell_tax_bps <= max_tax_bps
+    }
+
+    // Find amount that reaches the pool when selling `amount`
+    pub fn after_sell_tax(&self, amount: U256) -> U256 {
+        amount * (BPS - self.sell_tax_bps) / BPS
+    }
+}
+
+// Profiles are cached alongside the block they were measured in
+fn profile_cache() -> &'static DashMap<Address, (TokenProfile, U64)> {
+    static CACHE: OnceLock<DashMap<Address, (TokenProfile, U64)>> = OnceLock::new();
+    CACHE.get_or_init(DashMap::new)
+}
+
+// Get profile of a token, simulating a buy and sell if no fresh profile is cached
+//
+// Arguments:
+// * `pool`: pool used to buy and sell the token
+// * `token`: token to profile
+// * `next_block`: holds information about next block
+// * `fork_factory`: used to create a sandboxed fork for the simulation
+//
+// Returns:
+// Ok(TokenProfile): measured or cached token profile
+// Err(SimulationError): if token could not be bought or sold
+pub async fn get_token_profile(
+    pool: &Pool,
+    token: Address,
+    next_block: &BlockInfo,
+    fork_factory: &mut ForkFactory,
+) -> Result<TokenProfile, SimulationError> {
+    let ttl_blocks = utils::dotenv::get_token_profile_ttl_blocks();
+    if let Some(cached) = profile_cache().get(&token) {
+        let (profile, measured_at) = *cached;
+        if next_block.number <= measured_at + ttl_blocks {
+            return Ok(profile);
+        }
+    }
+
+    attach_braindance_module(fork_factory);
+    let profile = profile_token(pool, token, next_block, fork_factory.new_sandbox_fork())?;
+
+    insert_bounded(
+        profile_cache(),
+        token,
+        profile,
+        next_block.number,
+        ttl_blocks,
+        PROFILE_CACHE_MAX,
+    );
+    Ok(profile)
+}
+
+// Cache a profile, making room by dropping expired profiles and then the oldest ones
+//
+// Arguments:
+// * `cache`: profile cache to insert into
+// * `token`: token that was profiled
+// * `profile`: measured profile
+// * `block`: block the profile was measured in
+// * `ttl_blocks`: number of blocks a profile is reused for
+// * `max_len`: max number of cached profiles
+fn insert_bounded(
+    cache: &DashMap<Address, (TokenProfile, U64)>,
+    token: Address,
+    profile: TokenProfile,
+    block: U64,
+    ttl_blocks: u64,
+    max_len: usize,
+) {
+    if cache.len() >= max_len && !cache.contains_key(&token) {
+        cache.retain(|_, (_, measured_at)| block <= *measured_at + ttl_blocks);
+
+        while cache.len() >= max_len {
+            let oldest = cache.iter().min_by_key(|e| e.value().1).map(|e| *e.key());
+            match oldest {
+                Some(oldest) => cache.remove(&oldest),
+                None => break,
+            };
+        }
+    }
+
+    cache.insert(token, (profile, block));
+}
+
+// Measure buy tax, sell tax and rebasing behaviour of a token
+//
+// Arguments:
+// * `pool`: pool used to buy and sell the token
+// * `token`: token to profile
+// * `next_block`: holds information about next block
+// * `fork_db`: fork db used for evm simulations
+//
+// Returns:
+// Ok(TokenProfile): measured token profile
+// Err(SimulationError): if token could not be bought or sold
+fn profile_token(
+    pool: &Pool,
+    token: Address,
+    next_block: &BlockInfo,
+    fork_db: ForkDB,
+) -> Result<TokenProfile, SimulationError> {
+    let pool_variant = executable_variant(pool)?;
+    let mut evm = revm::EVM::new();
+    evm.database(fork_db);
+    setup_block_state(&mut evm, next_block);
+
+    let weth = utils::constants::get_weth_address();
+    let braindance = Address::from(braindance_address().0);
+    let probe_amount = parse_ether("0.05").unwrap();
+
+    // buy: `amount_out` is what the pool sent, `bought` is what actually reached us
+    let (amount_out, bought) =
+        braindance_swap(&mut evm, pool, probe_amount, weth, token, next_block)
+            .map_err(to_frontrun_error)?;
+    let buy_tax_bps = tax_bps(amount_out, bought);
+
+    // rebasing: balance should not move when time passes without any transfers
+    let balance_before = get_balance_of_evm(token, braindance, next_block, &mut evm)?;
+    evm.env.block.timestamp += rU256::from(86400);
+    evm.env.block.number += rU256::from(7200);
+    let balance_after = get_balance_of_evm(token, braindance, next_block, &mut evm)?;
+    setup_block_state(&mut evm, next_block);
+    let is_rebasing = balance_before != balance_after;
+
+    // sell: braindance prices the swap off what the pool actually received
+    let sell_tax_bps = match pool_variant {
+        ExecutableVariant::UniswapV2 => {
+            // braindance always prices with a 0.3% fee, quote the same way so fees are not
+            // mistaken for tax
+            let braindance_fee = U256::from(3000);
+            let expected_out =
+                get_amount_out_evm(bought, pool.address, token, weth, braindance_fee, &mut evm)?;
+            let (actual_out, _) = braindance_swap(&mut evm, pool, bought, token, weth, next_block)
+                .map_err(to_backrun_error)?;
+            tax_bps(expected_out, actual_out)
+        }
+        // v3 pools revert if they receive less than owed, measure what reached the pool anyway
+        // so that the sell is taxed the same way as on v2
+        ExecutableVariant::UniswapV3 => {
+            let pool_before = get_balance_of_evm(token, pool.address, next_block, &mut evm)?;
+            braindance_swap(&mut evm, pool, bought, token, weth, next_block)
+                .map_err(to_backrun_error)?;
+            let pool_after = get_balance_of_evm(token, pool.address, next_block, &mut evm)?;
+            tax_bps(bought, pool_after.saturating_sub(pool_before))
+        }
+    };
+
+    Ok(TokenProfile {
+        buy_tax_bps,
+        sell_tax_bps,
+        is_rebasing,
+    })
+}
+
+// Swap through braindance contract
+//
+// Returns:
+// Ok((amount_out, real_after_balance)): decoded braindance output
+// Err(SimulationError): evm error variants, remapped by caller to frontrun/backrun
+fn braindance_swap(
+    evm: &mut EVM<ForkDB>,
+    pool: &Pool,
+    amount_in: U256,
+    token_in: Address,
+    token_out: Address,
+    next_block: &BlockInfo,
+) -> Result<(U256, U256), SimulationError> {
+    let pool_variant = executable_variant(pool)?;
+    let data = match pool_variant {
+        ExecutableVariant::UniswapV2 => {
+            braindance::build_swap_v2_data(amount_in, pool.address, token_in, token_out)
+        }
+        ExecutableVariant::UniswapV3 => braindance::build_swap_v3_data(
+            I256::try_from(amount_in).map_err(|_| SimulationError::AmountOverflow(amount_in))?,
+            pool.address,
+            token_in,
+            token_out,
+        ),
+    };
+
+    evm.env.tx.caller = braindance_controller_address();
+    evm.env.tx.transact_to = TransactTo::Call(braindance_address().0.into());
+    evm.env.tx.data = data.0;
+    evm.env.tx.gas_limit = 700000;
+    evm.env.tx.gas_price = next_block.base_fee.into();
+    evm.env.tx.value = rU256::ZERO;
+
+    let output = match evm.transact_commit() {
+        Ok(ExecutionResult::Success { output, .. }) => match output {
+            Output::Call(o) => o,
+            Output::Create(o, _) => o,
+        },
+        Ok(ExecutionResult::Revert { output, .. }) => {
+            return Err(SimulationError::EvmReverted(output))
+        }
+        Ok(ExecutionResult::Halt { reason, .. }) => return Err(SimulationError::EvmHalted(reason)),
+        Err(e) => return Err(SimulationError::EvmError(e)),
+    };
+
+    let decoded = match pool_variant {
+        ExecutableVariant::UniswapV2 => braindance::decode_swap_v2_result(output.into()),
+        ExecutableVariant::UniswapV3 => braindance::decode_swap_v3_result(output.into()),
+    };
+
+    decoded.map_err(SimulationError::FailedToDecodeOutput)
+}
+
+// Find portion of `expected` that went missing (bps)
+fn tax_bps(expected: U256, real: U256) -> u64 {
+    if expected.is_zero() || real >= expected {
+        return 0;
+    }
+    ((expected - real) * BPS / expected).as_u64()
+}
+
+fn to_frontrun_error(e: SimulationError) -> SimulationError {
+    match e {
+        SimulationError::EvmError(e) => SimulationError::FrontrunEvmError(e),
+        SimulationError::EvmReverted(o) => SimulationError::FrontrunReverted(o),
+        SimulationError::EvmHalted(r) => SimulationError::FrontrunHalted(r),
+        e => e,
+    }
+}
+
+fn to_backrun_error(e: SimulationError) -> SimulationError {
+    match e {
+        SimulationError::EvmError(e) => SimulationError::BackrunEvmError(e),
+        SimulationError::EvmReverted(o) => SimulationError::BackrunReverted(o),
+        SimulationError::EvmHalted(r) => SimulationError::BackrunHalted(r),
+        e => e,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn profile(buy_tax_bps: u64, sell_tax_bps: u64, is_rebasing: bool) -> TokenProfile {
+        TokenProfile {
+            buy_tax_bps,
+            sell_tax_bps,
+            is_rebasing,
+        }
+    }
+
+    #[test]
+    fn tax_is_missing_portion_in_bps() {
+        assert_eq!(tax_bps(U256::from(1000), U256::from(1000)), 0);
+        assert_eq!(tax_bps(U256::from(1000), U256::from(1100)), 0);
+        assert_eq!(tax_bps(U256::from(1000), U256::from(950)), 500);
+        assert_eq!(tax_bps(U256::zero(), U256::zero()), 0);
+    }
+
+    #[test]
+    fn sell_tax_reduces_amount_reaching_pool() {
+        let amount = U256::from(10_000);
+        assert_eq!(profile(0, 0, false).after_sell_tax(amount), amount);
+        assert_eq!(
+            profile(0, 250, false).after_sell_tax(amount),
+            U256::from(9750)
+        );
+    }
+
+    #[test]
+    fn rebasing_or_taxed_tokens_are_not_sandwichable() {
+        assert!(profile(100, 100, false).is_sandwichable(100));
+        assert!(!profile(101, 0, false).is_sandwichable(100));
+        assert!(!profile(0, 101, false).is_sandwichable(100));
+        assert!(!profile(0, 0, true).is_sandwichable(100));
+    }
+
+    #[test]
+    fn cache_drops_expired_then_oldest_profiles() {
+        let cache = DashMap::new();
+        let token = Address::from_low_u64_be;
+
+        insert_bounded(&cache, token(1), profile(0, 0, false), 100.into(), 10, 3);
+        insert_bounded(&cache, token(2), profile(0, 0, false), 105.into(), 10, 3);
+        insert_bounded(&cache, token(3), profile(0, 0, false), 112.into(), 10, 3);
+        assert_eq!(cache.len(), 3);
+
+        // token 1 expired at block 110
+        insert_bounded(&cache, token(4), profile(0, 0, false), 112.into(), 10, 3);
+        assert_eq!(cache.len(), 3);
+        assert!(!cache.contains_key(&token(1)));
+
+        // nothing expired, oldest (token 2) makes room
+        insert_bounded(&cache, token(5), profile(0, 0, false), 113.into(), 10, 3);
+        assert_eq!(cache.len(), 3);
+        assert!(!cache.contains_key(&token(2)));
+        assert!(cache.contains_key(&token(5)));
+
+        // refreshing a cached token never evicts others
+        insert_bounded(&cache, token(5), profile(0, 0, false), 114.into(), 10, 3);
+        assert_eq!(cache.len(), 3);
+    }
+}
@@ -1,13 +1,22 @@
 use std::sync::Arc;
 
-use crate::utils;
+use crate::utils::chain::chain_config;
 use ethers::prelude::*;
 
+use super::slot_clock::{self, SlotClock};
+
 #[derive(Debug, Clone, Default)]
 pub struct BlockInfo {
     pub number: U64,
     pub timestamp: U256,
     pub base_fee: U256,
+    /// fee recipient of the builder expected to build the block
+    pub coinbase: Address,
+    pub gas_limit: U256,
+    /// only known once the block is proposed, next block uses the parent's value as placeholder
+    pub prevrandao: H256,
+    /// None before cancun
+    pub excess_blob_gas: Option<u64>,
 }
 
 impl BlockInfo {
@@ -17,27 +26,65 @@ impl BlockInfo {
             number,
             timestamp,
             base_fee,
+            ..Default::default()
+        }
+    }
+
+    // Create a `BlockInfo` instance from a mined block
+    pub fn from_block<TX>(block: &Block<TX>) -> Self {
+        Self {
+            number: block.number.unwrap_or_default(),
+            timestamp: block.timestamp,
+            base_fee: block.base_fee_per_gas.unwrap_or_default(),
+            coinbase: block.author.unwrap_or_default(),
+            gas_limit: block.gas_limit,
+            prevrandao: block.mix_hash.unwrap_or_default(),
+            excess_blob_gas: block.excess_blob_gas.map(|excess| excess.low_u64()),
         }
     }
 
     // Find the next block ahead of `prev_block`
     pub fn find_next_block_info(prev_block: Block<TxHash>) -> Self {
+        let chain = chain_config();
         let number = prev_block.number.unwrap_or_default() + 1;
-        let timestamp = prev_block.timestamp + 12;
-        let base_fee = utils::calculate_next_block_base_fee(prev_block);
+        let timestamp = prev_block.timestamp + chain.block_time;
+        let base_fee = chain.base_fee.next_base_fee(&prev_block);
+
+        // fall back to the parent's builder if no relay we send to has a known coinbase
+        let coinbase = chain
+            .expected_coinbase()
+            .unwrap_or(prev_block.author.unwrap_or_default());
 
         Self {
             number,
             timestamp,
             base_fee,
+            coinbase,
+            // builders rarely move the gas limit, and never by more than 1/1024
+            gas_limit: prev_block.gas_limit,
+            prevrandao: prev_block.mix_hash.unwrap_or_default(),
+            excess_blob_gas: chain.base_fee.next_excess_blob_gas(&prev_block),
         }
     }
+
+    // Milliseconds left until a deadline set relative to the start of this block's slot
+    //
+    // Arguments:
+    // * `&self`: reference to `BlockInfo` instance
+    // * `offset_ms`: deadline relative to slot start (negative = before the slot starts)
+    //
+    // Returns:
+    // i64: milliseconds until the deadline, negative once it has passed
+    pub fn time_remaining_ms(&self, offset_ms: i64) -> i64 {
+        slot_clock::time_remaining_ms(self.timestamp.as_u64(), offset_ms)
+    }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone)]
 pub struct BlockOracle {
     pub latest_block: BlockInfo,
     pub next_block: BlockInfo,
+    pub slot_clock: SlotClock,
 }
 
 impl BlockOracle {
@@ -55,22 +102,22 @@ impl BlockOracle {
         };
 
         // latets block info
-        let number = lb.number.unwrap();
-        let timestamp = lb.timestamp;
-        let base_fee = lb.base_fee_per_gas.unwrap_or_default();
-
-        let latest_block = BlockInfo::new(number, timestamp, base_fee);
+        let latest_block = BlockInfo::from_block(&lb);
 
         // next block info
-        let number = number + 1;
-        let timestamp = timestamp + 12;
-        let base_fee = utils::calculate_next_block_base_fee(lb);
-
-        let next_block = BlockInfo::new(number, timestamp, base_fee);
+        let slot_clock = SlotClock::for_chain();
+        let mut next_block = BlockInfo::find_next_block_info(lb);
+        next_block.timestamp = slot_clock
+            .next_block_timestamp(
+                latest_block.timestamp.as_u64(),
+                slot_clock::unix_now_ms() / 1000,
+            )
+            .into();
 
         Ok(BlockOracle {
             latest_block,
             next_block,
+            slot_clock,
         })
     }
 
@@ -80,15 +127,112 @@ impl BlockOracle {
         self.next_block.number = block_number + 1;
     }
 
-    // Updates block's timestamp
+    // Updates block's timestamp, next block goes in the first slot that has not passed yet
     pub fn update_block_timestamp(&mut self, timestamp: U256) {
         self.latest_block.timestamp = timestamp;
-        self.next_block.timestamp = timestamp + 12;
+        self.next_block.timestamp = self
+            .slot_clock
+            .next_block_timestamp(timestamp.as_u64(), slot_clock::unix_now_ms() / 1000)
+            .into();
+    }
+
+    // Move the next block into the following slot once that slot's build deadline is reached
+    // without a new block, chains without fixed slots never miss a slot
+    //
+    // Arguments:
+    // * `&mut self`: mutable reference to `BlockOracle` instance
+    // * `deadline_ms`: build deadline relative to slot start (negative = before the slot starts)
+    //
+    // Returns:
+    // bool: true if the next block's slot was missed
+    pub fn update_missed_slot(&mut self, deadline_ms: i64) -> bool {
+        // slots whose build deadline passed can no longer be built for
+        let now_ms = slot_clock::unix_now_ms() as i64 - deadline_ms;
+        let next_timestamp = self.slot_clock.next_block_timestamp(
+            self.latest_block.timestamp.as_u64(),
+            now_ms.max(0) as u64 / 1000,
+        );
+        let is_missed = U256::from(next_timestamp) > self.next_block.timestamp;
+        if is_missed {
+            self.next_block.timestamp = next_timestamp.into();
+        }
+        is_missed
     }
 
     // Updates block's base fee
     pub fn update_base_fee(&mut self, latest_block: Block<TxHash>) {
         self.latest_block.base_fee = latest_block.base_fee_per_gas.unwrap_or_default();
-        self.next_block.base_fee = utils::calculate_next_block_base_fee(latest_block);
+        self.next_block.base_fee = chain_config().base_fee.next_base_fee(&latest_block);
+    }
+
+    // Updates block's coinbase, gas limit, prevrandao and blob gas
+    pub fn update_block_env(&mut self, latest_block: &Block<TxHash>) {
+        let latest = BlockInfo::from_block(latest_block);
+        self.latest_block.coinbase = latest.coinbase;
+        self.latest_block.gas_limit = latest.gas_limit;
+        self.latest_block.prevrandao = latest.prevrandao;
+        self.latest_block.excess_blob_gas = latest.excess_blob_gas;
+
+        let next = BlockInfo::find_next_block_info(latest_block.clone());
+        self.next_block.coinbase = next.coinbase;
+        self.next_block.gas_limit = next.gas_limit;
+        self.next_block.prevrandao = next.prevrandao;
+        self.next_block.excess_blob_gas = next.excess_blob_gas;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn mined_block() -> Block<TxHash> {
+        Block {
+            number: Some(18_000_000u64.into()),
+            timestamp: 1_700_000_000u64.into(),
+            base_fee_per_gas: Some(U256::from(20_000_000_000u64)),
+            author: Some(Address::repeat_byte(0x69)),
+            gas_limit: 30_000_000u64.into(),
+            gas_used: 15_000_000u64.into(),
+            mix_hash: Some(H256::repeat_byte(0x42)),
+            excess_blob_gas: Some(U256::from(131_072)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn block_info_from_mined_block() {
+        let block_info = BlockInfo::from_block(&mined_block());
+
+        assert_eq!(block_info.number, 18_000_000u64.into());
+        assert_eq!(block_info.timestamp, 1_700_000_000u64.into());
+        assert_eq!(block_info.base_fee, U256::from(20_000_000_000u64));
+        assert_eq!(block_info.coinbase, Address::repeat_byte(0x69));
+        assert_eq!(block_info.gas_limit, 30_000_000u64.into());
+        assert_eq!(block_info.prevrandao, H256::repeat_byte(0x42));
+        assert_eq!(block_info.excess_blob_gas, Some(131_072));
+    }
+
+    #[test]
+    fn block_info_from_pre_merge_block() {
+        let block = Block::<TxHash> {
+            number: Some(1u64.into()),
+            ..Default::default()
+        };
+        let block_info = BlockInfo::from_block(&block);
+
+        assert_eq!(block_info.base_fee, U256::zero());
+        assert_eq!(block_info.coinbase, Address::zero());
+        assert_eq!(block_info.excess_blob_gas, None);
+    }
+
+    #[test]
+    fn next_block_info_builds_on_parent() {
+        let next_block = BlockInfo::find_next_block_info(mined_block());
+
+        // gas used at target keeps the base fee
+        assert_eq!(next_block.number, 18_000_001u64.into());
+        assert_eq!(next_block.base_fee, U256::from(20_000_000_000u64));
+        assert_eq!(next_block.gas_limit, 30_000_000u64.into());
+        assert_eq!(next_block.prevrandao, H256::repeat_byte(0x42));
     }
 }
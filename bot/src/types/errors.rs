@@ -3,12 +3,13 @@ use std::fmt;
 use ethers::prelude::{AbiError, ContractError};
 use ethers::providers::{Provider, ProviderError, Ws};
 use ethers::signers::WalletError;
-use ethers::types::H160;
+use ethers::types::{H160, U256};
 use thiserror::Error;
 use tokio::task::JoinError;
 
 use crate::prelude::is_sando_safu::OpCode;
-use crate::prelude::DatabaseError;
+use crate::prelude::token_profiler::TokenProfile;
+use crate::prelude::{DatabaseError, PoolVariant};
 
 #[derive(Error, Debug)]
 pub enum PairSyncError {
@@ -27,7 +28,7 @@ pub enum PairSyncError {
 #[derive(Error, Debug)]
 pub enum SendBundleError {
     #[error("Failed to sign transaction")]
-    SigningError(#[from] WalletError),
+    SigningError(#[from] SignerError),
     #[error("Max fee is less than next base fee")]
     MaxFeeLessThanNextBaseFee(),
     #[error("Negative miner tip")]
@@ -38,6 +39,50 @@ pub enum SendBundleError {
     FailedToSendBundle(),
     #[error("Revenue does not cover frontrun gas fees")]
     FrontrunGasFeesNotCovered(),
+    #[error("Risk limit exceeded")]
+    RiskLimitExceeded(#[from] RiskViolation),
+    #[error("Submission deadline passed {0}ms ago")]
+    SubmissionDeadlinePassed(i64),
+}
+
+#[derive(Error, Debug)]
+pub enum SignerError {
+    #[error("Wallet error")]
+    WalletError(#[from] WalletError),
+    #[error("Signer request failed")]
+    RequestError(#[from] reqwest::Error),
+    #[error("Signer connection failed")]
+    IoError(#[from] std::io::Error),
+    #[error("Invalid signer response")]
+    JsonError(#[from] serde_json::Error),
+    #[error("External signer error: {0}")]
+    External(String),
+    #[error("{0} is not supported by external signers")]
+    Unsupported(&'static str),
+}
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RiskViolation {
+    #[error("Frontrun of {0} wei exceeds max frontrun")]
+    FrontrunTooLarge(U256),
+    #[error("Frontrun of {0} wei exceeds max frontrun for token {1:?}")]
+    TokenFrontrunTooLarge(U256, H160),
+    #[error("Daily gas spend limit reached")]
+    DailyGasLimitReached(),
+    #[error("Profit of {0} wei is below min profit")]
+    ProfitTooLow(U256),
+}
+
+impl RiskViolation {
+    // Short name of violation, used as a metrics label
+    pub fn kind(&self) -> &'static str {
+        match self {
+            RiskViolation::FrontrunTooLarge(_) => "frontrun_too_large",
+            RiskViolation::TokenFrontrunTooLarge(..) => "token_frontrun_too_large",
+            RiskViolation::DailyGasLimitReached() => "daily_gas_limit_reached",
+            RiskViolation::ProfitTooLow(_) => "profit_too_low",
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -56,6 +101,34 @@ pub enum SimulationError {
     EvmReverted(revm::primitives::Bytes),
     AbiError(AbiError),
     ZeroOptimal(),
+    TokenNotSandwichable(TokenProfile),
+    UnsupportedPoolVariant(PoolVariant),
+    AmountOverflow(U256),
+}
+
+impl SimulationError {
+    // Name of variant, used to label failure metrics
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SimulationError::FrontrunEvmError(_) => "frontrun_evm_error",
+            SimulationError::FrontrunHalted(_) => "frontrun_halted",
+            SimulationError::FrontrunReverted(_) => "frontrun_reverted",
+            SimulationError::FrontrunNotSafu(_) => "frontrun_not_safu",
+            SimulationError::BackrunEvmError(_) => "backrun_evm_error",
+            SimulationError::BackrunHalted(_) => "backrun_halted",
+            SimulationError::BackrunReverted(_) => "backrun_reverted",
+            SimulationError::BackrunNotSafu(_) => "backrun_not_safu",
+            SimulationError::FailedToDecodeOutput(_) => "failed_to_decode_output",
+            SimulationError::EvmError(_) => "evm_error",
+            SimulationError::EvmHalted(_) => "evm_halted",
+            SimulationError::EvmReverted(_) => "evm_reverted",
+            SimulationError::AbiError(_) => "abi_error",
+            SimulationError::ZeroOptimal() => "zero_optimal",
+            SimulationError::TokenNotSandwichable(_) => "token_not_sandwichable",
+            SimulationError::UnsupportedPoolVariant(_) => "unsupported_pool_variant",
+            SimulationError::AmountOverflow(_) => "amount_overflow",
+        }
+    }
 }
 
 impl fmt::Display for SimulationError {
@@ -103,6 +176,23 @@ impl fmt::Display for SimulationError {
             SimulationError::ZeroOptimal() => {
                 write!(f, "No optimal sandwich found")
             }
+            SimulationError::TokenNotSandwichable(profile) => {
+                write!(
+                    f,
+                    "Token transfer behaviour not sandwichable : {:?}",
+                    profile
+                )
+            }
+            SimulationError::UnsupportedPoolVariant(pool_variant) => {
+                write!(
+                    f,
+                    "Pool variant not supported by sandwich contract : {:?}",
+                    pool_variant
+                )
+            }
+            SimulationError::AmountOverflow(amount) => {
+                write!(f, "Amount does not fit in swap calldata : {}", amount)
+            }
         }
     }
 }
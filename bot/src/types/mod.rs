@@ -5,3 +5,9 @@ pub use errors::*;
 
 pub mod block;
 pub use block::*;
+
+pub mod slot_clock;
+pub use slot_clock::*;
+
+pub mod token_registry;
+pub use token_registry::*;
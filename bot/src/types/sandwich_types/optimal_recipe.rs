@@ -17,6 +17,8 @@ pub struct OptimalRecipe {
     pub meats: Vec<Transaction>,
     pub target_pool: Pool,
     pub revenue: U256,
+    /// weth put into the frontrun (zero if unknown)
+    pub frontrun_in: U256,
     pub has_dust: bool,
     pub state_diffs: BTreeMap<H160, AccountDiff>,
 }
@@ -49,6 +51,7 @@ impl OptimalRecipe {
             meats,
             revenue,
             target_pool,
+            frontrun_in: U256::zero(),
             has_dust: false,
             state_diffs,
         }
@@ -60,6 +63,11 @@ impl OptimalRecipe {
         self.has_dust = dust;
     }
 
+    // Amount of weth the frontrun swaps in
+    pub fn set_frontrun_in(&mut self, frontrun_in: U256) {
+        self.frontrun_in = frontrun_in;
+    }
+
     // Used for logging
     pub fn print_meats_new_line(&self) -> String {
         let mut s = String::new();
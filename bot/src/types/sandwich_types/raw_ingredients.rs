@@ -3,7 +3,10 @@ use std::collections::BTreeMap;
 use ethers::prelude::*;
 use eyre::Result;
 
-use crate::{prelude::Pool, utils};
+use crate::{
+    prelude::Pool,
+    utils::{self, swap_decoder::DecodedSwap},
+};
 
 #[derive(Debug, Clone)]
 /// Holds all info needed to for sandwich simulations
@@ -18,6 +21,8 @@ pub struct RawIngredients {
     pub target_pool: Pool,
     /// holds the state diffs produced from meats
     pub state_diffs: BTreeMap<H160, AccountDiff>,
+    /// swap params read from each meat's calldata (None if meat does not call a known router)
+    pub victim_swaps: Vec<Option<DecodedSwap>>,
 }
 
 impl RawIngredients {
@@ -37,7 +42,12 @@ impl RawIngredients {
 
         let sandwich_contract = utils::dotenv::get_sandwich_contract_address();
 
-        let from = utils::dotenv::get_searcher_wallet().address();
+        let from = utils::signer::searcher_signer().address();
+
+        let victim_swaps = victim_txs
+            .iter()
+            .map(utils::swap_decoder::decode_swap)
+            .collect();
 
         Ok(RawIngredients {
             from,
@@ -47,6 +57,7 @@ impl RawIngredients {
             startend_token: input_token,
             intermediary_token: output_token,
             state_diffs,
+            victim_swaps,
         })
     }
 }
@@ -0,0 +1,132 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::utils::chain::chain_config;
+
+/// Maps timestamps onto fixed length slots counted from genesis
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotClock {
+    /// timestamp of slot 0, None if the chain has no fixed slots (devnets mine whenever)
+    pub genesis_time: Option<u64>,
+    /// seconds per slot
+    pub slot_duration: u64,
+}
+
+impl SlotClock {
+    // Create a new `SlotClock` instance
+    pub fn new(genesis_time: Option<u64>, slot_duration: u64) -> Self {
+        Self {
+            genesis_time,
+            slot_duration: slot_duration.max(1),
+        }
+    }
+
+    // Create clock for the configured chain, slots are only fixed if `CHAIN_GENESIS_TIME` is
+    // known
+    pub fn for_chain() -> Self {
+        let chain = chain_config();
+        Self::new(chain.genesis_time, chain.block_time)
+    }
+
+    // Can slots pass without a block, false if blocks simply follow each other
+    pub fn has_fixed_slots(&self) -> bool {
+        self.genesis_time.is_some()
+    }
+
+    // Slot that `timestamp` falls into (None without fixed slots)
+    pub fn slot_at(&self, timestamp: u64) -> Option<u64> {
+        let genesis_time = self.genesis_time?;
+        Some(timestamp.saturating_sub(genesis_time) / self.slot_duration)
+    }
+
+    // Timestamp `slot` starts at and its block's timestamp (None without fixed slots)
+    pub fn slot_start(&self, slot: u64) -> Option<u64> {
+        let genesis_time = self.genesis_time?;
+        Some(genesis_time + slot * self.slot_duration)
+    }
+
+    // Find the timestamp of the block after the latest one
+    //
+    // The next block goes in the slot after the latest block, unless that slot (and maybe more)
+    // already passed without a block, then it goes in the current slot (+24s, +36s ...)
+    //
+    // Without fixed slots the next block is expected one block time after the latest one
+    //
+    // Arguments:
+    // * `&self`: reference to `SlotClock` instance
+    // * `latest_block_timestamp`: timestamp of the latest block
+    // * `now`: current unix time (seconds)
+    //
+    // Returns:
+    // u64: timestamp of the next block
+    pub fn next_block_timestamp(&self, latest_block_timestamp: u64, now: u64) -> u64 {
+        let (latest_slot, current_slot) =
+            match (self.slot_at(latest_block_timestamp), self.slot_at(now)) {
+                (Some(latest_slot), Some(current_slot)) => (latest_slot, current_slot),
+                _ => return latest_block_timestamp + self.slot_duration,
+            };
+        let next_slot = (latest_slot + 1).max(current_slot);
+        self.slot_start(next_slot)
+            .unwrap_or(latest_block_timestamp + self.slot_duration)
+    }
+}
+
+// Milliseconds left until a deadline set relative to the start of a slot
+//
+// Arguments:
+// * `slot_start`: timestamp the slot starts at
+// * `offset_ms`: deadline relative to slot start (negative = before the slot starts)
+//
+// Returns:
+// i64: milliseconds until the deadline, negative once it has passed
+pub fn time_remaining_ms(slot_start: u64, offset_ms: i64) -> i64 {
+    (slot_start as i64 * 1000 + offset_ms) - unix_now_ms() as i64
+}
+
+// Time left until a deadline set relative to the start of a slot, zero if it has passed
+pub fn time_until(slot_start: u64, offset_ms: i64) -> Duration {
+    Duration::from_millis(time_remaining_ms(slot_start, offset_ms).max(0) as u64)
+}
+
+// Current unix time in milliseconds
+pub fn unix_now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn next_block_skips_missed_slots() {
+        let genesis = 1_606_824_023;
+        let clock = SlotClock::new(Some(genesis), 12);
+        let latest = clock.slot_start(100).unwrap();
+
+        assert_eq!(clock.slot_at(latest + 11), Some(100));
+        assert_eq!(clock.slot_at(latest + 12), Some(101));
+
+        // block for the next slot is still on its way
+        assert_eq!(clock.next_block_timestamp(latest, latest + 1), latest + 12);
+        assert_eq!(clock.next_block_timestamp(latest, latest + 15), latest + 12);
+
+        // slot 101 passed without a block
+        assert_eq!(clock.next_block_timestamp(latest, latest + 24), latest + 24);
+        assert_eq!(clock.next_block_timestamp(latest, latest + 40), latest + 36);
+    }
+
+    #[test]
+    fn next_block_follows_latest_without_fixed_slots() {
+        let clock = SlotClock::new(None, 12);
+        let latest = 1_700_000_005;
+
+        assert!(!clock.has_fixed_slots());
+        assert_eq!(clock.slot_at(latest), None);
+
+        // however long the next block takes, nothing is counted as missed
+        assert_eq!(clock.next_block_timestamp(latest, latest + 1), latest + 12);
+        assert_eq!(clock.next_block_timestamp(latest, latest + 40), latest + 12);
+    }
+}
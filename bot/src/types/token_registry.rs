@@ -0,0 +1,424 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+use dashmap::{DashMap, DashSet};
+use ethers::prelude::*;
+use hashbrown::HashSet;
+use serde::{Deserialize, Serialize};
+
+use crate::utils;
+
+/// How long the writer waits for more changes before persisting, bursts of failures (e.g. a
+/// poison token hit by many victims) end up in a single write
+const SAVE_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Why a token ended up in the registry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BlacklistReason {
+    /// frontrun used opcodes flagged by the salmonella inspector
+    FrontrunNotSafu,
+    /// backrun used opcodes flagged by the salmonella inspector
+    BackrunNotSafu,
+    /// selling the token back to the pool reverted
+    SellReverted,
+    /// token takes too much on transfers or rebases
+    TransferTax,
+}
+
+/// Automatically learned blacklist entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlacklistEntry {
+    pub reason: BlacklistReason,
+    /// block the failure was observed in
+    pub block: U64,
+    /// block after which the token is allowed to be simulated again, `None` if never
+    pub expiry_block: Option<U64>,
+}
+
+/// Remembers which tokens should not be sandwiched
+///
+/// Holds manual lists (loaded from env) and entries that were learned from failed simulations,
+/// learned entries are persisted to disk so that they survive restarts
+#[derive(Debug)]
+pub struct TokenRegistry {
+    // tokens that are never blacklisted automatically
+    whitelist: HashSet<Address>,
+    // tokens that are always skipped (loaded from env, extended through the admin api)
+    manual_blacklist: DashSet<Address>,
+    learned: Arc<DashMap<Address, BlacklistEntry>>,
+    // how many blocks a learned entry is kept for, `None` to keep forever
+    expiry_blocks: Option<u64>,
+    // file learned entries are loaded from
+    path: Option<PathBuf>,
+    // notifies the background writer that learned entries changed
+    writer: Option<mpsc::Sender<()>>,
+}
+
+impl TokenRegistry {
+    // Create a new empty registry
+    //
+    // Arguments:
+    // * `whitelist`: tokens that are never blacklisted automatically
+    // * `manual_blacklist`: tokens that are always skipped
+    // * `expiry_blocks`: how many blocks learned entries are kept for, `None` to keep forever
+    // * `path`: file to persist learned entries to, `None` to keep entries in memory only
+    //
+    // Returns:
+    // `TokenRegistry`: registry without any learned entries
+    pub fn new(
+        whitelist: HashSet<Address>,
+        manual_blacklist: HashSet<Address>,
+        expiry_blocks: Option<u64>,
+        path: Option<PathBuf>,
+    ) -> Self {
+        let learned = Arc::new(DashMap::new());
+
+        Self {
+            whitelist,
+            manual_blacklist: manual_blacklist.into_iter().collect(),
+            writer: path.clone().map(|path| spawn_writer(path, learned.clone())),
+            learned,
+            expiry_blocks,
+            path,
+        }
+    }
+
+    // Create registry from env vars and load previously learned entries from disk
+    //
+    // Env vars:
+    // * `TOKEN_WHITELIST`: comma separated tokens that are never blacklisted automatically
+    // * `TOKEN_BLACKLIST`: comma separated tokens that are always skipped
+    // * `TOKEN_BLACKLIST_EXPIRY_BLOCKS`: blocks a learned entry is kept for (0 = forever)
+    // * `TOKEN_REGISTRY_PATH`: json file that learned entries are persisted to
+    //
+    // Returns:
+    // `TokenRegistry`: registry holding manual lists and learned entries
+    pub fn from_env() -> Self {
+        let parse_tokens = |key: &str| -> HashSet<Address> {
+            utils::dotenv::get_list(key)
+                .iter()
+                .map(|t| {
+                    Address::from_str(t)
+                        .unwrap_or_else(|_| panic!("Failed to parse address in \"{}\"", key))
+                })
+                .collect()
+        };
+
+        let expiry_blocks = match utils::dotenv::get_token_blacklist_expiry_blocks() {
+            0 => None,
+            blocks => Some(blocks),
+        };
+
+        let registry = Self::new(
+            parse_tokens("TOKEN_WHITELIST"),
+            parse_tokens("TOKEN_BLACKLIST"),
+            expiry_blocks,
+            Some(utils::dotenv::get_token_registry_path()),
+        );
+
+        if let Err(e) = registry.load() {
+            log::warn!("Failed to load token registry: {:?}", e);
+        }
+
+        log::info!(
+            "Token registry loaded with {} learned entries",
+            registry.learned.len()
+        );
+
+        registry
+    }
+
+    // Check if a token should be skipped
+    //
+    // Arguments:
+    // * `&self`: reference to `TokenRegistry` instance
+    // * `token`: token to check
+    // * `block`: current block, used to expire learned entries
+    //
+    // Returns:
+    // bool: true if token should not be sandwiched, false otherwise
+    pub fn is_blocked(&self, token: &Address, block: U64) -> bool {
+        if self.manual_blacklist.contains(token) {
+            return true;
+        }
+
+        if self.whitelist.contains(token) {
+            return false;
+        }
+
+        let is_expired = match self.learned.get(token) {
+            Some(entry) => match entry.expiry_block {
+                Some(expiry_block) => block > expiry_block,
+                None => return true,
+            },
+            None => return false,
+        };
+
+        if is_expired {
+            self.learned.remove(token);
+            return false;
+        }
+
+        true
+    }
+
+    // Record a simulation failure for a token
+    //
+    // Arguments:
+    // * `&self`: reference to `TokenRegistry` instance
+    // * `token`: token that caused the failure
+    // * `reason`: why the simulation failed
+    // * `block`: block the failure was observed in
+    //
+    // Returns:
+    // bool: true if token was newly blacklisted, false if already known or whitelisted
+    pub fn record_failure(&self, token: Address, reason: BlacklistReason, block: U64) -> bool {
+        if self.whitelist.contains(&token) || self.is_blocked(&token, block) {
+            return false;
+        }
+
+        let entry = BlacklistEntry {
+            reason,
+            block,
+            expiry_block: self.expiry_blocks.map(|blocks| block + blocks),
+        };
+        self.learned.insert(token, entry);
+
+        self.save();
+
+        true
+    }
+
+    // Always skip a token (until restart or until unblocked)
+    //
+    // Arguments:
+    // * `&self`: reference to `TokenRegistry` instance
+    // * `token`: token to skip
+    //
+    // Returns:
+    // bool: true if token was not manually blacklisted yet
+    pub fn block(&self, token: Address) -> bool {
+        self.manual_blacklist.insert(token)
+    }
+
+    // Allow a token to be sandwiched again, clears manual and learned entries
+    //
+    // Arguments:
+    // * `&self`: reference to `TokenRegistry` instance
+    // * `token`: token to allow
+    //
+    // Returns:
+    // bool: true if token was blacklisted
+    pub fn unblock(&self, token: Address) -> bool {
+        let was_manual = self.manual_blacklist.remove(&token).is_some();
+        let was_learned = self.learned.remove(&token).is_some();
+
+        if was_learned {
+            self.save();
+        }
+
+        was_manual || was_learned
+    }
+
+    // Number of learned entries
+    pub fn learned_len(&self) -> usize {
+        self.learned.len()
+    }
+
+    // Load learned entries from disk (missing file is not an error), entries learned since
+    // startup are newer than the ones on disk and are kept
+    fn load(&self) -> eyre::Result<()> {
+        let path = match &self.path {
+            Some(path) if path.exists() => path,
+            _ => return Ok(()),
+        };
+
+        let data = std::fs::read_to_string(path)?;
+        let entries: Vec<(Address, BlacklistEntry)> = serde_json::from_str(&data)?;
+        for (token, entry) in entries {
+            self.learned.entry(token).or_insert(entry);
+        }
+
+        Ok(())
+    }
+
+    // Ask the background writer to persist learned entries
+    fn save(&self) {
+        let writer = match &self.writer {
+            Some(writer) => writer,
+            None => return,
+        };
+
+        if writer.send(()).is_err() {
+            log::error!("Token registry writer stopped, learned entries are not persisted");
+        }
+    }
+}
+
+// Spawn the thread that persists learned entries so that callers never block on disk io
+//
+// Arguments:
+// * `path`: file to persist learned entries to
+// * `learned`: learned entries shared with the registry
+//
+// Returns:
+// `mpsc::Sender`: channel used to notify the writer that learned entries changed
+fn spawn_writer(path: PathBuf, learned: Arc<DashMap<Address, BlacklistEntry>>) -> mpsc::Sender<()> {
+    let (tx, rx) = mpsc::channel::<()>();
+
+    std::thread::Builder::new()
+        .name("token-registry-writer".into())
+        .spawn(move || {
+            while rx.recv().is_ok() {
+                std::thread::sleep(SAVE_DEBOUNCE);
+                // changes made while waiting are part of this write
+                while rx.try_recv().is_ok() {}
+
+                let entries: Vec<(Address, BlacklistEntry)> = learned
+                    .iter()
+                    .map(|e| (*e.key(), e.value().clone()))
+                    .collect();
+
+                if let Err(e) = write_entries(&path, &entries) {
+                    log::error!("Failed to persist token registry: {:?}", e);
+                }
+            }
+        })
+        .expect("Failed to spawn token registry writer");
+
+    tx
+}
+
+// Write learned entries to disk
+//
+// Arguments:
+// * `path`: file to write to
+// * `entries`: learned entries to persist
+//
+// Returns:
+// Ok(()) if entries were written
+// Err(eyre::Error) if serialization or io failed
+fn write_entries(path: &Path, entries: &[(Address, BlacklistEntry)]) -> eyre::Result<()> {
+    // write to temp file first so that a crash mid write does not corrupt the registry
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, serde_json::to_string_pretty(entries)?)?;
+    std::fs::rename(tmp_path, path)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn token(n: u64) -> Address {
+        Address::from_low_u64_be(n)
+    }
+
+    fn entry(reason: BlacklistReason, block: u64) -> BlacklistEntry {
+        BlacklistEntry {
+            reason,
+            block: block.into(),
+            expiry_block: None,
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "token_registry_{}_{}.json",
+            name,
+            std::process::id()
+        ))
+    }
+
+    #[test]
+    fn learned_entries_expire() {
+        let registry = TokenRegistry::new(HashSet::new(), HashSet::new(), Some(10), None);
+
+        assert!(registry.record_failure(token(1), BlacklistReason::SellReverted, 100.into()));
+        assert!(!registry.record_failure(token(1), BlacklistReason::SellReverted, 101.into()));
+        assert!(registry.is_blocked(&token(1), 110.into()));
+        assert!(!registry.is_blocked(&token(1), 111.into()));
+        assert_eq!(registry.learned_len(), 0);
+    }
+
+    #[test]
+    fn whitelist_and_manual_lists_win() {
+        let whitelist = HashSet::from([token(1)]);
+        let manual_blacklist = HashSet::from([token(2)]);
+        let registry = TokenRegistry::new(whitelist, manual_blacklist, None, None);
+
+        assert!(!registry.record_failure(token(1), BlacklistReason::FrontrunNotSafu, 1.into()));
+        assert!(!registry.is_blocked(&token(1), 1.into()));
+        assert!(registry.is_blocked(&token(2), 1.into()));
+
+        assert!(registry.record_failure(token(3), BlacklistReason::BackrunNotSafu, 1.into()));
+        assert!(registry.unblock(token(3)));
+        assert!(registry.unblock(token(2)));
+        assert!(!registry.is_blocked(&token(2), 1.into()));
+        assert!(!registry.is_blocked(&token(3), 1.into()));
+    }
+
+    #[test]
+    fn load_merges_persisted_entries() {
+        let path = temp_path("load");
+        let persisted = vec![
+            (token(1), entry(BlacklistReason::TransferTax, 5)),
+            (token(2), entry(BlacklistReason::SellReverted, 5)),
+        ];
+        write_entries(&path, &persisted).unwrap();
+
+        let registry = TokenRegistry::new(HashSet::new(), HashSet::new(), None, None);
+        registry
+            .learned
+            .insert(token(2), entry(BlacklistReason::FrontrunNotSafu, 9));
+        let registry = TokenRegistry {
+            path: Some(path.clone()),
+            ..registry
+        };
+        registry.load().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(registry.learned_len(), 2);
+        assert_eq!(
+            registry.learned.get(&token(1)).unwrap().reason,
+            BlacklistReason::TransferTax
+        );
+        // entry learned since startup is kept over the persisted one
+        assert_eq!(
+            registry.learned.get(&token(2)).unwrap().reason,
+            BlacklistReason::FrontrunNotSafu
+        );
+    }
+
+    #[test]
+    fn writer_persists_learned_entries() {
+        let path = temp_path("writer");
+        let registry = TokenRegistry::new(HashSet::new(), HashSet::new(), None, Some(path.clone()));
+        for n in 1..=3 {
+            registry.record_failure(token(n), BlacklistReason::SellReverted, 1.into());
+        }
+        std::thread::sleep(SAVE_DEBOUNCE * 3);
+
+        let reloaded = TokenRegistry::new(HashSet::new(), HashSet::new(), None, Some(path.clone()));
+        reloaded.load().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(reloaded.learned_len(), 3);
+        assert!(reloaded.is_blocked(&token(2), 1.into()));
+    }
+
+    #[test]
+    fn missing_file_loads_nothing() {
+        let registry = TokenRegistry::new(
+            HashSet::new(),
+            HashSet::new(),
+            None,
+            Some(temp_path("missing")),
+        );
+        registry.load().unwrap();
+        assert_eq!(registry.learned_len(), 0);
+    }
+}
@@ -24,8 +24,11 @@ pub async fn alert_bundle<'a>(
     
 
     let webhook = match recipe.target_pool.pool_variant {
-        PoolVariant::UniswapV2 => utils::dotenv::get_v2_alert_webhook(),
-        PoolVariant::UniswapV3 => utils::dotenv::get_v3_alert_webhook(),
+        PoolVariant::UniswapV2
+        | PoolVariant::CurveStableSwap
+        | PoolVariant::BalancerWeighted
+        | PoolVariant::Solidly => utils::dotenv::get_v2_alert_webhook(),
+        PoolVariant::UniswapV3 | PoolVariant::UniswapV4 => utils::dotenv::get_v3_alert_webhook(),
     };
     let webhook_success = utils::dotenv::get_success_discord_webhook();
 
@@ -126,3 +129,38 @@ pub async fn alert_poison(malicious_token: Address, malicious_opcodes: Vec<OpCod
     .await
     .unwrap();
 }
+
+/// Alerts discord channel, via webhook, that risk limits halted bundle submission
+pub async fn alert_halt(reason: &str) {
+    let msg = format!(
+        "
+        bot halted, bundle submission paused:
+        reason: {}
+        resume through admin api once resolved
+        ",
+        reason
+    );
+
+    let mut map = HashMap::new();
+    map.insert("content", msg.clone());
+
+    let webhook = match utils::dotenv::get_risk_alert_webhook() {
+        Some(webhook) => webhook,
+        None => return,
+    };
+    let client = reqwest::Client::new();
+
+    tokio::spawn(async move {
+        let res = client.post(webhook).json(&map).send().await;
+
+        match res {
+            Ok(_) => {}
+            Err(err) => {
+                log::error!("Could not send alert to discord, err: {}", err);
+                log::error!("Message: {}", msg);
+            }
+        }
+    })
+    .await
+    .unwrap();
+}
@@ -0,0 +1,481 @@
+use std::{str::FromStr, sync::OnceLock};
+
+use ethers::prelude::*;
+use reqwest::Url;
+
+use crate::prelude::PoolVariant;
+
+use super::dotenv;
+
+/// Per chain settings, every chain specific value the bot uses is read from here
+#[derive(Debug, Clone)]
+pub struct ChainConfig {
+    /// preset the config was built from (`mainnet`, `devnet` or `custom`)
+    pub name: String,
+    pub chain_id: u64,
+    /// wrapped native token (weth on mainnet), every sandwich starts and ends in it
+    pub wrapped_native: Address,
+    /// seconds between blocks (slot length), used to predict the next block's timestamp
+    pub block_time: u64,
+    /// timestamp of the first slot, None if slots are counted from the first block seen
+    pub genesis_time: Option<u64>,
+    pub base_fee: BaseFeeRules,
+    pub submission: BundleSubmission,
+    /// factories (or singletons) pools are discovered through
+    pub dexes: Vec<DexConfig>,
+}
+
+/// Dex whose pools are synced, parsed from `variant:factory:creation_block[:swap_fee]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DexConfig {
+    pub pool_variant: PoolVariant,
+    pub factory: Address,
+    /// block factory was deployed in, pools are synced from here
+    pub creation_block: u64,
+    /// fee charged by the factory's pools (1e6 units), None keeps the default 0.3%
+    pub swap_fee: Option<u32>,
+}
+
+/// Eip-1559 parameters used to predict the next block's base fee
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BaseFeeRules {
+    /// gas limit / gas target
+    pub elasticity_multiplier: u64,
+    /// bounds how much the base fee can change from one block to the next
+    pub max_change_denominator: u64,
+    /// blob gas per block above which the blob base fee goes up (eip-4844)
+    pub target_blob_gas_per_block: u64,
+}
+
+/// How signed bundles reach block builders
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BundleSubmission {
+    /// `eth_sendBundle` to every relay, bundle is settled on the first relay that sees it land
+    Relays(Vec<RelayEndpoint>),
+    /// txs sent in bundle order straight to the rpc node, for devnets without builders
+    Direct,
+}
+
+/// Relay bundles are sent to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelayEndpoint {
+    pub name: String,
+    pub url: Url,
+    /// fee recipient of the relay's builder, set as coinbase when simulating
+    pub coinbase: Option<Address>,
+}
+
+// Chain config shared by the whole bot, read from env on first use
+pub fn chain_config() -> &'static ChainConfig {
+    static CHAIN: OnceLock<ChainConfig> = OnceLock::new();
+    CHAIN.get_or_init(ChainConfig::from_env)
+}
+
+impl ChainConfig {
+    // Ethereum mainnet, bundles go to the public relays and builders
+    pub fn mainnet() -> Self {
+        let dexes = [
+            // UniswapV2
+            "uniswap_v2:0x5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f:10000835",
+            // Sushiswap
+            "uniswap_v2:0xC0AEe478e3658e2610c5F7A4A2E1777cE9e4f2Ac:10794229",
+            // CryptoCom-Swap
+            "uniswap_v2:0x9DEB29c9a4c7A88a3C0257393b7f3335338D9A9D:10828414",
+            // Convergence-Swap
+            "uniswap_v2:0x4eef5746ED22A2fD368629C1852365bf5dcb79f1:12385067",
+            // Pancake-Swap
+            "uniswap_v2:0x1097053Fd2ea711dad45caCcc45EfF7548fCB362:15614590:2500",
+            // Shiba-Swap, home of shitcoins
+            "uniswap_v2:0x115934131916C8b277DD010Ee02de363c09d037c:12771526",
+            // Saitaswap
+            "uniswap_v2:0x35113a300ca0D7621374890ABFEAC30E88f214b1:15210780",
+            // Curve plain pools (discovered through curve's main registry)
+            "curve:0x90E00ACe148ca3b23Ac1bC8C240C2a7Dd9c2d7f5:11000000",
+            // Balancer two token weighted pools (discovered through the vault)
+            "balancer:0xBA12222222228d8Ba445958a75a0704d566BF2C8:12272146",
+            // UniswapV3
+            "uniswap_v3:0x1F98431c8aD98523631AE4a59f267346ea31F984:12369621",
+            // UniswapV4 pools cannot be sandwiched yet, opt in through `CHAIN_DEXES` with
+            // "uniswap_v4:0x000000000004444c5dc75cB358380D2e3dE08A90:21688329"
+        ];
+
+        let relays = [
+            (
+                "flashbots",
+                "https://relay.flashbots.net/",
+                Some("0xDAFEA492D9c6733ae3d56b7Ed1ADB60692c98Bc5"),
+            ),
+            (
+                "builder0x69",
+                "http://builder0x69.io/",
+                Some("0x690B9A9E9aa1C9dB991C7721a92d351Db4FaC990"),
+            ),
+            ("edennetwork", "https://api.edennetwork.io/v1/bundle", None),
+            (
+                "beaverbuild",
+                "https://rpc.beaverbuild.org/",
+                Some("0x95222290DD7278Aa3Ddd389Cc1E1d165CC4BAfe5"),
+            ),
+            (
+                "lightspeedbuilder",
+                "https://rpc.lightspeedbuilder.info/",
+                None,
+            ),
+            ("eth-builder", "https://eth-builder.com/", None),
+            ("ultrasound", "https://relay.ultrasound.money/", None),
+            ("agnostic-relay", "https://agnostic-relay.net/", None),
+            ("relayoor-wtf", "https://relayooor.wtf/", None),
+            (
+                "rsync-builder",
+                "https://rsync-builder.xyz/",
+                Some("0x1f9090aaE28b8a3dCeaDf281B0F12828e676c326"),
+            ),
+            //"http://relayooor.wtf/",
+            //"http://mainnet.aestus.live/",
+            //"https://mainnet-relay.securerpc.com",
+            //"http://agnostic-relay.net/",
+            //"http://relay.ultrasound.money/",
+        ];
+
+        Self {
+            name: "mainnet".to_string(),
+            chain_id: 1,
+            wrapped_native: Address::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2")
+                .unwrap(),
+            block_time: 12,
+            genesis_time: Some(1_606_824_023),
+            base_fee: BaseFeeRules::default(),
+            submission: BundleSubmission::Relays(
+                relays
+                    .into_iter()
+                    .map(|(name, url, coinbase)| RelayEndpoint {
+                        name: name.to_string(),
+                        url: Url::parse(url).unwrap(),
+                        coinbase: coinbase.map(|coinbase| Address::from_str(coinbase).unwrap()),
+                    })
+                    .collect(),
+            ),
+            dexes: dexes
+                .into_iter()
+                .map(|dex| DexConfig::from_str(dex).unwrap())
+                .collect(),
+        }
+    }
+
+    // Local anvil/hardhat node forked from mainnet, txs are sent straight to the node
+    pub fn devnet() -> Self {
+        Self {
+            name: "devnet".to_string(),
+            chain_id: 31337,
+            genesis_time: None,
+            submission: BundleSubmission::Direct,
+            ..Self::mainnet()
+        }
+    }
+
+    // Create config from `CHAIN`
+    //
+    // * `mainnet` (default)
+    // * `devnet`: mainnet fork on a local node
+    // * `custom`: any other evm chain, needs `CHAIN_ID` and `CHAIN_WRAPPED_NATIVE`
+    //
+    // `CHAIN_ID`, `CHAIN_WRAPPED_NATIVE`, `CHAIN_BLOCK_TIME`, `CHAIN_GENESIS_TIME`,
+    // `CHAIN_BASE_FEE_ELASTICITY`, `CHAIN_BASE_FEE_DENOMINATOR`, `CHAIN_TARGET_BLOB_GAS`,
+    // `BUNDLE_SUBMISSION`, `BUNDLE_RELAYS`, `BUILDER_COINBASES` and `CHAIN_DEXES` override the
+    // preset, `SOLIDLY_FACTORIES` are added to the preset's dexes
+    pub fn from_env() -> Self {
+        let mut chain = match dotenv::get_chain().as_str() {
+            "mainnet" => Self::mainnet(),
+            "devnet" => Self::devnet(),
+            "custom" => Self {
+                name: "custom".to_string(),
+                chain_id: dotenv::get_u64("CHAIN_ID")
+                    .expect("Required environment variable \"CHAIN_ID\" not set"),
+                wrapped_native: dotenv::get_address("CHAIN_WRAPPED_NATIVE")
+                    .expect("Required environment variable \"CHAIN_WRAPPED_NATIVE\" not set"),
+                genesis_time: None,
+                submission: BundleSubmission::Direct,
+                // mainnet factories do not exist on other chains
+                dexes: vec![],
+                ..Self::mainnet()
+            },
+            other => panic!(
+                "CHAIN must be one of mainnet, devnet or custom, got {}",
+                other
+            ),
+        };
+
+        if let Some(chain_id) = dotenv::get_u64("CHAIN_ID") {
+            chain.chain_id = chain_id;
+        }
+        if let Some(wrapped_native) = dotenv::get_address("CHAIN_WRAPPED_NATIVE") {
+            chain.wrapped_native = wrapped_native;
+        }
+        if let Some(block_time) = dotenv::get_u64("CHAIN_BLOCK_TIME") {
+            chain.block_time = block_time;
+        }
+        if let Some(genesis_time) = dotenv::get_u64("CHAIN_GENESIS_TIME") {
+            chain.genesis_time = Some(genesis_time);
+        }
+        if let Some(elasticity) = dotenv::get_u64("CHAIN_BASE_FEE_ELASTICITY") {
+            chain.base_fee.elasticity_multiplier = elasticity;
+        }
+        if let Some(denominator) = dotenv::get_u64("CHAIN_BASE_FEE_DENOMINATOR") {
+            chain.base_fee.max_change_denominator = denominator;
+        }
+        if let Some(target_blob_gas) = dotenv::get_u64("CHAIN_TARGET_BLOB_GAS") {
+            chain.base_fee.target_blob_gas_per_block = target_blob_gas;
+        }
+
+        if let Some(dexes) = dotenv::get_chain_dexes() {
+            chain.dexes = dexes;
+        }
+        for (factory, creation_block) in dotenv::get_solidly_factories() {
+            chain.dexes.push(DexConfig {
+                pool_variant: PoolVariant::Solidly,
+                factory,
+                creation_block,
+                swap_fee: None,
+            });
+        }
+
+        let relays = dotenv::get_bundle_relays();
+        if !relays.is_empty() {
+            chain.submission = BundleSubmission::Relays(relays);
+        }
+        match dotenv::get_bundle_submission().as_deref() {
+            None => {}
+            Some("direct") => chain.submission = BundleSubmission::Direct,
+            Some("relays") => {
+                if chain.submission == BundleSubmission::Direct {
+                    panic!("BUNDLE_SUBMISSION is relays but no \"BUNDLE_RELAYS\" are set");
+                }
+            }
+            Some(other) => panic!("BUNDLE_SUBMISSION must be relays or direct, got {}", other),
+        }
+
+        if let BundleSubmission::Relays(relays) = &mut chain.submission {
+            for (name, coinbase) in dotenv::get_builder_coinbases() {
+                match relays.iter_mut().find(|relay| relay.name == name) {
+                    Some(relay) => relay.coinbase = Some(coinbase),
+                    None => log::warn!("BUILDER_COINBASES entry for unknown relay {}", name),
+                }
+            }
+        }
+
+        chain
+    }
+
+    // Coinbase of the first relay (in send order) whose builder's fee recipient is known
+    //
+    // Recipes are simulated with this coinbase, before sending they are simulated again for the
+    // coinbases of the other relays (see `bundle_sender::relays_safe_for_recipe`)
+    //
+    // Returns:
+    // Option<Address>: None if bundles are not sent to relays or no coinbase is known
+    pub fn expected_coinbase(&self) -> Option<Address> {
+        match &self.submission {
+            BundleSubmission::Relays(relays) => relays.iter().find_map(|relay| relay.coinbase),
+            BundleSubmission::Direct => None,
+        }
+    }
+}
+
+impl FromStr for DexConfig {
+    type Err = String;
+
+    // Parse `variant:factory:creation_block[:swap_fee]`, variant is one of `uniswap_v2`,
+    // `uniswap_v3`, `uniswap_v4`, `curve`, `balancer` or `solidly`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':').map(str::trim);
+        let (variant, factory, creation_block) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(variant), Some(factory), Some(creation_block)) => {
+                (variant, factory, creation_block)
+            }
+            _ => return Err(format!("{} is not variant:factory:creation_block", s)),
+        };
+
+        let pool_variant = match variant {
+            "uniswap_v2" => PoolVariant::UniswapV2,
+            "uniswap_v3" => PoolVariant::UniswapV3,
+            "uniswap_v4" => PoolVariant::UniswapV4,
+            "curve" => PoolVariant::CurveStableSwap,
+            "balancer" => PoolVariant::BalancerWeighted,
+            "solidly" => PoolVariant::Solidly,
+            other => return Err(format!("unknown pool variant {}", other)),
+        };
+        let factory =
+            Address::from_str(factory).map_err(|_| format!("invalid factory {}", factory))?;
+        let creation_block = creation_block
+            .parse()
+            .map_err(|_| format!("invalid creation block {}", creation_block))?;
+        let swap_fee = match parts.next() {
+            Some(swap_fee) => Some(
+                swap_fee
+                    .parse()
+                    .map_err(|_| format!("invalid swap fee {}", swap_fee))?,
+            ),
+            None => None,
+        };
+        if parts.next().is_some() {
+            return Err(format!("{} has too many fields", s));
+        }
+
+        Ok(DexConfig {
+            pool_variant,
+            factory,
+            creation_block,
+            swap_fee,
+        })
+    }
+}
+
+impl Default for BaseFeeRules {
+    // Values set by eip-1559
+    fn default() -> Self {
+        Self {
+            elasticity_multiplier: 2,
+            max_change_denominator: 8,
+            target_blob_gas_per_block: 393_216,
+        }
+    }
+}
+
+impl BaseFeeRules {
+    // Calculate the base fee of the block after `block`
+    // based on math provided here: https://ethereum.stackexchange.com/questions/107173/how-is-the-base-fee-per-gas-computed-for-a-new-block
+    //
+    // Arguments:
+    // * `&self`: reference to `BaseFeeRules` instance
+    // * `block`: latest block
+    //
+    // Returns:
+    // U256: base fee of the next block
+    pub fn next_base_fee(&self, block: &Block<TxHash>) -> U256 {
+        // Get the block base fee per gas
+        let current_base_fee_per_gas = block.base_fee_per_gas.unwrap_or_default();
+
+        // Get the mount of gas used in the block
+        let current_gas_used = block.gas_used;
+
+        let current_gas_target = block.gas_limit / self.elasticity_multiplier;
+        if current_gas_target.is_zero() {
+            return current_base_fee_per_gas;
+        }
+
+        if current_gas_used == current_gas_target {
+            current_base_fee_per_gas
+        } else if current_gas_used > current_gas_target {
+            let gas_used_delta = current_gas_used - current_gas_target;
+            let base_fee_per_gas_delta = current_base_fee_per_gas * gas_used_delta
+                / current_gas_target
+                / self.max_change_denominator;
+
+            current_base_fee_per_gas + base_fee_per_gas_delta
+        } else {
+            let gas_used_delta = current_gas_target - current_gas_used;
+            let base_fee_per_gas_delta = current_base_fee_per_gas * gas_used_delta
+                / current_gas_target
+                / self.max_change_denominator;
+
+            current_base_fee_per_gas - base_fee_per_gas_delta
+        }
+    }
+
+    // Calculate the excess blob gas of the block after `block`
+    //
+    // Arguments:
+    // * `&self`: reference to `BaseFeeRules` instance
+    // * `block`: latest block
+    //
+    // Returns:
+    // Option<u64>: excess blob gas of the next block, None if `block` is from before cancun
+    pub fn next_excess_blob_gas(&self, block: &Block<TxHash>) -> Option<u64> {
+        let excess_blob_gas = block.excess_blob_gas?.low_u64();
+        let blob_gas_used = block.blob_gas_used.unwrap_or_default().low_u64();
+        Some((excess_blob_gas + blob_gas_used).saturating_sub(self.target_blob_gas_per_block))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dex_config_parses_entries() {
+        let pancake = DexConfig::from_str(
+            "uniswap_v2:0x1097053Fd2ea711dad45caCcc45EfF7548fCB362:15614590:2500",
+        )
+        .unwrap();
+        assert_eq!(pancake.pool_variant, PoolVariant::UniswapV2);
+        assert_eq!(pancake.creation_block, 15614590);
+        assert_eq!(pancake.swap_fee, Some(2500));
+
+        let v3 =
+            DexConfig::from_str("uniswap_v3:0x1F98431c8aD98523631AE4a59f267346ea31F984:1").unwrap();
+        assert_eq!(v3.swap_fee, None);
+
+        assert!(DexConfig::from_str("sushi:0x1F98431c8aD98523631AE4a59f267346ea31F984:1").is_err());
+        assert!(
+            DexConfig::from_str("uniswap_v2:0x1F98431c8aD98523631AE4a59f267346ea31F984").is_err()
+        );
+
+        // every preset entry parses
+        assert_eq!(ChainConfig::mainnet().dexes.len(), 10);
+    }
+
+    fn block(gas_used: u64, gas_limit: u64) -> Block<TxHash> {
+        Block {
+            base_fee_per_gas: Some(U256::from(1_000_000_000u64)),
+            gas_used: gas_used.into(),
+            gas_limit: gas_limit.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn next_base_fee_follows_chain_rules() {
+        let mainnet = BaseFeeRules::default();
+        assert_eq!(
+            mainnet.next_base_fee(&block(15_000_000, 30_000_000)),
+            1_000_000_000u64.into()
+        );
+        assert_eq!(
+            mainnet.next_base_fee(&block(30_000_000, 30_000_000)),
+            1_125_000_000u64.into()
+        );
+        assert_eq!(
+            mainnet.next_base_fee(&block(0, 30_000_000)),
+            875_000_000u64.into()
+        );
+
+        // chains with a different elasticity and denominator (e.g. op stack)
+        let rules = BaseFeeRules {
+            elasticity_multiplier: 6,
+            max_change_denominator: 50,
+            ..Default::default()
+        };
+        assert_eq!(
+            rules.next_base_fee(&block(30_000_000, 30_000_000)),
+            1_100_000_000u64.into()
+        );
+        assert_eq!(
+            rules.next_base_fee(&block(0, 30_000_000)),
+            980_000_000u64.into()
+        );
+    }
+
+    #[test]
+    fn next_excess_blob_gas_tracks_target() {
+        let rules = BaseFeeRules::default();
+        let mut parent = block(0, 30_000_000);
+        assert_eq!(rules.next_excess_blob_gas(&parent), None);
+
+        parent.excess_blob_gas = Some(U256::from(100_000));
+        parent.blob_gas_used = Some(U256::from(786_432));
+        assert_eq!(rules.next_excess_blob_gas(&parent), Some(493_216));
+
+        parent.blob_gas_used = Some(U256::zero());
+        assert_eq!(rules.next_excess_blob_gas(&parent), Some(0));
+    }
+}
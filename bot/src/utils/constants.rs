@@ -3,9 +3,31 @@ use std::str::FromStr;
 use ethers::prelude::*;
 use indoc::indoc;
 
-// Return weth address
+use super::chain::chain_config;
+
+// Return weth address (the wrapped native token of the configured chain)
 pub fn get_weth_address() -> Address {
-    Address::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2").unwrap()
+    chain_config().wrapped_native
+}
+
+// Address curve pools use to represent native ether
+pub fn get_eth_placeholder_address() -> Address {
+    Address::from_str("0xEeeeeEeeeEeEeeEeEeEeeEEEeeeeEeeeeeeeEEeE").unwrap()
+}
+
+// Balancer's vault, holds the balances of every balancer pool
+pub fn get_balancer_vault_address() -> Address {
+    Address::from_str("0xBA12222222228d8Ba445958a75a0704d566BF2C8").unwrap()
+}
+
+// Uniswap v4's `PoolManager`, holds the state of every v4 pool
+pub fn get_v4_pool_manager_address() -> Address {
+    Address::from_str("0x000000000004444c5dc75cB358380D2e3dE08A90").unwrap()
+}
+
+// Contracts that hold the state of many pools (pools inside of them are keyed by storage slot)
+pub fn is_pool_singleton(address: &Address) -> bool {
+    *address == get_balancer_vault_address() || *address == get_v4_pool_manager_address()
 }
 
 // Return the ethdev address (used if we need funds)
@@ -1,13 +1,55 @@
 use ethers::prelude::*;
 use std::str::FromStr;
 
-// Construct the searcher wallet
-pub fn get_searcher_wallet() -> LocalWallet {
-    let searcher_private_key = std::env::var("SEARCHER_PRIVATE_KEY")
-        .expect("Required environment variable \"SEARCHER_PRIVATE_KEY\" not set");
-    searcher_private_key
+use super::chain::{DexConfig, RelayEndpoint};
+use super::signer::SignerEndpoint;
+
+// Construct a wallet from the raw private key in `var` (`<PREFIX>_SIGNER=local`)
+pub fn get_private_key_wallet(var: &str) -> LocalWallet {
+    let private_key = std::env::var(var)
+        .unwrap_or_else(|_| panic!("Required environment variable \"{}\" not set", var));
+    private_key
         .parse::<LocalWallet>()
-        .expect("Failed to parse private key")
+        .unwrap_or_else(|_| panic!("Failed to parse private key in \"{}\"", var))
+}
+
+/// Return how a signer's key is held (`<PREFIX>_SIGNER`): `local`, `keystore` or `external`
+pub fn get_signer_kind(prefix: &str) -> String {
+    dotenv::var(format!("{}_SIGNER", prefix))
+        .ok()
+        .filter(|signer| !signer.is_empty())
+        .unwrap_or("local".to_string())
+}
+
+/// Return a signer's json keystore path and its password
+pub fn get_signer_keystore(prefix: &str) -> (std::path::PathBuf, String) {
+    (
+        required_var(&format!("{}_KEYSTORE_PATH", prefix)).into(),
+        required_var(&format!("{}_KEYSTORE_PASSWORD", prefix)),
+    )
+}
+
+/// Return where a signer's external signer listens, `unix://` urls are unix sockets
+pub fn get_signer_endpoint(prefix: &str) -> SignerEndpoint {
+    let var = format!("{}_SIGNER_URL", prefix);
+    let url = required_var(&var);
+    match url.strip_prefix("unix://") {
+        Some(path) => SignerEndpoint::Unix(path.into()),
+        None => SignerEndpoint::Http(
+            url.parse()
+                .unwrap_or_else(|_| panic!("{} is not a valid url", var)),
+        ),
+    }
+}
+
+/// Return the address whose key is held by a signer's external signer
+pub fn get_signer_address(prefix: &str) -> Address {
+    let var = format!("{}_ADDRESS", prefix);
+    Address::from_str(&required_var(&var)).unwrap_or_else(|_| panic!("Failed to parse \"{}\"", var))
+}
+
+fn required_var(var: &str) -> String {
+    dotenv::var(var).unwrap_or_else(|_| panic!("Required environment variable \"{}\" not set", var))
 }
 
 // Get block number that sandwich contract was deployed in
@@ -22,16 +64,6 @@ pub fn get_sandwich_inception_block() -> U64 {
     U64::from(inception_block)
 }
 
-/// Construct the bundle signer
-/// This is your flashbots searcher identity
-pub fn get_bundle_signer() -> LocalWallet {
-    let private_key = std::env::var("FLASHBOTS_AUTH_KEY")
-        .expect("Required environment variable \"FLASHBOTS_AUTH_KEY\" not set");
-    private_key
-        .parse::<LocalWallet>()
-        .expect("Failed to parse flashbots signer")
-}
-
 /// Returns the configured Sandwich Contract Address
 pub fn get_sandwich_contract_address() -> Address {
     let addr = std::env::var("SANDWICH_CONTRACT")
@@ -44,14 +76,12 @@ pub fn read_env_vars() -> Vec<(String, String)> {
     let mut env_vars = Vec::new();
     let keys = vec![
         "RPC_URL_WSS",
-        "SEARCHER_PRIVATE_KEY",
-        "FLASHBOTS_AUTH_KEY",
         "SANDWICH_CONTRACT",
         "V2_ALERT_DISCORD_WEBHOOK",
         "V3_ALERT_DISCORD_WEBHOOK",
         "POISON_ALERT_DISCORD_WEBHOOK",
         "SANDWICH_INCEPTION_BLOCK",
-        "SUCCESS_DISCORD_WEBHOOK"
+        "SUCCESS_DISCORD_WEBHOOK",
     ];
     for key in keys {
         let value = dotenv::var(key).expect(&format!(
@@ -72,6 +102,62 @@ pub async fn get_ws_provider() -> Provider<Ws> {
         .expect("RPC Connection Error")
 }
 
+/// Return an http rpc url used to send batched json-rpc calls (None if not set)
+pub fn get_http_rpc_url() -> Option<String> {
+    dotenv::var("RPC_URL_HTTP")
+        .ok()
+        .filter(|url| !url.is_empty())
+}
+
+/// Return the address the prometheus `/metrics` endpoint listens on (None if disabled)
+pub fn get_metrics_addr() -> Option<std::net::SocketAddr> {
+    let addr = dotenv::var("METRICS_ADDR").unwrap_or("127.0.0.1:9560".to_string());
+    match addr.is_empty() {
+        true => None,
+        false => Some(
+            addr.parse()
+                .expect("METRICS_ADDR is not a valid socket address"),
+        ),
+    }
+}
+
+/// Return the address the admin json-rpc api listens on (None if disabled)
+///
+/// Panics if the address is reachable from outside the host while `ADMIN_TOKEN` is not set
+pub fn get_admin_addr() -> Option<std::net::SocketAddr> {
+    let addr = dotenv::var("ADMIN_ADDR").unwrap_or("127.0.0.1:9561".to_string());
+    if addr.is_empty() {
+        return None;
+    }
+
+    let addr: std::net::SocketAddr = addr
+        .parse()
+        .expect("ADMIN_ADDR is not a valid socket address");
+    if !addr.ip().is_loopback() && get_admin_token().is_none() {
+        panic!(
+            "ADMIN_ADDR {} is not on loopback, set \"ADMIN_TOKEN\" to expose it",
+            addr
+        );
+    }
+    Some(addr)
+}
+
+/// Return the bearer token admin api calls must carry (None if calls are not authenticated)
+pub fn get_admin_token() -> Option<String> {
+    dotenv::var("ADMIN_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty())
+}
+
+/// Return the file json logs are appended to (None if disabled)
+pub fn get_json_log_file() -> Option<String> {
+    let path = dotenv::var("LOG_JSON_FILE").unwrap_or("output.jsonl".to_string());
+    match path.is_empty() {
+        true => None,
+        false => Some(path),
+    }
+}
+
 /// Return a webhook for v2 discord alert channel
 pub fn get_v2_alert_webhook() -> String {
     dotenv::var("V2_ALERT_DISCORD_WEBHOOK")
@@ -84,8 +170,10 @@ pub fn get_v3_alert_webhook() -> String {
         .expect("Required environment variable \"V3_ALERT_DISCORD_WEBHOOK\" not set")
 }
 pub fn get_success_discord_webhook() -> String {
-    dotenv::var("SUCCESS_DISCORD_WEBHOOK").expect("
-    Required environment variable \"SUCCESS_DISCORD_WEBHOOK\" not set")
+    dotenv::var("SUCCESS_DISCORD_WEBHOOK").expect(
+        "
+    Required environment variable \"SUCCESS_DISCORD_WEBHOOK\" not set",
+    )
 }
 
 /// Return a webhook for poison discord alert channel
@@ -99,4 +187,304 @@ pub fn get_interval_block_new_pool() -> u64 {
         .expect("Required environment variable \"INTERVAL_BLOCK_NEW_POOL\" not set")
         .parse()
         .expect("INTERVAL_BLOCK_NEW_POOL is not a valid u64")
-}
\ No newline at end of file
+}
+
+/// Return the number of blocks an underpriced victim is kept for before being dropped
+pub fn get_pending_victim_ttl_blocks() -> u64 {
+    dotenv::var("PENDING_VICTIM_TTL_BLOCKS")
+        .unwrap_or("5".to_string())
+        .parse()
+        .expect("PENDING_VICTIM_TTL_BLOCKS is not a valid u64")
+}
+
+/// Return the max number of underpriced victims held in memory at once
+pub fn get_pending_victim_max() -> usize {
+    dotenv::var("PENDING_VICTIM_MAX")
+        .unwrap_or("2000".to_string())
+        .parse()
+        .expect("PENDING_VICTIM_MAX is not a valid usize")
+}
+
+/// Return a comma separated env var as a list, empty if not set
+pub fn get_list(key: &str) -> Vec<String> {
+    dotenv::var(key)
+        .unwrap_or_default()
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Return a boolean env var, `default` if not set
+pub fn get_bool(key: &str, default: bool) -> bool {
+    match dotenv::var(key) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("{} is not a valid bool", key)),
+        Err(_) => default,
+    }
+}
+
+/// Return the number of blocks a learned token blacklist entry is kept for (0 = forever)
+pub fn get_token_blacklist_expiry_blocks() -> u64 {
+    dotenv::var("TOKEN_BLACKLIST_EXPIRY_BLOCKS")
+        .unwrap_or("50400".to_string())
+        .parse()
+        .expect("TOKEN_BLACKLIST_EXPIRY_BLOCKS is not a valid u64")
+}
+
+/// Return the path of the file that learned token blacklist entries are persisted to
+pub fn get_token_registry_path() -> std::path::PathBuf {
+    dotenv::var("TOKEN_REGISTRY_PATH")
+        .unwrap_or("token_registry.json".to_string())
+        .into()
+}
+
+/// Return the number of blocks a measured token profile is reused for
+pub fn get_token_profile_ttl_blocks() -> u64 {
+    dotenv::var("TOKEN_PROFILE_TTL_BLOCKS")
+        .unwrap_or("300".to_string())
+        .parse()
+        .expect("TOKEN_PROFILE_TTL_BLOCKS is not a valid u64")
+}
+
+/// Return the max buy/sell tax (in bps) a token can have to be sandwiched
+pub fn get_max_token_tax_bps() -> u64 {
+    dotenv::var("MAX_TOKEN_TAX_BPS")
+        .unwrap_or("500".to_string())
+        .parse()
+        .expect("MAX_TOKEN_TAX_BPS is not a valid u64")
+}
+
+/// Return solidly style factories to monitor as (factory, creation block) pairs
+///
+/// Read from `SOLIDLY_FACTORIES` as comma separated `address:creation_block` entries
+pub fn get_solidly_factories() -> Vec<(Address, u64)> {
+    get_list("SOLIDLY_FACTORIES")
+        .iter()
+        .map(|entry| {
+            let (factory, creation_block) = entry
+                .split_once(':')
+                .expect("SOLIDLY_FACTORIES entries must be formatted as address:creation_block");
+            let factory = Address::from_str(factory)
+                .expect("Failed to parse factory in \"SOLIDLY_FACTORIES\"");
+            let creation_block = creation_block
+                .parse()
+                .expect("SOLIDLY_FACTORIES creation block is not a valid u64");
+            (factory, creation_block)
+        })
+        .collect()
+}
+
+/// Return true if the fee of each UniswapV2 style pool should be detected by simulation
+/// instead of trusting the fee configured for its factory
+pub fn get_detect_v2_swap_fees() -> bool {
+    get_bool("DETECT_V2_SWAP_FEES", false)
+}
+
+/// Return the min weth (in ether) a pool needs to hold to be simulated
+pub fn get_min_pool_weth_liquidity() -> U256 {
+    let min_liquidity = dotenv::var("MIN_POOL_WETH_LIQUIDITY").unwrap_or("1".to_string());
+    ethers::utils::parse_ether(min_liquidity)
+        .expect("MIN_POOL_WETH_LIQUIDITY is not a valid amount")
+}
+
+/// Return the number of past blocks pool snapshots are rebuilt from on startup
+pub fn get_pool_snapshot_backfill_blocks() -> u64 {
+    dotenv::var("POOL_SNAPSHOT_BACKFILL_BLOCKS")
+        .unwrap_or("1800".to_string())
+        .parse()
+        .expect("POOL_SNAPSHOT_BACKFILL_BLOCKS is not a valid u64")
+}
+
+/// Return the number of simulations run at once (defaults to the number of cores)
+pub fn get_simulation_workers() -> usize {
+    match dotenv::var("SIMULATION_WORKERS")
+        .ok()
+        .filter(|w| !w.is_empty())
+    {
+        Some(workers) => workers
+            .parse()
+            .expect("SIMULATION_WORKERS is not a valid usize"),
+        None => std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(4),
+    }
+}
+
+/// Return the max number of simulations waiting for a worker before the oldest is dropped
+pub fn get_simulation_queue_capacity() -> usize {
+    dotenv::var("SIMULATION_QUEUE_CAPACITY")
+        .unwrap_or("256".to_string())
+        .parse()
+        .expect("SIMULATION_QUEUE_CAPACITY is not a valid usize")
+}
+
+/// Return an ether denominated env var in wei, None if not set or empty
+pub fn get_ether(key: &str) -> Option<U256> {
+    dotenv::var(key)
+        .ok()
+        .filter(|value| !value.is_empty())
+        .map(|value| {
+            ethers::utils::parse_ether(value)
+                .unwrap_or_else(|_| panic!("{} is not a valid amount", key))
+        })
+}
+
+/// Return per token frontrun caps (in wei)
+///
+/// Read from `MAX_FRONTRUN_PER_TOKEN` as comma separated `token:amount_in_ether` entries
+pub fn get_max_frontrun_per_token() -> Vec<(Address, U256)> {
+    get_list("MAX_FRONTRUN_PER_TOKEN")
+        .iter()
+        .map(|entry| {
+            let (token, amount) = entry
+                .split_once(':')
+                .expect("MAX_FRONTRUN_PER_TOKEN entries must be formatted as token:amount");
+            let token = Address::from_str(token)
+                .expect("Failed to parse token in \"MAX_FRONTRUN_PER_TOKEN\"");
+            let amount = ethers::utils::parse_ether(amount)
+                .expect("MAX_FRONTRUN_PER_TOKEN amount is not a valid amount");
+            (token, amount)
+        })
+        .collect()
+}
+
+/// Return the number of bundles in a row that can miss inclusion before the bot halts
+pub fn get_max_consecutive_misses() -> Option<u64> {
+    dotenv::var("MAX_CONSECUTIVE_MISSES")
+        .ok()
+        .filter(|misses| !misses.is_empty())
+        .map(|misses| {
+            misses
+                .parse()
+                .expect("MAX_CONSECUTIVE_MISSES is not a valid u64")
+        })
+}
+
+/// Return the webhook risk halts are reported to (defaults to the poison alert channel)
+pub fn get_risk_alert_webhook() -> Option<String> {
+    ["RISK_ALERT_DISCORD_WEBHOOK", "POISON_ALERT_DISCORD_WEBHOOK"]
+        .iter()
+        .find_map(|key| dotenv::var(key).ok().filter(|webhook| !webhook.is_empty()))
+}
+
+/// Return the chain preset to run against: `mainnet`, `devnet` or `custom`
+pub fn get_chain() -> String {
+    dotenv::var("CHAIN")
+        .ok()
+        .filter(|chain| !chain.is_empty())
+        .unwrap_or("mainnet".to_string())
+}
+
+/// Return a u64 env var, None if not set or empty
+pub fn get_u64(key: &str) -> Option<u64> {
+    dotenv::var(key)
+        .ok()
+        .filter(|value| !value.is_empty())
+        .map(|value| {
+            value
+                .parse()
+                .unwrap_or_else(|_| panic!("{} is not a valid u64", key))
+        })
+}
+
+/// Return an address env var, None if not set or empty
+pub fn get_address(key: &str) -> Option<Address> {
+    dotenv::var(key)
+        .ok()
+        .filter(|value| !value.is_empty())
+        .map(|value| {
+            Address::from_str(&value).unwrap_or_else(|_| panic!("{} is not a valid address", key))
+        })
+}
+
+/// Return how bundles are submitted (`relays` or `direct`), None to use the chain's default
+pub fn get_bundle_submission() -> Option<String> {
+    dotenv::var("BUNDLE_SUBMISSION")
+        .ok()
+        .filter(|submission| !submission.is_empty())
+}
+
+/// Return the dexes pools are synced from, None to use the chain's default
+///
+/// Read from `CHAIN_DEXES` as comma separated `variant:factory:creation_block[:swap_fee]` entries
+pub fn get_chain_dexes() -> Option<Vec<DexConfig>> {
+    let entries = get_list("CHAIN_DEXES");
+    if entries.is_empty() {
+        return None;
+    }
+    let dexes = entries
+        .iter()
+        .map(|entry| {
+            entry
+                .parse()
+                .unwrap_or_else(|e| panic!("Failed to parse \"CHAIN_DEXES\" entry: {}", e))
+        })
+        .collect();
+    Some(dexes)
+}
+
+/// Return the relays bundles are sent to
+///
+/// Read from `BUNDLE_RELAYS` as comma separated `name=url` entries
+pub fn get_bundle_relays() -> Vec<RelayEndpoint> {
+    get_list("BUNDLE_RELAYS")
+        .iter()
+        .map(|entry| {
+            let (name, url) = entry
+                .split_once('=')
+                .expect("BUNDLE_RELAYS entries must be formatted as name=url");
+            let url = url
+                .parse()
+                .expect("Failed to parse url in \"BUNDLE_RELAYS\"");
+            RelayEndpoint {
+                name: name.to_string(),
+                url,
+                coinbase: None,
+            }
+        })
+        .collect()
+}
+
+/// Return the fee recipients of the builders behind relays
+///
+/// Read from `BUILDER_COINBASES` as comma separated `relay_name:address` entries
+pub fn get_builder_coinbases() -> Vec<(String, Address)> {
+    get_list("BUILDER_COINBASES")
+        .iter()
+        .map(|entry| {
+            let (name, coinbase) = entry
+                .split_once(':')
+                .expect("BUILDER_COINBASES entries must be formatted as relay_name:address");
+            let coinbase = Address::from_str(coinbase)
+                .expect("Failed to parse address in \"BUILDER_COINBASES\"");
+            (name.to_string(), coinbase)
+        })
+        .collect()
+}
+
+/// Return when the mega sandwich is built, in ms relative to the start of the target slot
+pub fn get_mega_sandwich_deadline_ms() -> i64 {
+    dotenv::var("MEGA_SANDWICH_DEADLINE_MS")
+        .ok()
+        .filter(|deadline| !deadline.is_empty())
+        .map(|deadline| {
+            deadline
+                .parse()
+                .expect("MEGA_SANDWICH_DEADLINE_MS is not a valid i64")
+        })
+        .unwrap_or(-1500)
+}
+
+/// Return the last moment bundles are sent, in ms relative to the start of the target slot
+pub fn get_submission_deadline_ms() -> Option<i64> {
+    dotenv::var("SUBMISSION_DEADLINE_MS")
+        .ok()
+        .filter(|deadline| !deadline.is_empty())
+        .map(|deadline| {
+            deadline
+                .parse()
+                .expect("SUBMISSION_DEADLINE_MS is not a valid i64")
+        })
+}
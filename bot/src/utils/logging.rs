@@ -0,0 +1,154 @@
+use std::{
+    fmt,
+    fs::OpenOptions,
+    io::{self, Write},
+    sync::Mutex,
+};
+
+use colored::Colorize;
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::{
+    fmt::{format::Writer, FmtContext, FormatEvent, FormatFields},
+    layer::SubscriberExt,
+    registry::LookupSpan,
+    util::SubscriberInitExt,
+    EnvFilter, Layer,
+};
+
+use super::dotenv;
+
+// Setup logging to the console and `output.log` (colored, same look as before tracing) and to a
+// json file
+//
+// `log::` macros are forwarded to tracing so that every line logged while a victim is being
+// processed carries the victim's span (victim hash, target block, pool, relay) in the json output
+//
+// Returns:
+// Ok(()) if logger was installed
+// Err(eyre::Error) if a log file could not be opened or a logger is already installed
+pub fn init_logging() -> eyre::Result<()> {
+    // hide all logs for everything other than bot (unless overridden through `RUST_LOG`)
+    let filter = || {
+        EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| EnvFilter::new("error,rusty_sando=info"))
+    };
+
+    let console_layer = tracing_subscriber::fmt::layer()
+        .event_format(ConsoleFormat)
+        .with_filter(filter());
+
+    let log_file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open("output.log")?;
+    let file_layer = tracing_subscriber::fmt::layer()
+        .event_format(ConsoleFormat)
+        .with_writer(Mutex::new(log_file))
+        .with_filter(filter());
+
+    let json_layer = match dotenv::get_json_log_file() {
+        Some(path) => {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            let layer = tracing_subscriber::fmt::layer()
+                .json()
+                .with_current_span(true)
+                .with_span_list(true)
+                .with_writer(Mutex::new(StripAnsi(file)))
+                .with_filter(filter());
+            Some(layer)
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(console_layer)
+        .with(file_layer)
+        .with(json_layer)
+        .try_init()?;
+
+    Ok(())
+}
+
+/// Formats events as `[%H:%M:%S][LEVEL] message`, spans are left to the json output
+struct ConsoleFormat;
+
+impl<S, N> FormatEvent<S, N> for ConsoleFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let level = *event.metadata().level();
+        let level = match level {
+            Level::TRACE => "TRACE".cyan(),
+            Level::DEBUG => "DEBUG".magenta(),
+            Level::INFO => "INFO".green(),
+            Level::WARN => "WARN".red(),
+            Level::ERROR => "ERROR".bright_red(),
+        };
+
+        write!(
+            writer,
+            "{}[{}] ",
+            chrono::Local::now().format("[%H:%M:%S]"),
+            level
+        )?;
+        ctx.format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
+
+/// Writer that drops the ansi color codes `colored` puts into messages
+///
+/// Json escapes the escape character, so codes show up as `\u001b[..m` in serialized lines
+struct StripAnsi<W>(W);
+
+impl<W: Write> Write for StripAnsi<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write_all(&strip_escaped_ansi(buf))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+// Remove every `\u001b[...m` sequence from a serialized json line
+fn strip_escaped_ansi(buf: &[u8]) -> Vec<u8> {
+    const ESCAPE: &[u8] = b"\\u001b[";
+
+    let mut stripped = Vec::with_capacity(buf.len());
+    let mut i = 0;
+    while i < buf.len() {
+        if buf[i..].starts_with(ESCAPE) {
+            let code_len = buf[i + ESCAPE.len()..].iter().position(|b| *b == b'm');
+            if let Some(code_len) = code_len {
+                i += ESCAPE.len() + code_len + 1;
+                continue;
+            }
+        }
+        stripped.push(buf[i]);
+        i += 1;
+    }
+    stripped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn escaped_color_codes_are_stripped() {
+        let line = br#"{"message":"\u001b[32m0xabc [weth_is_input]\u001b[0m"}"#;
+        assert_eq!(
+            strip_escaped_ansi(line),
+            br#"{"message":"0xabc [weth_is_input]"}"#.to_vec()
+        );
+    }
+}
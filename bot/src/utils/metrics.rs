@@ -0,0 +1,379 @@
+use std::{
+    fmt::Write,
+    sync::{
+        atomic::{AtomicI64, AtomicU64, Ordering},
+        OnceLock,
+    },
+    time::Duration,
+};
+
+use dashmap::DashMap;
+
+// upper bounds (seconds) of simulation latency buckets
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Monotonically increasing count
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Value that can go up and down
+#[derive(Debug, Default)]
+pub struct Gauge(AtomicI64);
+
+impl Gauge {
+    pub fn set(&self, value: i64) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Counter split by the value of a single label
+#[derive(Debug, Default)]
+pub struct LabeledCounter(DashMap<String, AtomicU64>);
+
+impl LabeledCounter {
+    pub fn inc(&self, label: &str) {
+        self.add(label, 1);
+    }
+
+    pub fn add(&self, label: &str, n: u64) {
+        match self.0.get(label) {
+            Some(count) => count.fetch_add(n, Ordering::Relaxed),
+            None => self
+                .0
+                .entry(label.to_string())
+                .or_default()
+                .fetch_add(n, Ordering::Relaxed),
+        };
+    }
+
+    pub fn get(&self, label: &str) -> u64 {
+        self.0
+            .get(label)
+            .map(|count| count.load(Ordering::Relaxed))
+            .unwrap_or_default()
+    }
+}
+
+/// Distribution of durations over fixed buckets
+#[derive(Debug)]
+pub struct Histogram {
+    // observations at or below each bound of `LATENCY_BUCKETS` (not cumulative)
+    buckets: Vec<AtomicU64>,
+    sum_us: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: LATENCY_BUCKETS.iter().map(|_| AtomicU64::new(0)).collect(),
+            sum_us: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Histogram {
+    pub fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        self.sum_us
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Counters and histograms exported on the `/metrics` endpoint
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// txs received from the mempool stream
+    pub mempool_txs_seen: Counter,
+    /// txs dropped before simulation, by reason
+    pub mempool_txs_filtered: LabeledCounter,
+    /// sandwichable pools found in victim state diffs
+    pub pools_matched: Counter,
+    /// time taken to find (or fail to find) an optimal sandwich
+    pub simulation_latency: Histogram,
+    /// failed simulations, by `SimulationError` variant
+    pub simulation_failures: LabeledCounter,
+    /// victim legs on pools without an executor that left slippage room, by pool variant
+    pub unexecutable_opportunities: LabeledCounter,
+    /// bundles accepted by a relay, by relay name
+    pub bundles_sent: LabeledCounter,
+    pub bundles_included: Counter,
+    /// profit of included bundles (gwei)
+    pub profit_gwei: Counter,
+    /// rpc round trips made by fork backends, by fetch method
+    pub rpc_calls: LabeledCounter,
+    /// seconds between the latest block's timestamp and when the block oracle saw it
+    pub block_oracle_lag_seconds: Gauge,
+    /// slots that passed without a block
+    pub missed_slots: Counter,
+    /// mined blocks whose state diffs could not be replayed into the fork state cache
+    pub block_state_replay_failures: Counter,
+    /// dry run bundles replayed in their target block, by outcome
+    pub shadow_bundles: LabeledCounter,
+    /// replayed profit of dry run bundles that would have landed (gwei)
+    pub shadow_profit_gwei: Counter,
+    /// bundles stopped by risk limits before signing, by violation
+    pub risk_rejections: LabeledCounter,
+    /// times risk limits halted bundle submission
+    pub risk_halts: Counter,
+    /// change in sandwich contract weth and searcher eth since start of day (gwei)
+    pub realised_pnl_gwei: Gauge,
+    /// gas paid by included bundles since start of day (gwei)
+    pub daily_gas_spent_gwei: Gauge,
+}
+
+// Metrics shared by the whole bot
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::default)
+}
+
+impl Metrics {
+    // Render all metrics in prometheus' text exposition format
+    //
+    // Arguments:
+    // * `&self`: reference to `Metrics` instance
+    // * `out`: buffer to append to
+    //
+    // Returns: This function returns nothing
+    pub fn render(&self, out: &mut String) {
+        write_counter(
+            out,
+            "mempool_txs_seen_total",
+            "Txs received from the mempool stream",
+            self.mempool_txs_seen.get(),
+        );
+        write_labeled(
+            out,
+            "mempool_txs_filtered_total",
+            "Txs dropped before simulation",
+            "reason",
+            &self.mempool_txs_filtered,
+        );
+        write_counter(
+            out,
+            "pools_matched_total",
+            "Sandwichable pools found in victim state diffs",
+            self.pools_matched.get(),
+        );
+        write_histogram(
+            out,
+            "simulation_latency_seconds",
+            "Time taken to search for an optimal sandwich",
+            &self.simulation_latency,
+        );
+        write_labeled(
+            out,
+            "simulation_failures_total",
+            "Failed simulations by error",
+            "error",
+            &self.simulation_failures,
+        );
+        write_labeled(
+            out,
+            "unexecutable_opportunities_total",
+            "Victim legs on pools without an executor that left slippage room",
+            "variant",
+            &self.unexecutable_opportunities,
+        );
+        write_labeled(
+            out,
+            "bundles_sent_total",
+            "Bundles accepted by a relay",
+            "relay",
+            &self.bundles_sent,
+        );
+        write_counter(
+            out,
+            "bundles_included_total",
+            "Bundles included on chain",
+            self.bundles_included.get(),
+        );
+        write_counter(
+            out,
+            "profit_gwei_total",
+            "Profit of included bundles in gwei",
+            self.profit_gwei.get(),
+        );
+        write_labeled(
+            out,
+            "rpc_calls_total",
+            "Rpc round trips made by fork backends",
+            "method",
+            &self.rpc_calls,
+        );
+        write_gauge(
+            out,
+            "block_oracle_lag_seconds",
+            "Delay between a block's timestamp and the block oracle seeing it",
+            self.block_oracle_lag_seconds.get(),
+        );
+        write_counter(
+            out,
+            "missed_slots_total",
+            "Slots that passed without a block",
+            self.missed_slots.get(),
+        );
+        write_counter(
+            out,
+            "block_state_replay_failures_total",
+            "Mined blocks whose state diffs could not be replayed into the fork state cache",
+            self.block_state_replay_failures.get(),
+        );
+        write_labeled(
+            out,
+            "shadow_bundles_total",
+            "Dry run bundles replayed in their target block",
+            "outcome",
+            &self.shadow_bundles,
+        );
+        write_counter(
+            out,
+            "shadow_profit_gwei_total",
+            "Replayed profit of dry run bundles that would have landed in gwei",
+            self.shadow_profit_gwei.get(),
+        );
+        write_labeled(
+            out,
+            "risk_rejections_total",
+            "Bundles stopped by risk limits before signing",
+            "violation",
+            &self.risk_rejections,
+        );
+        write_counter(
+            out,
+            "risk_halts_total",
+            "Times risk limits halted bundle submission",
+            self.risk_halts.get(),
+        );
+        write_gauge(
+            out,
+            "realised_pnl_gwei",
+            "Change in sandwich weth and searcher eth since start of day in gwei",
+            self.realised_pnl_gwei.get(),
+        );
+        write_gauge(
+            out,
+            "daily_gas_spent_gwei",
+            "Gas paid by included bundles since start of day in gwei",
+            self.daily_gas_spent_gwei.get(),
+        );
+    }
+}
+
+// Names are prefixed so that metrics do not clash with other exporters on the same prometheus
+fn write_header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP rusty_sando_{} {}", name, help);
+    let _ = writeln!(out, "# TYPE rusty_sando_{} {}", name, kind);
+}
+
+pub fn write_counter(out: &mut String, name: &str, help: &str, value: u64) {
+    write_header(out, name, help, "counter");
+    let _ = writeln!(out, "rusty_sando_{} {}", name, value);
+}
+
+pub fn write_gauge(out: &mut String, name: &str, help: &str, value: impl std::fmt::Display) {
+    write_header(out, name, help, "gauge");
+    let _ = writeln!(out, "rusty_sando_{} {}", name, value);
+}
+
+fn write_labeled(out: &mut String, name: &str, help: &str, label: &str, counter: &LabeledCounter) {
+    write_header(out, name, help, "counter");
+
+    // sort so that scrapes are stable and easy to diff
+    let mut values: Vec<(String, u64)> = counter
+        .0
+        .iter()
+        .map(|entry| (entry.key().clone(), entry.value().load(Ordering::Relaxed)))
+        .collect();
+    values.sort();
+
+    for (value, count) in values {
+        let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+        let _ = writeln!(
+            out,
+            "rusty_sando_{}{{{}=\"{}\"}} {}",
+            name, label, value, count
+        );
+    }
+}
+
+fn write_histogram(out: &mut String, name: &str, help: &str, histogram: &Histogram) {
+    write_header(out, name, help, "histogram");
+
+    let mut cumulative = 0;
+    for (bound, bucket) in LATENCY_BUCKETS.iter().zip(histogram.buckets.iter()) {
+        cumulative += bucket.load(Ordering::Relaxed);
+        let _ = writeln!(
+            out,
+            "rusty_sando_{}_bucket{{le=\"{}\"}} {}",
+            name, bound, cumulative
+        );
+    }
+
+    let count = histogram.count.load(Ordering::Relaxed);
+    let sum = histogram.sum_us.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+    let _ = writeln!(out, "rusty_sando_{}_bucket{{le=\"+Inf\"}} {}", name, count);
+    let _ = writeln!(out, "rusty_sando_{}_sum {}", name, sum);
+    let _ = writeln!(out, "rusty_sando_{}_count {}", name, count);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let histogram = Histogram::default();
+        histogram.observe(Duration::from_millis(3));
+        histogram.observe(Duration::from_millis(40));
+        histogram.observe(Duration::from_secs(60));
+
+        let mut out = String::new();
+        write_histogram(&mut out, "latency", "", &histogram);
+
+        assert!(out.contains("rusty_sando_latency_bucket{le=\"0.005\"} 1\n"));
+        assert!(out.contains("rusty_sando_latency_bucket{le=\"0.05\"} 2\n"));
+        assert!(out.contains("rusty_sando_latency_bucket{le=\"10\"} 2\n"));
+        assert!(out.contains("rusty_sando_latency_bucket{le=\"+Inf\"} 3\n"));
+        assert!(out.contains("rusty_sando_latency_count 3\n"));
+    }
+
+    #[test]
+    fn labeled_counter_renders_each_label() {
+        let counter = LabeledCounter::default();
+        counter.inc("flashbots");
+        counter.add("beaverbuild", 2);
+
+        let mut out = String::new();
+        write_labeled(&mut out, "bundles_sent_total", "", "relay", &counter);
+
+        assert!(out.contains("rusty_sando_bundles_sent_total{relay=\"beaverbuild\"} 2\n"));
+        assert!(out.contains("rusty_sando_bundles_sent_total{relay=\"flashbots\"} 1\n"));
+    }
+}
@@ -0,0 +1,394 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use async_trait::async_trait;
+use ethers::{
+    prelude::*,
+    types::transaction::{eip2718::TypedTransaction, eip712::Eip712},
+    utils::rlp::Rlp,
+};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixStream,
+};
+
+use crate::types::SignerError;
+
+use super::{chain::chain_config, dotenv};
+
+/// Key that signs the searcher's frontrun and backrun txs (or relay requests, see `SignerRole`)
+#[derive(Debug, Clone)]
+pub enum SearcherSigner {
+    /// key read from a raw private key or decrypted from a json keystore
+    Local(LocalWallet),
+    /// key held by a web3signer compatible signer, never seen by the bot
+    External(ExternalSigner),
+}
+
+/// Where an external signer listens
+#[derive(Debug, Clone)]
+pub enum SignerEndpoint {
+    Http(reqwest::Url),
+    Unix(PathBuf),
+}
+
+/// Signs through `eth_signTransaction` and `eth_sign` json-rpc calls
+#[derive(Debug, Clone)]
+pub struct ExternalSigner {
+    endpoint: SignerEndpoint,
+    address: Address,
+    chain_id: u64,
+    client: reqwest::Client,
+}
+
+/// What a signer's key is used for, each role is configured through its own env variables
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignerRole {
+    /// signs frontrun and backrun txs, variables are prefixed with `SEARCHER_`
+    Searcher,
+    /// signs relay requests (`X-Flashbots-Signature`), the identity relays track reputation
+    /// for, variables are prefixed with `RELAY_`
+    Relay,
+}
+
+impl SignerRole {
+    // Prefix of env variables configuring the role's signer
+    pub fn env_prefix(&self) -> &'static str {
+        match self {
+            SignerRole::Searcher => "SEARCHER",
+            SignerRole::Relay => "RELAY",
+        }
+    }
+
+    // Env variable holding the role's raw private key
+    pub fn private_key_var(&self) -> &'static str {
+        match self {
+            SignerRole::Searcher => "SEARCHER_PRIVATE_KEY",
+            // kept from before relay keys could live outside of the bot
+            SignerRole::Relay => "FLASHBOTS_AUTH_KEY",
+        }
+    }
+}
+
+// Searcher signer shared by the whole bot, read from env on first use
+pub fn searcher_signer() -> &'static SearcherSigner {
+    static SIGNER: OnceLock<SearcherSigner> = OnceLock::new();
+    SIGNER.get_or_init(|| {
+        SearcherSigner::from_env(SignerRole::Searcher).with_chain_id(chain_config().chain_id)
+    })
+}
+
+// Signer relay requests are authenticated with, read from env on first use
+pub fn relay_signer() -> &'static SearcherSigner {
+    static SIGNER: OnceLock<SearcherSigner> = OnceLock::new();
+    SIGNER.get_or_init(|| {
+        SearcherSigner::from_env(SignerRole::Relay).with_chain_id(chain_config().chain_id)
+    })
+}
+
+impl SearcherSigner {
+    // Create signer from `<PREFIX>_SIGNER` of `role`
+    //
+    // * `local` (default): raw key in `SEARCHER_PRIVATE_KEY` / `FLASHBOTS_AUTH_KEY`
+    // * `keystore`: json keystore at `<PREFIX>_KEYSTORE_PATH`, `<PREFIX>_KEYSTORE_PASSWORD`
+    // * `external`: signer at `<PREFIX>_SIGNER_URL` (http(s):// or unix://) holding the key of
+    //   `<PREFIX>_ADDRESS`
+    pub fn from_env(role: SignerRole) -> Self {
+        let prefix = role.env_prefix();
+        match dotenv::get_signer_kind(prefix).as_str() {
+            "local" => {
+                SearcherSigner::Local(dotenv::get_private_key_wallet(role.private_key_var()))
+            }
+            "keystore" => {
+                let (path, password) = dotenv::get_signer_keystore(prefix);
+                Self::from_keystore(&path, password)
+                    .unwrap_or_else(|e| panic!("Failed to decrypt {} keystore: {:?}", prefix, e))
+            }
+            "external" => SearcherSigner::External(ExternalSigner::new(
+                dotenv::get_signer_endpoint(prefix),
+                dotenv::get_signer_address(prefix),
+            )),
+            other => panic!(
+                "{}_SIGNER must be one of local, keystore or external, got {}",
+                prefix, other
+            ),
+        }
+    }
+
+    // Decrypt a json keystore
+    pub fn from_keystore(
+        path: &Path,
+        password: impl AsRef<[u8]>,
+    ) -> Result<SearcherSigner, SignerError> {
+        let wallet = LocalWallet::decrypt_keystore(path, password)?;
+        Ok(SearcherSigner::Local(wallet))
+    }
+}
+
+#[async_trait]
+impl Signer for SearcherSigner {
+    type Error = SignerError;
+
+    async fn sign_message<S: Send + Sync + AsRef<[u8]>>(
+        &self,
+        message: S,
+    ) -> Result<Signature, Self::Error> {
+        match self {
+            SearcherSigner::Local(wallet) => Ok(wallet.sign_message(message).await?),
+            SearcherSigner::External(signer) => signer.sign_message(message.as_ref()).await,
+        }
+    }
+
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Signature, Self::Error> {
+        match self {
+            SearcherSigner::Local(wallet) => Ok(wallet.sign_transaction(tx).await?),
+            SearcherSigner::External(signer) => signer.sign_transaction(tx).await,
+        }
+    }
+
+    async fn sign_typed_data<T: Eip712 + Send + Sync>(
+        &self,
+        payload: &T,
+    ) -> Result<Signature, Self::Error> {
+        match self {
+            SearcherSigner::Local(wallet) => Ok(wallet.sign_typed_data(payload).await?),
+            SearcherSigner::External(_) => Err(SignerError::Unsupported("eth_signTypedData")),
+        }
+    }
+
+    fn address(&self) -> Address {
+        match self {
+            SearcherSigner::Local(wallet) => wallet.address(),
+            SearcherSigner::External(signer) => signer.address,
+        }
+    }
+
+    fn chain_id(&self) -> u64 {
+        match self {
+            SearcherSigner::Local(wallet) => wallet.chain_id(),
+            SearcherSigner::External(signer) => signer.chain_id,
+        }
+    }
+
+    fn with_chain_id<T: Into<u64>>(self, chain_id: T) -> Self {
+        match self {
+            SearcherSigner::Local(wallet) => SearcherSigner::Local(wallet.with_chain_id(chain_id)),
+            SearcherSigner::External(signer) => SearcherSigner::External(ExternalSigner {
+                chain_id: chain_id.into(),
+                ..signer
+            }),
+        }
+    }
+}
+
+impl ExternalSigner {
+    pub fn new(endpoint: SignerEndpoint, address: Address) -> Self {
+        Self {
+            endpoint,
+            address,
+            chain_id: 1,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    // Sign tx through `eth_signTransaction`
+    //
+    // Signer returns the signed tx, the signature is only accepted if it was made by `address`
+    // over the exact tx that was asked for
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Signature, SignerError> {
+        let request = match tx {
+            TypedTransaction::Legacy(tx) => json!(tx),
+            TypedTransaction::Eip2930(tx) => json!(tx),
+            TypedTransaction::Eip1559(tx) => json!(tx),
+        };
+        let raw_tx: Bytes = self
+            .request("eth_signTransaction", json!([request]))
+            .await?;
+
+        let (_, signature) = TypedTransaction::decode_signed(&Rlp::new(&raw_tx))
+            .map_err(|e| SignerError::External(format!("invalid signed tx: {}", e)))?;
+        self.verify(signature, tx.sighash())
+    }
+
+    // Sign message through `eth_sign` (eip-191 prefixed)
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        let params = json!([self.address, Bytes::from(message.to_vec())]);
+        let signature: Bytes = self.request("eth_sign", params).await?;
+
+        let signature = Signature::try_from(signature.as_ref())
+            .map_err(|e| SignerError::External(format!("invalid signature: {}", e)))?;
+        self.verify(signature, ethers::utils::hash_message(message))
+    }
+
+    fn verify(&self, signature: Signature, hash: H256) -> Result<Signature, SignerError> {
+        match signature.recover(hash) {
+            Ok(signer) if signer == self.address => Ok(signature),
+            _ => Err(SignerError::External(format!(
+                "signature was not made by {:?}",
+                self.address
+            ))),
+        }
+    }
+
+    async fn request<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Value,
+    ) -> Result<T, SignerError> {
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+
+        let response: Value = match &self.endpoint {
+            SignerEndpoint::Http(url) => {
+                self.client
+                    .post(url.clone())
+                    .json(&request)
+                    .send()
+                    .await?
+                    .json()
+                    .await?
+            }
+            SignerEndpoint::Unix(path) => post_unix(path, &request).await?,
+        };
+
+        if let Some(error) = response.get("error") {
+            return Err(SignerError::External(error.to_string()));
+        }
+        let result = response.get("result").cloned().unwrap_or(Value::Null);
+        Ok(serde_json::from_value(result)?)
+    }
+}
+
+// Post a json body over http/1.1 on a unix socket
+async fn post_unix(path: &Path, body: &Value) -> Result<Value, SignerError> {
+    let body = body.to_string();
+    let request = format!(
+        "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    );
+
+    let mut stream = UnixStream::connect(path).await?;
+    stream.write_all(request.as_bytes()).await?;
+    let mut response = vec![];
+    stream.read_to_end(&mut response).await?;
+
+    let body = http_response_body(&response)
+        .ok_or_else(|| SignerError::External("malformed http response".to_string()))?;
+    Ok(serde_json::from_slice(&body)?)
+}
+
+// Return the body of an http/1.1 response, undoing chunked transfer encoding
+fn http_response_body(response: &[u8]) -> Option<Vec<u8>> {
+    let header_end = response.windows(4).position(|w| w == b"\r\n\r\n")?;
+    let headers = String::from_utf8_lossy(&response[..header_end]).to_lowercase();
+    let body = &response[header_end + 4..];
+
+    if !headers.contains("transfer-encoding: chunked") {
+        return Some(body.to_vec());
+    }
+
+    let mut decoded = vec![];
+    let mut rest = body;
+    loop {
+        let line_end = rest.windows(2).position(|w| w == b"\r\n")?;
+        let size = std::str::from_utf8(&rest[..line_end]).ok()?;
+        let size = usize::from_str_radix(size.split(';').next()?.trim(), 16).ok()?;
+        rest = &rest[line_end + 2..];
+
+        if size == 0 {
+            return Some(decoded);
+        }
+        decoded.extend_from_slice(rest.get(..size)?);
+        rest = rest.get(size + 2..)?;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ethers::core::rand::thread_rng;
+    use tokio::net::UnixListener;
+
+    use super::*;
+    use crate::utils::sign_eip1559;
+
+    fn tx(from: Address) -> Eip1559TransactionRequest {
+        Eip1559TransactionRequest::new()
+            .from(from)
+            .to(Address::repeat_byte(0xaa))
+            .chain_id(1u64)
+            .nonce(7u64)
+            .gas(100_000u64)
+            .max_fee_per_gas(30_000_000_000u64)
+            .max_priority_fee_per_gas(0u64)
+    }
+
+    #[tokio::test]
+    async fn keystore_signer_signs_txs() {
+        let dir = std::env::temp_dir();
+        let (wallet, uuid) =
+            LocalWallet::new_keystore(&dir, &mut thread_rng(), "password", None).unwrap();
+        let keystore = dir.join(uuid);
+
+        let signer = SearcherSigner::from_keystore(&keystore, "password").unwrap();
+        std::fs::remove_file(keystore).unwrap();
+        assert_eq!(signer.address(), wallet.address());
+
+        let raw_tx = sign_eip1559(tx(wallet.address()), &signer).await.unwrap();
+        let (_, signature) = TypedTransaction::decode_signed(&Rlp::new(&raw_tx)).unwrap();
+        let sighash = TypedTransaction::Eip1559(tx(wallet.address())).sighash();
+        assert_eq!(signature.recover(sighash).unwrap(), wallet.address());
+    }
+
+    #[tokio::test]
+    async fn external_signer_over_unix_socket() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let socket = std::env::temp_dir().join(format!("signer-{:?}.sock", wallet.address()));
+        let listener = UnixListener::bind(&socket).unwrap();
+
+        // answers a single eth_signTransaction call with a chunked response
+        let signing_wallet = wallet.clone();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![];
+            let mut chunk = [0u8; 4096];
+            let body = loop {
+                let n = stream.read(&mut chunk).await.unwrap();
+                request.extend_from_slice(&chunk[..n]);
+                let body = http_response_body(&request).unwrap_or_default();
+                if serde_json::from_slice::<Value>(&body).is_ok() {
+                    break body;
+                }
+            };
+
+            let call: Value = serde_json::from_slice(&body).unwrap();
+            let tx: Eip1559TransactionRequest =
+                serde_json::from_value(call["params"][0].clone()).unwrap();
+            let tx = TypedTransaction::Eip1559(tx);
+            let signature = signing_wallet.sign_transaction(&tx).await.unwrap();
+
+            let body = json!({ "jsonrpc": "2.0", "id": 1, "result": tx.rlp_signed(&signature) })
+                .to_string();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let signer = SearcherSigner::External(ExternalSigner::new(
+            SignerEndpoint::Unix(socket.clone()),
+            wallet.address(),
+        ));
+        let raw_tx = sign_eip1559(tx(wallet.address()), &signer).await;
+        std::fs::remove_file(socket).unwrap();
+
+        let (_, signature) = TypedTransaction::decode_signed(&Rlp::new(&raw_tx.unwrap())).unwrap();
+        let sighash = TypedTransaction::Eip1559(tx(wallet.address())).sighash();
+        assert_eq!(signature.recover(sighash).unwrap(), wallet.address());
+    }
+}
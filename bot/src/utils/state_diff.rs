@@ -1,4 +1,9 @@
-use crate::{prelude::Pool, utils};
+use crate::{
+    prelude::{
+        uniswap_v4, Pool, PoolExtra, PoolIdentifier, PoolSnapshots, PoolVariant, TokenRegistry,
+    },
+    utils::{self, swap_decoder::DecodedSwap},
+};
 use dashmap::DashMap;
 use ethers::prelude::*;
 use futures::stream::FuturesUnordered;
@@ -28,6 +33,57 @@ impl SandwichablePool {
     }
 }
 
+/// One leg of a victim's route
+#[derive(Clone, Copy, Debug)]
+pub struct RouteHop {
+    pub pool: Pool,
+    /// token victim sells into pool (native eth is represented as weth)
+    pub token_in: Address,
+    /// token victim receives from pool (native eth is represented as weth)
+    pub token_out: Address,
+}
+
+impl RouteHop {
+    // Find tokens flowing in and out of a pool from its swap direction
+    pub fn from_sandwichable_pool(sandwichable_pool: &SandwichablePool) -> Self {
+        let pool = sandwichable_pool.pool;
+        let weth = utils::constants::get_weth_address();
+        let eth_like = [
+            weth,
+            utils::constants::get_eth_placeholder_address(),
+            Address::zero(),
+        ];
+        let other_token = match eth_like.contains(&pool.token_0) {
+            true => pool.token_1,
+            false => pool.token_0,
+        };
+
+        let (token_in, token_out) = match sandwichable_pool.is_weth_input {
+            true => (weth, other_token),
+            false => (other_token, weth),
+        };
+
+        Self {
+            pool,
+            token_in,
+            token_out,
+        }
+    }
+}
+
+/// Tracked pools a victim swaps through, ordered by token flow
+#[derive(Clone, Debug, Default)]
+pub struct VictimRoute {
+    pub hops: Vec<RouteHop>,
+}
+
+impl VictimRoute {
+    // Does victim swap through more than one tracked pool
+    pub fn is_multi_hop(&self) -> bool {
+        self.hops.len() > 1
+    }
+}
+
 // Extract state diffs from a given tx
 //
 // Arguments:
@@ -82,49 +138,280 @@ pub async fn get_from_txs(
 ///
 /// Arguments:
 /// * `state_diffs`: BTreeMap of Address and AccountDiff
-/// * `all_pools`: HashMap of Address and Pool
+/// * `all_pools`: HashMap of PoolIdentifier and Pool
+/// * `pool_snapshots`: pools known to hold too little weth are skipped
+/// * `token_registry`: pools holding a blocked token are skipped
+/// * `block_number`: current block number, used to expire learned token registry entries
 ///
 /// Returns:
 /// Some(Vec<SandwichablePool>): Vec of pools that have been interacted with
 /// None: If state_diffs is empty
 pub fn extract_pools(
     state_diffs: &BTreeMap<Address, AccountDiff>,
-    all_pools: &DashMap<Address, Pool>,
+    all_pools: &DashMap<PoolIdentifier, Pool>,
+    pool_snapshots: &PoolSnapshots,
+    token_registry: &TokenRegistry,
+    block_number: U64,
 ) -> Option<Vec<SandwichablePool>> {
-    // capture all addresses that have a state change and are also a pool
-    let touched_pools: Vec<Pool> = state_diffs
-        .keys()
-        .filter_map(|e| all_pools.get(e).map(|p| (*p.value()).clone()))
-        .collect();
+    if state_diffs.is_empty() {
+        return None;
+    }
 
-    // find direction of swap based on state diff (does weth have state changes?)
-    let weth_state_diff = &state_diffs
-        .get(&utils::constants::get_weth_address())?
-        .storage;
+    let weth = utils::constants::get_weth_address();
+
+    let mut touched_pools: Vec<Pool> = vec![];
+    for (address, account_diff) in state_diffs {
+        // capture all addresses that have a state change and are also a pool
+        if let Some(pool) = all_pools.get(&PoolIdentifier::Address(*address)) {
+            touched_pools.push(*pool.value());
+        }
+
+        // pools living inside of a singleton are keyed by the slot holding their state, only
+        // singletons are worth a lookup per touched slot
+        if !utils::constants::is_pool_singleton(address) {
+            continue;
+        }
+        for slot in account_diff.storage.keys() {
+            let id = PoolIdentifier::Singleton {
+                singleton: *address,
+                slot: *slot,
+            };
+            if let Some(pool) = all_pools.get(&id) {
+                touched_pools.push(*pool.value());
+            }
+        }
+    }
+
+    let touched_pools: Vec<Pool> = touched_pools
+        .into_iter()
+        .filter(|p| {
+            [p.token_0, p.token_1]
+                .iter()
+                .all(|t| *t == weth || !token_registry.is_blocked(t, block_number))
+        })
+        .filter(|p| pool_snapshots.is_liquid(p))
+        .collect();
 
     let mut sandwichable_pools: Vec<SandwichablePool> = vec![];
 
-    // find storage mapping index for each pool
+    // find direction of swap for each pool
     for pool in touched_pools {
-        // find mapping storage location
-        let storage_key = TxHash::from(ethers::utils::keccak256(abi::encode(&[
-            abi::Token::Address(pool.address),
-            abi::Token::Uint(U256::from(3)),
-        ])));
-        let is_weth_input = match weth_state_diff.get(&storage_key)? {
-            Diff::Changed(c) => {
-                let from = U256::from(c.from.to_fixed_bytes());
-                let to = U256::from(c.to.to_fixed_bytes());
-                to > from
+        let is_weth_input = match pool.pool_variant {
+            PoolVariant::UniswapV2 | PoolVariant::UniswapV3 | PoolVariant::Solidly => {
+                find_weth_input_from_weth_diff(&pool, state_diffs)
             }
-            _ => continue,
+            PoolVariant::CurveStableSwap => find_weth_input_from_curve_diff(&pool, state_diffs),
+            PoolVariant::BalancerWeighted => find_weth_input_from_vault_diff(&pool, state_diffs),
+            PoolVariant::UniswapV4 => find_weth_input_from_pool_manager_diff(&pool, state_diffs),
         };
-        sandwichable_pools.push(SandwichablePool::new(pool, is_weth_input));
+
+        if let Some(is_weth_input) = is_weth_input {
+            sandwichable_pools.push(SandwichablePool::new(pool, is_weth_input));
+        }
     }
 
     Some(sandwichable_pools)
 }
 
+// Order the pools a victim touched into the route its tokens flow through
+//
+// State diffs only tell which pools moved and in which direction, if the victim's calldata could
+// be decoded its path is used to order the hops. Otherwise hops are ordered on token flow, every
+// tracked pool holds weth so on a multi hop route weth leaves the hops that buy it before it
+// enters the hops that sell it
+//
+// Arguments:
+// * `sandwichable_pools`: pools touched by victim (output of `extract_pools`)
+// * `victim_swap`: swap decoded from victim's calldata, if any
+//
+// Returns:
+// VictimRoute: hops ordered from the victim's token in to its token out
+pub fn reconstruct_route(
+    sandwichable_pools: &[SandwichablePool],
+    victim_swap: Option<&DecodedSwap>,
+) -> VictimRoute {
+    let weth = utils::constants::get_weth_address();
+
+    let mut hops: Vec<RouteHop> = sandwichable_pools
+        .iter()
+        .map(RouteHop::from_sandwichable_pool)
+        .collect();
+
+    match victim_swap {
+        Some(victim_swap) => hops.sort_by_key(|hop| {
+            victim_swap
+                .path
+                .iter()
+                .position(|token| *token == hop.token_in)
+                .unwrap_or(usize::MAX)
+        }),
+        None => hops.sort_by_key(|hop| hop.token_in == weth),
+    }
+
+    VictimRoute { hops }
+}
+
+// Find swap direction from the pool's balance mapping inside of weth contract
+//
+// Arguments:
+// * `pool`: pool that was touched
+// * `state_diffs`: state diffs produced by victim
+//
+// Returns:
+// Some(bool): true if pool's weth balance increased
+// None: if weth balance of pool did not change
+fn find_weth_input_from_weth_diff(
+    pool: &Pool,
+    state_diffs: &BTreeMap<Address, AccountDiff>,
+) -> Option<bool> {
+    let weth_state_diff = &state_diffs
+        .get(&utils::constants::get_weth_address())?
+        .storage;
+
+    // find mapping storage location
+    let storage_key = TxHash::from(ethers::utils::keccak256(abi::encode(&[
+        abi::Token::Address(pool.address),
+        abi::Token::Uint(U256::from(3)),
+    ])));
+
+    match weth_state_diff.get(&storage_key)? {
+        Diff::Changed(c) => {
+            let from = U256::from(c.from.to_fixed_bytes());
+            let to = U256::from(c.to.to_fixed_bytes());
+            Some(to > from)
+        }
+        _ => None,
+    }
+}
+
+// Find swap direction from the `balances` array stored in a curve pool
+//
+// Arguments:
+// * `pool`: curve pool that was touched
+// * `state_diffs`: state diffs produced by victim
+//
+// Returns:
+// Some(bool): true if pool's weth (or native eth) balance increased
+// None: if balance of weth side did not change
+fn find_weth_input_from_curve_diff(
+    pool: &Pool,
+    state_diffs: &BTreeMap<Address, AccountDiff>,
+) -> Option<bool> {
+    let (token_0_index, balances_slot) = match pool.extra {
+        PoolExtra::Curve {
+            token_0_index,
+            balances_slot,
+            ..
+        } => (token_0_index as u64, balances_slot),
+        _ => return None,
+    };
+
+    let eth_like = [
+        utils::constants::get_weth_address(),
+        utils::constants::get_eth_placeholder_address(),
+    ];
+    let weth_index = match eth_like.contains(&pool.token_0) {
+        true => token_0_index,
+        false => 1 - token_0_index,
+    };
+
+    let pool_state_diff = &state_diffs.get(&pool.address)?.storage;
+    let storage_key = H256::from_low_u64_be(balances_slot + weth_index);
+
+    match pool_state_diff.get(&storage_key)? {
+        Diff::Changed(c) => {
+            let from = U256::from(c.from.to_fixed_bytes());
+            let to = U256::from(c.to.to_fixed_bytes());
+            Some(to > from)
+        }
+        _ => None,
+    }
+}
+
+// Find swap direction from the packed cash balances balancer's vault keeps for two token pools
+//
+// Arguments:
+// * `pool`: balancer pool that was touched
+// * `state_diffs`: state diffs produced by victim
+//
+// Returns:
+// Some(bool): true if pool's weth cash increased
+// None: if weth cash of pool did not change
+fn find_weth_input_from_vault_diff(
+    pool: &Pool,
+    state_diffs: &BTreeMap<Address, AccountDiff>,
+) -> Option<bool> {
+    let balances_key = match pool.extra {
+        PoolExtra::Balancer { balances_key, .. } => balances_key,
+        _ => return None,
+    };
+
+    let vault_state_diff = &state_diffs
+        .get(&utils::constants::get_balancer_vault_address())?
+        .storage;
+
+    // sharedCash is packed as [ 32 bits block | 112 bits cash B | 112 bits cash A ]
+    let weth_cash = |shared_cash: H256| -> U256 {
+        let shared_cash = U256::from(shared_cash.to_fixed_bytes());
+        let mask = (U256::one() << 112) - 1;
+        match pool.token_0 == utils::constants::get_weth_address() {
+            true => shared_cash & mask,
+            false => (shared_cash >> 112) & mask,
+        }
+    };
+
+    match vault_state_diff.get(&balances_key)? {
+        Diff::Changed(c) => {
+            let from = weth_cash(c.from);
+            let to = weth_cash(c.to);
+            match from == to {
+                true => None,
+                false => Some(to > from),
+            }
+        }
+        _ => None,
+    }
+}
+
+// Find swap direction from the price change of a v4 pool inside of the pool manager
+//
+// v4 pools do not have their own token balances, price of token 0 (in terms of token 1) goes down
+// when token 0 is sold into the pool
+//
+// Arguments:
+// * `pool`: v4 pool that was touched
+// * `state_diffs`: state diffs produced by victim
+//
+// Returns:
+// Some(bool): true if weth (or native eth) was sold into the pool
+// None: if price of pool did not change
+fn find_weth_input_from_pool_manager_diff(
+    pool: &Pool,
+    state_diffs: &BTreeMap<Address, AccountDiff>,
+) -> Option<bool> {
+    let state_slot = match pool.extra {
+        PoolExtra::UniswapV4 { state_slot, .. } => state_slot,
+        _ => return None,
+    };
+
+    let pool_manager_diff = &state_diffs.get(&pool.address)?.storage;
+
+    match pool_manager_diff.get(&state_slot)? {
+        Diff::Changed(c) => {
+            let from = uniswap_v4::sqrt_price_from_slot0(c.from);
+            let to = uniswap_v4::sqrt_price_from_slot0(c.to);
+            if from == to {
+                return None;
+            }
+
+            let eth_like = [utils::constants::get_weth_address(), Address::zero()];
+            let is_zero_for_one = to < from;
+            Some(is_zero_for_one == eth_like.contains(&pool.token_0))
+        }
+        _ => None,
+    }
+}
+
 // Turn state_diffs into a new cache_db
 //
 // Arguments:
@@ -195,3 +482,82 @@ pub async fn to_cache_db(
 
     Ok(cache_db)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::swap_decoder::{SwapAmounts, SwapRouter};
+
+    fn weth_pool(token: Address, pool: u8) -> Pool {
+        Pool::new(
+            Address::repeat_byte(pool),
+            utils::constants::get_weth_address(),
+            token,
+            U256::from(3000),
+            PoolVariant::UniswapV2,
+        )
+    }
+
+    fn victim_swap(path: Vec<Address>) -> DecodedSwap {
+        DecodedSwap {
+            router: SwapRouter::UniswapV2Router,
+            path,
+            amounts: SwapAmounts::ExactIn {
+                amount_in: U256::one(),
+                amount_out_min: U256::zero(),
+            },
+            deadline: None,
+        }
+    }
+
+    #[test]
+    fn route_follows_victim_path() {
+        let weth = utils::constants::get_weth_address();
+        let (token_a, token_b) = (Address::repeat_byte(0x0a), Address::repeat_byte(0x0b));
+        // token_a -> weth -> token_b, touched pools come out of the state diff in address order
+        let sandwichable_pools = [
+            SandwichablePool::new(weth_pool(token_b, 0x01), true),
+            SandwichablePool::new(weth_pool(token_a, 0x02), false),
+        ];
+
+        let swap = victim_swap(vec![token_a, weth, token_b]);
+        let route = reconstruct_route(&sandwichable_pools, Some(&swap));
+
+        assert!(route.is_multi_hop());
+        assert_eq!(route.hops[0].pool.address, Address::repeat_byte(0x02));
+        assert_eq!(
+            (route.hops[0].token_in, route.hops[0].token_out),
+            (token_a, weth)
+        );
+        assert_eq!(route.hops[1].pool.address, Address::repeat_byte(0x01));
+        assert_eq!(
+            (route.hops[1].token_in, route.hops[1].token_out),
+            (weth, token_b)
+        );
+    }
+
+    #[test]
+    fn route_without_calldata_follows_weth_flow() {
+        let weth = utils::constants::get_weth_address();
+        let (token_a, token_b) = (Address::repeat_byte(0x0a), Address::repeat_byte(0x0b));
+        let sandwichable_pools = [
+            SandwichablePool::new(weth_pool(token_b, 0x01), true),
+            SandwichablePool::new(weth_pool(token_a, 0x02), false),
+        ];
+
+        // weth has to be bought before it can be sold
+        let route = reconstruct_route(&sandwichable_pools, None);
+        assert_eq!(route.hops[0].token_out, weth);
+        assert_eq!(route.hops[1].token_in, weth);
+    }
+
+    #[test]
+    fn single_pool_is_not_multi_hop() {
+        let token_a = Address::repeat_byte(0x0a);
+        let sandwichable_pools = [SandwichablePool::new(weth_pool(token_a, 0x01), true)];
+
+        let route = reconstruct_route(&sandwichable_pools, None);
+        assert!(!route.is_multi_hop());
+        assert_eq!(route.hops[0].token_out, token_a);
+    }
+}
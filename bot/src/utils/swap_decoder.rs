@@ -0,0 +1,798 @@
+// decoders for swaps sent through routers and aggregators, lets us read a victim's slippage
+// tolerance and deadline straight from its calldata instead of from the state it touches
+use std::sync::OnceLock;
+
+use ethers::{
+    abi::{self, param_type::Reader, ParamType, Token},
+    prelude::*,
+    utils::id,
+};
+use hashbrown::HashMap;
+
+use crate::utils;
+
+/// Router (or aggregator) family a swap was decoded from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapRouter {
+    UniswapV2Router,
+    SwapRouter,
+    SwapRouter02,
+    UniversalRouter,
+    OneInch,
+    ZeroEx,
+}
+
+/// Amounts a victim commits to, depends on which side of the swap is exact
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapAmounts {
+    ExactIn {
+        amount_in: U256,
+        amount_out_min: U256,
+    },
+    ExactOut {
+        amount_out: U256,
+        amount_in_max: U256,
+    },
+    /// swap spends a balance left by earlier calls, calldata does not tell how much
+    Unknown,
+}
+
+/// Swap parameters read from a victim's calldata
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedSwap {
+    pub router: SwapRouter,
+    /// tokens swapped through in order (aggregators only expose the first and last token)
+    pub path: Vec<Address>,
+    pub amounts: SwapAmounts,
+    /// timestamp after which swap reverts, None if calldata has no deadline
+    pub deadline: Option<U256>,
+}
+
+impl DecodedSwap {
+    // Token victim sells
+    pub fn token_in(&self) -> Option<Address> {
+        self.path.first().copied()
+    }
+
+    // Token victim buys
+    pub fn token_out(&self) -> Option<Address> {
+        self.path.last().copied()
+    }
+
+    // Max weth (or native eth) victim sells, None if swap does not start from weth
+    pub fn weth_in(&self) -> Option<U256> {
+        if self.token_in()? != utils::constants::get_weth_address() {
+            return None;
+        }
+        match self.amounts {
+            SwapAmounts::ExactIn { amount_in, .. } => Some(amount_in),
+            SwapAmounts::ExactOut { amount_in_max, .. } => Some(amount_in_max),
+            SwapAmounts::Unknown => None,
+        }
+    }
+
+    // Check if swap will revert because its deadline is before `timestamp`
+    pub fn is_expired(&self, timestamp: U256) -> bool {
+        matches!(self.deadline, Some(deadline) if deadline < timestamp)
+    }
+}
+
+// Universal router commands that swap, the rest (wraps, transfers, permits) are skipped
+mod commands {
+    pub const V3_SWAP_EXACT_IN: u8 = 0x00;
+    pub const V3_SWAP_EXACT_OUT: u8 = 0x01;
+    pub const V2_SWAP_EXACT_IN: u8 = 0x08;
+    pub const V2_SWAP_EXACT_OUT: u8 = 0x09;
+
+    // top bits of a command are flags (e.g. allow revert)
+    pub const COMMAND_TYPE_MASK: u8 = 0x3f;
+}
+
+// Universal router amount meaning "use whatever the router holds" (`Constants.CONTRACT_BALANCE`)
+fn contract_balance() -> U256 {
+    U256::one() << 255
+}
+
+// Decode the swap made by a transaction
+//
+// Arguments:
+// * `tx`: victim transaction
+//
+// Returns:
+// Some(DecodedSwap): swap parameters if tx calls a known router function
+// None: if calldata does not match any known swap function
+pub fn decode_swap(tx: &Transaction) -> Option<DecodedSwap> {
+    decode_calldata(&tx.input, tx.value)
+}
+
+// Decode calldata of a swap, `value` is eth sent along (amount in of eth input swaps)
+fn decode_calldata(input: &[u8], value: U256) -> Option<DecodedSwap> {
+    if input.len() < 4 {
+        return None;
+    }
+
+    let (selector, args) = input.split_at(4);
+    let decoder = decoders().get(selector)?;
+    let tokens = abi::decode(&decoder.params, args).ok()?;
+    (decoder.decode)(&tokens, value)
+}
+
+type DecodeFn = fn(&[Token], U256) -> Option<DecodedSwap>;
+
+struct Decoder {
+    params: Vec<ParamType>,
+    decode: DecodeFn,
+}
+
+// Supported swap functions keyed by selector
+fn decoders() -> &'static HashMap<[u8; 4], Decoder> {
+    static DECODERS: OnceLock<HashMap<[u8; 4], Decoder>> = OnceLock::new();
+    DECODERS.get_or_init(|| {
+        let functions: Vec<(&str, DecodeFn)> = vec![
+            // uniswap v2 router (and its forks)
+            (
+                "swapExactTokensForTokens(uint256,uint256,address[],address,uint256)",
+                v2_exact_in,
+            ),
+            (
+                "swapExactTokensForETH(uint256,uint256,address[],address,uint256)",
+                v2_exact_in,
+            ),
+            (
+                "swapExactTokensForTokensSupportingFeeOnTransferTokens(uint256,uint256,address[],address,uint256)",
+                v2_exact_in,
+            ),
+            (
+                "swapExactTokensForETHSupportingFeeOnTransferTokens(uint256,uint256,address[],address,uint256)",
+                v2_exact_in,
+            ),
+            (
+                "swapTokensForExactTokens(uint256,uint256,address[],address,uint256)",
+                v2_exact_out,
+            ),
+            (
+                "swapTokensForExactETH(uint256,uint256,address[],address,uint256)",
+                v2_exact_out,
+            ),
+            (
+                "swapExactETHForTokens(uint256,address[],address,uint256)",
+                v2_exact_eth_in,
+            ),
+            (
+                "swapExactETHForTokensSupportingFeeOnTransferTokens(uint256,address[],address,uint256)",
+                v2_exact_eth_in,
+            ),
+            (
+                "swapETHForExactTokens(uint256,address[],address,uint256)",
+                v2_eth_exact_out,
+            ),
+            // uniswap v3 swap router
+            (
+                "exactInputSingle((address,address,uint24,address,uint256,uint256,uint256,uint160))",
+                v3_exact_input_single,
+            ),
+            (
+                "exactInput((bytes,address,uint256,uint256,uint256))",
+                v3_exact_input,
+            ),
+            (
+                "exactOutputSingle((address,address,uint24,address,uint256,uint256,uint256,uint160))",
+                v3_exact_output_single,
+            ),
+            (
+                "exactOutput((bytes,address,uint256,uint256,uint256))",
+                v3_exact_output,
+            ),
+            ("multicall(bytes[])", multicall),
+            // uniswap swap router 02 (deadline moved into multicall)
+            (
+                "swapExactTokensForTokens(uint256,uint256,address[],address)",
+                router02_v2_exact_in,
+            ),
+            (
+                "swapTokensForExactTokens(uint256,uint256,address[],address)",
+                router02_v2_exact_out,
+            ),
+            (
+                "exactInputSingle((address,address,uint24,address,uint256,uint256,uint160))",
+                router02_exact_input_single,
+            ),
+            (
+                "exactInput((bytes,address,uint256,uint256))",
+                router02_exact_input,
+            ),
+            (
+                "exactOutputSingle((address,address,uint24,address,uint256,uint256,uint160))",
+                router02_exact_output_single,
+            ),
+            (
+                "exactOutput((bytes,address,uint256,uint256))",
+                router02_exact_output,
+            ),
+            ("multicall(uint256,bytes[])", multicall_with_deadline),
+            ("multicall(bytes32,bytes[])", multicall_with_blockhash),
+            // uniswap universal router
+            ("execute(bytes,bytes[],uint256)", universal_router_execute),
+            ("execute(bytes,bytes[])", universal_router_execute),
+            // 1inch aggregation router v5 and v6
+            (
+                "swap(address,(address,address,address,address,uint256,uint256,uint256),bytes,bytes)",
+                one_inch_swap,
+            ),
+            (
+                "swap(address,(address,address,address,address,uint256,uint256,uint256),bytes)",
+                one_inch_swap,
+            ),
+            // 0x exchange proxy
+            (
+                "transformERC20(address,address,uint256,uint256,(uint32,bytes)[])",
+                zero_ex_transform_erc20,
+            ),
+            (
+                "sellToUniswap(address[],uint256,uint256,bool)",
+                zero_ex_sell_to_uniswap,
+            ),
+            (
+                "sellTokenForTokenToUniswapV3(bytes,uint256,uint256,address)",
+                zero_ex_sell_token_to_uniswap_v3,
+            ),
+            (
+                "sellEthForTokenToUniswapV3(bytes,uint256,address)",
+                zero_ex_sell_eth_to_uniswap_v3,
+            ),
+        ];
+
+        functions
+            .into_iter()
+            .map(|(signature, decode)| {
+                let args = &signature[signature.find('(').unwrap()..];
+                let params = match Reader::read(args) {
+                    Ok(ParamType::Tuple(params)) => params,
+                    _ => panic!("Failed to parse swap signature {}", signature),
+                };
+                (id(signature), Decoder { params, decode })
+            })
+            .collect()
+    })
+}
+
+// ========= UNISWAP V2 ROUTER
+
+fn v2_exact_in(tokens: &[Token], _: U256) -> Option<DecodedSwap> {
+    Some(DecodedSwap {
+        router: SwapRouter::UniswapV2Router,
+        path: address_array(tokens.get(2)?)?,
+        amounts: SwapAmounts::ExactIn {
+            amount_in: uint(tokens.first()?)?,
+            amount_out_min: uint(tokens.get(1)?)?,
+        },
+        deadline: Some(uint(tokens.get(4)?)?),
+    })
+}
+
+fn v2_exact_out(tokens: &[Token], _: U256) -> Option<DecodedSwap> {
+    Some(DecodedSwap {
+        router: SwapRouter::UniswapV2Router,
+        path: address_array(tokens.get(2)?)?,
+        amounts: SwapAmounts::ExactOut {
+            amount_out: uint(tokens.first()?)?,
+            amount_in_max: uint(tokens.get(1)?)?,
+        },
+        deadline: Some(uint(tokens.get(4)?)?),
+    })
+}
+
+fn v2_exact_eth_in(tokens: &[Token], value: U256) -> Option<DecodedSwap> {
+    Some(DecodedSwap {
+        router: SwapRouter::UniswapV2Router,
+        path: address_array(tokens.get(1)?)?,
+        amounts: SwapAmounts::ExactIn {
+            amount_in: value,
+            amount_out_min: uint(tokens.first()?)?,
+        },
+        deadline: Some(uint(tokens.get(3)?)?),
+    })
+}
+
+fn v2_eth_exact_out(tokens: &[Token], value: U256) -> Option<DecodedSwap> {
+    Some(DecodedSwap {
+        router: SwapRouter::UniswapV2Router,
+        path: address_array(tokens.get(1)?)?,
+        amounts: SwapAmounts::ExactOut {
+            amount_out: uint(tokens.first()?)?,
+            amount_in_max: value,
+        },
+        deadline: Some(uint(tokens.get(3)?)?),
+    })
+}
+
+// ========= UNISWAP V3 SWAP ROUTER
+
+fn v3_exact_input_single(tokens: &[Token], _: U256) -> Option<DecodedSwap> {
+    let params = tuple(tokens.first()?)?;
+    Some(DecodedSwap {
+        router: SwapRouter::SwapRouter,
+        path: vec![address(params.first()?)?, address(params.get(1)?)?],
+        amounts: SwapAmounts::ExactIn {
+            amount_in: uint(params.get(5)?)?,
+            amount_out_min: uint(params.get(6)?)?,
+        },
+        deadline: Some(uint(params.get(4)?)?),
+    })
+}
+
+fn v3_exact_input(tokens: &[Token], _: U256) -> Option<DecodedSwap> {
+    let params = tuple(tokens.first()?)?;
+    Some(DecodedSwap {
+        router: SwapRouter::SwapRouter,
+        path: v3_path(params.first()?, false)?,
+        amounts: SwapAmounts::ExactIn {
+            amount_in: uint(params.get(3)?)?,
+            amount_out_min: uint(params.get(4)?)?,
+        },
+        deadline: Some(uint(params.get(2)?)?),
+    })
+}
+
+fn v3_exact_output_single(tokens: &[Token], _: U256) -> Option<DecodedSwap> {
+    let params = tuple(tokens.first()?)?;
+    Some(DecodedSwap {
+        router: SwapRouter::SwapRouter,
+        path: vec![address(params.first()?)?, address(params.get(1)?)?],
+        amounts: SwapAmounts::ExactOut {
+            amount_out: uint(params.get(5)?)?,
+            amount_in_max: uint(params.get(6)?)?,
+        },
+        deadline: Some(uint(params.get(4)?)?),
+    })
+}
+
+fn v3_exact_output(tokens: &[Token], _: U256) -> Option<DecodedSwap> {
+    let params = tuple(tokens.first()?)?;
+    Some(DecodedSwap {
+        router: SwapRouter::SwapRouter,
+        path: v3_path(params.first()?, true)?,
+        amounts: SwapAmounts::ExactOut {
+            amount_out: uint(params.get(3)?)?,
+            amount_in_max: uint(params.get(4)?)?,
+        },
+        deadline: Some(uint(params.get(2)?)?),
+    })
+}
+
+// Both routers batch calls through `multicall`, first call that swaps is the one we care about
+fn multicall(tokens: &[Token], value: U256) -> Option<DecodedSwap> {
+    tokens
+        .first()?
+        .clone()
+        .into_array()?
+        .into_iter()
+        .filter_map(|call| call.into_bytes())
+        .find_map(|call| decode_calldata(&call, value))
+}
+
+// ========= UNISWAP SWAP ROUTER 02
+
+fn router02_v2_exact_in(tokens: &[Token], _: U256) -> Option<DecodedSwap> {
+    Some(DecodedSwap {
+        router: SwapRouter::SwapRouter02,
+        path: address_array(tokens.get(2)?)?,
+        amounts: SwapAmounts::ExactIn {
+            amount_in: uint(tokens.first()?)?,
+            amount_out_min: uint(tokens.get(1)?)?,
+        },
+        deadline: None,
+    })
+}
+
+fn router02_v2_exact_out(tokens: &[Token], _: U256) -> Option<DecodedSwap> {
+    Some(DecodedSwap {
+        router: SwapRouter::SwapRouter02,
+        path: address_array(tokens.get(2)?)?,
+        amounts: SwapAmounts::ExactOut {
+            amount_out: uint(tokens.first()?)?,
+            amount_in_max: uint(tokens.get(1)?)?,
+        },
+        deadline: None,
+    })
+}
+
+fn router02_exact_input_single(tokens: &[Token], _: U256) -> Option<DecodedSwap> {
+    let params = tuple(tokens.first()?)?;
+    Some(DecodedSwap {
+        router: SwapRouter::SwapRouter02,
+        path: vec![address(params.first()?)?, address(params.get(1)?)?],
+        amounts: SwapAmounts::ExactIn {
+            amount_in: uint(params.get(4)?)?,
+            amount_out_min: uint(params.get(5)?)?,
+        },
+        deadline: None,
+    })
+}
+
+fn router02_exact_input(tokens: &[Token], _: U256) -> Option<DecodedSwap> {
+    let params = tuple(tokens.first()?)?;
+    Some(DecodedSwap {
+        router: SwapRouter::SwapRouter02,
+        path: v3_path(params.first()?, false)?,
+        amounts: SwapAmounts::ExactIn {
+            amount_in: uint(params.get(2)?)?,
+            amount_out_min: uint(params.get(3)?)?,
+        },
+        deadline: None,
+    })
+}
+
+fn router02_exact_output_single(tokens: &[Token], _: U256) -> Option<DecodedSwap> {
+    let params = tuple(tokens.first()?)?;
+    Some(DecodedSwap {
+        router: SwapRouter::SwapRouter02,
+        path: vec![address(params.first()?)?, address(params.get(1)?)?],
+        amounts: SwapAmounts::ExactOut {
+            amount_out: uint(params.get(4)?)?,
+            amount_in_max: uint(params.get(5)?)?,
+        },
+        deadline: None,
+    })
+}
+
+fn router02_exact_output(tokens: &[Token], _: U256) -> Option<DecodedSwap> {
+    let params = tuple(tokens.first()?)?;
+    Some(DecodedSwap {
+        router: SwapRouter::SwapRouter02,
+        path: v3_path(params.first()?, true)?,
+        amounts: SwapAmounts::ExactOut {
+            amount_out: uint(params.get(2)?)?,
+            amount_in_max: uint(params.get(3)?)?,
+        },
+        deadline: None,
+    })
+}
+
+fn multicall_with_deadline(tokens: &[Token], value: U256) -> Option<DecodedSwap> {
+    let deadline = uint(tokens.first()?)?;
+    let mut swap = multicall(tokens.get(1..)?, value)?;
+    swap.deadline = Some(deadline);
+    Some(swap)
+}
+
+// `previousBlockhash` pins the calls to a block, it does not bound the swap in time
+fn multicall_with_blockhash(tokens: &[Token], value: U256) -> Option<DecodedSwap> {
+    multicall(tokens.get(1..)?, value)
+}
+
+// ========= UNISWAP UNIVERSAL ROUTER
+
+fn universal_router_execute(tokens: &[Token], value: U256) -> Option<DecodedSwap> {
+    let commands = tokens.first()?.clone().into_bytes()?;
+    let inputs = tokens.get(1)?.clone().into_array()?;
+    let deadline = tokens.get(2).and_then(uint);
+
+    commands.iter().zip(inputs).find_map(|(command, input)| {
+        let input = input.into_bytes()?;
+        let command = command & commands::COMMAND_TYPE_MASK;
+
+        // every swap input is (recipient, amount, amount limit, path, payer is user)
+        let path_type = match command {
+            commands::V3_SWAP_EXACT_IN | commands::V3_SWAP_EXACT_OUT => ParamType::Bytes,
+            commands::V2_SWAP_EXACT_IN | commands::V2_SWAP_EXACT_OUT => {
+                ParamType::Array(Box::new(ParamType::Address))
+            }
+            _ => return None,
+        };
+        let params = abi::decode(
+            &[
+                ParamType::Address,
+                ParamType::Uint(256),
+                ParamType::Uint(256),
+                path_type,
+                ParamType::Bool,
+            ],
+            &input,
+        )
+        .ok()?;
+
+        let amount = uint(params.get(1)?)?;
+        let amount_limit = uint(params.get(2)?)?;
+        let path = match command {
+            commands::V3_SWAP_EXACT_IN => v3_path(params.get(3)?, false)?,
+            commands::V3_SWAP_EXACT_OUT => v3_path(params.get(3)?, true)?,
+            _ => address_array(params.get(3)?)?,
+        };
+
+        let amounts = match command {
+            // eth swaps wrap first and then spend the router's balance, any other balance was
+            // left by earlier commands and its size is unknown
+            commands::V3_SWAP_EXACT_IN | commands::V2_SWAP_EXACT_IN
+                if amount == contract_balance() && value.is_zero() =>
+            {
+                SwapAmounts::Unknown
+            }
+            commands::V3_SWAP_EXACT_IN | commands::V2_SWAP_EXACT_IN => SwapAmounts::ExactIn {
+                amount_in: match amount == contract_balance() {
+                    true => value,
+                    false => amount,
+                },
+                amount_out_min: amount_limit,
+            },
+            _ => SwapAmounts::ExactOut {
+                amount_out: amount,
+                amount_in_max: amount_limit,
+            },
+        };
+
+        Some(DecodedSwap {
+            router: SwapRouter::UniversalRouter,
+            path,
+            amounts,
+            deadline,
+        })
+    })
+}
+
+// ========= AGGREGATORS
+
+fn one_inch_swap(tokens: &[Token], value: U256) -> Option<DecodedSwap> {
+    // (srcToken, dstToken, srcReceiver, dstReceiver, amount, minReturnAmount, flags)
+    let desc = tuple(tokens.get(1)?)?;
+    let token_in = address(desc.first()?)?;
+    Some(DecodedSwap {
+        router: SwapRouter::OneInch,
+        path: vec![to_weth(token_in), to_weth(address(desc.get(1)?)?)],
+        amounts: SwapAmounts::ExactIn {
+            amount_in: eth_or(token_in, value, uint(desc.get(4)?)?),
+            amount_out_min: uint(desc.get(5)?)?,
+        },
+        deadline: None,
+    })
+}
+
+fn zero_ex_transform_erc20(tokens: &[Token], value: U256) -> Option<DecodedSwap> {
+    let token_in = address(tokens.first()?)?;
+    Some(DecodedSwap {
+        router: SwapRouter::ZeroEx,
+        path: vec![to_weth(token_in), to_weth(address(tokens.get(1)?)?)],
+        amounts: SwapAmounts::ExactIn {
+            amount_in: eth_or(token_in, value, uint(tokens.get(2)?)?),
+            amount_out_min: uint(tokens.get(3)?)?,
+        },
+        deadline: None,
+    })
+}
+
+fn zero_ex_sell_to_uniswap(tokens: &[Token], value: U256) -> Option<DecodedSwap> {
+    let path: Vec<Address> = address_array(tokens.first()?)?;
+    let token_in = *path.first()?;
+    Some(DecodedSwap {
+        router: SwapRouter::ZeroEx,
+        path: path.into_iter().map(to_weth).collect(),
+        amounts: SwapAmounts::ExactIn {
+            amount_in: eth_or(token_in, value, uint(tokens.get(1)?)?),
+            amount_out_min: uint(tokens.get(2)?)?,
+        },
+        deadline: None,
+    })
+}
+
+fn zero_ex_sell_token_to_uniswap_v3(tokens: &[Token], _: U256) -> Option<DecodedSwap> {
+    Some(DecodedSwap {
+        router: SwapRouter::ZeroEx,
+        path: v3_path(tokens.first()?, false)?,
+        amounts: SwapAmounts::ExactIn {
+            amount_in: uint(tokens.get(1)?)?,
+            amount_out_min: uint(tokens.get(2)?)?,
+        },
+        deadline: None,
+    })
+}
+
+fn zero_ex_sell_eth_to_uniswap_v3(tokens: &[Token], value: U256) -> Option<DecodedSwap> {
+    Some(DecodedSwap {
+        router: SwapRouter::ZeroEx,
+        path: v3_path(tokens.first()?, false)?,
+        amounts: SwapAmounts::ExactIn {
+            amount_in: value,
+            amount_out_min: uint(tokens.get(1)?)?,
+        },
+        deadline: None,
+    })
+}
+
+// ========= TOKEN HELPERS
+
+fn uint(token: &Token) -> Option<U256> {
+    token.clone().into_uint()
+}
+
+fn address(token: &Token) -> Option<Address> {
+    token.clone().into_address()
+}
+
+fn tuple(token: &Token) -> Option<Vec<Token>> {
+    token.clone().into_tuple()
+}
+
+fn address_array(token: &Token) -> Option<Vec<Address>> {
+    token
+        .clone()
+        .into_array()?
+        .into_iter()
+        .map(|t| t.into_address())
+        .collect()
+}
+
+// Split a packed v3 path into its tokens
+//
+// Arguments:
+// * `token`: path encoded as [token (20) | fee (3) | token (20) | ...]
+// * `is_exact_output`: exact output paths are encoded from token out to token in
+//
+// Returns:
+// Some(Vec<Address>): tokens ordered from token in to token out
+// None: if path is malformed
+fn v3_path(token: &Token, is_exact_output: bool) -> Option<Vec<Address>> {
+    let path = token.clone().into_bytes()?;
+    if path.len() < 43 || (path.len() - 20) % 23 != 0 {
+        return None;
+    }
+
+    let mut tokens: Vec<Address> = path
+        .chunks(23)
+        .map(|hop| Address::from_slice(&hop[..20]))
+        .collect();
+    if is_exact_output {
+        tokens.reverse();
+    }
+    Some(tokens)
+}
+
+// Aggregators represent native eth with a placeholder, pools only ever see weth
+fn to_weth(token: Address) -> Address {
+    match token == utils::constants::get_eth_placeholder_address() || token.is_zero() {
+        true => utils::constants::get_weth_address(),
+        false => token,
+    }
+}
+
+// Amount in of a swap that may sell native eth
+fn eth_or(token_in: Address, value: U256, amount: U256) -> U256 {
+    match to_weth(token_in) != token_in && !value.is_zero() {
+        true => value,
+        false => amount,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn calldata(signature: &str, tokens: &[Token]) -> Vec<u8> {
+        [id(signature).to_vec(), abi::encode(tokens)].concat()
+    }
+
+    fn token_a() -> Address {
+        Address::repeat_byte(0xaa)
+    }
+
+    #[test]
+    fn decodes_v2_router_eth_swap() {
+        let weth = utils::constants::get_weth_address();
+        let input = calldata(
+            "swapExactETHForTokens(uint256,address[],address,uint256)",
+            &[
+                Token::Uint(U256::from(1_000)),
+                Token::Array(vec![Token::Address(weth), Token::Address(token_a())]),
+                Token::Address(Address::repeat_byte(0x01)),
+                Token::Uint(U256::from(1_700_000_000u64)),
+            ],
+        );
+
+        let swap = decode_calldata(&input, U256::exp10(18)).unwrap();
+        assert_eq!(swap.router, SwapRouter::UniswapV2Router);
+        assert_eq!(swap.path, vec![weth, token_a()]);
+        assert_eq!(
+            swap.amounts,
+            SwapAmounts::ExactIn {
+                amount_in: U256::exp10(18),
+                amount_out_min: U256::from(1_000),
+            }
+        );
+        assert!(swap.is_expired(U256::from(1_700_000_012u64)));
+        assert!(!swap.is_expired(U256::from(1_700_000_000u64)));
+    }
+
+    #[test]
+    fn decodes_swap_router02_multicall_deadline() {
+        let weth = utils::constants::get_weth_address();
+        let mut path = weth.as_bytes().to_vec();
+        path.extend_from_slice(&[0x00, 0x0b, 0xb8]); // 3000 fee tier
+        path.extend_from_slice(token_a().as_bytes());
+
+        let exact_input = calldata(
+            "exactInput((bytes,address,uint256,uint256))",
+            &[Token::Tuple(vec![
+                Token::Bytes(path),
+                Token::Address(Address::repeat_byte(0x01)),
+                Token::Uint(U256::from(500)),
+                Token::Uint(U256::from(400)),
+            ])],
+        );
+        let input = calldata(
+            "multicall(uint256,bytes[])",
+            &[
+                Token::Uint(U256::from(42)),
+                Token::Array(vec![Token::Bytes(exact_input)]),
+            ],
+        );
+
+        let swap = decode_calldata(&input, U256::zero()).unwrap();
+        assert_eq!(swap.router, SwapRouter::SwapRouter02);
+        assert_eq!(swap.path, vec![weth, token_a()]);
+        assert_eq!(swap.deadline, Some(U256::from(42)));
+    }
+
+    #[test]
+    fn decodes_universal_router_v3_exact_out() {
+        let weth = utils::constants::get_weth_address();
+        // exact output paths start from token out
+        let mut path = token_a().as_bytes().to_vec();
+        path.extend_from_slice(&[0x00, 0x01, 0xf4]);
+        path.extend_from_slice(weth.as_bytes());
+
+        let wrap_eth = abi::encode(&[
+            Token::Address(Address::repeat_byte(0x02)),
+            Token::Uint(U256::from(900)),
+        ]);
+        let swap_input = abi::encode(&[
+            Token::Address(Address::repeat_byte(0x01)),
+            Token::Uint(U256::from(100)),
+            Token::Uint(U256::from(900)),
+            Token::Bytes(path),
+            Token::Bool(false),
+        ]);
+        let input = calldata(
+            "execute(bytes,bytes[],uint256)",
+            &[
+                Token::Bytes(vec![0x0b, commands::V3_SWAP_EXACT_OUT]),
+                Token::Array(vec![Token::Bytes(wrap_eth), Token::Bytes(swap_input)]),
+                Token::Uint(U256::from(7)),
+            ],
+        );
+
+        let swap = decode_calldata(&input, U256::from(900)).unwrap();
+        assert_eq!(swap.router, SwapRouter::UniversalRouter);
+        assert_eq!(swap.path, vec![weth, token_a()]);
+        assert_eq!(
+            swap.amounts,
+            SwapAmounts::ExactOut {
+                amount_out: U256::from(100),
+                amount_in_max: U256::from(900),
+            }
+        );
+        assert_eq!(swap.deadline, Some(U256::from(7)));
+    }
+
+    #[test]
+    fn universal_router_contract_balance_without_eth_is_unknown() {
+        let weth = utils::constants::get_weth_address();
+        let swap_input = abi::encode(&[
+            Token::Address(Address::repeat_byte(0x01)),
+            Token::Uint(contract_balance()),
+            Token::Uint(U256::from(100)),
+            Token::Array(vec![Token::Address(token_a()), Token::Address(weth)]),
+            Token::Bool(false),
+        ]);
+        let input = calldata(
+            "execute(bytes,bytes[],uint256)",
+            &[
+                Token::Bytes(vec![commands::V2_SWAP_EXACT_IN]),
+                Token::Array(vec![Token::Bytes(swap_input)]),
+                Token::Uint(U256::from(7)),
+            ],
+        );
+
+        let swap = decode_calldata(&input, U256::zero()).unwrap();
+        assert_eq!(swap.path, vec![token_a(), weth]);
+        assert_eq!(swap.amounts, SwapAmounts::Unknown);
+    }
+}
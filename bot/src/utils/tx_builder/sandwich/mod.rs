@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
-use crate::utils;
-use ethers::prelude::{k256::ecdsa::SigningKey, *};
+use crate::utils::{self, signer::SearcherSigner};
+use ethers::prelude::*;
 use tokio::sync::RwLock;
 
 pub mod v2;
@@ -12,7 +12,7 @@ pub struct SandwichMaker {
     pub v2: v2::SandwichLogicV2,
     pub v3: v3::SandwichLogicV3,
     pub sandwich_address: Address,
-    pub searcher_wallet: Wallet<SigningKey>,
+    pub searcher_wallet: SearcherSigner,
     pub nonce: Arc<RwLock<U256>>,
 }
 
@@ -20,7 +20,7 @@ impl SandwichMaker {
     // Create a new `SandwichMaker` instance
     pub async fn new() -> Self {
         let sandwich_address = utils::dotenv::get_sandwich_contract_address();
-        let searcher_wallet = utils::dotenv::get_searcher_wallet();
+        let searcher_wallet = utils::signer::searcher_signer().clone();
 
         let client = utils::create_websocket_client().await.unwrap();
 